@@ -0,0 +1,443 @@
+use crate::format::Container;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Source audio codecs MP4 can carry, and so copy into it.
+const MP4_AUDIO: &[&str] = &["aac", "mp3", "ac3", "eac3", "alac", "opus", "flac"];
+
+/// How the source's audio is carried into video output.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioCodec {
+    /// Left out.
+    None,
+    /// Copied as it is.
+    Copy,
+    /// AAC at 320 kb/s.
+    Aac,
+    /// 24-bit 48 kHz PCM.
+    Pcm,
+}
+
+impl AudioCodec {
+    /// Codec the audio of video in `container` is delivered in unless the
+    /// job picks one.
+    pub fn default_for(container: Container) -> AudioCodec {
+        match container {
+            Container::Mp4 => AudioCodec::Aac,
+            Container::Mov | Container::Mxf => AudioCodec::Pcm,
+        }
+    }
+
+    /// Options that map every audio stream of input `input` and encode
+    /// them in the codec, or nothing if the audio is left out. Non-empty
+    /// `filters` are applied to the stream at the same index.
+    pub fn mux_args(self, input: usize, filters: &[String]) -> Vec<String> {
+        let codec: &[&str] = match self {
+            AudioCodec::None => return Vec::new(),
+            AudioCodec::Copy => &["-c:a", "copy"],
+            AudioCodec::Aac => &["-c:a", "aac", "-b:a", "320k"],
+            AudioCodec::Pcm => &["-c:a", "pcm_s24le", "-ar", "48000"],
+        };
+        let mut args = vec!["-map".to_string(), format!("{}:a", input)];
+        args.extend(codec.iter().map(|a| a.to_string()));
+        for (index, filter) in filters.iter().enumerate().filter(|(_, f)| !f.is_empty()) {
+            args.extend([format!("-filter:a:{}", index), filter.clone()]);
+        }
+        args
+    }
+
+    /// Fail unless `container` can hold audio of the codec; `source` are
+    /// the codecs of the source's audio streams, which copied audio keeps.
+    pub fn check_container(self, container: Container, source: &[&str]) -> std::result::Result<(), String> {
+        match (self, container) {
+            (AudioCodec::Copy | AudioCodec::Aac, Container::Mxf) => {
+                Err(format!("MXF carries PCM audio, not {}; use --audio pcm or none", self))
+            }
+            (AudioCodec::Pcm, Container::Mp4) => Err("MP4 can't carry PCM audio; use --audio aac or copy".to_string()),
+            (AudioCodec::Copy, Container::Mp4) => match source.iter().find(|c| !MP4_AUDIO.contains(c)) {
+                Some(codec) => Err(format!("The input's {} audio can't be copied into MP4; use --audio aac", codec)),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for AudioCodec {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<AudioCodec, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(AudioCodec::None),
+            "copy" => Ok(AudioCodec::Copy),
+            "aac" => Ok(AudioCodec::Aac),
+            "pcm" => Ok(AudioCodec::Pcm),
+            _ => Err(format!("invalid audio codec '{}', expected none, copy, aac or pcm", text)),
+        }
+    }
+}
+
+impl fmt::Display for AudioCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AudioCodec::None => "none",
+            AudioCodec::Copy => "copy",
+            AudioCodec::Aac => "aac",
+            AudioCodec::Pcm => "pcm",
+        })
+    }
+}
+
+/// How the source's audio channels are laid out in tracks of the output.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackLayout {
+    /// One track per channel, as broadcasters usually ask for in MXF.
+    #[default]
+    Mono,
+    /// One track per pair of channels.
+    Stereo,
+    /// One track per six channels, in ffmpeg's 5.1 order (L, R, C, LFE, Ls,
+    /// Rs).
+    #[serde(rename = "5.1")]
+    Surround,
+}
+
+impl TrackLayout {
+    /// Channels in each track.
+    pub fn channels(self) -> usize {
+        match self {
+            TrackLayout::Mono => 1,
+            TrackLayout::Stereo => 2,
+            TrackLayout::Surround => 6,
+        }
+    }
+}
+
+impl FromStr for TrackLayout {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<TrackLayout, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "mono" => Ok(TrackLayout::Mono),
+            "stereo" => Ok(TrackLayout::Stereo),
+            "5.1" => Ok(TrackLayout::Surround),
+            _ => Err(format!("invalid audio layout '{}', expected mono, stereo or 5.1", text)),
+        }
+    }
+}
+
+impl fmt::Display for TrackLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TrackLayout::Mono => "mono",
+            TrackLayout::Stereo => "stereo",
+            TrackLayout::Surround => "5.1",
+        })
+    }
+}
+
+/// One output track made from source channels, e.g. `1+2:stereo` or
+/// `3-8:5.1`. Channels matching the layout are carried as they are; 5.1
+/// channels mapped to a stereo or mono track, and stereo channels mapped to
+/// a mono track, are downmixed with the ITU-R BS.775 coefficients.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TrackMap {
+    /// Source channels, numbered from 1 across all audio streams.
+    pub channels: Vec<u32>,
+    pub layout: TrackLayout,
+}
+
+impl TrackMap {
+    /// Whether the channels can make a track of the layout, carried or
+    /// downmixed.
+    pub fn fits(&self) -> bool {
+        let count = self.channels.len();
+        count == self.layout.channels()
+            || matches!((count, self.layout), (6, TrackLayout::Stereo | TrackLayout::Mono) | (2, TrackLayout::Mono))
+    }
+
+    /// The pan filter making the track from a stream holding every source
+    /// channel in order.
+    pub fn pan(&self) -> String {
+        let c: Vec<usize> = self.channels.iter().map(|&n| n as usize - 1).collect();
+        // Downmixes are renormalized (`<`) so they can't clip
+        let gains: Vec<String> = match (c.len(), self.layout) {
+            (6, TrackLayout::Stereo) => vec![
+                format!("c0<c{}+0.707*c{}+0.707*c{}", c[0], c[2], c[4]),
+                format!("c1<c{}+0.707*c{}+0.707*c{}", c[1], c[2], c[5]),
+            ],
+            (6, TrackLayout::Mono) => vec![format!(
+                "c0<0.5*c{}+0.5*c{}+0.707*c{}+0.354*c{}+0.354*c{}",
+                c[0], c[1], c[2], c[4], c[5]
+            )],
+            (2, TrackLayout::Mono) => vec![format!("c0<0.5*c{}+0.5*c{}", c[0], c[1])],
+            _ => c.iter().enumerate().map(|(out, source)| format!("c{}=c{}", out, source)).collect(),
+        };
+        format!("pan={}|{}", self.layout, gains.join("|"))
+    }
+}
+
+impl FromStr for TrackMap {
+    type Err = String;
+
+    /// Parse `<channels>:<layout>`, the channels as `+`-separated numbers
+    /// and ranges, e.g. `1+2:stereo`, `3-8:5.1` or `1-6:stereo` to downmix.
+    fn from_str(text: &str) -> std::result::Result<TrackMap, String> {
+        let invalid = || format!("invalid audio track '{}', expected <channels>:<layout>, e.g. 1+2:stereo", text);
+        let (channels, layout) = text.trim().rsplit_once(':').ok_or_else(invalid)?;
+        let layout: TrackLayout = layout.parse()?;
+        let mut numbers = Vec::new();
+        for part in channels.split('+') {
+            let (first, last) = part.split_once('-').unwrap_or((part, part));
+            let (first, last) = match (first.trim().parse::<u32>(), last.trim().parse::<u32>()) {
+                (Ok(first), Ok(last)) if first >= 1 && first <= last => (first, last),
+                _ => return Err(invalid()),
+            };
+            numbers.extend(first..=last);
+        }
+        Ok(TrackMap { channels: numbers, layout })
+    }
+}
+
+impl fmt::Display for TrackMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Runs of three or more consecutive channels are written as ranges
+        let mut parts: Vec<String> = Vec::new();
+        let mut index = 0;
+        while index < self.channels.len() {
+            let first = self.channels[index];
+            let mut last = first;
+            while self.channels.get(index + 1) == Some(&(last + 1)) {
+                last += 1;
+                index += 1;
+            }
+            match last - first {
+                0 => parts.push(first.to_string()),
+                1 => parts.extend([first.to_string(), last.to_string()]),
+                _ => parts.push(format!("{}-{}", first, last)),
+            }
+            index += 1;
+        }
+        write!(f, "{}:{}", parts.join("+"), self.layout)
+    }
+}
+
+/// Which of the source's audio channels go into the output, in which
+/// tracks, and what the tracks are labeled.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AudioTracks {
+    /// Channels per track.
+    #[serde(default)]
+    pub layout: TrackLayout,
+    /// Source channels carried, numbered from 1 across all audio streams,
+    /// in output order; empty carries every channel.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<u32>,
+    /// Label of each output track, written as its title; tracks past the
+    /// end are unlabeled.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    /// Output tracks mapped from source channels, each in its own layout;
+    /// when given, `layout` and `channels` are not used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tracks: Vec<TrackMap>,
+}
+
+impl AudioTracks {
+    /// Source channels carried, numbered from 1, for a source whose audio
+    /// streams have `streams` channels each.
+    pub fn source_channels(&self, streams: &[u32]) -> Vec<u32> {
+        if self.channels.is_empty() {
+            (1..=streams.iter().sum()).collect()
+        } else {
+            self.channels.clone()
+        }
+    }
+
+    /// The output tracks, for a source whose audio streams have `streams`
+    /// channels each.
+    pub fn mapped(&self, streams: &[u32]) -> Vec<TrackMap> {
+        if !self.tracks.is_empty() {
+            return self.tracks.clone();
+        }
+        let channels = self.source_channels(streams);
+        let layout = self.layout;
+        channels.chunks(layout.channels()).map(|c| TrackMap { channels: c.to_vec(), layout }).collect()
+    }
+
+    /// Fail unless the tracks can be made from a source whose audio streams
+    /// have `streams` channels each.
+    pub fn check(&self, streams: &[u32]) -> std::result::Result<(), String> {
+        let total: u32 = streams.iter().sum();
+        if let Some(channel) = self.channels.iter().find(|&&c| c == 0 || c > total) {
+            return Err(format!("--audio-channels {} doesn't exist; the input has {} audio channels", channel, total));
+        }
+        if let Some(track) = self.tracks.iter().find(|t| t.channels.iter().any(|&c| c > total)) {
+            return Err(format!("--audio-tracks {} needs channels the input lacks; it has {}", track, total));
+        }
+        if let Some(track) = self.tracks.iter().find(|t| !t.fits()) {
+            return Err(format!(
+                "--audio-tracks {}: {} channels can't make a {} track",
+                track,
+                track.channels.len(),
+                track.layout
+            ));
+        }
+        let channels = self.source_channels(streams);
+        if self.tracks.is_empty() && channels.len() % self.layout.channels() != 0 {
+            return Err(format!("{} channels can't be laid out in {} tracks", channels.len(), self.layout));
+        }
+        let tracks = self.mapped(streams).len();
+        if self.labels.len() > tracks {
+            return Err(format!("{} --audio-labels given for {} audio tracks", self.labels.len(), tracks));
+        }
+        Ok(())
+    }
+
+    /// Options that take the tracks from input `input` (whose audio streams
+    /// have `streams` channels each) and encode them as 24-bit 48 kHz PCM,
+    /// as MXF OP1a expects. Empty if the source has no audio. Non-empty
+    /// `filters` are applied to the stream at the same index before merging.
+    pub fn mux_args(&self, input: usize, streams: &[u32], filters: &[String]) -> Vec<String> {
+        if streams.is_empty() {
+            return Vec::new();
+        }
+        // Merge every stream into one so channels can be picked across them
+        let mut graph = String::new();
+        let mut inputs = String::new();
+        for index in 0..streams.len() {
+            match filters.get(index).filter(|f| !f.is_empty()) {
+                Some(filter) => {
+                    graph.push_str(&format!("[{}:a:{}]{}[norm{}];", input, index, filter, index));
+                    inputs.push_str(&format!("[norm{}]", index));
+                }
+                None => inputs.push_str(&format!("[{}:a:{}]", input, index)),
+            }
+        }
+        let merge = if streams.len() > 1 { format!("amerge=inputs={}", streams.len()) } else { "anull".to_string() };
+        let tracks = self.mapped(streams);
+        let outputs: String = (0..tracks.len()).map(|i| format!("[all{}]", i)).collect();
+        graph.push_str(&format!("{}{},asplit={}{}", inputs, merge, tracks.len(), outputs));
+        for (index, track) in tracks.iter().enumerate() {
+            graph.push_str(&format!(";[all{}]{}[track{}]", index, track.pan(), index));
+        }
+
+        let mut args = vec!["-filter_complex".to_string(), graph];
+        for index in 0..tracks.len() {
+            args.extend(["-map".to_string(), format!("[track{}]", index)]);
+        }
+        args.extend(["-c:a", "pcm_s24le", "-ar", "48000"].map(String::from));
+        for (index, label) in self.labels.iter().enumerate() {
+            args.extend([format!("-metadata:s:a:{}", index), format!("title={}", label)]);
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_map_round_trips() {
+        for text in ["1+2:stereo", "3-8:5.1", "1-6:stereo", "1+3+5:mono"] {
+            let track: TrackMap = text.parse().unwrap();
+            assert_eq!(track.to_string(), text);
+        }
+        let track: TrackMap = "3-8:5.1".parse().unwrap();
+        assert_eq!(track, TrackMap { channels: vec![3, 4, 5, 6, 7, 8], layout: TrackLayout::Surround });
+        let track: TrackMap = "1+2+3:mono".parse().unwrap();
+        assert_eq!(track.to_string(), "1-3:mono");
+    }
+
+    #[test]
+    fn track_map_rejects_bad_channels() {
+        for text in ["0:mono", "3-2:stereo", "1+2", "1+:stereo", "a-b:mono", "1:quad"] {
+            assert!(text.parse::<TrackMap>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn track_map_pans_surround_down_to_stereo_and_mono() {
+        let stereo: TrackMap = "3-8:stereo".parse().unwrap();
+        assert!(stereo.fits());
+        assert_eq!(stereo.pan(), "pan=stereo|c0<c2+0.707*c4+0.707*c6|c1<c3+0.707*c4+0.707*c7");
+
+        let mono: TrackMap = "1-6:mono".parse().unwrap();
+        assert!(mono.fits());
+        assert_eq!(mono.pan(), "pan=mono|c0<0.5*c0+0.5*c1+0.707*c2+0.354*c4+0.354*c5");
+
+        let carried: TrackMap = "2+1:stereo".parse().unwrap();
+        assert_eq!(carried.pan(), "pan=stereo|c0=c1|c1=c0");
+        assert!(!"1-3:stereo".parse::<TrackMap>().unwrap().fits());
+    }
+
+    #[test]
+    fn check_rejects_tracks_the_source_cant_fill() {
+        let stereo = AudioTracks { layout: TrackLayout::Stereo, ..Default::default() };
+        assert_eq!(stereo.check(&[2, 2]), Ok(()));
+        assert!(stereo.check(&[2, 1]).unwrap_err().contains("3 channels can't be laid out in stereo tracks"));
+
+        let missing = AudioTracks { channels: vec![1, 5], ..Default::default() };
+        assert!(missing.check(&[2, 2]).unwrap_err().starts_with("--audio-channels 5 doesn't exist"));
+        let zero = AudioTracks { channels: vec![0], ..Default::default() };
+        assert!(zero.check(&[2]).is_err());
+
+        let tracks = AudioTracks { tracks: vec!["1-6:stereo".parse().unwrap()], ..Default::default() };
+        assert_eq!(tracks.check(&[6]), Ok(()));
+        assert!(tracks.check(&[2]).unwrap_err().contains("needs channels the input lacks"));
+        let unfit = AudioTracks { tracks: vec!["1-3:stereo".parse().unwrap()], ..Default::default() };
+        assert!(unfit.check(&[6]).unwrap_err().contains("3 channels can't make a stereo track"));
+    }
+
+    #[test]
+    fn check_rejects_more_labels_than_tracks() {
+        let labels = |count| (0..count).map(|i| format!("Track {}", i)).collect();
+        let tracks = AudioTracks { layout: TrackLayout::Stereo, labels: labels(2), ..Default::default() };
+        assert_eq!(tracks.check(&[2, 2]), Ok(()));
+        let tracks = AudioTracks { labels: labels(3), ..tracks };
+        assert_eq!(tracks.check(&[2, 2]), Err("3 --audio-labels given for 2 audio tracks".to_string()));
+    }
+
+    #[test]
+    fn mux_args_pass_a_single_stream_through() {
+        let labels = vec!["Main".to_string()];
+        let tracks = AudioTracks { layout: TrackLayout::Stereo, labels, ..Default::default() };
+        let args = tracks.mux_args(1, &[2], &[]);
+        assert_eq!(
+            args,
+            [
+                "-filter_complex",
+                "[1:a:0]anull,asplit=1[all0];[all0]pan=stereo|c0=c0|c1=c1[track0]",
+                "-map",
+                "[track0]",
+                "-c:a",
+                "pcm_s24le",
+                "-ar",
+                "48000",
+                "-metadata:s:a:0",
+                "title=Main"
+            ]
+        );
+        assert!(tracks.mux_args(1, &[], &[]).is_empty());
+    }
+
+    #[test]
+    fn mux_args_merge_streams_and_normalize_each() {
+        let maps = vec!["1-6:5.1".parse().unwrap(), "7+8:stereo".parse().unwrap()];
+        let tracks = AudioTracks { tracks: maps, ..Default::default() };
+        let filters = ["loudnorm=I=-23".to_string(), String::new()];
+        let args = tracks.mux_args(0, &[6, 2], &filters);
+        assert_eq!(
+            args[1],
+            "[0:a:0]loudnorm=I=-23[norm0];[norm0][0:a:1]amerge=inputs=2,asplit=2[all0][all1];\
+            [all0]pan=5.1|c0=c0|c1=c1|c2=c2|c3=c3|c4=c4|c5=c5[track0];[all1]pan=stereo|c0=c6|c1=c7[track1]"
+        );
+        assert_eq!(args[2..6], ["-map", "[track0]", "-map", "[track1]"]);
+        assert!(!args.iter().any(|arg| arg.starts_with("-metadata")));
+    }
+}
@@ -0,0 +1,244 @@
+use crate::config::JobConfig;
+use crate::console::{error, info};
+use crate::{interrupt, progress, DeliveryError, Result};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+/// One line of a jobs file: the job definition, or why it didn't parse.
+#[derive(Debug)]
+pub struct Entry {
+    /// Line of the jobs file, from 1.
+    pub line: usize,
+    pub config: Result<JobConfig>,
+}
+
+/// How a job of a batch ended.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    /// The job ran to completion.
+    Done,
+    /// There was nothing to do, e.g. the output already exists.
+    Skipped,
+    Failed,
+    /// The batch was interrupted before the job started.
+    NotRun,
+}
+
+/// What happened to one job of a batch.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct JobReport {
+    pub line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<PathBuf>,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frames: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub seconds: f64,
+}
+
+/// What happened to every job of a batch, in the order of the jobs file.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub done: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub not_run: usize,
+    pub jobs: Vec<JobReport>,
+}
+
+impl Report {
+    fn new(mut jobs: Vec<JobReport>) -> Report {
+        jobs.sort_by_key(|job| job.line);
+        let count = |status| jobs.iter().filter(|job| job.status == status).count();
+        Report {
+            done: count(Status::Done),
+            skipped: count(Status::Skipped),
+            failed: count(Status::Failed),
+            not_run: count(Status::NotRun),
+            jobs,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut json = serde_json::to_string_pretty(self)
+            .map_err(|e| DeliveryError::Config(format!("Failed to serialize batch report: {}", e)))?;
+        json.push('\n');
+        fs::write(path, json)
+            .map_err(|e| DeliveryError::io(format!("Failed to write batch report {}", path.display()), e))
+    }
+}
+
+/// File the report of the batch in `jobs` is saved to: `<jobs>.report.json`.
+pub fn report_path(jobs: &Path) -> PathBuf {
+    let mut name = jobs.file_name().unwrap_or_default().to_os_string();
+    name.push(".report.json");
+    jobs.with_file_name(name)
+}
+
+/// Read the jobs file at `path`: one JSON job definition per line, with the
+/// keys of a TOML job config and relative paths resolved against the file's
+/// directory. Blank lines are skipped; a line that doesn't parse becomes an
+/// entry holding the error, so the rest of the batch can still run.
+pub fn load(path: &Path) -> Result<Vec<Entry>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| DeliveryError::io(format!("Failed to read jobs {}", path.display()), e))?;
+    let absolute = std::path::absolute(path)
+        .map_err(|e| DeliveryError::io(format!("Failed to resolve jobs {}", path.display()), e))?;
+    let base = absolute.parent().unwrap_or(Path::new(""));
+    let entries: Vec<Entry> = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let config = serde_json::from_str::<JobConfig>(line)
+                .map(|config| config.relative_to(base))
+                .map_err(|e| DeliveryError::Config(format!("Failed to parse job: {}", e)));
+            Entry { line: index + 1, config }
+        })
+        .collect();
+    if entries.is_empty() {
+        return Err(DeliveryError::Config(format!("{} contains no jobs", path.display())));
+    }
+    Ok(entries)
+}
+
+/// Run the `jobs` of a batch, `at_once` at a time, each with `run`, which
+/// returns the number of frames the job wrote or `None` if it had nothing to
+/// do. Each job is given as its line, its input (for the report) and the job
+/// itself, or the error building it. A failed job doesn't stop the others;
+/// once interrupted, the jobs not yet started are left alone.
+pub fn run_all<T, F>(jobs: Vec<(usize, Option<PathBuf>, Result<T>)>, at_once: usize, run: F) -> Report
+where
+    T: Send,
+    F: Fn(T) -> Result<Option<usize>> + Sync,
+{
+    let total = jobs.len();
+    let at_once = at_once.clamp(1, total.max(1));
+    let _span = tracing::info_span!("batch", jobs = total).entered();
+    info!("\n🗂 Running {} job(s), {} at a time...", total, at_once);
+    let started = Instant::now();
+    // Several jobs drawing bars at once would tear each other's apart
+    if at_once > 1 {
+        progress::set_bars(false);
+    }
+
+    let queue = Mutex::new(VecDeque::from(jobs));
+    let reports: Mutex<Vec<JobReport>> = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..at_once {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap().pop_front();
+                let Some((line, input, job)) = next else { break };
+                let report = |status, frames, error, seconds| JobReport { line, input, status, frames, error, seconds };
+                if interrupt::requested() {
+                    reports.lock().unwrap().push(report(Status::NotRun, None, None, 0.0));
+                    continue;
+                }
+                let _span = tracing::info_span!("batch_job", line).entered();
+                info!("\n🗂 Job on line {}", line);
+                let job_started = Instant::now();
+                let result = job.and_then(&run);
+                let seconds = job_started.elapsed().as_secs_f64();
+                reports.lock().unwrap().push(match result {
+                    Ok(Some(frames)) => report(Status::Done, Some(frames), None, seconds),
+                    Ok(None) => report(Status::Skipped, None, None, seconds),
+                    Err(e) => {
+                        error!("❌ The job on line {} failed: {}", line, e);
+                        report(Status::Failed, None, Some(e.to_string()), seconds)
+                    }
+                });
+            });
+        }
+    });
+    progress::set_bars(true);
+
+    let report = Report::new(reports.into_inner().unwrap());
+    info!(
+        "🗂 {} of {} job(s) done, {} skipped, {} failed in {:.2} seconds",
+        report.done,
+        total,
+        report.skipped,
+        report.failed,
+        started.elapsed().as_secs_f32()
+    );
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn mixed_batch_runs_every_job_and_reports_each() {
+        let jobs = vec![
+            (1, Some(PathBuf::from("a.mov")), Ok(10)),
+            (2, Some(PathBuf::from("b.mov")), Ok(0)),
+            (3, None, Err(DeliveryError::Config("unknown field `speed`".to_string()))),
+            (4, Some(PathBuf::from("d.mov")), Ok(-1)),
+            (5, Some(PathBuf::from("e.mov")), Ok(25)),
+        ];
+        let report = run_all(jobs, 2, |frames: i32| match frames {
+            0 => Ok(None),
+            n if n < 0 => Err(DeliveryError::SegmentFailed { id: 2, stderr: "boom".to_string() }),
+            n => Ok(Some(n as usize)),
+        });
+
+        assert_eq!((report.done, report.skipped, report.failed, report.not_run), (2, 1, 2, 0));
+        let summary: Vec<_> = report.jobs.iter().map(|job| (job.line, job.status, job.frames)).collect();
+        assert_eq!(
+            summary,
+            [
+                (1, Status::Done, Some(10)),
+                (2, Status::Skipped, None),
+                (3, Status::Failed, None),
+                (4, Status::Failed, None),
+                (5, Status::Done, Some(25))
+            ]
+        );
+        assert_eq!(report.jobs[2].error.as_deref(), Some("unknown field `speed`"));
+        assert_eq!(report.jobs[3].error.as_deref(), Some("Segment 2 failed:\nboom"));
+        assert_eq!(report.jobs[4].input, Some(PathBuf::from("e.mov")));
+    }
+
+    #[test]
+    fn load_reads_one_job_per_line() {
+        let dir = env::temp_dir().join(format!("delivery_encoder_batch_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("jobs.ndjson");
+        let lines = [
+            r#"{"input": "ep101.mov", "output_dir": "/renders/ep101", "codec": "prores"}"#,
+            "",
+            r#"{"input": "ep102.mov", "speed": 2}"#,
+            r#"{"input": "ep103.mov""#,
+        ];
+        fs::write(&path, lines.join("\n")).unwrap();
+        let entries = load(&path);
+        fs::write(&path, "\n\n").unwrap();
+        let empty = load(&path);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let entries = entries.unwrap();
+        assert_eq!(entries.iter().map(|e| e.line).collect::<Vec<_>>(), [1, 3, 4]);
+        let first = entries[0].config.as_ref().unwrap();
+        assert_eq!(first.input, Some(std::path::absolute(&dir).unwrap().join("ep101.mov")));
+        assert_eq!(first.output_dir, Some(PathBuf::from("/renders/ep101")));
+        assert_eq!(first.codec.as_deref(), Some("prores"));
+        assert!(matches!(&entries[1].config, Err(DeliveryError::Config(msg)) if msg.contains("unknown field `speed`")));
+        assert!(entries[2].config.is_err());
+        assert!(empty.is_err());
+    }
+
+    #[test]
+    fn report_lands_next_to_the_jobs() {
+        assert_eq!(report_path(Path::new("queue/jobs.ndjson")), PathBuf::from("queue/jobs.ndjson.report.json"));
+    }
+}
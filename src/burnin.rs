@@ -0,0 +1,232 @@
+use crate::overlay::Position;
+use crate::smpte::EditRate;
+use crate::{ffmpeg, timecode};
+use serde::{Deserialize, Serialize};
+
+/// Default height of burned-in text in pixels.
+pub const DEFAULT_SIZE: u32 = 36;
+
+/// Default distance in pixels between burned-in text and the edges.
+pub const DEFAULT_MARGIN: u32 = 32;
+
+/// Values substituted into a burn-in template.
+#[derive(Debug, Clone, Default)]
+pub struct TextVars {
+    /// `{filename}`: file name of the source.
+    pub filename: String,
+    /// `{date}`: the day the job was planned, `YYYY-MM-DD`.
+    pub date: String,
+    /// `{jobid}`: the job id.
+    pub jobid: String,
+    /// `{recipient}`: who the copy is for, if anyone.
+    pub recipient: Option<String>,
+}
+
+/// Substitute `vars` into `template`, e.g. `{filename} - {recipient}`.
+/// `{{` and `}}` stand for literal braces.
+pub fn resolve(template: &str, vars: &TextVars) -> Result<String, String> {
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find(['{', '}']) {
+        text.push_str(&rest[..open]);
+        if rest[open..].starts_with("{{") || rest[open..].starts_with("}}") {
+            text.push_str(&rest[open..open + 1]);
+            rest = &rest[open + 2..];
+            continue;
+        }
+        if rest[open..].starts_with('}') {
+            return Err(format!("unmatched '}}' in burn-in text '{}'", template));
+        }
+        let close = rest[open..]
+            .find('}')
+            .map(|i| open + i)
+            .ok_or_else(|| format!("unclosed '{{' in burn-in text '{}'", template))?;
+        match &rest[open + 1..close] {
+            "filename" => text.push_str(&vars.filename),
+            "date" => text.push_str(&vars.date),
+            "jobid" => text.push_str(&vars.jobid),
+            "recipient" => match &vars.recipient {
+                Some(recipient) => text.push_str(recipient),
+                None => return Err(format!("burn-in text '{}' uses {{recipient}}; set --recipient", template)),
+            },
+            name => return Err(format!("unknown variable '{{{}}}' in burn-in text '{}'", name, template)),
+        }
+        rest = &rest[close + 1..];
+    }
+    text.push_str(rest);
+    Ok(text)
+}
+
+/// A line of text burned into every frame with drawtext, e.g. to label a
+/// review copy.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TextBurnIn {
+    /// Template of the text; see [`resolve`].
+    pub text: String,
+    /// Corner, edge or centre the text is placed at.
+    pub position: Position,
+    /// Distance in pixels from the edges the text is placed at.
+    pub margin: u32,
+    /// Font file (`.ttf`, `.otf`, ...) or fontconfig family name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font: Option<String>,
+    /// Text height in pixels.
+    pub size: u32,
+    /// Text colour, e.g. `white` or `yellow@0.8`.
+    pub color: String,
+    /// Colour of a box drawn behind the text, if any, e.g. `black@0.5`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub box_color: Option<String>,
+}
+
+impl TextBurnIn {
+    /// `text` in white at the bottom of the frame, with the default size and
+    /// margin.
+    pub fn new(text: impl Into<String>) -> TextBurnIn {
+        TextBurnIn {
+            text: text.into(),
+            position: Position::Bottom,
+            margin: DEFAULT_MARGIN,
+            font: None,
+            size: DEFAULT_SIZE,
+            color: "white".to_string(),
+            box_color: None,
+        }
+    }
+
+    /// Filters appended to a graph with one unlabeled output to burn `text`
+    /// (the resolved template) in.
+    pub fn filter_suffix(&self, text: &str) -> String {
+        let mut options = vec![format!("text={}", ffmpeg::escape_filter_value(text)), "expansion=none".to_string()];
+        options.extend(style_options(&self.font, self.size, &self.color, self.box_color.as_deref()));
+        options.extend(place_options(self.position, self.margin));
+        format!(",drawtext={}", options.join(":"))
+    }
+}
+
+/// Whether the burn-in `font` is a font file, as it looks like a path,
+/// rather than a fontconfig family.
+pub fn is_font_file(font: &str) -> bool {
+    font.contains(['/', '\\']) || font.rsplit_once('.').is_some_and(|(_, ext)| ext.len() <= 4)
+}
+
+// drawtext's font, size, colour and box options. The font is a `fontfile`
+// if it looks like a path, else a fontconfig family.
+fn style_options(font: &Option<String>, size: u32, color: &str, box_color: Option<&str>) -> Vec<String> {
+    let mut options = Vec::new();
+    if let Some(font) = font {
+        let name = if is_font_file(font) { "fontfile" } else { "font" };
+        options.push(format!("{}={}", name, ffmpeg::escape_filter_value(font)));
+    }
+    options.push(format!("fontsize={}", size));
+    options.push(format!("fontcolor={}", ffmpeg::escape_filter_value(color)));
+    if let Some(color) = box_color {
+        options.push(format!("box=1:boxcolor={}:boxborderw={}", ffmpeg::escape_filter_value(color), size / 4));
+    }
+    options
+}
+
+// drawtext's `x` and `y` placing the text at `position`.
+fn place_options(position: Position, margin: u32) -> [String; 2] {
+    [format!("x={}", position.x_within(margin, "w-tw")), format!("y={}", position.y_within(margin, "h-th"))]
+}
+
+/// Colour of the box behind the timecode and frame number burn-ins.
+const BURN_IN_BOX: &str = "black@0.5";
+
+/// The running timecode burned into every frame, white on a dark box.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TimecodeBurnIn {
+    /// Corner, edge or centre the timecode is placed at.
+    pub position: Position,
+    /// Distance in pixels from the edges the timecode is placed at.
+    pub margin: u32,
+    /// Font file or fontconfig family name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font: Option<String>,
+    /// Text height in pixels.
+    pub size: u32,
+    /// Timecode of the source's first frame and the rate it runs at, once
+    /// probed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_rate: Option<f64>,
+}
+
+impl Default for TimecodeBurnIn {
+    fn default() -> TimecodeBurnIn {
+        TimecodeBurnIn {
+            position: Position::Top,
+            margin: DEFAULT_MARGIN,
+            font: None,
+            size: DEFAULT_SIZE,
+            start: None,
+            frame_rate: None,
+        }
+    }
+}
+
+impl TimecodeBurnIn {
+    /// Filters appended to a graph with one unlabeled output to burn in the
+    /// timecode, for a graph whose first frame is frame `first_frame` of the
+    /// source. drawtext counts the frames it draws on, so each segment
+    /// starts it at the timecode of its own first frame.
+    pub fn filter_suffix(&self, first_frame: u64) -> String {
+        let (Some(start), Some(fps)) = (&self.start, self.frame_rate) else {
+            return String::new();
+        };
+        let rate = EditRate::from_fps(fps);
+        let mut options = vec![
+            format!("timecode={}", ffmpeg::escape_filter_value(&timecode::add_frames(start, first_frame, fps))),
+            format!("rate={}/{}", rate.numerator, rate.denominator),
+        ];
+        options.extend(style_options(&self.font, self.size, "white", Some(BURN_IN_BOX)));
+        options.extend(place_options(self.position, self.margin));
+        format!(",drawtext={}", options.join(":"))
+    }
+}
+
+/// The number of the output frame burned into every frame, as the frame
+/// files are numbered (start frame and padding included), white on a dark
+/// box, so notes such as "fix frame 10342" point straight at a file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FrameNumberBurnIn {
+    /// Corner, edge or centre the number is placed at.
+    pub position: Position,
+    /// Distance in pixels from the edges the number is placed at.
+    pub margin: u32,
+    /// Font file or fontconfig family name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font: Option<String>,
+    /// Text height in pixels.
+    pub size: u32,
+    /// Rate of the frames, once probed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_rate: Option<f64>,
+}
+
+impl Default for FrameNumberBurnIn {
+    fn default() -> FrameNumberBurnIn {
+        FrameNumberBurnIn {
+            position: Position::TopRight,
+            margin: DEFAULT_MARGIN,
+            font: None,
+            size: DEFAULT_SIZE,
+            frame_rate: None,
+        }
+    }
+}
+
+impl FrameNumberBurnIn {
+    /// Filters appended to a graph with one unlabeled output to burn in the
+    /// frame number, counting from `first_number` for the graph's first
+    /// frame and zero-padded to `padding` digits.
+    pub fn filter_suffix(&self, first_number: u64, padding: usize) -> String {
+        let text = format!("%{{eif:n+{}:d:{}}}", first_number, padding);
+        let mut options = vec![format!("text={}", ffmpeg::escape_filter_value(&text))];
+        options.extend(style_options(&self.font, self.size, "white", Some(BURN_IN_BOX)));
+        options.extend(place_options(self.position, self.margin));
+        format!(",drawtext={}", options.join(":"))
+    }
+}
@@ -0,0 +1,49 @@
+use crate::{DeliveryError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the state file kept in the segments directory.
+pub const STATE_FILE: &str = "state.json";
+
+/// Which segments of a run have finished, so an interrupted run can be resumed
+/// without re-encoding them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Checkpoint {
+    pub completed: BTreeSet<usize>,
+}
+
+fn state_path(segments_dir: &Path) -> PathBuf {
+    segments_dir.join(STATE_FILE)
+}
+
+impl Checkpoint {
+    /// The state saved in `segments_dir`, or an empty one if there is none.
+    pub fn load(segments_dir: &Path) -> Result<Checkpoint> {
+        let path = state_path(segments_dir);
+        if !path.exists() {
+            return Ok(Checkpoint::default());
+        }
+        let text = fs::read_to_string(&path)
+            .map_err(|e| DeliveryError::io(format!("Failed to read {}", path.display()), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| DeliveryError::Config(format!("Failed to parse {}: {}", path.display(), e)))
+    }
+
+    /// Write the state to `segments_dir`. The file is replaced atomically so a
+    /// crash never leaves it half written.
+    pub fn save(&self, segments_dir: &Path) -> Result<()> {
+        let path = state_path(segments_dir);
+        let tmp = path.with_extension("json.tmp");
+        let json = serde_json::to_string(self)
+            .map_err(|e| DeliveryError::Config(format!("Failed to serialize checkpoint: {}", e)))?;
+        fs::write(&tmp, json)
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| DeliveryError::io(format!("Failed to write {}", path.display()), e))
+    }
+
+    pub fn is_done(&self, id: usize) -> bool {
+        self.completed.contains(&id)
+    }
+}
@@ -0,0 +1,76 @@
+use crate::console::{info, warning};
+use crate::{DeliveryError, Result, SEGMENTS_DIR};
+use sha2::{Digest, Sha256};
+use std::fs;
+use crate::segment::Segment;
+use std::path::{Path, PathBuf};
+
+/// A RAM-backed directory for intermediates: `/dev/shm` on Linux. Other
+/// systems have no tmpfs mounted by default; create a RAM disk there and pass
+/// it as the temp directory instead.
+pub fn ram_disk() -> Result<PathBuf> {
+    let shm = Path::new("/dev/shm");
+    if cfg!(target_os = "linux") && shm.is_dir() {
+        Ok(shm.to_path_buf())
+    } else {
+        Err(DeliveryError::Config(
+            "No RAM-backed temp directory on this system; mount a RAM disk and pass it with --temp-dir".to_string(),
+        ))
+    }
+}
+
+/// Name of the segments directory of `job`, its id or its input's absolute
+/// path, in a temp directory every job on the machine shares:
+/// `tmp_segments_<hash>`, the same for every run of the job.
+pub fn shared_segments_dir_name(job: &str) -> String {
+    let hash: String = Sha256::digest(job.as_bytes()).iter().take(6).map(|b| format!("{:02x}", b)).collect();
+    format!("{}_{}", SEGMENTS_DIR, hash)
+}
+
+/// Create an empty temporary segments directory, wiping leftovers from a previous run.
+pub fn prepare_segments_dir(segments_dir: &Path) -> Result<()> {
+    let _span = tracing::info_span!("prepare", segments_dir = %segments_dir.display()).entered();
+    info!("\n📂 Creating temporary segments directory: {}", segments_dir.display());
+    if segments_dir.exists() {
+        warning!("⚠️ Temporary directory exists, cleaning...");
+        fs::remove_dir_all(segments_dir)
+            .map_err(|e| DeliveryError::io("Failed to clean existing segments directory", e))?;
+    }
+    fs::create_dir_all(segments_dir)
+        .map_err(|e| DeliveryError::io("Failed to create segments directory", e))?;
+    info!("✅ Created temporary segments directory");
+    Ok(())
+}
+
+/// Keep a previous run's segments directory for resuming, removing only the
+/// partial output of the segments that still have to be encoded.
+pub fn prepare_resume(segments_dir: &Path, pending: &[Segment]) -> Result<()> {
+    let _span = tracing::info_span!("prepare", segments_dir = %segments_dir.display()).entered();
+    fs::create_dir_all(segments_dir)
+        .map_err(|e| DeliveryError::io("Failed to create segments directory", e))?;
+    for segment in pending {
+        remove_dir(&segment.dir(segments_dir))?;
+    }
+    Ok(())
+}
+
+/// Remove the temporary segments directory. Failure is reported but not fatal.
+pub fn remove_segments_dir(segments_dir: &Path) {
+    let _span = tracing::info_span!("cleanup", segments_dir = %segments_dir.display()).entered();
+    info!("\n🧹 Cleaning up temporary files...");
+    if let Err(e) = fs::remove_dir_all(segments_dir) {
+        warning!("⚠️ Failed to clean temporary directory: {}", e);
+    } else {
+        info!("✅ Temporary files cleaned");
+    }
+}
+
+/// Remove `dir` and everything in it. Returns `false` if it did not exist.
+pub fn remove_dir(dir: &Path) -> Result<bool> {
+    if !dir.exists() {
+        return Ok(false);
+    }
+    fs::remove_dir_all(dir)
+        .map_err(|e| DeliveryError::io(format!("Failed to remove {}", dir.display()), e))?;
+    Ok(true)
+}
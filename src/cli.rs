@@ -0,0 +1,942 @@
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use delivery_encoder::audio::{AudioCodec, TrackLayout, TrackMap};
+use delivery_encoder::color::{self, ColorRange, ToneOperator, ToneTarget};
+use delivery_encoder::guides::Guide;
+use delivery_encoder::hook::HookMode;
+use delivery_encoder::icc::IccProfile;
+use delivery_encoder::metrics::QualityMetric;
+use delivery_encoder::openfiles::MaxOpenFiles;
+use delivery_encoder::overlay::{self, ChromaKey, OverlayScale, Position, Window};
+use delivery_encoder::segment::SplitOn;
+use delivery_encoder::shots::ShotListFormat;
+use delivery_encoder::stems::StemFormat;
+use delivery_encoder::{
+    crfsearch, loudness, qc, units, Container, DeliveryPreset, FrameFormat, HwAccel, IntermediateCodec, OnExisting,
+    OutputFormat, Package, Rendition, Timecode, TwoPass, VfrMode, VideoCodec,
+};
+use std::path::PathBuf;
+use std::time::Duration;
+
+const EXIT_CODES: &str = "Exit codes:
+  0  success
+  2  invalid configuration or arguments
+  3  input file missing
+  4  ffmpeg/ffprobe not found
+  5  probing the input failed
+  6  a segment's ffmpeg process (or splitting the source, searching the CRF, joining or packaging the video) failed
+  7  filesystem or process I/O error
+  8  downloading or verifying FFmpeg failed
+  9  ffmpeg lacks a filter or encoder the job needs
+  10  not enough free disk space for the estimated output
+  11  the segments produced a different number of frames than expected
+  12  a QC check found problems in the delivery (--qc-fail) or couldn't run
+  13  the input doesn't decode cleanly (--verify-source)
+  130  interrupted with Ctrl+C or SIGTERM";
+
+/// Composite an overlay onto a video and export the result as an image sequence.
+///
+/// Without a subcommand the full pipeline runs, as with `encode`.
+#[derive(Parser, Debug)]
+#[command(version, about, after_help = EXIT_CODES, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub encode: EncodeArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the full pipeline: probe, encode segments, combine and clean up
+    Encode(Box<EncodeArgs>),
+    /// Print information about the input video
+    Probe(ProbeArgs),
+    /// Merge the segments left by a previous run into the output directory
+    Combine(CombineArgs),
+    /// Remove the temporary segments and, optionally, the output directory
+    Clean(CleanArgs),
+    /// Continue a failed run, encoding only the segments it did not complete
+    Resume(Box<ResumeArgs>),
+    /// Download a static FFmpeg build, checked against its SHA-256, into assets/bin/<os>/
+    FetchFfmpeg(FetchArgs),
+}
+
+/// Console and log options shared by every subcommand.
+#[derive(clap::Args, Debug)]
+pub struct OutputArgs {
+    /// Only print errors and the final summary
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print ffmpeg command lines and output (-v), plus raw progress reports (-vv)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Write a complete JSON-lines log, including all ffmpeg output, to this file
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Rotate the log file when it exceeds this size (e.g. 512K, 10M)
+    #[arg(long, global = true, default_value = "10M", value_parser = units::parse_size)]
+    pub log_max_size: u64,
+
+    /// Number of rotated log files to keep
+    #[arg(long, global = true, default_value_t = 5)]
+    pub log_keep: usize,
+
+    /// ASCII-only output with [INFO]/[ERROR] tags and no progress bars
+    /// (implied by NO_COLOR or a non-UTF-8 locale)
+    #[arg(long, global = true)]
+    pub plain: bool,
+
+    /// Progress output: human-readable text or JSON lines on stdout
+    #[arg(long, global = true, value_enum, default_value_t = ProgressFormat::Human)]
+    pub progress_format: ProgressFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressFormat {
+    Human,
+    Json,
+}
+
+/// Overrides for the ffmpeg and ffprobe executables.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ToolArgs {
+    /// ffmpeg executable to use instead of the bundled build or the one on PATH
+    #[arg(long)]
+    pub ffmpeg_path: Option<PathBuf>,
+
+    /// ffprobe executable to use instead of the bundled build or the one on PATH
+    #[arg(long)]
+    pub ffprobe_path: Option<PathBuf>,
+}
+
+/// How segments are supervised while they encode.
+#[derive(clap::Args, Debug, Clone)]
+pub struct RunArgs {
+    /// Number of segments encoded in parallel (default: available CPU threads)
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub threads: Option<u64>,
+
+    /// Threads each ffmpeg process may use (default: CPU threads / --threads)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub ffmpeg_threads: Option<u64>,
+
+    /// Pin each worker's ffmpeg to its own set of --ffmpeg-threads CPUs
+    /// (Linux and Windows)
+    #[arg(long)]
+    pub pin_cpus: bool,
+
+    /// Run ffmpeg at low priority (nice 10 / below normal) so the machine
+    /// stays usable while encoding
+    #[arg(long)]
+    pub background: bool,
+
+    /// Decode the source and encode video output in hardware: auto,
+    /// videotoolbox, nvenc, qsv or none (default: none)
+    #[arg(long, value_name = "MODE")]
+    pub hwaccel: Option<HwAccel>,
+
+    /// NVIDIA GPUs to spread the workers across with --hwaccel nvenc
+    /// (e.g. 0,1; default: ffmpeg's choice, the first GPU)
+    #[arg(long, value_name = "IDS", value_delimiter = ',')]
+    pub gpus: Vec<usize>,
+
+    /// Memory each segment's ffmpeg needs, used to limit how many run at once
+    /// (e.g. 4G; default: estimated from the resolution)
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_size)]
+    pub max_mem_per_worker: Option<u64>,
+
+    /// Raise the open file limit to this many files and plan parallel
+    /// segments and packaging within it, or `keep` to never raise it
+    /// (default: raised only when it would limit --threads)
+    #[arg(long, value_name = "N|keep")]
+    pub max_open_files: Option<MaxOpenFiles>,
+
+    /// Kill a segment's ffmpeg when it reports no progress or output for this
+    /// long (e.g. 120s, 5m)
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub stall_timeout: Option<Duration>,
+
+    /// Kill a segment's ffmpeg when it runs longer than this in total
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub segment_timeout: Option<Duration>,
+
+    /// Re-encode a failed or timed out segment up to this many times
+    #[arg(long, default_value_t = 0)]
+    pub retries: usize,
+
+    /// Keep completed segments when interrupted so the run can be resumed
+    #[arg(long)]
+    pub keep_on_interrupt: bool,
+
+    /// Keep the temporary segments after a successful run
+    #[arg(long)]
+    pub keep_temp: bool,
+
+    /// Skip the free disk space check before encoding
+    #[arg(long)]
+    pub no_space_check: bool,
+
+    /// Only warn when the segments produce a different number of frames than
+    /// expected
+    #[arg(long)]
+    pub allow_frame_mismatch: bool,
+
+    /// Before encoding, decode the whole source (segments in parallel) and
+    /// stop if it has decode errors, e.g. a damaged ProRes file
+    #[arg(long)]
+    pub verify_source: bool,
+
+    /// Before combining, report joins between segments with a duplicated
+    /// boundary frame, a gap or an overlap
+    #[arg(long)]
+    pub check_boundaries: bool,
+
+    /// After delivering, report audio that stays silent for this long or
+    /// longer (e.g. 2s), in the output video or, for frames, the source
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub qc_silence: Option<Duration>,
+
+    /// Level below which --qc-silence counts audio as silent (default: -60dB)
+    #[arg(long, value_name = "DB", value_parser = qc::parse_level, allow_hyphen_values = true, requires = "qc_silence")]
+    pub qc_silence_threshold: Option<f64>,
+
+    /// After delivering, report black video lasting this long or longer
+    /// (e.g. 0.5s), in the output video or, for frames, the source
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub qc_black: Option<Duration>,
+
+    /// Black expected at the start, e.g. before the program (default: 0s)
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration, requires = "qc_black")]
+    pub qc_black_head: Option<Duration>,
+
+    /// Black expected at the end, e.g. after the credits (default: 0s)
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration, requires = "qc_black")]
+    pub qc_black_tail: Option<Duration>,
+
+    /// After delivering, report video frozen on one frame for this long or
+    /// longer (e.g. 2s), in the output video or, for frames, the source
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub qc_freeze: Option<Duration>,
+
+    /// Score video output against the source with vmaf, psnr and/or ssim,
+    /// segment by segment in parallel, into <video>.quality.json (the
+    /// overlay lowers the scores where it covers the picture)
+    #[arg(long, value_name = "METRICS", value_delimiter = ',', num_args = 1..)]
+    pub quality_metrics: Vec<QualityMetric>,
+
+    /// Fail the job (exit code 12) when a QC check finds anything; the
+    /// delivery is kept for inspection
+    #[arg(long)]
+    pub qc_fail: bool,
+}
+
+/// Location of the temporary segments.
+#[derive(clap::Args, Debug, Clone)]
+pub struct TempArgs {
+    /// Directory to create the segments in, e.g. a fast scratch disk shared
+    /// by several jobs, each of which gets its own tmp_segments_<hash>
+    /// (default: tmp_segments in the project root)
+    #[arg(long, value_name = "DIR")]
+    pub temp_dir: Option<PathBuf>,
+
+    /// Where to create tmp_segments without --temp-dir: next to the project,
+    /// in the system temp directory, or on a RAM disk (/dev/shm), where each
+    /// job gets its own tmp_segments_<hash> (default: project)
+    #[arg(long, value_enum, conflicts_with = "temp_dir")]
+    pub temp: Option<TempLocation>,
+}
+
+/// The job whose segments to pick up from --temp-dir, the system temp
+/// directory or the RAM disk, which hold one directory per job.
+#[derive(clap::Args, Debug)]
+pub struct TempJobArgs {
+    /// Job id of the run, with --temp-dir or --temp system or in-memory
+    #[arg(long)]
+    pub job_id: Option<String>,
+
+    /// Source video of a run without a job id, with --temp-dir or --temp
+    /// system or in-memory (default: assets/video.mov in the project root)
+    #[arg(long, conflicts_with = "job_id")]
+    pub input: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TempLocation {
+    Project,
+    System,
+    InMemory,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct EncodeArgs {
+    /// Source video (default: assets/video.mov in the project root)
+    #[arg(short, long)]
+    pub input: Option<PathBuf>,
+
+    /// Overlay composited on top of every frame: an image, a video with alpha
+    /// (ProRes 4444, VP9) or an image sequence such as logo_%04d.png
+    /// (default: assets/overlay.png)
+    #[arg(long)]
+    pub overlay: Option<PathBuf>,
+
+    /// Loop an animated overlay over the whole program instead of playing it
+    /// once and holding its last frame
+    #[arg(long)]
+    pub overlay_loop: bool,
+
+    /// Directory the frames are written to (default: output)
+    #[arg(short, long)]
+    pub output_dir: Option<PathBuf>,
+
+    /// Number of segments the input is split into; threads pick up the next
+    /// segment as they finish (default: 4 per thread, at least 2s each)
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub segments: Option<u64>,
+
+    /// Size segments by estimated encode cost (from the input's packet sizes)
+    /// instead of equal duration, so busy scenes get shorter segments
+    #[arg(long)]
+    pub adaptive_segments: bool,
+
+    /// Place the segment boundaries evenly over time, or at the scene cuts
+    /// ffmpeg detects nearest to them (scenes), so segments hold whole shots
+    /// (default: time)
+    #[arg(long, value_name = "MODE", conflicts_with = "adaptive_segments")]
+    pub split_on: Option<SplitOn>,
+
+    /// First copy the source into one file per segment with the segment muxer
+    /// (cut at keyframes, no re-encoding), then encode each file whole instead
+    /// of seeking in the input
+    #[arg(long)]
+    pub presplit: bool,
+
+    /// Handling of variable frame rate inputs: warn, or cfr:<fps> to convert
+    /// to a constant rate (e.g. cfr:25 or cfr:30000/1001) (default: warn)
+    #[arg(long, value_name = "MODE")]
+    pub vfr_mode: Option<VfrMode>,
+
+    /// Filter graph passed to -filter_complex (default: [0:v][1:v]overlay)
+    #[arg(long)]
+    pub filter: Option<String>,
+
+    /// 3D LUT (.cube) applied to the video before anything is composited over
+    /// it, e.g. to deliver a log master in its display transform
+    #[arg(long, value_name = "FILE")]
+    pub lut: Option<PathBuf>,
+
+    /// Tone map an HDR (PQ or HLG) master to sdr (BT.709) before anything is
+    /// composited over it, and tag the output to match
+    #[arg(long, value_name = "TARGET")]
+    pub tonemap: Option<ToneTarget>,
+
+    /// How highlights are rolled off: hable (zscale and tonemap) or bt2390
+    /// (libplacebo, which needs Vulkan) (default: hable)
+    #[arg(long, value_name = "OPERATOR")]
+    pub tonemap_operator: Option<ToneOperator>,
+
+    /// Convert the video to these primaries (bt709, bt2020, smpte432 or
+    /// display-p3, ...) with zscale and tag the output with them; with
+    /// --lut, only tag it
+    #[arg(long, value_name = "PRIMARIES", value_parser = color::parse_primaries)]
+    pub color_primaries: Option<String>,
+
+    /// Convert the video to this transfer (bt709, iec61966-2-1 or srgb,
+    /// smpte2084 or pq, arib-std-b67 or hlg, ...) and tag the output with it
+    #[arg(long, value_name = "TRANSFER", value_parser = color::parse_transfer)]
+    pub color_trc: Option<String>,
+
+    /// Convert the video to this YUV matrix (bt709, bt470bg, bt2020nc, ...)
+    /// and tag the output with it
+    #[arg(long, value_name = "MATRIX", value_parser = color::parse_matrix)]
+    pub colorspace: Option<String>,
+
+    /// Convert the video to full or limited range and tag the output with it
+    #[arg(long, value_name = "RANGE")]
+    pub color_range: Option<ColorRange>,
+
+    /// Find the letterbox or pillarbox bars baked into the video by sampling
+    /// it with cropdetect, and crop them off every segment alike
+    #[arg(long)]
+    pub autocrop: bool,
+
+    /// Corner, edge or centre the overlay is placed at: top-left, top,
+    /// top-right, left, center, right, bottom-left, bottom or bottom-right
+    /// (default: top-left)
+    #[arg(long, value_name = "POSITION", conflicts_with = "filter")]
+    pub overlay_position: Option<Position>,
+
+    /// Distance in pixels between the overlay and the edges it is placed at
+    /// (default: 0)
+    #[arg(long, value_name = "PIXELS", conflicts_with = "filter")]
+    pub overlay_margin: Option<u32>,
+
+    /// overlay x expression, e.g. W-w-48 or 100, replacing the position's
+    #[arg(long, value_name = "EXPR", conflicts_with = "filter")]
+    pub overlay_x: Option<String>,
+
+    /// overlay y expression, e.g. H-h-48 or 100, replacing the position's
+    #[arg(long, value_name = "EXPR", conflicts_with = "filter")]
+    pub overlay_y: Option<String>,
+
+    /// Scale the overlay to a share of the video's width (e.g. 0.15w) or
+    /// height (e.g. 0.1h) before compositing it, keeping its aspect ratio
+    #[arg(long, value_name = "SHARE", conflicts_with = "filter")]
+    pub overlay_scale: Option<OverlayScale>,
+
+    /// Opacity of the overlay from 0 (invisible) to 1 (as it is), e.g. 0.4
+    /// for a subtle watermark on review copies (default: 1)
+    #[arg(long, value_name = "OPACITY", value_parser = overlay::parse_opacity, conflicts_with = "filter")]
+    pub overlay_opacity: Option<f64>,
+
+    /// Key out the green (or blue) screen of an overlay delivered without
+    /// alpha: COLOR[:SIMILARITY[:BLEND]], e.g. 0x00B140 or 0x00B140:0.2:0.1
+    /// (default similarity 0.15, blend 0.05)
+    #[arg(long, value_name = "KEY", conflicts_with = "filter")]
+    pub overlay_key: Option<ChromaKey>,
+
+    /// Only show the overlay from START to END of the program, e.g.
+    /// 00:00:05-00:00:15 (HH:MM:SS, MM:SS or seconds)
+    #[arg(long, value_name = "START-END", conflicts_with = "filter")]
+    pub overlay_window: Option<Window>,
+
+    /// JSON or CSV schedule of overlays shown in their own stretches of the
+    /// program (start,end,overlay per line), placed like --overlay
+    #[arg(long, value_name = "FILE", conflicts_with = "filter")]
+    pub overlay_schedule: Option<PathBuf>,
+
+    /// Text burned into every frame, e.g. "{filename} / {recipient} / {date}"
+    /// (variables: {filename}, {date}, {jobid}, {recipient})
+    #[arg(long, value_name = "TEXT")]
+    pub burn_in: Option<String>,
+
+    /// Where the burn-in goes, as with --overlay-position (default: bottom)
+    #[arg(long, value_name = "POSITION")]
+    pub burn_in_position: Option<Position>,
+
+    /// Distance in pixels between the burn-in and the edges (default: 32)
+    #[arg(long, value_name = "PIXELS")]
+    pub burn_in_margin: Option<u32>,
+
+    /// Font of the burn-in: a font file or a fontconfig family name
+    #[arg(long, value_name = "FONT")]
+    pub burn_in_font: Option<String>,
+
+    /// Text height of the burn-in in pixels (default: 36)
+    #[arg(long, value_name = "PIXELS")]
+    pub burn_in_size: Option<u32>,
+
+    /// Text colour of the burn-in, e.g. yellow or white@0.8 (default: white)
+    #[arg(long, value_name = "COLOR")]
+    pub burn_in_color: Option<String>,
+
+    /// Draw a box behind the burn-in, in COLOR (default: black@0.5)
+    #[arg(long, value_name = "COLOR", num_args = 0..=1, default_missing_value = "black@0.5")]
+    pub burn_in_box: Option<String>,
+
+    /// Who the copy is for: {recipient} in the burn-in text
+    #[arg(long, value_name = "NAME")]
+    pub recipient: Option<String>,
+
+    /// Burn in the running timecode, from the source's start timecode (or
+    /// --timecode); takes --burn-in-font, --burn-in-size and --burn-in-margin
+    #[arg(long)]
+    pub burn_timecode: bool,
+
+    /// Where the timecode goes, as with --overlay-position (default: top)
+    #[arg(long, value_name = "POSITION")]
+    pub burn_timecode_position: Option<Position>,
+
+    /// Burn in the output frame number, as the frames are numbered (with
+    /// --start-frame); takes --burn-in-font, --burn-in-size and --burn-in-margin
+    #[arg(long)]
+    pub burn_frame_number: bool,
+
+    /// Where the frame number goes, as with --overlay-position (default:
+    /// top-right)
+    #[arg(long, value_name = "POSITION")]
+    pub burn_frame_number_position: Option<Position>,
+
+    /// Draw broadcast guides over the picture for review: action-safe,
+    /// title-safe and/or center-cross, fitted to the frame's aspect ratio
+    #[arg(long, value_name = "GUIDES", value_delimiter = ',', num_args = 1..)]
+    pub guides: Vec<Guide>,
+
+    /// Put a slate before video output, listing the title, episode, version,
+    /// date, running time and audio layout; the audio follows it
+    #[arg(long)]
+    pub slate: bool,
+
+    /// Title on the slate (default: the job id, or the input's name)
+    #[arg(long, value_name = "TEXT")]
+    pub slate_title: Option<String>,
+
+    /// Episode on the slate, e.g. 101
+    #[arg(long, value_name = "TEXT")]
+    pub slate_episode: Option<String>,
+
+    /// Version on the slate, e.g. v3 or "Final Mix"
+    #[arg(long, value_name = "TEXT")]
+    pub slate_version: Option<String>,
+
+    /// Date on the slate (default: today, YYYY-MM-DD)
+    #[arg(long, value_name = "TEXT")]
+    pub slate_date: Option<String>,
+
+    /// How long the slate is shown, e.g. 10s or 5 (default: 10s)
+    #[arg(long, value_name = "DURATION", value_parser = units::parse_duration)]
+    pub slate_duration: Option<Duration>,
+
+    /// Follow the slate with a 2-pop: a frame of 1 kHz tone, with a "2",
+    /// two seconds before the program
+    #[arg(long)]
+    pub two_pop: bool,
+
+    /// Screener mode: make one copy per recipient listed in FILE (one per
+    /// line, e.g. "Jane Doe <jane@example.com>"), each with their name burned
+    /// in and written to a subdirectory of the output named after them
+    #[arg(long, value_name = "FILE", conflicts_with = "plan_out")]
+    pub screeners: Option<PathBuf>,
+
+    /// Where the recipient's name goes, as with --overlay-position (default:
+    /// center)
+    #[arg(long, value_name = "POSITION")]
+    pub screener_position: Option<Position>,
+
+    /// Opacity of the recipient's name from 0 to 1 (default: 0.3)
+    #[arg(long, value_name = "OPACITY", value_parser = overlay::parse_opacity)]
+    pub screener_opacity: Option<f64>,
+
+    /// Text height of the recipient's name in pixels (default: 64); the font
+    /// is --burn-in-font
+    #[arg(long, value_name = "PIXELS")]
+    pub screener_size: Option<u32>,
+
+    /// Screeners encoded at once, sharing --threads between them (default: 2)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub screener_jobs: Option<u64>,
+
+    /// Pass the composited frames of every segment through COMMAND, run by
+    /// the shell, before encoding, e.g. a forensic watermark vendor's tool;
+    /// the frames' size, pixel format and rate, the segment and the recipient
+    /// are in DELIVERY_* environment variables
+    #[arg(long, value_name = "COMMAND")]
+    pub watermark_hook: Option<String>,
+
+    /// How the hook gets the frames: pipe (raw frames at the source's size on
+    /// stdin, handed back on stdout) or files (PNG frames in
+    /// $DELIVERY_INPUT_DIR, written to $DELIVERY_OUTPUT_DIR) (default: pipe)
+    #[arg(long, value_name = "MODE")]
+    pub watermark_hook_mode: Option<HookMode>,
+
+    /// Image format of the frames: png, jpeg, tiff, exr or dpx (32-bit float
+    /// and 10-bit for DI), or webp (default: png)
+    #[arg(long, value_name = "FORMAT")]
+    pub frame_format: Option<FrameFormat>,
+
+    /// Deliver an image sequence (frames) or a single video file (video),
+    /// encoded per segment and joined without re-encoding (default: frames)
+    #[arg(long, value_name = "FORMAT")]
+    pub output_format: Option<OutputFormat>,
+
+    /// Codec of --output-format video: h264, hevc or av1 (.mp4), prores or
+    /// dnxhr (.mov), or j2k (.mxf) (default: h264)
+    #[arg(long)]
+    pub codec: Option<VideoCodec>,
+
+    /// Container of video output: mp4, mov or mxf (default: the codec's)
+    #[arg(long)]
+    pub container: Option<Container>,
+
+    /// Start timecode of video output: source to carry over the input's, or
+    /// HH:MM:SS:FF (HH:MM:SS;FF for drop-frame)
+    #[arg(long, value_name = "TIMECODE")]
+    pub timecode: Option<Timecode>,
+
+    /// Audio of video output: copy the source's as it is, encode it to aac
+    /// or pcm (24-bit 48 kHz), or none to leave it out (default: aac in MP4,
+    /// pcm in MOV and MXF)
+    #[arg(long, value_name = "CODEC")]
+    pub audio: Option<AudioCodec>,
+
+    /// Lay the audio of MXF output out in mono tracks (one per channel),
+    /// stereo tracks (one per pair) or 5.1 tracks (one per six channels)
+    /// (default: mono)
+    #[arg(long, value_name = "LAYOUT")]
+    pub audio_layout: Option<TrackLayout>,
+
+    /// Source audio channels carried into MXF output, numbered from 1 across
+    /// all audio streams, in output order (e.g. 1,2,5,6; default: all)
+    #[arg(long, value_name = "CHANNELS", value_delimiter = ',', num_args = 1..)]
+    pub audio_channels: Option<Vec<u32>>,
+
+    /// Map source channels to the audio tracks of MXF output, each as
+    /// <channels>:<layout> with channels numbered from 1 across all audio
+    /// streams (e.g. 1+2:stereo,3-8:5.1); 5.1 channels mapped to a stereo
+    /// or mono track are downmixed with the standard coefficients
+    #[arg(long, value_name = "TRACKS", value_delimiter = ',', num_args = 1..,
+        conflicts_with_all = ["audio_layout", "audio_channels"])]
+    pub audio_tracks: Option<Vec<TrackMap>>,
+
+    /// Labels of the audio tracks of MXF output, in order (e.g. "Mix L,Mix R")
+    #[arg(long, value_name = "LABELS", value_delimiter = ',', num_args = 1..)]
+    pub audio_labels: Option<Vec<String>>,
+
+    /// Normalize the audio of video output to this integrated loudness
+    /// (EBU R128) with two-pass loudnorm, e.g. -23LUFS for broadcast or
+    /// -14LUFS for the web; true peaks are limited to -1 dBTP
+    #[arg(long, value_name = "LUFS", value_parser = loudness::parse_target, allow_hyphen_values = true)]
+    pub loudness_target: Option<f64>,
+
+    /// Deliver to a named specification: dnxhr-lb, dnxhr-sq, dnxhr-hq,
+    /// dnxhr-hqx or dnxhr-444 set the codec, profile, bit depth, MXF and the
+    /// source timecode; dcp-2k or dcp-4k encode DCI JPEG 2000 and package
+    /// it as a DCP in <output>/dcp (24 fps sources); options given with it
+    /// override them
+    #[arg(long, value_name = "NAME")]
+    pub delivery_preset: Option<DeliveryPreset>,
+
+    /// Encode segments to lossless ffv1 or utvideo files instead of frames
+    /// and expand them to frames when combining; much faster to write
+    #[arg(long, value_name = "CODEC")]
+    pub intermediate: Option<IntermediateCodec>,
+
+    /// Add a rendition to the output ladder as <name>:<height>[:<bitrate>],
+    /// e.g. --rendition 1080p:1080:8M --rendition 720p:720:4M; all are
+    /// scaled from one decode of the source (needs --output-format video)
+    #[arg(long, value_name = "RENDITION")]
+    pub rendition: Option<Vec<Rendition>>,
+
+    /// Also write each of the source's audio tracks to its own file next to
+    /// the frames or video, as wav (24-bit PCM) or aac (.m4a), named
+    /// <name>_audio1, <name>_audio2, ...
+    #[arg(long, value_name = "FORMAT")]
+    pub extract_audio: Option<StemFormat>,
+
+    /// Also write a list of the source's shots, found by scene detection,
+    /// next to the output as csv or json (<name>_shots.csv), with a thumbnail
+    /// of every shot in <name>_shots/
+    #[arg(long, value_name = "FORMAT")]
+    pub shot_list: Option<ShotListFormat>,
+
+    /// Package the video for streaming after it is joined: hls writes media
+    /// segments and master.m3u8 to <output>/hls, dash fragmented MP4 and
+    /// manifest.mpd to <output>/dash; both may be given, e.g. hls,dash.
+    /// imf wraps prores or j2k video and the source audio in a basic IMF
+    /// package (CPL, PKL and ASSETMAP) in <output>/imf, dcp DCI j2k video
+    /// in a DCP in <output>/dcp
+    /// (needs --output-format video)
+    #[arg(long, value_delimiter = ',', num_args = 1..)]
+    pub package: Option<Vec<Package>>,
+
+    /// Length of the packaged media segments in seconds (default: 6)
+    #[arg(long, value_name = "SECONDS", requires = "package")]
+    pub package_segment: Option<f64>,
+
+    /// Constant quality of h264, hevc and av1 video output, 0 (lossless) to 51
+    /// (worst), 63 for av1 (default: 18 for h264, 20 for hevc, 30 for av1)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=63), conflicts_with = "bitrate")]
+    pub crf: Option<u8>,
+
+    /// Pick the CRF of h264, hevc and av1 video output per title: encode
+    /// short probe clips at a few CRFs and use the highest that still scores
+    /// this VMAF, e.g. 93 (needs ffmpeg with libvmaf)
+    #[arg(long, value_name = "SCORE", value_parser = crfsearch::parse_target,
+        conflicts_with_all = ["crf", "bitrate", "two_pass"])]
+    pub target_vmaf: Option<f64>,
+
+    /// Encode video output to an average bitrate such as 12M or 800k instead
+    /// of constant quality (h264, hevc and av1)
+    #[arg(long, value_name = "RATE", value_parser = units::parse_bitrate)]
+    pub bitrate: Option<u64>,
+
+    /// Cap the video bitrate at this peak (VBV); the same as --bitrate for
+    /// constant bitrate delivery (h264 and hevc)
+    #[arg(long, value_name = "RATE", value_parser = units::parse_bitrate)]
+    pub maxrate: Option<u64>,
+
+    /// Size in bits of the buffer --maxrate is measured over, e.g. 24M
+    /// (default: twice --maxrate)
+    #[arg(long, value_name = "BITS", value_parser = units::parse_bitrate, requires = "maxrate")]
+    pub bufsize: Option<u64>,
+
+    /// Encoder preset of h264, hevc and av1 video, ultrafast to placebo;
+    /// av1 maps them to SVT-AV1 presets or libaom -cpu-used (default: medium)
+    #[arg(long)]
+    pub preset: Option<String>,
+
+    /// Codec profile of video output: baseline, main or high for h264, main
+    /// or main10 for hevc, proxy, lt, standard, hq, 4444 or 4444xq for prores,
+    /// main for av1, lb, sq, hq, hqx or 444 for dnxhr, cinema2k or cinema4k
+    /// (DCI X'Y'Z') for j2k
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    /// Codec level of h264 and hevc video, e.g. 4.1
+    #[arg(long)]
+    pub level: Option<String>,
+
+    /// Encode video output in two passes to hit the --bitrate (or every
+    /// rendition's bitrate) exactly: segment makes every segment average it,
+    /// global runs all first passes before any second and gives busy
+    /// segments more of it than calm ones (default: segment)
+    #[arg(long, value_name = "MODE", num_args = 0..=1, default_missing_value = "segment")]
+    pub two_pass: Option<TwoPass>,
+
+    /// Bits per channel of the frames, e.g. 16 for PNG/TIFF to keep 10-bit
+    /// masters intact (default: 8, 10 for DPX, 32-bit float for EXR)
+    #[arg(long, value_name = "BITS")]
+    pub bit_depth: Option<u8>,
+
+    /// zlib compression level of PNG frames, 0 (fastest, largest) to 9
+    /// (slowest, smallest)
+    #[arg(long, value_name = "LEVEL", value_parser = clap::value_parser!(u8).range(0..=9))]
+    pub png_compression: Option<u8>,
+
+    /// Quality of JPEG frames, 1 (smallest) to 100 (best) (default: 100)
+    #[arg(long, value_name = "QUALITY", value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub jpeg_quality: Option<u8>,
+
+    /// Embed an ICC profile in PNG and TIFF frames so review and compositing
+    /// apps agree on their colour: srgb, or a profile file (default: srgb)
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "srgb")]
+    pub icc_profile: Option<IccProfile>,
+
+    /// Keep the source's alpha channel (e.g. ProRes 4444) through the filter
+    /// graph and write RGBA frames (not with JPEG)
+    #[arg(long)]
+    pub preserve_alpha: bool,
+
+    /// Flatten the source's alpha against this color (e.g. white, #20252a)
+    /// before compositing
+    #[arg(long, value_name = "COLOR", conflicts_with = "preserve_alpha")]
+    pub flatten_on: Option<String>,
+
+    /// Output frame file names, e.g. "{basename}.{frame:04}.{ext}"; variables
+    /// are {basename}, {job}, {date}, {ext} and {frame[:width]}
+    /// (default: video{frame}.{ext})
+    #[arg(long, value_name = "TEMPLATE")]
+    pub name_template: Option<String>,
+
+    /// Number of the first output frame (default: 1)
+    #[arg(long, value_name = "N")]
+    pub start_frame: Option<u64>,
+
+    /// Digits frame numbers are zero-padded to when the template gives no
+    /// width (default: 5)
+    #[arg(long, value_name = "DIGITS", value_parser = clap::value_parser!(u64).range(0..=20))]
+    pub frame_padding: Option<u64>,
+
+    /// Job id substituted for {job} in the name template (default: the output
+    /// directory's name)
+    #[arg(long)]
+    pub job_id: Option<String>,
+
+    /// Write into a new <job>_<YYYYMMDD>_<HHMMSS> subdirectory of the output
+    /// directory and point <output>/latest at it once the run succeeds
+    #[arg(long)]
+    pub timestamped: bool,
+
+    /// What to do when the output directory already holds frames: fail,
+    /// skip, overwrite, or version (write to <output>_v002, ...) (default: fail)
+    #[arg(long, value_name = "POLICY")]
+    pub on_existing: Option<OnExisting>,
+
+    #[command(flatten)]
+    pub tools: ToolArgs,
+
+    #[command(flatten)]
+    pub temp: TempArgs,
+
+    /// Probe and plan, print the ffmpeg commands and output layout, then exit
+    /// without writing anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Save the computed plan (segments, commands, expected frame counts) as JSON
+    #[arg(long, value_name = "FILE")]
+    pub plan_out: Option<PathBuf>,
+
+    /// Execute a plan saved with --plan-out instead of probing the input
+    #[arg(long, value_name = "FILE",
+        conflicts_with_all = [
+            "input", "overlay", "overlay_loop", "segments", "adaptive_segments", "split_on", "vfr_mode",
+            "filter", "lut", "tonemap", "tonemap_operator",
+            "color_primaries", "color_trc", "colorspace", "color_range", "autocrop",
+            "overlay_position", "overlay_margin", "overlay_x", "overlay_y", "overlay_scale",
+            "overlay_opacity", "overlay_key", "overlay_window", "overlay_schedule",
+            "burn_in", "burn_in_position", "burn_in_margin", "burn_in_font", "burn_in_size",
+            "burn_in_color", "burn_in_box", "recipient",
+            "burn_timecode", "burn_timecode_position", "burn_frame_number", "burn_frame_number_position", "guides",
+            "slate", "slate_title", "slate_episode", "slate_version", "slate_date", "slate_duration", "two_pop",
+            "screeners", "screener_position", "screener_opacity", "screener_size", "screener_jobs",
+            "watermark_hook", "watermark_hook_mode",
+            "frame_format", "output_format", "codec", "container", "timecode",
+            "audio", "audio_layout", "audio_channels", "audio_tracks", "audio_labels", "loudness_target",
+            "delivery_preset", "intermediate", "rendition", "extract_audio", "shot_list", "package", "package_segment",
+            "crf", "target_vmaf", "bitrate", "maxrate", "bufsize",
+            "preset", "profile", "level", "two_pass", "bit_depth",
+            "png_compression", "jpeg_quality", "icc_profile", "preserve_alpha",
+            "flatten_on", "name_template", "start_frame", "frame_padding",
+            "job_id", "timestamped", "config",
+        ])]
+    pub plan_in: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub run: RunArgs,
+
+    /// Re-encode only these segments of the previous run (e.g. 3,7) and
+    /// combine them with the segments it already completed; with --temp
+    /// system or in-memory, --job-id or --input tell which run
+    #[arg(long, value_name = "IDS", value_delimiter = ',', num_args = 1..,
+        conflicts_with_all = [
+            "overlay", "overlay_loop", "segments", "adaptive_segments", "split_on", "presplit", "vfr_mode",
+            "filter", "lut", "tonemap", "tonemap_operator",
+            "color_primaries", "color_trc", "colorspace", "color_range", "autocrop",
+            "overlay_position", "overlay_margin", "overlay_x", "overlay_y", "overlay_scale",
+            "overlay_opacity", "overlay_key", "overlay_window", "overlay_schedule",
+            "burn_in", "burn_in_position", "burn_in_margin", "burn_in_font", "burn_in_size",
+            "burn_in_color", "burn_in_box", "recipient",
+            "burn_timecode", "burn_timecode_position", "burn_frame_number", "burn_frame_number_position", "guides",
+            "slate", "slate_title", "slate_episode", "slate_version", "slate_date", "slate_duration", "two_pop",
+            "screeners", "screener_position", "screener_opacity", "screener_size", "screener_jobs",
+            "watermark_hook", "watermark_hook_mode",
+            "frame_format", "output_format", "codec", "container", "timecode",
+            "audio", "audio_layout", "audio_channels", "audio_tracks", "audio_labels", "loudness_target",
+            "delivery_preset", "intermediate", "rendition", "extract_audio", "shot_list", "package", "package_segment",
+            "crf", "target_vmaf", "bitrate", "maxrate", "bufsize",
+            "preset", "profile", "level", "two_pass", "bit_depth",
+            "png_compression", "jpeg_quality", "icc_profile", "preserve_alpha",
+            "flatten_on", "name_template", "start_frame", "frame_padding",
+            "timestamped", "dry_run", "plan_out", "plan_in", "config",
+        ])]
+    pub only_segments: Option<Vec<usize>>,
+
+    /// TOML job definition; flags given on the command line override its values
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Run a batch of jobs: one JSON job definition per line of FILE, with
+    /// the keys of --config; flags given on the command line apply to every
+    /// job. Failed jobs don't stop the batch, and what happened to each is
+    /// written to --batch-report
+    #[arg(long, value_name = "FILE",
+        conflicts_with_all = ["input", "overlay", "output_dir", "job_id", "plan_in", "plan_out", "only_segments",
+            "config"])]
+    pub jobs: Option<PathBuf>,
+
+    /// Jobs of --jobs run at once, sharing --threads between them (default: 1)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), requires = "jobs")]
+    pub job_concurrency: Option<u64>,
+
+    /// Where --jobs writes its batch report (default: <FILE>.report.json)
+    #[arg(long, value_name = "FILE", requires = "jobs")]
+    pub batch_report: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ProbeArgs {
+    /// Video to inspect (default: assets/video.mov in the project root)
+    #[arg(short, long)]
+    pub input: Option<PathBuf>,
+
+    /// Print the summary as JSON on stdout
+    #[arg(long)]
+    pub json: bool,
+
+    #[command(flatten)]
+    pub tools: ToolArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CombineArgs {
+    /// Directory the frames are written to (default: the previous run's)
+    #[arg(short, long)]
+    pub output_dir: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub temp: TempArgs,
+
+    #[command(flatten)]
+    pub temp_job: TempJobArgs,
+
+    /// Copy the frames and keep the temporary segments
+    #[arg(long)]
+    pub keep_temp: bool,
+
+    /// Join the segments of a run with --intermediate or --output-format
+    /// video into this single file with the concat demuxer, without
+    /// re-encoding, instead of writing frames
+    #[arg(long, value_name = "FILE", conflicts_with = "output_dir")]
+    pub to_video: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub tools: ToolArgs,
+
+    /// Only warn when the segments hold a different number of frames than
+    /// expected
+    #[arg(long)]
+    pub allow_frame_mismatch: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CleanArgs {
+    /// Also remove the output directory
+    #[arg(long)]
+    pub output: bool,
+
+    /// Output directory removed with --output (default: output)
+    #[arg(short, long, requires = "output")]
+    pub output_dir: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub temp: TempArgs,
+
+    #[command(flatten)]
+    pub temp_job: TempJobArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ResumeArgs {
+    /// Directory the frames are written to (default: the previous run's)
+    #[arg(short, long)]
+    pub output_dir: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub run: RunArgs,
+
+    #[command(flatten)]
+    pub temp: TempArgs,
+
+    #[command(flatten)]
+    pub temp_job: TempJobArgs,
+
+    #[command(flatten)]
+    pub tools: ToolArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct FetchArgs {
+    /// Pins manifest listing archives and checksums per platform; none ship
+    /// with the tool, so record builds you have verified or use --url
+    #[arg(long, default_value = "assets/ffmpeg-pins.toml")]
+    pub pins: PathBuf,
+
+    /// Archive to download instead of those in the pins manifest
+    #[arg(long, requires = "sha256")]
+    pub url: Option<String>,
+
+    /// Expected SHA-256 of the archive given with --url
+    #[arg(long, requires = "url")]
+    pub sha256: Option<String>,
+
+    /// Replace an existing installation
+    #[arg(long)]
+    pub force: bool,
+}
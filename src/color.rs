@@ -0,0 +1,390 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Transfer of PQ (HDR10) video, as ffprobe and ffmpeg name it.
+pub const PQ: &str = "smpte2084";
+
+/// Transfer of HLG video.
+pub const HLG: &str = "arib-std-b67";
+
+/// Primaries, transfers and matrices ffmpeg and zscale both know, by the
+/// names ffmpeg gives them.
+const PRIMARIES: &[&str] =
+    &["bt709", "bt470m", "bt470bg", "smpte170m", "smpte240m", "film", "bt2020", "smpte428", "smpte431", "smpte432"];
+const TRANSFERS: &[&str] = &[
+    "bt709", "bt470m", "bt470bg", "smpte170m", "smpte240m", "linear", "log100", "log316", "iec61966-2-4",
+    "iec61966-2-1", "bt2020-10", "bt2020-12", PQ, HLG,
+];
+const MATRICES: &[&str] =
+    &["bt709", "fcc", "bt470bg", "smpte170m", "smpte240m", "ycgco", "bt2020nc", "bt2020c", "ictcp"];
+
+/// Common names accepted for some of them.
+const PRIMARY_ALIASES: &[(&str, &str)] =
+    &[("rec709", "bt709"), ("rec2020", "bt2020"), ("dci-p3", "smpte431"), ("display-p3", "smpte432")];
+const TRANSFER_ALIASES: &[(&str, &str)] =
+    &[("rec709", "bt709"), ("srgb", "iec61966-2-1"), ("pq", PQ), ("hlg", HLG)];
+const MATRIX_ALIASES: &[(&str, &str)] = &[("rec709", "bt709"), ("bt2020", "bt2020nc"), ("rec2020", "bt2020nc")];
+
+// ffmpeg's name for `text` if it is one of the `known` names of `kind` or
+// one of their `aliases`.
+fn parse_tag(
+    kind: &str,
+    known: &[&str],
+    aliases: &[(&str, &str)],
+    text: &str,
+) -> std::result::Result<String, String> {
+    let tag = text.trim().to_ascii_lowercase();
+    if let Some((_, name)) = aliases.iter().find(|(alias, _)| *alias == tag) {
+        return Ok(name.to_string());
+    }
+    match known.contains(&tag.as_str()) {
+        true => Ok(tag),
+        false => Err(format!("invalid {} '{}', expected one of {}", kind, text, known.join(", "))),
+    }
+}
+
+/// Parse colour primaries such as `bt709` or `smpte432` (Display P3).
+pub fn parse_primaries(text: &str) -> std::result::Result<String, String> {
+    parse_tag("colour primaries", PRIMARIES, PRIMARY_ALIASES, text)
+}
+
+/// Parse a transfer such as `bt709`, `iec61966-2-1` (sRGB) or `smpte2084`
+/// (PQ).
+pub fn parse_transfer(text: &str) -> std::result::Result<String, String> {
+    parse_tag("transfer", TRANSFERS, TRANSFER_ALIASES, text)
+}
+
+/// Parse a YUV matrix such as `bt709` or `bt2020nc`.
+pub fn parse_matrix(text: &str) -> std::result::Result<String, String> {
+    parse_tag("colour matrix", MATRICES, MATRIX_ALIASES, text)
+}
+
+/// Range of the code values.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorRange {
+    /// 16-235 for 8 bits, as broadcast video is.
+    Limited,
+    /// 0-255 for 8 bits.
+    Full,
+}
+
+impl ColorRange {
+    /// ffmpeg's name for the range.
+    pub fn tag(self) -> &'static str {
+        match self {
+            ColorRange::Limited => "tv",
+            ColorRange::Full => "pc",
+        }
+    }
+}
+
+impl FromStr for ColorRange {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<ColorRange, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "limited" | "tv" => Ok(ColorRange::Limited),
+            "full" | "pc" => Ok(ColorRange::Full),
+            _ => Err(format!("invalid colour range '{}', expected full or limited", text)),
+        }
+    }
+}
+
+impl fmt::Display for ColorRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ColorRange::Limited => "limited",
+            ColorRange::Full => "full",
+        })
+    }
+}
+
+/// Colour the output is converted to and tagged with, where the job sets
+/// it; the rest stays as it is.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct OutputColor {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub primaries: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transfer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub space: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<ColorRange>,
+}
+
+impl OutputColor {
+    /// Whether the job sets any of it.
+    pub fn is_set(&self) -> bool {
+        *self != OutputColor::default()
+    }
+
+    /// zscale converting video in the colour `from` to this one. Whatever
+    /// `from` doesn't say is taken to be BT.709 in limited range.
+    pub fn filter(&self, from: &SourceColor) -> String {
+        let input = |tag: &Option<String>, default: &str| tag.clone().unwrap_or_else(|| default.to_string());
+        let mut options = vec![
+            format!("pin={}", input(&from.primaries, "bt709")),
+            format!("tin={}", input(&from.transfer, "bt709")),
+            format!("min={}", input(&from.space, "bt709")),
+            format!("rin={}", input(&from.range, "tv")),
+        ];
+        options.extend(self.primaries.iter().map(|p| format!("p={}", p)));
+        options.extend(self.transfer.iter().map(|t| format!("t={}", t)));
+        options.extend(self.space.iter().map(|m| format!("m={}", m)));
+        options.extend(self.range.iter().map(|r| format!("r={}", r.tag())));
+        format!("zscale={}", options.join(":"))
+    }
+
+    /// `from` with the colour the job sets in place of its own.
+    pub fn applied_to(&self, from: &SourceColor) -> SourceColor {
+        SourceColor {
+            primaries: self.primaries.clone().or(from.primaries.clone()),
+            transfer: self.transfer.clone().or(from.transfer.clone()),
+            space: self.space.clone().or(from.space.clone()),
+            range: self.range.map(|r| r.tag().to_string()).or(from.range.clone()),
+            ..from.clone()
+        }
+    }
+}
+
+/// What HDR video is tone mapped to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneTarget {
+    /// SDR BT.709.
+    Sdr,
+}
+
+impl FromStr for ToneTarget {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<ToneTarget, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "sdr" => Ok(ToneTarget::Sdr),
+            _ => Err(format!("invalid tone mapping target '{}', expected sdr", text)),
+        }
+    }
+}
+
+/// How highlights beyond SDR's range are rolled off.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ToneOperator {
+    /// John Hable's filmic curve, with zscale and tonemap.
+    #[default]
+    Hable,
+    /// The ITU-R BT.2390 EETF, with libplacebo (which needs Vulkan).
+    Bt2390,
+}
+
+impl FromStr for ToneOperator {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<ToneOperator, String> {
+        match text.trim().to_ascii_lowercase().replace(['.', '-'], "").as_str() {
+            "hable" => Ok(ToneOperator::Hable),
+            "bt2390" => Ok(ToneOperator::Bt2390),
+            _ => Err(format!("invalid tone mapping operator '{}', expected hable or bt2390", text)),
+        }
+    }
+}
+
+impl fmt::Display for ToneOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ToneOperator::Hable => "hable",
+            ToneOperator::Bt2390 => "bt2390",
+        })
+    }
+}
+
+/// HDR (PQ or HLG) video tone mapped to SDR BT.709 before anything is
+/// composited over it, and the output tagged to match.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tonemap {
+    pub operator: ToneOperator,
+    /// Transfer of the source, [`PQ`] or [`HLG`], once probed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transfer: Option<String>,
+}
+
+impl Tonemap {
+    /// Tone mapping with `operator`.
+    pub fn new(operator: ToneOperator) -> Tonemap {
+        Tonemap { operator, transfer: None }
+    }
+
+    /// Whether the source's transfer is still to be probed.
+    pub fn unprobed(&self) -> bool {
+        self.transfer.is_none()
+    }
+
+    /// Filters mapping the source to SDR, keeping its alpha channel if
+    /// `alpha`. The source's tags are set first, as they are often missing
+    /// on HDR masters and both paths read them.
+    pub fn filters(&self, alpha: bool) -> String {
+        let transfer = self.transfer.as_deref().unwrap_or(PQ);
+        let tags = format!("setparams=color_primaries=bt2020:color_trc={}:colorspace=bt2020nc", transfer);
+        match self.operator {
+            ToneOperator::Hable => format!(
+                "{},zscale=t=linear:npl=100,format={},zscale=p=bt709,tonemap=tonemap=hable:desat=0,\
+                zscale=t=bt709:m=bt709:r=tv",
+                tags,
+                if alpha { "gbrapf32le" } else { "gbrpf32le" }
+            ),
+            ToneOperator::Bt2390 => format!(
+                "{},libplacebo=tonemapping=bt.2390:colorspace=bt709:color_primaries=bt709:color_trc=bt709:range=tv",
+                tags
+            ),
+        }
+    }
+}
+
+/// Colour volume of the display an HDR master was graded on (SMPTE ST
+/// 2086).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct MasteringDisplay {
+    /// CIE 1931 x and y of the red, green and blue primaries and the white
+    /// point.
+    pub red: [f64; 2],
+    pub green: [f64; 2],
+    pub blue: [f64; 2],
+    pub white_point: [f64; 2],
+    /// Luminance range in cd/m².
+    pub min_luminance: f64,
+    pub max_luminance: f64,
+}
+
+impl MasteringDisplay {
+    // x265's `master-display`: chromaticities in 0.00002 and luminance in
+    // 0.0001 cd/m² steps.
+    fn x265_param(&self) -> String {
+        let xy = |[x, y]: [f64; 2]| format!("({},{})", (x * 50000.0).round(), (y * 50000.0).round());
+        format!(
+            "master-display=G{}B{}R{}WP{}L({},{})",
+            xy(self.green),
+            xy(self.blue),
+            xy(self.red),
+            xy(self.white_point),
+            (self.max_luminance * 10000.0).round(),
+            (self.min_luminance * 10000.0).round()
+        )
+    }
+}
+
+/// Brightest pixel (MaxCLL) and brightest frame average (MaxFALL) of an HDR
+/// master in cd/m² (CTA-861.3).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ContentLight {
+    pub max_content: u32,
+    pub max_average: u32,
+}
+
+/// Colour of the source as probed, so HDR masters are delivered with their
+/// tags and metadata instead of being passed off as SDR.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SourceColor {
+    /// Tags as ffprobe names them, if the source sets them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub primaries: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transfer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub space: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mastering_display: Option<MasteringDisplay>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_light: Option<ContentLight>,
+}
+
+impl SourceColor {
+    /// Whether the source is HDR: PQ or HLG.
+    pub fn is_hdr(&self) -> bool {
+        matches!(self.transfer.as_deref(), Some(PQ | HLG))
+    }
+
+    /// SDR BT.709 in limited range, as tone mapping produces.
+    pub fn sdr() -> SourceColor {
+        let bt709 = Some("bt709".to_string());
+        SourceColor {
+            primaries: bt709.clone(),
+            transfer: bt709.clone(),
+            space: bt709,
+            range: Some("tv".to_string()),
+            ..SourceColor::default()
+        }
+    }
+
+    /// The colour of HDR video, BT.2020 in limited range where it doesn't
+    /// say.
+    pub fn with_hdr_defaults(&self) -> SourceColor {
+        let or = |tag: &Option<String>, default: &str| Some(tag.clone().unwrap_or_else(|| default.to_string()));
+        SourceColor {
+            primaries: or(&self.primaries, "bt2020"),
+            transfer: or(&self.transfer, PQ),
+            space: or(&self.space, "bt2020nc"),
+            range: or(&self.range, "tv"),
+            ..self.clone()
+        }
+    }
+
+    /// Output options tagging video with the tags this colour has.
+    pub fn tag_args(&self) -> Vec<String> {
+        let tags = [
+            ("-color_primaries", &self.primaries),
+            ("-color_trc", &self.transfer),
+            ("-colorspace", &self.space),
+            ("-color_range", &self.range),
+        ];
+        tags.into_iter().filter_map(|(option, tag)| Some([option.to_string(), tag.clone()?])).flatten().collect()
+    }
+
+    /// x265 params writing the mastering display and content light level
+    /// SEI the source has.
+    pub fn x265_params(&self) -> Vec<String> {
+        let mut params: Vec<String> = self.mastering_display.iter().map(|m| m.x265_param()).collect();
+        if let Some(light) = &self.content_light {
+            params.push(format!("max-cll={},{}", light.max_content, light.max_average));
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_primaries_knows_ffmpegs_names_and_aliases() {
+        assert_eq!(parse_primaries("bt709"), Ok("bt709".to_string()));
+        assert_eq!(parse_primaries(" SMPTE432 "), Ok("smpte432".to_string()));
+        assert_eq!(parse_primaries("rec2020"), Ok("bt2020".to_string()));
+        assert_eq!(parse_primaries("Display-P3"), Ok("smpte432".to_string()));
+        assert_eq!(parse_primaries("dci-p3"), Ok("smpte431".to_string()));
+        assert!(parse_primaries("p4").unwrap_err().starts_with("invalid colour primaries 'p4', expected one of bt709"));
+        assert!(parse_primaries("").is_err());
+    }
+
+    #[test]
+    fn parse_transfer_knows_ffmpegs_names_and_aliases() {
+        assert_eq!(parse_transfer("iec61966-2-1"), Ok("iec61966-2-1".to_string()));
+        assert_eq!(parse_transfer("sRGB"), Ok("iec61966-2-1".to_string()));
+        assert_eq!(parse_transfer("pq"), Ok(PQ.to_string()));
+        assert_eq!(parse_transfer("HLG"), Ok(HLG.to_string()));
+        assert_eq!(parse_transfer("rec709"), Ok("bt709".to_string()));
+        assert!(parse_transfer("gamma22").unwrap_err().starts_with("invalid transfer 'gamma22'"));
+    }
+
+    #[test]
+    fn parse_matrix_knows_ffmpegs_names_and_aliases() {
+        assert_eq!(parse_matrix("bt2020nc"), Ok("bt2020nc".to_string()));
+        assert_eq!(parse_matrix("bt2020"), Ok("bt2020nc".to_string()));
+        assert_eq!(parse_matrix("Rec709"), Ok("bt709".to_string()));
+        assert!(parse_matrix("smpte432").unwrap_err().starts_with("invalid colour matrix 'smpte432'"));
+        assert!(parse_matrix("bt2020x").is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::console::{debug, info, warning};
+use crate::naming::FrameNames;
+use crate::progress::CopyProgress;
+use crate::segment::Segment;
+use crate::{diskspace, DeliveryError, FrameFormat, Result};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Numeric frame index encoded in a file stem such as `00042.png`.
+fn frame_number(path: &Path) -> Option<u32> {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .and_then(|n| n.split('.').next())
+        .and_then(|n| n.parse::<u32>().ok())
+}
+
+/// The frames with extension `ext` in `segment_dir`, in frame order.
+pub fn segment_frames(segment_dir: &Path, ext: &str) -> io::Result<Vec<PathBuf>> {
+    let mut frames: Vec<PathBuf> = fs::read_dir(segment_dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == ext))
+        .collect();
+    // Sort frames numerically
+    frames.sort_by_key(|p| frame_number(p));
+    Ok(frames)
+}
+
+/// Frames named by `names` that a previous run left in `output_dir`.
+pub fn output_frames(output_dir: &Path, names: &FrameNames) -> io::Result<Vec<PathBuf>> {
+    if !output_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut frames: Vec<PathBuf> = fs::read_dir(output_dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.file_name().and_then(|n| n.to_str()).is_some_and(|n| names.matches(n)))
+        .collect();
+    frames.sort();
+    Ok(frames)
+}
+
+// Copy `from` to `to` through a temporary file next to `to`, so an
+// interrupted copy never leaves a truncated frame behind. Returns the bytes
+// copied.
+fn copy_file(from: &Path, to: &Path) -> io::Result<u64> {
+    let mut partial = to.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let bytes = fs::copy(from, &partial)?;
+    fs::rename(&partial, to)?;
+    Ok(bytes)
+}
+
+// Rename `from` to `to`, copying instead when they turn out to be on
+// different devices.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_file(from, to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// Move every segment's frames into `output_dir` as one continuous sequence
+/// named by `names`, in segment order. With `keep_segments` the frames
+/// are copied instead, leaving the segments intact. Frames are also copied
+/// (and then removed) when the segments and the output are on different
+/// volumes, where they can't be renamed. Returns the number of frames
+/// written, or the first error reading a segment or moving a frame.
+pub fn combine(
+    segments_dir: &Path,
+    segments: &[Segment],
+    output_dir: &Path,
+    names: &FrameNames,
+    format: FrameFormat,
+    keep_segments: bool,
+) -> Result<usize> {
+    let _span = tracing::info_span!("combine", segments = segments.len()).entered();
+    info!("\n🔗 Combining segments...");
+    let combine_start = Instant::now();
+
+    let mut frames = Vec::new();
+    for segment in segments {
+        let segment_dir = segment.dir(segments_dir);
+        debug!("🔍 Processing segment {}: {}", segment.id, segment_dir.display());
+
+        match segment_frames(&segment_dir, format.extension()) {
+            Ok(found) if found.is_empty() => {
+                warning!("⚠️ No {} frames found in segment {}: {}", format, segment.id, segment_dir.display());
+            }
+            Ok(found) => {
+                debug!("📦 Segment {} has {} frames", segment.id, found.len());
+                frames.extend(found);
+            }
+            Err(e) => {
+                return Err(DeliveryError::io(format!("Failed to read segment {} directory", segment.id), e))
+            }
+        }
+    }
+
+    let cross_device = !diskspace::same_volume(segments_dir, output_dir);
+    let mut progress = (keep_segments || cross_device).then(|| {
+        if cross_device && !keep_segments {
+            info!("ℹ️ Segments and output are on different volumes, copying frames");
+        }
+        CopyProgress::new(frames.iter().filter_map(|f| fs::metadata(f).ok()).map(|m| m.len()).sum())
+    });
+
+    let mut result = Ok(());
+    for (index, frame) in frames.iter().enumerate() {
+        let dest = output_dir.join(names.name(index));
+        result = match &mut progress {
+            Some(progress) => copy_file(frame, &dest).and_then(|bytes| {
+                progress.inc(bytes);
+                if keep_segments { Ok(()) } else { fs::remove_file(frame) }
+            }),
+            None => move_file(frame, &dest),
+        }
+        .map_err(|e| DeliveryError::io(format!("Failed to move {}", frame.display()), e));
+        if result.is_err() {
+            break;
+        }
+    }
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+    result?;
+
+    let combine_duration = combine_start.elapsed();
+    info!("✅ Combined {} frames in {:.2} seconds", frames.len(), combine_duration.as_secs_f32());
+    Ok(frames.len())
+}
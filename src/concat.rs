@@ -0,0 +1,134 @@
+use crate::console::{debug, info};
+use crate::segment::Segment;
+use crate::{ffmpeg, probe, process, DeliveryError, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+
+/// List of the segment chunks read by the concat demuxer, in the segments
+/// directory.
+pub const LIST_FILE: &str = "concat.txt";
+
+// `path` quoted for a concat list.
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"))
+}
+
+/// A concat demuxer list of the chunk of every segment (named `chunk.<ext>`
+/// in its directory), in segment order. Paths are absolute, so the list works
+/// wherever ffmpeg runs. `durations` (seconds, one per segment) are written
+/// as `duration` directives, so each chunk starts exactly where the frames
+/// of the one before end, whatever its container claims. A `lead` chunk,
+/// with its duration, plays before the segments.
+pub fn list(
+    segments_dir: &Path,
+    segments: &[Segment],
+    ext: &str,
+    durations: Option<&[f64]>,
+    lead: Option<(&Path, f64)>,
+) -> String {
+    let mut list = String::from("ffconcat version 1.0\n");
+    let mut entry = |chunk: PathBuf, duration: Option<f64>| {
+        let chunk = std::path::absolute(&chunk).unwrap_or(chunk);
+        list.push_str(&format!("file {}\n", quote(&chunk)));
+        if let Some(duration) = duration {
+            list.push_str(&format!("duration {:.6}\n", duration));
+        }
+    };
+    if let Some((chunk, duration)) = lead {
+        entry(chunk.to_path_buf(), Some(duration));
+    }
+    for (index, segment) in segments.iter().enumerate() {
+        entry(segment.chunk(segments_dir, ext), durations.and_then(|d| d.get(index)).copied());
+    }
+    list
+}
+
+/// Streams and metadata added to the video while the chunks are joined.
+#[derive(Debug, Clone, Default)]
+pub struct Mux {
+    /// Start timecode.
+    pub timecode: Option<String>,
+    /// File the audio is taken from, as ffmpeg's second input.
+    pub audio_source: Option<PathBuf>,
+    /// Options that map and encode the audio of `audio_source`.
+    pub audio_args: Vec<String>,
+    /// Chunk played before the segments, e.g. a slate, and its number of
+    /// frames.
+    pub lead: Option<(PathBuf, u64)>,
+}
+
+/// The ffmpeg invocation that joins the chunks in `list` into `output`
+/// without re-encoding the video, adding what `mux` holds. Timestamps are
+/// regenerated so the joined file plays continuously.
+pub fn join_command(ffmpeg: &Path, list: &Path, mux: &Mux, output: &Path) -> Command {
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-v", "error", "-fflags", "+genpts", "-f", "concat", "-safe", "0"])
+        .arg("-i").arg(ffmpeg::path_arg(list));
+    match &mux.audio_source {
+        Some(source) => {
+            cmd.arg("-i").arg(ffmpeg::path_arg(source))
+                .args(["-map", "0:v", "-c:v", "copy"])
+                .args(&mux.audio_args);
+        }
+        None => {
+            cmd.args(["-map", "0", "-c", "copy"]);
+        }
+    }
+    if let Some(timecode) = &mux.timecode {
+        cmd.args(["-timecode", timecode]);
+    }
+    // Only the MP4/QuickTime muxer knows -movflags; others reject it
+    if output.extension().is_some_and(|e| ["mp4", "mov", "m4v"].iter().any(|x| e.eq_ignore_ascii_case(x))) {
+        cmd.args(["-movflags", "+faststart"]);
+    }
+    cmd.arg("-y").arg(ffmpeg::path_arg(output));
+    cmd
+}
+
+/// Join the chunks of `segments` into the single video file `output`,
+/// adding what `mux` holds. With a known `frame_rate` each chunk's frames
+/// are counted with `ffprobe` to give it its exact duration in the list.
+#[allow(clippy::too_many_arguments)]
+pub fn join(
+    ffmpeg: &Path,
+    ffprobe: &Path,
+    segments_dir: &Path,
+    segments: &[Segment],
+    ext: &str,
+    frame_rate: f64,
+    mux: &Mux,
+    output: &Path,
+) -> Result<()> {
+    let _span = tracing::info_span!("combine", segments = segments.len()).entered();
+    info!("\n🔗 Joining {} segments...", segments.len());
+    let started = Instant::now();
+
+    let durations = if frame_rate > 0.0 {
+        let mut durations = Vec::with_capacity(segments.len());
+        for segment in segments {
+            durations.push(probe::count_frames(ffprobe, &segment.chunk(segments_dir, ext))? as f64 / frame_rate);
+        }
+        Some(durations)
+    } else {
+        None
+    };
+    let lead = mux.lead.as_ref().map(|(chunk, frames)| (chunk.as_path(), *frames as f64 / frame_rate));
+    let list_path = segments_dir.join(LIST_FILE);
+    fs::write(&list_path, list(segments_dir, segments, ext, durations.as_deref(), lead))
+        .map_err(|e| DeliveryError::io(format!("Failed to write {}", list_path.display()), e))?;
+
+    let mut cmd = join_command(ffmpeg, &list_path, mux, output);
+    process::contain(&mut cmd);
+    debug!("Command: {}", ffmpeg::display_command(&cmd));
+    let result = cmd.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffmpeg.to_path_buf()),
+        _ => DeliveryError::io("Failed to execute ffmpeg", e),
+    })?;
+    if !result.status.success() {
+        return Err(DeliveryError::JoinFailed(String::from_utf8_lossy(&result.stderr).trim().to_string()));
+    }
+    info!("✅ Joined {} segments in {:.2} seconds", segments.len(), started.elapsed().as_secs_f32());
+    Ok(())
+}
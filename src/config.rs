@@ -0,0 +1,458 @@
+use crate::burnin;
+use crate::icc::IccProfile;
+use crate::overlay::{self, OverlayLayer, OverlaySettings, Playback};
+use crate::rendition::Rendition;
+use crate::{units, DeliveryError, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Encode job definition loaded from a TOML file.
+///
+/// Every key is optional so a file can act as a partial template; anything
+/// left out falls back to the command line or the built-in defaults.
+/// Relative paths are resolved against the directory containing the file.
+///
+/// ```toml
+/// input = "masters/ep101.mov"
+/// overlay = "brand/logo.png"
+/// output_dir = "renders/ep101"
+/// threads = 8
+/// hwaccel = "nvenc"
+/// gpus = [0, 1]
+/// max_open_files = "8192"
+/// segments = 32
+/// adaptive_segments = true
+/// split_on = "scenes"
+/// presplit = true
+/// vfr_mode = "cfr:25"
+/// frame_format = "tiff"
+/// output_format = "frames"
+/// codec = "prores"
+/// container = "mov"
+/// timecode = "source"
+/// audio = "pcm"
+/// audio_layout = "stereo"
+/// audio_channels = [1, 2, 5, 6]
+/// audio_tracks = ["1+2:stereo", "3-8:5.1"]
+/// audio_labels = ["Mix", "M&E"]
+/// loudness_target = "-23LUFS"
+/// intermediate = "ffv1"
+/// extract_audio = "wav"
+/// shot_list = "csv"
+/// package = ["hls", "dash"]
+/// package_segment = 4.0
+/// target_vmaf = 93.0
+/// bitrate = "12M"
+/// maxrate = "16M"
+/// bufsize = "24M"
+/// preset = "slow"
+/// profile = "high"
+/// level = "4.1"
+/// two_pass = "global"
+/// bit_depth = 16
+/// png_compression = 1
+/// icc_profile = "profiles/review.icc"
+/// preserve_alpha = true
+/// name_template = "{basename}.{frame:04}.{ext}"
+/// start_frame = 1001
+/// job_id = "ep101"
+/// timestamped_output = true
+/// on_existing = "version"
+/// filter = "[0:v][1:v]overlay=W-w-48:48"
+/// lut = "luts/show.cube"
+/// tonemap = "sdr"
+/// tonemap_operator = "hable"
+/// color_primaries = "bt709"
+/// color_trc = "bt709"
+/// colorspace = "bt709"
+/// color_range = "limited"
+/// autocrop = true
+/// overlay_position = "top-right"
+/// overlay_margin = 48
+/// overlay_x = "W-w-48"
+/// overlay_y = "48"
+/// overlay_scale = "0.15w"
+/// overlay_opacity = 0.4
+/// overlay_key = "0x00B140:0.15:0.05"
+/// overlay_window = "00:00:05-00:00:15"
+/// overlay_loop = true
+/// overlay_schedule = "brand/sponsors.csv"
+/// burn_in = "{filename} / {recipient} / {date}"
+/// burn_in_position = "top"
+/// burn_in_margin = 32
+/// burn_in_font = "fonts/Inter-Regular.ttf"
+/// burn_in_size = 36
+/// burn_in_color = "white"
+/// burn_in_box = "black@0.5"
+/// recipient = "Acme Post"
+/// burn_timecode = true
+/// burn_timecode_position = "top"
+/// burn_frame_number = true
+/// burn_frame_number_position = "top-right"
+/// guides = ["action-safe", "title-safe"]
+/// slate = true
+/// slate_title = "The Long Way Home"
+/// slate_episode = "101"
+/// slate_version = "v3"
+/// slate_duration = "10s"
+/// two_pop = true
+/// screeners = "screeners.txt"
+/// screener_position = "bottom-right"
+/// screener_opacity = 0.3
+/// screener_size = 64
+/// screener_jobs = 2
+/// watermark_hook = "/opt/vendor/bin/embed --session \"$DELIVERY_RECIPIENT\""
+/// watermark_hook_mode = "pipe"
+/// ffmpeg_path = "/opt/ffmpeg-vmaf/bin/ffmpeg"
+/// temp_dir = "/mnt/scratch"
+///
+/// [[overlays]]
+/// path = "brand/rating.png"
+/// position = "bottom-left"
+/// margin = 48
+/// scale = "0.1h"
+/// window = "00:00:00-00:00:10"
+///
+/// [[overlays]]
+/// path = "brand/bug_%04d.png"
+/// position = "top-right"
+/// loop = true
+///
+/// [[renditions]]
+/// name = "1080p"
+/// height = 1080
+/// bitrate = "8M"
+///
+/// [[renditions]]
+/// name = "720p"
+/// height = 720
+/// bitrate = "4M"
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct JobConfig {
+    pub input: Option<PathBuf>,
+    pub overlay: Option<PathBuf>,
+    pub output_dir: Option<PathBuf>,
+    pub threads: Option<usize>,
+    /// `auto`, `videotoolbox`, `nvenc`, `qsv` or `none`, as with `--hwaccel`.
+    pub hwaccel: Option<String>,
+    /// NVIDIA GPUs the workers are spread across, as with `--gpus`.
+    pub gpus: Option<Vec<usize>>,
+    /// `keep` or a number of files such as `"8192"`, as with
+    /// `--max-open-files`.
+    pub max_open_files: Option<String>,
+    pub segments: Option<usize>,
+    /// Size segments by estimated encode cost instead of equal duration.
+    pub adaptive_segments: Option<bool>,
+    /// `time` or `scenes`, as with `--split-on`.
+    pub split_on: Option<String>,
+    /// Split the source into one file per segment before encoding.
+    pub presplit: Option<bool>,
+    /// `warn` or `cfr:<fps>`, as with `--vfr-mode`.
+    pub vfr_mode: Option<String>,
+    /// `png`, `jpeg`, `tiff`, `exr`, `dpx` or `webp`, as with `--frame-format`.
+    pub frame_format: Option<String>,
+    /// `frames` or `video`, as with `--output-format`.
+    pub output_format: Option<String>,
+    /// `h264`, `hevc`, `prores`, `av1`, `dnxhr` or `j2k`, as with `--codec`.
+    pub codec: Option<String>,
+    /// `mp4`, `mov` or `mxf`, as with `--container`.
+    pub container: Option<String>,
+    /// `source` or `HH:MM:SS:FF`, as with `--timecode`.
+    pub timecode: Option<String>,
+    /// `none`, `copy`, `aac` or `pcm`, as with `--audio`.
+    pub audio: Option<String>,
+    /// `mono` or `stereo`, as with `--audio-layout`.
+    pub audio_layout: Option<String>,
+    /// Source audio channels of MXF output, as with `--audio-channels`.
+    pub audio_channels: Option<Vec<u32>>,
+    /// Channel to track mapping of MXF output such as `["1+2:stereo",
+    /// "3-8:5.1"]`, as with `--audio-tracks`.
+    pub audio_tracks: Option<Vec<String>>,
+    /// Audio track labels of MXF output, as with `--audio-labels`.
+    pub audio_labels: Option<Vec<String>>,
+    /// Integrated loudness such as `-23LUFS`, as with `--loudness-target`.
+    pub loudness_target: Option<String>,
+    /// A named delivery specification such as `dnxhr-hqx`, as with
+    /// `--delivery-preset`.
+    pub delivery_preset: Option<String>,
+    /// `ffv1` or `utvideo`, as with `--intermediate`.
+    pub intermediate: Option<String>,
+    /// `wav` or `aac`, as with `--extract-audio`.
+    pub extract_audio: Option<String>,
+    /// `csv` or `json`, as with `--shot-list`.
+    pub shot_list: Option<String>,
+    /// `hls`, `dash`, `imf` and/or `dcp`, as with `--package`.
+    pub package: Option<Vec<String>>,
+    /// Media segment length in seconds, as with `--package-segment`.
+    pub package_segment: Option<f64>,
+    /// Constant quality 0-51, as with `--crf`.
+    pub crf: Option<u8>,
+    /// VMAF score the CRF is searched for, as with `--target-vmaf`.
+    pub target_vmaf: Option<f64>,
+    /// Average bitrate such as `12M`, as with `--bitrate`.
+    pub bitrate: Option<String>,
+    /// Peak bitrate such as `16M`, as with `--maxrate`.
+    pub maxrate: Option<String>,
+    /// Rate buffer size in bits such as `24M`, as with `--bufsize`.
+    pub bufsize: Option<String>,
+    /// Encoder preset, as with `--preset`.
+    pub preset: Option<String>,
+    /// Codec profile, as with `--profile`.
+    pub profile: Option<String>,
+    /// Codec level, as with `--level`.
+    pub level: Option<String>,
+    /// `segment` or `global`, as with `--two-pass`.
+    pub two_pass: Option<String>,
+    /// Bits per channel, as with `--bit-depth`.
+    pub bit_depth: Option<u8>,
+    /// PNG compression level 0-9, as with `--png-compression`.
+    pub png_compression: Option<u8>,
+    /// JPEG quality 1-100, as with `--jpeg-quality`.
+    pub jpeg_quality: Option<u8>,
+    /// `srgb` or an ICC profile file, as with `--icc-profile`.
+    pub icc_profile: Option<String>,
+    /// Keep the source's alpha channel, as with `--preserve-alpha`.
+    pub preserve_alpha: Option<bool>,
+    /// Flatten the source's alpha against this color, as with `--flatten-on`.
+    pub flatten_on: Option<String>,
+    /// Output frame names, as with `--name-template`.
+    pub name_template: Option<String>,
+    pub start_frame: Option<u64>,
+    pub frame_padding: Option<usize>,
+    /// `{job}` in the name template.
+    pub job_id: Option<String>,
+    /// Write each run into its own timestamped subdirectory, as with `--timestamped`.
+    pub timestamped_output: Option<bool>,
+    /// `fail`, `skip`, `overwrite` or `version`, as with `--on-existing`.
+    pub on_existing: Option<String>,
+    pub filter: Option<String>,
+    /// 3D LUT applied to the video, as with `--lut`.
+    pub lut: Option<PathBuf>,
+    /// `sdr` to tone map HDR video, as with `--tonemap`.
+    pub tonemap: Option<String>,
+    /// `hable` or `bt2390`, as with `--tonemap-operator`.
+    pub tonemap_operator: Option<String>,
+    /// Primaries the video is converted to, as with `--color-primaries`.
+    pub color_primaries: Option<String>,
+    /// Transfer the video is converted to, as with `--color-trc`.
+    pub color_trc: Option<String>,
+    /// YUV matrix the video is converted to, as with `--colorspace`.
+    pub colorspace: Option<String>,
+    /// `full` or `limited`, as with `--color-range`.
+    pub color_range: Option<String>,
+    /// Crop off the source's black bars, as with `--autocrop`.
+    pub autocrop: Option<bool>,
+    /// Anchor of the overlay such as `top-right`, as with `--overlay-position`.
+    pub overlay_position: Option<String>,
+    /// Overlay margin in pixels, as with `--overlay-margin`.
+    pub overlay_margin: Option<u32>,
+    /// overlay `x` expression, as with `--overlay-x`.
+    pub overlay_x: Option<String>,
+    /// overlay `y` expression, as with `--overlay-y`.
+    pub overlay_y: Option<String>,
+    /// Overlay size such as `0.15w`, as with `--overlay-scale`.
+    pub overlay_scale: Option<String>,
+    /// Overlay opacity 0-1, as with `--overlay-opacity`.
+    pub overlay_opacity: Option<f64>,
+    /// Green screen keyed out of the overlay, as with `--overlay-key`.
+    pub overlay_key: Option<String>,
+    /// Stretch the overlay is shown in, as with `--overlay-window`.
+    pub overlay_window: Option<String>,
+    /// Loop an animated overlay, as with `--overlay-loop`.
+    pub overlay_loop: Option<bool>,
+    /// Schedule of overlays by time, as with `--overlay-schedule`.
+    pub overlay_schedule: Option<PathBuf>,
+    /// Text burned into every frame, as with `--burn-in`.
+    pub burn_in: Option<String>,
+    /// Anchor of the burn-in, as with `--burn-in-position`.
+    pub burn_in_position: Option<String>,
+    /// Burn-in margin in pixels, as with `--burn-in-margin`.
+    pub burn_in_margin: Option<u32>,
+    /// Font file or family of the burn-in, as with `--burn-in-font`.
+    pub burn_in_font: Option<String>,
+    /// Burn-in text height in pixels, as with `--burn-in-size`.
+    pub burn_in_size: Option<u32>,
+    /// Burn-in text colour, as with `--burn-in-color`.
+    pub burn_in_color: Option<String>,
+    /// Colour of the box behind the burn-in, as with `--burn-in-box`.
+    pub burn_in_box: Option<String>,
+    /// `{recipient}` in the burn-in text, as with `--recipient`.
+    pub recipient: Option<String>,
+    /// Burn in the running timecode, as with `--burn-timecode`.
+    pub burn_timecode: Option<bool>,
+    /// Anchor of the timecode, as with `--burn-timecode-position`.
+    pub burn_timecode_position: Option<String>,
+    /// Burn in the output frame number, as with `--burn-frame-number`.
+    pub burn_frame_number: Option<bool>,
+    /// Anchor of the frame number, as with `--burn-frame-number-position`.
+    pub burn_frame_number_position: Option<String>,
+    /// Broadcast guides drawn for review, as with `--guides`.
+    pub guides: Option<Vec<String>>,
+    /// Put a slate before video output, as with `--slate`.
+    pub slate: Option<bool>,
+    /// Title on the slate, as with `--slate-title`.
+    pub slate_title: Option<String>,
+    /// Episode on the slate, as with `--slate-episode`.
+    pub slate_episode: Option<String>,
+    /// Version on the slate, as with `--slate-version`.
+    pub slate_version: Option<String>,
+    /// Date on the slate, as with `--slate-date`.
+    pub slate_date: Option<String>,
+    /// How long the slate is shown, e.g. `10s`, as with `--slate-duration`.
+    pub slate_duration: Option<String>,
+    /// Follow the slate with a 2-pop, as with `--two-pop`.
+    pub two_pop: Option<bool>,
+    /// List of screener recipients, as with `--screeners`.
+    pub screeners: Option<PathBuf>,
+    /// Anchor of the recipient's name, as with `--screener-position`.
+    pub screener_position: Option<String>,
+    /// Opacity 0-1 of the recipient's name, as with `--screener-opacity`.
+    pub screener_opacity: Option<f64>,
+    /// Text height of the recipient's name, as with `--screener-size`.
+    pub screener_size: Option<u32>,
+    /// Screeners encoded at once, as with `--screener-jobs`.
+    pub screener_jobs: Option<usize>,
+    /// Command the composited frames pass through before encoding, as with
+    /// `--watermark-hook`.
+    pub watermark_hook: Option<String>,
+    /// How the hook gets the frames, `pipe` or `files`, as with
+    /// `--watermark-hook-mode`.
+    pub watermark_hook_mode: Option<String>,
+    /// Custom ffmpeg build; a bare name is looked up on `PATH`.
+    pub ffmpeg_path: Option<PathBuf>,
+    /// Custom ffprobe build; a bare name is looked up on `PATH`.
+    pub ffprobe_path: Option<PathBuf>,
+    /// Directory that holds each job's temporary segments (e.g. a scratch disk).
+    pub temp_dir: Option<PathBuf>,
+    /// Rendition ladder of video output, as with `--rendition`.
+    pub renditions: Option<Vec<RenditionConfig>>,
+    /// More overlays composited over `overlay`, in the order they're listed.
+    pub overlays: Option<Vec<OverlayLayerConfig>>,
+}
+
+/// One `[[renditions]]` entry of a job definition.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RenditionConfig {
+    pub name: String,
+    pub height: u32,
+    /// Peak bitrate such as `8M` or `800k`.
+    pub bitrate: Option<String>,
+}
+
+impl RenditionConfig {
+    pub fn to_rendition(&self) -> Result<Rendition> {
+        let bitrate = self.bitrate.as_deref().map(units::parse_bitrate).transpose().map_err(DeliveryError::Config)?;
+        Ok(Rendition { name: self.name.clone(), height: self.height, bitrate })
+    }
+}
+
+/// One `[[overlays]]` entry of a job definition, set up like the overlay
+/// with the `overlay_*` keys.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct OverlayLayerConfig {
+    pub path: PathBuf,
+    pub position: Option<String>,
+    pub margin: Option<u32>,
+    pub x: Option<String>,
+    pub y: Option<String>,
+    pub scale: Option<String>,
+    pub opacity: Option<f64>,
+    pub window: Option<String>,
+    /// Green screen to key out, `COLOR[:SIMILARITY[:BLEND]]`.
+    pub key: Option<String>,
+    /// Loop the layer if it is animated.
+    #[serde(rename = "loop")]
+    pub looped: Option<bool>,
+}
+
+impl OverlayLayerConfig {
+    pub fn to_layer(&self) -> Result<OverlayLayer> {
+        let opacity = self.opacity.map(|o| overlay::parse_opacity(&o.to_string()));
+        let settings = OverlaySettings {
+            position: parse(&self.position)?.unwrap_or_default(),
+            margin: self.margin.unwrap_or(0),
+            x: self.x.clone(),
+            y: self.y.clone(),
+            scale: parse(&self.scale)?,
+            opacity: opacity.transpose().map_err(DeliveryError::Config)?,
+            window: parse(&self.window)?,
+            key: parse(&self.key)?,
+        };
+        let playback = Playback { looped: self.looped.unwrap_or(false), ..Playback::default() };
+        Ok(OverlayLayer { path: self.path.clone(), settings, playback })
+    }
+}
+
+// `text` parsed, if set.
+fn parse<T: FromStr<Err = String>>(text: &Option<String>) -> Result<Option<T>> {
+    text.as_deref().map(str::parse).transpose().map_err(DeliveryError::Config)
+}
+
+impl JobConfig {
+    /// Read the job config at `path`, with the relative paths in it made
+    /// absolute against its directory.
+    pub fn load(path: &Path) -> Result<JobConfig> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| DeliveryError::io(format!("Failed to read config {}", path.display()), e))?;
+        let config: JobConfig = toml::from_str(&text)
+            .map_err(|e| DeliveryError::Config(format!("Failed to parse config {}: {}", path.display(), e)))?;
+
+        // Absolute, as the working directory moves to the project root after
+        // the config is loaded
+        let absolute = std::path::absolute(path)
+            .map_err(|e| DeliveryError::io(format!("Failed to resolve config {}", path.display()), e))?;
+        Ok(config.relative_to(absolute.parent().unwrap_or(Path::new(""))))
+    }
+
+    /// The config with the relative paths in it resolved against `base`, the
+    /// directory of the file it was read from.
+    pub fn relative_to(self, base: &Path) -> JobConfig {
+        let mut config = self;
+        for p in [
+            &mut config.input,
+            &mut config.overlay,
+            &mut config.overlay_schedule,
+            &mut config.lut,
+            &mut config.screeners,
+            &mut config.output_dir,
+            &mut config.temp_dir,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if p.is_relative() {
+                *p = base.join(&*p);
+            }
+        }
+        for layer in config.overlays.iter_mut().flatten() {
+            if layer.path.is_relative() {
+                layer.path = base.join(&layer.path);
+            }
+        }
+        for p in [&mut config.ffmpeg_path, &mut config.ffprobe_path]
+            .into_iter()
+            .flatten()
+        {
+            if p.is_relative() && p.components().count() > 1 {
+                *p = base.join(&*p);
+            }
+        }
+        if let Some(font) = config.burn_in_font.as_mut().filter(|f| burnin::is_font_file(f)) {
+            if Path::new(font).is_relative() {
+                *font = base.join(&*font).to_string_lossy().into_owned();
+            }
+        }
+        if let Some(profile) = &mut config.icc_profile {
+            if let Ok(IccProfile::File(path)) = profile.parse() {
+                *profile = base.join(path).to_string_lossy().into_owned();
+            }
+        }
+        config
+    }
+}
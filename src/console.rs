@@ -0,0 +1,232 @@
+use crate::{events, logfile};
+use indicatif::MultiProgress;
+use std::env;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Mutex;
+
+/// How much console output the user asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Errors and the final summary only.
+    Quiet,
+    /// Pipeline milestones and warnings.
+    Normal,
+    /// Adds full ffmpeg command lines and every ffmpeg stderr line.
+    Verbose,
+    /// Adds raw ffmpeg progress reports.
+    Trace,
+}
+
+/// Importance of a single console line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        2 => Verbosity::Verbose,
+        _ => Verbosity::Trace,
+    }
+}
+
+/// Whether lines at `level` are printed with the current verbosity.
+pub fn enabled(level: Level) -> bool {
+    let required = match level {
+        Level::Error => Verbosity::Quiet,
+        Level::Warn | Level::Info => Verbosity::Normal,
+        Level::Debug => Verbosity::Verbose,
+        Level::Trace => Verbosity::Trace,
+    };
+    verbosity() >= required
+}
+
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// ASCII-friendly output: no emoji, no progress bars, every line tagged with
+/// its level (`[INFO]`, `[ERROR]`, ...).
+pub fn set_plain(plain: bool) {
+    PLAIN.store(plain, Ordering::Relaxed);
+}
+
+pub fn plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+/// Whether the environment asks for plain output: `NO_COLOR` is set, the
+/// locale is not UTF-8, or we are on a legacy Windows console.
+pub fn plain_by_environment() -> bool {
+    if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        return true;
+    }
+    if cfg!(windows) {
+        // Windows Terminal renders emoji, the classic console host does not.
+        return env::var_os("WT_SESSION").is_none();
+    }
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|key| env::var(key).ok())
+        .find(|value| !value.is_empty());
+    match locale {
+        Some(locale) => {
+            let locale = locale.to_ascii_lowercase();
+            !(locale.contains("utf-8") || locale.contains("utf8"))
+        }
+        None => false,
+    }
+}
+
+impl Level {
+    fn tag(self) -> &'static str {
+        match self {
+            Level::Error => "[ERROR]",
+            Level::Warn => "[WARN]",
+            Level::Info => "[INFO]",
+            Level::Debug => "[DEBUG]",
+            Level::Trace => "[TRACE]",
+        }
+    }
+}
+
+// Emoji, dingbats and the other pictographs used as line markers.
+fn is_symbol(c: char) -> bool {
+    matches!(c as u32,
+        0x2139 | 0x2190..=0x2BFF | 0xFE0F | 0x200D | 0x1F000..=0x1FAFF)
+}
+
+fn strip_symbols(msg: &str) -> String {
+    msg.chars().filter(|c| !is_symbol(*c)).collect()
+}
+
+/// Rewrite a message as level-tagged lines with pictographs removed.
+fn plain_lines(level: Level, msg: &str) -> Vec<String> {
+    msg.lines()
+        .map(strip_symbols)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| format!("{} {}", level.tag(), line.trim()))
+        .collect()
+}
+
+/// Progress bars currently drawn on the terminal, if any. Lines printed while
+/// bars are visible must go through them or the bars get torn apart.
+static BARS: Mutex<Option<MultiProgress>> = Mutex::new(None);
+
+/// Route console output through `bars` until [`detach`] is called.
+pub fn attach(bars: MultiProgress) {
+    *BARS.lock().unwrap() = Some(bars);
+}
+
+pub fn detach() {
+    *BARS.lock().unwrap() = None;
+}
+
+/// Print a message to stdout, above any active progress bars. When JSON
+/// events own stdout the message goes to stderr instead.
+pub fn write(level: Level, msg: String) {
+    let msg = if plain() { plain_lines(level, &msg).join("\n") } else { msg };
+    if msg.is_empty() {
+        return;
+    }
+    // Write errors (e.g. a closed pipe) are ignored rather than panicking
+    if events::enabled() {
+        let _ = writeln!(io::stderr().lock(), "{}", msg);
+        return;
+    }
+    match BARS.lock().unwrap().as_ref() {
+        Some(bars) if !bars.is_hidden() => {
+            let _ = bars.println(msg);
+        }
+        _ => {
+            let _ = writeln!(io::stdout().lock(), "{}", msg);
+        }
+    }
+}
+
+// Whether an installed tracing subscriber is interested in `level`.
+fn tracing_wants(level: Level) -> bool {
+    match level {
+        Level::Error => tracing::enabled!(tracing::Level::ERROR),
+        Level::Warn => tracing::enabled!(tracing::Level::WARN),
+        Level::Info => tracing::enabled!(tracing::Level::INFO),
+        Level::Debug => tracing::enabled!(tracing::Level::DEBUG),
+        Level::Trace => tracing::enabled!(tracing::Level::TRACE),
+    }
+}
+
+// Forward a message as a tracing event inside whatever span is current.
+fn trace_event(level: Level, msg: &str) {
+    let msg = strip_symbols(msg);
+    let msg = msg.trim();
+    match level {
+        Level::Error => tracing::error!("{}", msg),
+        Level::Warn => tracing::warn!("{}", msg),
+        Level::Info => tracing::info!("{}", msg),
+        Level::Debug => tracing::debug!("{}", msg),
+        Level::Trace => tracing::trace!("{}", msg),
+    }
+}
+
+/// Whether a message at `level` goes anywhere: the console, the log file or a
+/// tracing subscriber. Trace output is never written to the log file.
+pub fn wanted(level: Level) -> bool {
+    enabled(level) || (level != Level::Trace && logfile::active()) || tracing_wants(level)
+}
+
+/// Print a message regardless of verbosity.
+pub fn line(msg: String) {
+    trace_event(Level::Info, &msg);
+    logfile::write(Level::Info, &strip_symbols(&msg));
+    write(Level::Info, msg);
+}
+
+/// Send a message to tracing, the log file and, if `level` is enabled, the
+/// console.
+pub fn log(level: Level, msg: String) {
+    trace_event(level, &msg);
+    if level != Level::Trace {
+        logfile::write(level, &strip_symbols(&msg));
+    }
+    if enabled(level) {
+        write(level, msg);
+    }
+}
+
+// `println!` replacements that respect verbosity, feed the log file and
+// cooperate with progress bars. The format arguments are only evaluated when
+// the message goes somewhere.
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::console::wanted($level) {
+            $crate::console::log($level, format!($($arg)*))
+        }
+    };
+}
+macro_rules! error {
+    ($($arg:tt)*) => { $crate::console::log_at!($crate::console::Level::Error, $($arg)*) };
+}
+macro_rules! warning {
+    ($($arg:tt)*) => { $crate::console::log_at!($crate::console::Level::Warn, $($arg)*) };
+}
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::console::log_at!($crate::console::Level::Info, $($arg)*) };
+}
+macro_rules! debug {
+    ($($arg:tt)*) => { $crate::console::log_at!($crate::console::Level::Debug, $($arg)*) };
+}
+macro_rules! trace {
+    ($($arg:tt)*) => { $crate::console::log_at!($crate::console::Level::Trace, $($arg)*) };
+}
+pub(crate) use {debug, error, info, log_at, trace, warning};
@@ -0,0 +1,210 @@
+use crate::console::{debug, info, warning};
+use crate::metrics::{self, QualityMetric};
+use crate::plan::JobPlan;
+use crate::segment::Segment;
+use crate::{ffmpeg, interrupt, process, worker, DeliveryError, EncodeJob, Result};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+/// Directory in the segments directory the probe clips are encoded to, one
+/// subdirectory per CRF.
+pub const SEARCH_DIR: &str = "crf_search";
+
+/// Number of probe clips, spread evenly over the source.
+const PROBE_CLIPS: usize = 3;
+
+/// Length of a probe clip in seconds.
+const PROBE_DURATION: f64 = 4.0;
+
+/// Number of CRFs the probe clips are encoded at.
+const CANDIDATES: usize = 4;
+
+/// CRF step between the candidates; about the change that halves (or
+/// doubles) the bitrate of x264 and x265.
+const CRF_STEP: u8 = 6;
+
+/// Parse a VMAF target such as `93`.
+pub fn parse_target(text: &str) -> std::result::Result<f64, String> {
+    match text.trim().parse::<f64>() {
+        Ok(target) if target > 0.0 && target <= 100.0 => Ok(target),
+        _ => Err(format!("invalid VMAF target '{}', expected a score above 0 and up to 100, e.g. 93", text)),
+    }
+}
+
+/// The CRFs the probe clips are encoded at: the codec's default and a few
+/// steps worse, as web deliveries rarely need better than the default.
+pub fn candidates(job: &EncodeJob) -> Vec<u8> {
+    let (first, max) = (job.codec.default_crf(), job.codec.max_crf());
+    let mut crfs: Vec<u8> = (0..CANDIDATES).map(|i| first.saturating_add(i as u8 * CRF_STEP).min(max)).collect();
+    crfs.dedup();
+    crfs
+}
+
+/// The probe clips of a `duration` second source: [`PROBE_CLIPS`] stretches
+/// of [`PROBE_DURATION`] seconds centred on evenly spread points, or the
+/// whole source if it is too short for them.
+pub fn clips(duration: f64) -> Vec<Segment> {
+    if duration <= PROBE_DURATION * PROBE_CLIPS as f64 {
+        return vec![Segment { id: 0, start: 0.0, duration, frames: None, source: None }];
+    }
+    (0..PROBE_CLIPS)
+        .map(|id| {
+            let centre = duration * (id + 1) as f64 / (PROBE_CLIPS + 1) as f64;
+            Segment { id, start: centre - PROBE_DURATION / 2.0, duration: PROBE_DURATION, frames: None, source: None }
+        })
+        .collect()
+}
+
+/// The highest CRF expected to score `target` going by `points`, each a
+/// CRF and the VMAF it scored, interpolating linearly between the two
+/// candidates either side of the target and rounding towards quality.
+/// Returns `None` when even the best candidate scores below the target.
+pub fn fit(points: &[(u8, f64)], target: f64) -> Option<u8> {
+    let mut points = points.to_vec();
+    points.sort_by_key(|&(crf, _)| crf);
+    let passing = points.iter().rposition(|&(_, score)| score >= target)?;
+    let (crf, score) = points[passing];
+    let Some(&(next_crf, next_score)) = points.get(passing + 1) else {
+        return Some(crf);
+    };
+    if score <= next_score {
+        return Some(crf);
+    }
+    let step = (score - target) / (score - next_score) * f64::from(next_crf - crf);
+    Some(crf + step.floor() as u8)
+}
+
+// Job encoding the probe clips at `crf`, into their own directory. The
+// watermark hook is left out: it needn't see the clips to score them.
+fn probe_job(job: &EncodeJob, crf: u8) -> EncodeJob {
+    let mut probe = job.clone();
+    probe.segments_dir = job.segments_dir.join(SEARCH_DIR).join(format!("crf_{}", crf));
+    probe.video_settings.crf = Some(crf);
+    probe.watermark_hook = None;
+    probe
+}
+
+/// The ffmpeg invocations encoding `clip` at `crf` and scoring the result
+/// against the source.
+pub fn probe_commands(job: &EncodeJob, plan: &JobPlan, clip: &Segment, crf: u8) -> (Command, Command) {
+    let probe = probe_job(job, crf);
+    let encode = worker::encode_command(&probe, clip, None, &[None], None);
+    let score = metrics::clip_command(
+        &job.ffmpeg,
+        &chunk(&probe, clip),
+        &job.input,
+        job.crop.unwrap_or_default(),
+        clip,
+        (plan.width, plan.height),
+        &[QualityMetric::Vmaf],
+    );
+    (encode, score)
+}
+
+// Chunk `clip` is encoded to by the probe job `probe`.
+fn chunk(probe: &EncodeJob, clip: &Segment) -> PathBuf {
+    clip.chunk(&probe.segments_dir, &probe.chunk_extension().unwrap_or_default())
+}
+
+// Encode `clip` at `crf` and return its VMAF.
+fn probe(job: &EncodeJob, plan: &JobPlan, clip: &Segment, crf: u8) -> Result<f64> {
+    let dir = clip.dir(&probe_job(job, crf).segments_dir);
+    fs::create_dir_all(&dir).map_err(|e| DeliveryError::io(format!("Failed to create {}", dir.display()), e))?;
+    let (mut encode, score) = probe_commands(job, plan, clip, crf);
+    process::contain(&mut encode);
+    if job.background {
+        process::lower_priority(&mut encode);
+    }
+    debug!("Command: {}", ffmpeg::display_command(&encode));
+    let output = encode.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(job.ffmpeg.clone()),
+        _ => DeliveryError::io("Failed to execute ffmpeg", e),
+    })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(DeliveryError::CrfSearchFailed(format!("clip {} at CRF {}: {}", clip.id, crf, stderr.trim())));
+    }
+    let log = metrics::run(&job.ffmpeg, score).map_err(|e| match e {
+        DeliveryError::QcFailed(log) => DeliveryError::CrfSearchFailed(format!("scoring clip {}: {}", clip.id, log)),
+        e => e,
+    })?;
+    metrics::parse_scores(&log, &[QualityMetric::Vmaf]).vmaf.ok_or_else(|| {
+        DeliveryError::CrfSearchFailed(format!("libvmaf printed no score for clip {} at CRF {}", clip.id, crf))
+    })
+}
+
+/// Encode probe clips of the source at a few CRFs, `threads` at a time,
+/// and pick the highest CRF whose clips still average `target` VMAF. The
+/// clips are composited like the delivery, so the overlay counts against
+/// the score just as it does with `--quality-metrics`.
+pub fn search(job: &EncodeJob, plan: &JobPlan, target: f64) -> Result<u8> {
+    let clips = clips(plan.segments.iter().map(|s| s.duration).sum());
+    let crfs = candidates(job);
+    let _span = tracing::info_span!("crf_search", clips = clips.len(), candidates = crfs.len()).entered();
+    info!(
+        "\n🎯 Searching the CRF for VMAF {}: {} probe clip(s) at CRF {}...",
+        target,
+        clips.len(),
+        crfs.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(", ")
+    );
+    let started = Instant::now();
+
+    let tasks: Vec<(u8, &Segment)> = crfs.iter().flat_map(|&crf| clips.iter().map(move |clip| (crf, clip))).collect();
+    let total = tasks.len();
+    let queue: Mutex<VecDeque<(u8, &Segment)>> = Mutex::new(tasks.into_iter().collect());
+    let scores: Mutex<Vec<(u8, f64, f64)>> = Mutex::new(Vec::new());
+    let failures: Mutex<Vec<DeliveryError>> = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..job.threads.clamp(1, total) {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap().pop_front();
+                let Some((crf, clip)) = next else { break };
+                if interrupt::requested() {
+                    break;
+                }
+                match probe(job, plan, clip, crf) {
+                    Ok(vmaf) => scores.lock().unwrap().push((crf, clip.duration, vmaf)),
+                    Err(e) => failures.lock().unwrap().push(e),
+                }
+            });
+        }
+    });
+
+    let dir = job.segments_dir.join(SEARCH_DIR);
+    if let Err(e) = fs::remove_dir_all(&dir) {
+        debug!("Couldn't remove {}: {}", dir.display(), e);
+    }
+    interrupt::check()?;
+    if let Some(e) = failures.into_inner().unwrap().into_iter().next() {
+        return Err(e);
+    }
+
+    let scores = scores.into_inner().unwrap();
+    let points: Vec<(u8, f64)> = crfs
+        .iter()
+        .map(|&crf| {
+            let clips = scores.iter().filter(|&&(c, ..)| c == crf);
+            let (weighted, duration) =
+                clips.fold((0.0, 0.0), |(sum, total), &(_, d, vmaf)| (sum + vmaf * d, total + d));
+            (crf, if duration > 0.0 { weighted / duration } else { 0.0 })
+        })
+        .collect();
+    for (crf, vmaf) in &points {
+        info!("   CRF {}: VMAF {:.2}", crf, vmaf);
+    }
+    let crf = match fit(&points, target) {
+        Some(crf) => crf,
+        None => {
+            let best = crfs[0];
+            warning!("⚠️ Even CRF {} scores below VMAF {}; encoding at CRF {}", best, target, best);
+            best
+        }
+    };
+    info!("✅ Encoding at CRF {} (searched in {:.2} seconds)", crf, started.elapsed().as_secs_f32());
+    Ok(crf)
+}
@@ -0,0 +1,176 @@
+use crate::console::{debug, info, warning};
+use crate::{ffmpeg, process, DeliveryError, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+use std::time::Instant;
+
+/// Points across the video cropdetect looks at.
+const SAMPLES: usize = 12;
+
+/// Frames cropdetect looks at from each point; their picture areas are
+/// merged, so one dark frame doesn't decide.
+const SAMPLE_FRAMES: u32 = 5;
+
+/// Luma, 0 to 255, up to which a row or column counts as black.
+const BLACK_LIMIT: u32 = 24;
+
+/// Black bars cropped off the edges of the video, in pixels.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Crop {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+impl Crop {
+    /// Whether there is nothing to crop.
+    pub fn is_empty(&self) -> bool {
+        *self == Crop::default()
+    }
+
+    /// Size of a `width` x `height` picture once cropped.
+    pub fn apply(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        (width.saturating_sub(self.left + self.right), height.saturating_sub(self.top + self.bottom))
+    }
+
+    /// The crop filter cutting the bars off.
+    pub fn filter(&self) -> String {
+        format!("crop=iw-{}:ih-{}:{}:{}", self.left + self.right, self.top + self.bottom, self.left, self.top)
+    }
+}
+
+impl fmt::Display for Crop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "top {}, bottom {}, left {}, right {}", self.top, self.bottom, self.left, self.right)
+    }
+}
+
+/// The ffmpeg invocation running cropdetect over a few frames of the video
+/// of `input` from `at` seconds, logging the picture area it finds.
+pub fn detect_command(ffmpeg: &Path, input: &Path, at: f64) -> Command {
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-hide_banner", "-nostats", "-nostdin", "-ss", &format!("{:.3}", at), "-i"]).arg(ffmpeg::path_arg(input));
+    cmd.args(["-map", "0:v:0", "-frames:v", &SAMPLE_FRAMES.to_string(), "-vf"]);
+    cmd.arg(format!("cropdetect=limit={}:round=2:reset=0", BLACK_LIMIT));
+    cmd.args(["-an", "-f", "null", "-"]);
+    cmd
+}
+
+/// The picture area (width, height, x, y) cropdetect settled on in `log`,
+/// or `None` if the frames were black.
+pub fn parse_area(log: &str) -> Option<(u32, u32, u32, u32)> {
+    let cropdetect = log.lines().rev().filter(|line| line.contains("cropdetect"));
+    let (_, area) = cropdetect.filter_map(|line| line.split_once(" crop=")).next()?;
+    let values: Vec<i64> = area.trim().split(':').map(|v| v.parse().ok()).collect::<Option<_>>()?;
+    match values[..] {
+        [width, height, x, y] if width > 0 && height > 0 && x >= 0 && y >= 0 => {
+            Some((width as u32, height as u32, x as u32, y as u32))
+        }
+        _ => None,
+    }
+}
+
+// The bars of a `width` x `height` picture outside every one of the picture
+// `areas`, rounded down to even numbers of pixels.
+fn bars(areas: &[(u32, u32, u32, u32)], (width, height): (u32, u32)) -> Crop {
+    let even = |bar: u32| bar - bar % 2;
+    Crop {
+        top: even(areas.iter().map(|a| a.3).min().unwrap_or_default()),
+        bottom: even(height.saturating_sub(areas.iter().map(|a| a.3 + a.1).max().unwrap_or(height))),
+        left: even(areas.iter().map(|a| a.2).min().unwrap_or_default()),
+        right: even(width.saturating_sub(areas.iter().map(|a| a.2 + a.0).max().unwrap_or(width))),
+    }
+}
+
+/// Find the black bars of the `width` x `height`, `duration` seconds long
+/// video of `input` by sampling it. Only bars every sample has are cropped,
+/// so dark shots don't lose picture, and they are whole chroma samples.
+pub fn detect(ffmpeg: &Path, input: &Path, (width, height): (u32, u32), duration: f64) -> Result<Crop> {
+    let _span = tracing::info_span!("autocrop", input = %input.display()).entered();
+    info!("\n✂️ Detecting black bars...");
+    let started = Instant::now();
+
+    let mut areas = Vec::new();
+    for sample in 0..SAMPLES {
+        let at = duration * (sample as f64 + 0.5) / SAMPLES as f64;
+        let mut cmd = detect_command(ffmpeg, input, at);
+        process::contain(&mut cmd);
+        debug!("Command: {}", ffmpeg::display_command(&cmd));
+        let output = cmd.output().map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffmpeg.to_path_buf()),
+            _ => DeliveryError::io("Failed to execute ffmpeg", e),
+        })?;
+        let log = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() {
+            return Err(DeliveryError::ProbeFailed(format!("crop detection failed: {}", log.trim())));
+        }
+        match parse_area(&log) {
+            Some((w, h, x, y)) => {
+                debug!("ℹ️ Picture at {:.2}s: {}x{} at {},{}", at, w, h, x, y);
+                areas.push((w, h, x, y));
+            }
+            None => debug!("ℹ️ No picture at {:.2}s", at),
+        }
+    }
+    if areas.is_empty() {
+        warning!("⚠️ Every sampled frame is black; not cropping");
+        return Ok(Crop::default());
+    }
+
+    let crop = bars(&areas, (width, height));
+    let elapsed = started.elapsed().as_secs_f32();
+    if crop.is_empty() {
+        info!("✅ No black bars in {} samples ({:.2} seconds)", areas.len(), elapsed);
+    } else {
+        let (cropped_width, cropped_height) = crop.apply((width, height));
+        info!(
+            "✅ Cropping {}x{} to {}x{} ({}) from {} samples ({:.2} seconds)",
+            width, height, cropped_width, cropped_height, crop, areas.len(), elapsed
+        );
+    }
+    Ok(crop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_area_takes_the_last_cropdetect_line() {
+        let log = "\
+[Parsed_cropdetect_0 @ 0x6000] x1:0 x2:1919 y1:140 y2:939 w:1920 h:800 x:0 y:140 pts:0 t:0.000 crop=1920:800:0:140
+frame=    1 fps=0.0 q=-0.0 size=N/A time=00:00:00.04 bitrate=N/A speed=N/A
+[Parsed_cropdetect_0 @ 0x6000] x1:0 x2:1919 y1:138 y2:941 w:1920 h:804 x:0 y:138 pts:1 t:0.040 crop=1920:804:0:138
+[out#0/null @ 0x7000] video:1kB audio:0kB subtitle:0kB other streams:0kB global headers:0kB
+";
+        assert_eq!(parse_area(log), Some((1920, 804, 0, 138)));
+    }
+
+    #[test]
+    fn parse_area_rejects_black_frames_and_junk() {
+        let black = "[Parsed_cropdetect_0 @ 0x6000] x1:1919 x2:0 y1:1079 y2:0 w:-1904 h:-1064 x:1912 y:1072 \
+                     pts:0 t:0.000 crop=-1904:-1064:1912:1072";
+        assert_eq!(parse_area(black), None);
+        assert_eq!(parse_area("[Parsed_cropdetect_0 @ 0x6000] crop=1920:800:0"), None);
+        assert_eq!(parse_area("[Parsed_cropdetect_0 @ 0x6000] crop=1920:800:0:abc"), None);
+        assert_eq!(parse_area("frame=    5 fps=0.0 q=-0.0 Lsize=N/A"), None);
+    }
+
+    #[test]
+    fn bars_are_the_union_of_the_samples_rounded_down_to_even() {
+        // Letterboxed, with one sample's picture reaching further down
+        let crop = bars(&[(1920, 800, 0, 139), (1920, 804, 0, 141)], (1920, 1080));
+        assert_eq!(crop, Crop { top: 138, bottom: 134, left: 0, right: 0 });
+        assert_eq!(crop.apply((1920, 1080)), (1920, 808));
+
+        // Pillarboxed, with an odd left bar
+        let crop = bars(&[(1437, 1080, 241, 0)], (1920, 1080));
+        assert_eq!(crop, Crop { top: 0, bottom: 0, left: 240, right: 242 });
+        assert_eq!(crop.filter(), "crop=iw-482:ih-0:240:0");
+
+        assert!(bars(&[(1920, 1080, 0, 0)], (1920, 1080)).is_empty());
+    }
+}
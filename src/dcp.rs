@@ -0,0 +1,233 @@
+use crate::console::info;
+use crate::package::{self, Package};
+use crate::plan::JobPlan;
+use crate::smpte::{self, Asset, EditRate, HashAlgorithm};
+use crate::{output, Result};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Frame rate of a DCP: the DCI rate the size limits of the cinema2k and
+/// cinema4k encoding profiles are set for.
+pub const FRAME_RATE: f64 = 24.0;
+
+/// Digest the packing list and composition record assets with.
+const HASH: HashAlgorithm = HashAlgorithm::Sha1;
+
+/// XML namespace of DCP packing lists (SMPTE ST 429-8).
+pub const PKL_NAMESPACE: &str = "http://www.smpte-ra.org/schemas/429-8/2007/PKL";
+
+/// Aspect ratio above which frames go in the scope (2.39:1) rather than the
+/// flat (1.85:1) picture area.
+const SCOPE_ABOVE: f64 = 1.9;
+
+/// Picture area of a DCP encoded with `profile` (cinema2k or cinema4k) for
+/// frames of `width` x `height`: flat unless they are wider than 1.9:1,
+/// then scope, as [`filter_suffix`] fits them.
+pub fn picture_size(profile: &str, width: u32, height: u32) -> (u32, u32) {
+    let scale = if profile == "cinema4k" { 2 } else { 1 };
+    if height > 0 && width as f64 / height as f64 > SCOPE_ABOVE {
+        (2048 * scale, 858 * scale)
+    } else {
+        (1998 * scale, 1080 * scale)
+    }
+}
+
+/// Filters appended to a graph with one unlabeled output to fit its frames
+/// into the picture area of `profile`, letterboxed or pillarboxed in black.
+pub fn filter_suffix(profile: &str) -> String {
+    let scale = if profile == "cinema4k" { 2 } else { 1 };
+    let (flat_w, flat_h, scope_w, scope_h) = (1998 * scale, 1080 * scale, 2048 * scale, 858 * scale);
+    // Scaled flat frames are always taller than the scope area, which tells
+    // pad which one scale picked
+    format!(
+        ",scale=w='if(gt(a,{a}),{sw},{fw})':h='if(gt(a,{a}),{sh},{fh})':force_original_aspect_ratio=decrease\
+        ,pad=w='if(gt(ih,{sh}),{fw},{sw})':h='if(gt(ih,{sh}),{fh},{sh})':x=(ow-iw)/2:y=(oh-ih)/2,setsar=1",
+        a = SCOPE_ABOVE,
+        fw = flat_w,
+        fh = flat_h,
+        sw = scope_w,
+        sh = scope_h
+    )
+}
+
+/// One reel asset of a composition: a track file and its digest.
+#[derive(Debug, Clone)]
+pub struct ReelAsset {
+    /// UUID of the track file.
+    pub id: String,
+    /// Length in frames.
+    pub duration: u64,
+    /// Base64 SHA-1 of the track file.
+    pub hash: String,
+}
+
+/// Composition playlist (SMPTE ST 429-7) `id` playing `picture` (of
+/// `size`) and, if given, `sound` in a single reel at 24 fps.
+pub fn cpl(
+    id: &str,
+    title: &str,
+    issued: &str,
+    size: (u32, u32),
+    picture: &ReelAsset,
+    sound: Option<&ReelAsset>,
+) -> String {
+    let title = smpte::escape(title);
+    let rate = EditRate::from_fps(FRAME_RATE);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<CompositionPlaylist xmlns=\"http://www.smpte-ra.org/schemas/429-7/2006/CPL\">\n");
+    xml.push_str(&format!("  <Id>urn:uuid:{}</Id>\n  <AnnotationText>{}</AnnotationText>\n", id, title));
+    xml.push_str(&format!("  <IssueDate>{}</IssueDate>\n", issued));
+    xml.push_str(&format!("  <Issuer>{}</Issuer>\n  <Creator>{}</Creator>\n", smpte::CREATOR, smpte::CREATOR));
+    xml.push_str(&format!("  <ContentTitleText>{}</ContentTitleText>\n", title));
+    xml.push_str("  <ContentKind>feature</ContentKind>\n");
+    xml.push_str(&format!("  <ContentVersion>\n    <Id>urn:uuid:{}</Id>\n", smpte::uuid()));
+    xml.push_str(&format!("    <LabelText>{}</LabelText>\n  </ContentVersion>\n", title));
+    xml.push_str("  <RatingList/>\n  <ReelList>\n    <Reel>\n");
+    xml.push_str(&format!("      <Id>urn:uuid:{}</Id>\n      <AssetList>\n", smpte::uuid()));
+    for (element, asset) in [("MainPicture", Some(picture)), ("MainSound", sound)] {
+        let Some(asset) = asset else {
+            continue;
+        };
+        xml.push_str(&format!("        <{}>\n", element));
+        xml.push_str(&format!("          <Id>urn:uuid:{}</Id>\n", asset.id));
+        xml.push_str(&format!("          <EditRate>{}</EditRate>\n", rate));
+        xml.push_str(&format!("          <IntrinsicDuration>{}</IntrinsicDuration>\n", asset.duration));
+        xml.push_str("          <EntryPoint>0</EntryPoint>\n");
+        xml.push_str(&format!("          <Duration>{}</Duration>\n", asset.duration));
+        xml.push_str(&format!("          <Hash>{}</Hash>\n", asset.hash));
+        if element == "MainPicture" {
+            xml.push_str(&format!("          <FrameRate>{}</FrameRate>\n", rate));
+            xml.push_str(&format!("          <ScreenAspectRatio>{} {}</ScreenAspectRatio>\n", size.0, size.1));
+        }
+        xml.push_str(&format!("        </{}>\n", element));
+    }
+    xml.push_str("      </AssetList>\n    </Reel>\n  </ReelList>\n</CompositionPlaylist>\n");
+    xml
+}
+
+/// Wrap the finished `video` (of `frames` DCI frames) and the audio of the
+/// plan's source into a single-reel DCP in the `dcp/` subdirectory of
+/// `output_dir`, replacing an older package there: a picture and a sound
+/// MXF track file, a CPL playing them, and the PKL, ASSETMAP and VOLINDEX
+/// that go with it. Returns the path of the asset map.
+///
+/// The track files are ffmpeg's MXF OP1a rather than the OP-Atom of SMPTE
+/// ST 429-3, which most servers ingest but a strict QC will flag.
+pub fn package(ffmpeg: &Path, plan: &JobPlan, video: &Path, frames: u64, output_dir: &Path) -> Result<PathBuf> {
+    let _span = tracing::info_span!("package").entered();
+    info!("\n📦 Packaging as DCP...");
+    let started = Instant::now();
+
+    let dir = output_dir.join(Package::Dcp.dir_name());
+    let staging = output::staging_dir(&dir);
+    output::prepare_staging(&staging)?;
+    let title = plan.input.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let issued = smpte::issue_date();
+
+    let picture_id = smpte::uuid();
+    let picture_file = format!("PICTURE_{}.mxf", picture_id);
+    smpte::run(ffmpeg, smpte::video_track_command(ffmpeg, video, &staging.join(&picture_file)))?;
+    let mut assets = vec![Asset::read(&staging, &picture_id, picture_file, "application/mxf", HASH)?];
+    let picture = ReelAsset { id: picture_id, duration: frames, hash: assets[0].hash.clone() };
+
+    let sound = if plan.audio_channels.is_empty() {
+        None
+    } else {
+        let id = smpte::uuid();
+        let file = format!("SOUND_{}.mxf", id);
+        let seconds = frames as f64 / FRAME_RATE;
+        let cmd = smpte::audio_track_command(ffmpeg, &plan.input, &plan.audio_channels, seconds, &staging.join(&file));
+        smpte::run(ffmpeg, cmd)?;
+        let asset = Asset::read(&staging, &id, file, "application/mxf", HASH)?;
+        let sound = ReelAsset { id, duration: frames, hash: asset.hash.clone() };
+        assets.push(asset);
+        Some(sound)
+    };
+
+    let profile = plan.video_settings.profile.as_deref().unwrap_or("cinema2k");
+    let size = picture_size(profile, plan.width, plan.height);
+    let cpl_id = smpte::uuid();
+    let cpl_file = format!("CPL_{}.xml", cpl_id);
+    smpte::write(&staging, &cpl_file, &cpl(&cpl_id, &title, &issued, size, &picture, sound.as_ref()))?;
+    assets.insert(0, Asset::read(&staging, &cpl_id, cpl_file, "text/xml", HASH)?);
+
+    let pkl_id = smpte::uuid();
+    let pkl_file = format!("PKL_{}.xml", pkl_id);
+    smpte::write(&staging, &pkl_file, &smpte::pkl(PKL_NAMESPACE, &pkl_id, &title, &issued, HASH, &assets))?;
+    let pkl_asset = Asset::read(&staging, &pkl_id, pkl_file, "text/xml", HASH)?;
+    let map = smpte::asset_map(&smpte::uuid(), &title, &issued, &pkl_asset, &assets);
+    smpte::write(&staging, Package::Dcp.manifest(), &map)?;
+    smpte::write(&staging, smpte::VOLINDEX, &smpte::volume_index())?;
+
+    package::publish(&staging, &dir)?;
+    info!("✅ Packaged in {:.2} seconds", started.elapsed().as_secs_f32());
+    Ok(dir.join(Package::Dcp.manifest()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picture_size_picks_flat_or_scope() {
+        let cases = [
+            ("cinema2k", 1920, 1080, (1998, 1080)),
+            ("cinema2k", 1998, 1080, (1998, 1080)),
+            ("cinema2k", 1440, 1080, (1998, 1080)),
+            ("cinema2k", 2048, 858, (2048, 858)),
+            ("cinema2k", 1920, 800, (2048, 858)),
+            ("cinema4k", 3840, 2160, (3996, 2160)),
+            ("cinema4k", 4096, 1716, (4096, 1716)),
+            ("cinema2k", 1920, 0, (1998, 1080)),
+        ];
+        for (profile, width, height, size) in cases {
+            assert_eq!(picture_size(profile, width, height), size, "{} {}x{}", profile, width, height);
+        }
+    }
+
+    #[test]
+    fn filter_suffix_fits_the_profile_area() {
+        let cases = [
+            ("cinema2k", "if(gt(a,1.9),2048,1998)", "if(gt(a,1.9),858,1080)", "if(gt(ih,858),1998,2048)"),
+            ("cinema4k", "if(gt(a,1.9),4096,3996)", "if(gt(a,1.9),1716,2160)", "if(gt(ih,1716),3996,4096)"),
+        ];
+        for (profile, scale_w, scale_h, pad_w) in cases {
+            let suffix = filter_suffix(profile);
+            assert!(suffix.starts_with(",scale="), "{}", suffix);
+            assert!(suffix.contains(&format!("scale=w='{}':h='{}'", scale_w, scale_h)), "{}", suffix);
+            assert!(suffix.contains(&format!("pad=w='{}'", pad_w)), "{}", suffix);
+            assert!(suffix.ends_with(":x=(ow-iw)/2:y=(oh-ih)/2,setsar=1"), "{}", suffix);
+        }
+    }
+
+    #[test]
+    fn cpl_plays_picture_and_sound_in_one_reel() {
+        let picture = ReelAsset { id: "picture-id".to_string(), duration: 240, hash: "cGljdHVyZQ==".to_string() };
+        let sound = ReelAsset { id: "sound-id".to_string(), duration: 240, hash: "c291bmQ=".to_string() };
+        let xml = cpl("cpl-id", "Tom & Jerry", "2026-10-16T00:00:00Z", (2048, 858), &picture, Some(&sound));
+
+        for element in [
+            "<Id>urn:uuid:cpl-id</Id>",
+            "<AnnotationText>Tom &amp; Jerry</AnnotationText>",
+            "<ContentTitleText>Tom &amp; Jerry</ContentTitleText>",
+            "<IssueDate>2026-10-16T00:00:00Z</IssueDate>",
+            "<ContentKind>feature</ContentKind>",
+            "<Id>urn:uuid:picture-id</Id>",
+            "<Hash>cGljdHVyZQ==</Hash>",
+            "<FrameRate>24 1</FrameRate>",
+            "<ScreenAspectRatio>2048 858</ScreenAspectRatio>",
+            "<Id>urn:uuid:sound-id</Id>",
+            "<Hash>c291bmQ=</Hash>",
+        ] {
+            assert!(xml.contains(element), "missing {}", element);
+        }
+        assert_eq!(xml.matches("<Reel>").count(), 1);
+        assert_eq!(xml.matches("<EditRate>24 1</EditRate>").count(), 2);
+        assert_eq!(xml.matches("<Duration>240</Duration>").count(), 2);
+        assert!(xml.find("<MainPicture>").unwrap() < xml.find("<MainSound>").unwrap());
+
+        let silent = cpl("cpl-id", "Title", "2026-10-16T00:00:00Z", (1998, 1080), &picture, None);
+        assert!(!silent.contains("MainSound"));
+        assert!(silent.contains("<ScreenAspectRatio>1998 1080</ScreenAspectRatio>"));
+    }
+}
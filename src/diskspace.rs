@@ -0,0 +1,143 @@
+use crate::console::{debug, info};
+use crate::units::format_size;
+use crate::{FrameFormat, IntermediateCodec, VideoCodec};
+use crate::{DeliveryError, Result};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Extra room left on every volume on top of the estimate.
+const HEADROOM: f64 = 1.1;
+
+/// Estimated size of `frames` frames in `format` with `depth` bits per
+/// channel at `width` x `height`.
+pub fn estimate_frame_bytes(frames: u64, width: u32, height: u32, format: FrameFormat, depth: u8) -> u64 {
+    (frames as f64 * width as f64 * height as f64 * format.bytes_per_pixel(depth)) as u64
+}
+
+/// Estimated size of `frames` frames of `codec` video at `width` x `height`.
+pub fn estimate_video_bytes(frames: u64, width: u32, height: u32, codec: VideoCodec) -> u64 {
+    (frames as f64 * width as f64 * height as f64 * codec.bytes_per_pixel()) as u64
+}
+
+/// Size of `frames` frames at `frame_rate` encoded at `bitrate` bits per
+/// second.
+pub fn estimate_bitrate_bytes(frames: u64, frame_rate: f64, bitrate: u64) -> u64 {
+    (frames as f64 / frame_rate * bitrate as f64 / 8.0) as u64
+}
+
+/// Estimated size of `frames` frames of `codec` chunks with `depth` bits per
+/// channel at `width` x `height`.
+pub fn estimate_intermediate_bytes(frames: u64, width: u32, height: u32, codec: IntermediateCodec, depth: u8) -> u64 {
+    (frames as f64 * width as f64 * height as f64 * codec.bytes_per_pixel(depth)) as u64
+}
+
+// The path itself or its nearest existing ancestor, which is what the free
+// space of a not yet created directory depends on.
+fn existing_ancestor(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    absolute
+        .ancestors()
+        .find(|p| p.exists())
+        .map(Path::to_path_buf)
+        .unwrap_or(absolute)
+}
+
+/// Bytes available to the current user on the volume holding `path`.
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(existing_ancestor(path).as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: statvfs only writes into the zeroed struct we pass.
+    unsafe {
+        let mut stat: libc::statvfs = std::mem::zeroed();
+        if libc::statvfs(path.as_ptr(), &mut stat) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+}
+
+/// Bytes available to the current user on the volume holding `path`.
+#[cfg(windows)]
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = existing_ancestor(path).as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available = 0u64;
+    // SAFETY: `wide` is NUL terminated and the out pointer is valid.
+    let ok = unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut()) };
+    if ok == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(available)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn free_space(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "free space query not supported"))
+}
+
+/// Identifies the volume a path lives on, so requirements on the same volume
+/// can be added up.
+#[cfg(unix)]
+fn volume_id(path: &Path) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(existing_ancestor(path)).ok().map(|m| m.dev().to_string())
+}
+
+#[cfg(not(unix))]
+fn volume_id(path: &Path) -> Option<String> {
+    existing_ancestor(path)
+        .components()
+        .next()
+        .map(|c| c.as_os_str().to_string_lossy().to_ascii_lowercase())
+}
+
+/// Whether `a` and `b` are (or would be created) on the same volume.
+pub fn same_volume(a: &Path, b: &Path) -> bool {
+    match (volume_id(a), volume_id(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Fail if any volume lacks room for the bytes about to be written to it.
+/// `needs` lists directories and the bytes each will receive; entries on the
+/// same volume are added up. Volumes whose free space can't be determined
+/// are skipped.
+pub fn check(needs: &[(&Path, u64)]) -> Result<()> {
+    let _span = tracing::info_span!("preflight").entered();
+    info!("\n💾 Checking free disk space...");
+
+    let mut volumes: Vec<(Option<String>, &Path, u64)> = Vec::new();
+    for &(path, bytes) in needs {
+        let id = volume_id(path);
+        match volumes.iter_mut().find(|(v, _, _)| id.is_some() && *v == id) {
+            Some(volume) => volume.2 += bytes,
+            None => volumes.push((id, path, bytes)),
+        }
+    }
+
+    for (_, path, bytes) in volumes {
+        let required = (bytes as f64 * HEADROOM) as u64;
+        match free_space(path) {
+            Ok(available) => {
+                debug!("💾 {}: need ~{}, {} free", path.display(), format_size(required), format_size(available));
+                if available < required {
+                    return Err(DeliveryError::InsufficientSpace {
+                        path: path.to_path_buf(),
+                        required,
+                        available,
+                    });
+                }
+            }
+            Err(e) => debug!("⚠️ Could not determine free space for {}: {}", path.display(), e),
+        }
+    }
+    info!("✅ Enough free disk space");
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can stop a job.
+#[derive(Debug)]
+pub enum DeliveryError {
+    /// The job definition or command line is invalid.
+    Config(String),
+    /// An input file (video, overlay, ...) does not exist.
+    MissingInput { name: &'static str, path: PathBuf },
+    /// No usable ffmpeg/ffprobe executable.
+    FfmpegNotFound(PathBuf),
+    /// ffprobe could not be run or its output made no sense.
+    ProbeFailed(String),
+    /// A segment's ffmpeg process failed; `stderr` holds the tail of its output.
+    SegmentFailed { id: usize, stderr: String },
+    /// Splitting the source into per-segment pieces failed.
+    SplitFailed(String),
+    /// Joining the segment chunks into one video failed.
+    JoinFailed(String),
+    /// Packaging the video for streaming failed.
+    PackageFailed(String),
+    /// Encoding or scoring the probe clips of the CRF search failed.
+    CrfSearchFailed(String),
+    /// Extracting or processing the source's audio failed.
+    AudioFailed(String),
+    /// Decoding the source (`--verify-source`) logged errors.
+    DamagedSource(String),
+    /// A QC check of the delivery could not be run or found problems.
+    QcFailed(String),
+    /// A segment's ffmpeg process was killed by the watchdog.
+    SegmentTimedOut { id: usize, reason: String },
+    /// The segments produced a different number of frames than planned.
+    FrameCountMismatch { expected: u64, actual: u64 },
+    /// The ffmpeg build lacks a filter or encoder the job needs.
+    MissingCapability(String),
+    /// Downloading or verifying an FFmpeg build failed.
+    FetchFailed(String),
+    /// A volume lacks room for the frames the job would write to it.
+    InsufficientSpace { path: PathBuf, required: u64, available: u64 },
+    /// Jobs of a batch failed; `report` has what happened to each.
+    BatchFailed { failed: usize, total: usize, report: PathBuf },
+    /// The run was stopped with Ctrl+C or SIGTERM.
+    Interrupted,
+    /// A filesystem or process operation failed.
+    Io { context: String, source: io::Error },
+}
+
+pub type Result<T> = std::result::Result<T, DeliveryError>;
+
+impl DeliveryError {
+    /// Wrap an `io::Error` with a short description of what was being attempted.
+    pub fn io(context: impl Into<String>, source: io::Error) -> DeliveryError {
+        DeliveryError::Io { context: context.into(), source }
+    }
+}
+
+impl fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeliveryError::Config(msg) => write!(f, "{}", msg),
+            DeliveryError::MissingInput { name, path } => {
+                write!(f, "{} not found: {}", name, path.display())
+            }
+            DeliveryError::FfmpegNotFound(path) => {
+                write!(f, "FFmpeg executable not found: {}", path.display())
+            }
+            DeliveryError::ProbeFailed(msg) => write!(f, "FFprobe failed: {}", msg),
+            DeliveryError::SegmentFailed { id, stderr } => {
+                write!(f, "Segment {} failed", id)?;
+                if !stderr.is_empty() {
+                    write!(f, ":\n{}", stderr)?;
+                }
+                Ok(())
+            }
+            DeliveryError::SplitFailed(stderr) => write!(f, "Splitting the source failed:\n{}", stderr),
+            DeliveryError::JoinFailed(stderr) => write!(f, "Joining the segments failed:\n{}", stderr),
+            DeliveryError::PackageFailed(stderr) => write!(f, "Packaging the video failed:\n{}", stderr),
+            DeliveryError::CrfSearchFailed(stderr) => write!(f, "Searching the CRF failed:\n{}", stderr),
+            DeliveryError::AudioFailed(stderr) => write!(f, "Processing the audio failed:\n{}", stderr),
+            DeliveryError::DamagedSource(errors) => write!(f, "The input doesn't decode cleanly:\n{}", errors),
+            DeliveryError::QcFailed(msg) => write!(f, "Quality control failed:\n{}", msg),
+            DeliveryError::SegmentTimedOut { id, reason } => {
+                write!(f, "Segment {} timed out: {}", id, reason)
+            }
+            DeliveryError::FrameCountMismatch { expected, actual } => write!(
+                f,
+                "Expected {} frames but the segments produced {} (use --allow-frame-mismatch to accept)",
+                expected, actual
+            ),
+            DeliveryError::MissingCapability(msg) => write!(f, "{}", msg),
+            DeliveryError::FetchFailed(msg) => write!(f, "{}", msg),
+            DeliveryError::InsufficientSpace { path, required, available } => write!(
+                f,
+                "Not enough disk space for {}: about {} needed, {} available (use --no-space-check to skip)",
+                path.display(),
+                crate::units::format_size(*required),
+                crate::units::format_size(*available)
+            ),
+            DeliveryError::BatchFailed { failed, total, report } => {
+                write!(f, "{} of {} jobs failed (see {})", failed, total, report.display())
+            }
+            DeliveryError::Interrupted => write!(f, "Interrupted"),
+            DeliveryError::Io { context, source } => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+impl std::error::Error for DeliveryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DeliveryError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
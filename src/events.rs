@@ -0,0 +1,85 @@
+use crate::clock::UtcTime;
+use crate::qc::Check;
+use serde::Serialize;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Emit machine-readable events on stdout. Human-readable console output moves
+/// to stderr so stdout stays one JSON object per line.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Job lifecycle events for `--progress-format json`.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    JobStarted {
+        input: String,
+        output_dir: String,
+        segments: usize,
+        duration: f64,
+    },
+    SegmentProgress {
+        segment: usize,
+        frame: u64,
+        out_time: f64,
+        percent: f64,
+        overall_percent: f64,
+    },
+    SegmentDone {
+        segment: usize,
+        success: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// Loudness of one audio stream of a finished delivery.
+    AudioLevels {
+        file: String,
+        stream: usize,
+        integrated: f64,
+        true_peak: f64,
+        range: f64,
+    },
+    /// A problem a QC check found in a delivery, in seconds.
+    QcFinding {
+        file: String,
+        check: Check,
+        start: f64,
+        end: f64,
+    },
+    JobDone {
+        frames: usize,
+        elapsed: f64,
+    },
+    Error {
+        message: String,
+        exit_code: i32,
+    },
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    timestamp: String,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Write `event` as one JSON line on stdout, if events are enabled.
+pub fn emit(event: Event) {
+    if !enabled() {
+        return;
+    }
+    let record = Record { timestamp: UtcTime::now().rfc3339(), event: &event };
+    if let Ok(line) = serde_json::to_string(&record) {
+        let mut stdout = std::io::stdout().lock();
+        let _ = writeln!(stdout, "{}", line);
+        let _ = stdout.flush();
+    }
+}
@@ -0,0 +1,167 @@
+use crate::console::info;
+use crate::{DeliveryError, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// One downloadable archive of a pinned FFmpeg build.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PinnedArchive {
+    pub url: String,
+    /// Expected SHA-256 of the archive, hex encoded.
+    pub sha256: String,
+}
+
+/// Archives pinned for the current platform in a pins manifest.
+///
+/// The manifest maps an OS name (`macos`, `windows`, `linux`) to the list of
+/// archives that together contain `ffmpeg` and `ffprobe`.
+pub fn load_pins(path: &Path) -> Result<Vec<PinnedArchive>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| DeliveryError::io(format!("Failed to read pins {}", path.display()), e))?;
+    let mut pins: HashMap<String, Vec<PinnedArchive>> = toml::from_str(&text)
+        .map_err(|e| DeliveryError::Config(format!("Failed to parse pins {}: {}", path.display(), e)))?;
+
+    let archives = pins.remove(env::consts::OS).unwrap_or_default();
+    if archives.is_empty() {
+        return Err(DeliveryError::Config(format!(
+            "No FFmpeg archives for {} in {}; add a verified build there or pass --url and --sha256",
+            env::consts::OS,
+            path.display()
+        )));
+    }
+    Ok(archives)
+}
+
+/// Hex encoded SHA-256 of a file's contents.
+pub fn sha256_file(path: &Path) -> Result<String> {
+    Ok(sha256_digest(path)?.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// SHA-256 of a file's contents.
+pub fn sha256_digest(path: &Path) -> Result<Vec<u8>> {
+    let mut file = File::open(path)
+        .map_err(|e| DeliveryError::io(format!("Failed to open {}", path.display()), e))?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file
+            .read(&mut buf)
+            .map_err(|e| DeliveryError::io(format!("Failed to read {}", path.display()), e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+fn run_tool(cmd: &mut Command, what: &str) -> Result<()> {
+    let status = cmd
+        .status()
+        .map_err(|e| DeliveryError::FetchFailed(format!("Failed to run {}: {}", what, e)))?;
+    if !status.success() {
+        return Err(DeliveryError::FetchFailed(format!(
+            "{} exited with code {:?}",
+            what,
+            status.code()
+        )));
+    }
+    Ok(())
+}
+
+// curl ships with macOS and Windows 10+, so we don't need an HTTP client of our own.
+fn download(url: &str, dest: &Path) -> Result<()> {
+    info!("⬇️ Downloading {}", url);
+    run_tool(
+        Command::new("curl")
+            .args(["--fail", "--location", "--retry", "3", "--progress-bar", "--output"])
+            .arg(dest)
+            .arg(url),
+        "curl",
+    )
+}
+
+// bsdtar (macOS, Windows 10+) reads zip as well as tar archives; GNU tar does not.
+fn extract(archive: &Path, dest: &Path) -> Result<()> {
+    let is_zip = archive.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+    if is_zip && env::consts::OS == "linux" {
+        run_tool(Command::new("unzip").arg("-oq").arg(archive).arg("-d").arg(dest), "unzip")
+    } else {
+        run_tool(Command::new("tar").arg("-xf").arg(archive).arg("-C").arg(dest), "tar")
+    }
+}
+
+/// Depth-first search for an executable called `file_name` below `dir`.
+fn find_file(dir: &Path, file_name: &str) -> Option<PathBuf> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir).ok()?.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    entries.sort();
+    for path in &entries {
+        if path.is_file() && path.file_name().is_some_and(|n| n == file_name) {
+            return Some(path.clone());
+        }
+    }
+    entries.iter().filter(|p| p.is_dir()).find_map(|p| find_file(p, file_name))
+}
+
+fn install(source: &Path, dest: &Path) -> io::Result<()> {
+    fs::copy(source, dest)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dest, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// Download every archive, verify its checksum, and install the `ffmpeg` and
+/// `ffprobe` executables they contain into `dest_dir`.
+pub fn fetch_ffmpeg(archives: &[PinnedArchive], dest_dir: &Path) -> Result<()> {
+    let staging = env::temp_dir().join(format!("delivery_encoder_fetch_{}", std::process::id()));
+    let result = fetch_into(archives, &staging, dest_dir);
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+fn fetch_into(archives: &[PinnedArchive], staging: &Path, dest_dir: &Path) -> Result<()> {
+    let extracted = staging.join("extracted");
+    fs::create_dir_all(&extracted)
+        .map_err(|e| DeliveryError::io("Failed to create download directory", e))?;
+
+    for (i, archive) in archives.iter().enumerate() {
+        let file_name = archive.url.rsplit('/').next().filter(|n| !n.is_empty()).unwrap_or("archive");
+        let download_path = staging.join(format!("{}_{}", i, file_name));
+        download(&archive.url, &download_path)?;
+
+        let actual = sha256_file(&download_path)?;
+        if !actual.eq_ignore_ascii_case(archive.sha256.trim()) {
+            return Err(DeliveryError::FetchFailed(format!(
+                "Checksum mismatch for {}\n  expected: {}\n  actual:   {}",
+                archive.url, archive.sha256, actual
+            )));
+        }
+        info!("✅ SHA-256 verified: {}", actual);
+
+        extract(&download_path, &extracted)?;
+    }
+
+    fs::create_dir_all(dest_dir)
+        .map_err(|e| DeliveryError::io(format!("Failed to create {}", dest_dir.display()), e))?;
+    for tool in ["ffmpeg", "ffprobe"] {
+        let file_name = format!("{}{}", tool, env::consts::EXE_SUFFIX);
+        let source = find_file(&extracted, &file_name).ok_or_else(|| {
+            DeliveryError::FetchFailed(format!("Downloaded archives contain no {}", file_name))
+        })?;
+        let dest = dest_dir.join(&file_name);
+        install(&source, &dest)
+            .map_err(|e| DeliveryError::io(format!("Failed to install {}", dest.display()), e))?;
+        info!("📦 Installed {}", dest.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,304 @@
+use crate::console::info;
+use crate::{DeliveryError, Result};
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directory holding the ffmpeg build shipped for this platform, relative to
+/// the project root.
+pub fn bundled_dir() -> Option<PathBuf> {
+    let platform = match env::consts::OS {
+        "macos" => "macos",
+        "windows" => "windows",
+        "linux" => "linux",
+        _ => return None,
+    };
+    Some(Path::new("assets/bin").join(platform))
+}
+
+/// `name` with the platform's executable suffix (`ffmpeg.exe` on Windows).
+fn executable_name(name: &str) -> String {
+    format!("{}{}", name, env::consts::EXE_SUFFIX)
+}
+
+/// Search the directories in `PATH` for an executable called `name`.
+pub fn find_in_path(name: &str) -> Option<PathBuf> {
+    let file_name = executable_name(name);
+    env::var_os("PATH").and_then(|paths| {
+        env::split_paths(&paths)
+            .map(|dir| dir.join(&file_name))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+/// Find the `tool` executable (`"ffmpeg"` or `"ffprobe"`).
+///
+/// An explicit `override_path` wins; a bare name there is looked up on `PATH`.
+/// Otherwise the bundled build under `assets/bin/<os>/` is used when present,
+/// falling back to whatever is installed on `PATH`.
+pub fn locate(tool: &str, override_path: Option<&Path>) -> Result<PathBuf> {
+    if let Some(path) = override_path {
+        if path.components().count() == 1 {
+            let name = path.to_string_lossy();
+            return find_in_path(&name).ok_or_else(|| DeliveryError::FfmpegNotFound(path.to_path_buf()));
+        }
+        if path.is_file() {
+            return Ok(path.to_path_buf());
+        }
+        return Err(DeliveryError::FfmpegNotFound(path.to_path_buf()));
+    }
+
+    let bundled = bundled_dir().map(|dir| dir.join(executable_name(tool)));
+    if let Some(path) = bundled.as_ref().filter(|p| p.is_file()) {
+        return Ok(path.clone());
+    }
+
+    match find_in_path(tool) {
+        Some(path) => {
+            if let Some(bundled) = &bundled {
+                info!("ℹ️ Bundled {} not found at {}, using {}", tool, bundled.display(), path.display());
+            }
+            Ok(path)
+        }
+        None => Err(DeliveryError::FfmpegNotFound(
+            bundled.unwrap_or_else(|| PathBuf::from(tool)),
+        )),
+    }
+}
+
+/// What an ffmpeg build can do, gathered once before any worker starts.
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities {
+    /// First line of `ffmpeg -version`.
+    pub version: String,
+    pub filters: HashSet<String>,
+    pub encoders: HashSet<String>,
+    /// Hardware acceleration methods, e.g. `videotoolbox` or `cuda`.
+    pub hwaccels: HashSet<String>,
+}
+
+fn query_lines(ffmpeg: &Path, flag: &str) -> Result<String> {
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", flag])
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffmpeg.to_path_buf()),
+            _ => DeliveryError::io(format!("Failed to run ffmpeg {}", flag), e),
+        })?;
+    if !output.status.success() {
+        return Err(DeliveryError::MissingCapability(format!(
+            "`{} {}` failed: {}",
+            ffmpeg.display(),
+            flag,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl Capabilities {
+    /// Run `ffmpeg -version`, `-filters`, `-encoders` and `-hwaccels` and
+    /// parse the results.
+    pub fn query(ffmpeg: &Path) -> Result<Capabilities> {
+        let version = query_lines(ffmpeg, "-version")?
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        // " TSC overlay           VV->V      Overlay a video source on top of the input."
+        let filters = query_lines(ffmpeg, "-filters")?
+            .lines()
+            .filter_map(|line| {
+                let mut tokens = line.split_whitespace();
+                let (_, name, io) = (tokens.next()?, tokens.next()?, tokens.next()?);
+                io.contains("->").then(|| name.to_string())
+            })
+            .collect();
+
+        // " V....D png                  PNG (Portable Network Graphics) image"
+        let encoders = query_lines(ffmpeg, "-encoders")?
+            .lines()
+            .skip_while(|line| !line.trim_start().starts_with("---"))
+            .skip(1)
+            .filter_map(|line| line.split_whitespace().nth(1).map(str::to_string))
+            .collect();
+
+        // "Hardware acceleration methods:" followed by one name per line
+        let hwaccels = query_lines(ffmpeg, "-hwaccels")?
+            .lines()
+            .skip(1)
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        Ok(Capabilities { version, filters, encoders, hwaccels })
+    }
+
+    /// Fail unless every filter and encoder in the lists is available.
+    pub fn require(&self, filters: &[String], encoders: &[&str]) -> Result<()> {
+        let missing_filters: Vec<&str> = filters
+            .iter()
+            .map(String::as_str)
+            .filter(|f| !self.filters.contains(*f))
+            .collect();
+        let missing_encoders: Vec<&str> = encoders
+            .iter()
+            .copied()
+            .filter(|e| !self.encoders.contains(*e))
+            .collect();
+
+        let mut problems = Vec::new();
+        if !missing_filters.is_empty() {
+            problems.push(format!("filter(s) {}", missing_filters.join(", ")));
+        }
+        if !missing_encoders.is_empty() {
+            problems.push(format!("encoder(s) {}", missing_encoders.join(", ")));
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(DeliveryError::MissingCapability(format!(
+            "Your ffmpeg ({}) lacks {}",
+            self.version,
+            problems.join(" and ")
+        )))
+    }
+}
+
+/// `path` as an ffmpeg input or output argument. ffmpeg reads a leading `-`
+/// as an option and `name:` as a protocol, so relative paths that could be
+/// mistaken for either get a `./` prefix.
+pub fn path_arg(path: &Path) -> OsString {
+    let text = path.as_os_str().to_string_lossy();
+    if path.is_relative() && (text.starts_with('-') || text.contains(':')) {
+        Path::new(".").join(path).into_os_string()
+    } else {
+        path.as_os_str().to_owned()
+    }
+}
+
+/// An image sequence pattern such as `%05d.png` inside `dir`. A `%` in the
+/// directory itself is doubled so the image muxer doesn't read it as part of
+/// the pattern.
+pub fn sequence_pattern(dir: &Path, pattern: &str) -> OsString {
+    let mut escaped = Vec::new();
+    for &byte in path_arg(dir).as_encoded_bytes() {
+        escaped.push(byte);
+        if byte == b'%' {
+            escaped.push(b'%');
+        }
+    }
+    // SAFETY: only ASCII was inserted next to ASCII bytes of a valid OsStr.
+    let dir = unsafe { OsString::from_encoded_bytes_unchecked(escaped) };
+    Path::new(&dir).join(pattern).into_os_string()
+}
+
+/// Escape `value` for use as a filter option inside a `-filter_complex`
+/// graph, e.g. a file name for `movie=` or `lut3d=`. Two levels apply: the
+/// option parser treats `\`, `'` and `:` as special, then the graph parser
+/// also `[`, `]`, `,` and `;`.
+pub fn escape_filter_value(value: &str) -> String {
+    let mut option = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | '\'' | ':') {
+            option.push('\\');
+        }
+        option.push(c);
+    }
+    let mut graph = String::with_capacity(option.len());
+    for c in option.chars() {
+        if matches!(c, '\\' | '\'' | '[' | ']' | ',' | ';') {
+            graph.push('\\');
+        }
+        graph.push(c);
+    }
+    graph
+}
+
+/// [`escape_filter_value`] for a path. Non-UTF-8 parts are replaced, as
+/// filter graphs are text.
+pub fn escape_filter_path(path: &Path) -> String {
+    escape_filter_value(&path.to_string_lossy())
+}
+
+/// Names of the filters used in a `-filter_complex` graph.
+///
+/// This is a lightweight scan meant for preflight checks, not a full graph
+/// parser: it splits chains on unquoted, unescaped `;`/`,` and strips
+/// `[label]` pads.
+pub fn filter_names(graph: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = graph.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                current.push(c);
+                current.extend(chars.next());
+            }
+            '\'' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            ';' | ',' if !quoted => parts.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    parts.push(current);
+
+    let mut names: Vec<String> = Vec::new();
+    for part in &parts {
+        let mut rest = part.trim();
+        while let Some(stripped) = rest.strip_prefix('[') {
+            rest = stripped.split_once(']').map_or("", |(_, r)| r).trim_start();
+        }
+        let name: String = rest
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .collect();
+        if name.starts_with(|c: char| c.is_ascii_alphabetic()) && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+// Quote an argument so the printed command can be pasted into a POSIX shell.
+#[cfg(not(windows))]
+fn shell_quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:=%,+@".contains(c));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+// Quote an argument so the printed command can be pasted into cmd.exe or
+// PowerShell.
+#[cfg(windows)]
+fn shell_quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./\\:=,+@".contains(c));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("\"{}\"", arg.replace('"', "\\\""))
+    }
+}
+
+/// A command line for display, with arguments quoted where needed.
+pub fn display_command(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|a| shell_quote(&a.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
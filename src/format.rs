@@ -0,0 +1,852 @@
+use crate::color::SourceColor;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Size/speed trade-offs for the formats that offer one; `None` keeps the
+/// encoder default.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FrameQuality {
+    /// zlib level for PNG, 0 (fastest, largest) to 9 (slowest, smallest).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub png_compression: Option<u8>,
+    /// JPEG quality, 1 (smallest) to 100 (best).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jpeg_quality: Option<u8>,
+}
+
+/// ffmpeg's mjpeg quantizer for `quality` 1-100: 31 (worst) to 2 (best).
+fn jpeg_qscale(quality: u8) -> u8 {
+    let quality = quality.clamp(1, 100) as u32;
+    (2 + (100 - quality) * 29 / 99) as u8
+}
+
+/// Image format the frames are exported in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameFormat {
+    #[default]
+    Png,
+    /// Editorial proxies.
+    Jpeg,
+    Tiff,
+    /// OpenEXR, 32-bit float.
+    Exr,
+    /// DPX, 10-bit for DI.
+    Dpx,
+    Webp,
+}
+
+impl FrameFormat {
+    /// File extension of the frames, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            FrameFormat::Png => "png",
+            FrameFormat::Jpeg => "jpg",
+            FrameFormat::Tiff => "tif",
+            FrameFormat::Exr => "exr",
+            FrameFormat::Dpx => "dpx",
+            FrameFormat::Webp => "webp",
+        }
+    }
+
+    /// ffmpeg encoder that writes the format.
+    pub fn encoder(self) -> &'static str {
+        match self {
+            FrameFormat::Png => "png",
+            FrameFormat::Jpeg => "mjpeg",
+            FrameFormat::Tiff => "tiff",
+            FrameFormat::Exr => "exr",
+            FrameFormat::Dpx => "dpx",
+            FrameFormat::Webp => "libwebp",
+        }
+    }
+
+    /// Bits per channel the format is written with unless the job sets one.
+    pub fn default_bit_depth(self) -> u8 {
+        match self {
+            FrameFormat::Exr => 32,
+            FrameFormat::Dpx => 10,
+            _ => 8,
+        }
+    }
+
+    /// Bits per channel the format can be written with.
+    pub fn bit_depths(self) -> &'static [u8] {
+        match self {
+            FrameFormat::Png | FrameFormat::Tiff => &[8, 16],
+            FrameFormat::Jpeg | FrameFormat::Webp => &[8],
+            FrameFormat::Exr => &[16, 32],
+            FrameFormat::Dpx => &[8, 10, 12, 16],
+        }
+    }
+
+    /// Fail unless the format can be written with `depth` bits per channel.
+    pub fn check_bit_depth(self, depth: u8) -> std::result::Result<(), String> {
+        if self.bit_depths().contains(&depth) {
+            return Ok(());
+        }
+        let supported: Vec<String> = self.bit_depths().iter().map(|d| d.to_string()).collect();
+        Err(format!("{} frames can't be written with {} bits per channel, only {}", self, depth, supported.join(", ")))
+    }
+
+    /// Whether the format can store an alpha channel.
+    pub fn supports_alpha(self) -> bool {
+        self != FrameFormat::Jpeg
+    }
+
+    /// Pixel format the encoder writes at `depth` bits per channel, with an
+    /// alpha channel if `alpha` is set, or `None` to let ffmpeg pick (8-bit
+    /// RGB or RGBA).
+    pub fn pixel_format(self, depth: u8, alpha: bool) -> Option<&'static str> {
+        if alpha {
+            return match (self, depth) {
+                (FrameFormat::Png | FrameFormat::Tiff | FrameFormat::Dpx, 8) => Some("rgba"),
+                (FrameFormat::Png, 16) => Some("rgba64be"),
+                (FrameFormat::Tiff | FrameFormat::Dpx, 16) => Some("rgba64le"),
+                (FrameFormat::Dpx, 10) => Some("gbrap10le"),
+                (FrameFormat::Dpx, 12) => Some("gbrap12le"),
+                (FrameFormat::Exr, _) => Some("gbrapf32le"),
+                (FrameFormat::Webp, _) => Some("yuva420p"),
+                _ => None,
+            };
+        }
+        match (self, depth) {
+            (FrameFormat::Png, 16) => Some("rgb48be"),
+            (FrameFormat::Tiff, 16) => Some("rgb48le"),
+            (FrameFormat::Exr, _) => Some("gbrpf32le"),
+            (FrameFormat::Dpx, 8) => Some("rgb24"),
+            (FrameFormat::Dpx, 10) => Some("gbrp10le"),
+            (FrameFormat::Dpx, 12) => Some("gbrp12le"),
+            (FrameFormat::Dpx, 16) => Some("rgb48le"),
+            _ => None,
+        }
+    }
+
+    /// Encoder options passed after `-c:v` for `depth` bits per channel:
+    /// high quality JPEG (unless `quality` says otherwise) and WebP, lossless
+    /// compression for TIFF and EXR (stored as half floats at 16 bits), the
+    /// PNG compression level from `quality`, and the pixel format, with
+    /// alpha if `alpha` is set.
+    pub fn encoder_args(self, depth: u8, alpha: bool, quality: &FrameQuality) -> Vec<String> {
+        let mut args: Vec<String> = match (self, quality.png_compression, quality.jpeg_quality) {
+            (FrameFormat::Png, Some(level), _) => vec!["-compression_level".to_string(), level.min(9).to_string()],
+            (FrameFormat::Jpeg, _, Some(q)) => vec!["-q:v".to_string(), jpeg_qscale(q).to_string()],
+            _ => Vec::new(),
+        };
+        let defaults: &[&str] = match (self, depth) {
+            (FrameFormat::Png, _) | (FrameFormat::Dpx, _) => &[],
+            (FrameFormat::Jpeg, _) if quality.jpeg_quality.is_some() => &[],
+            (FrameFormat::Jpeg, _) => &["-q:v", "2"],
+            (FrameFormat::Tiff, _) => &["-compression_algo", "deflate"],
+            (FrameFormat::Exr, 16) => &["-compression", "zip1", "-format", "half"],
+            (FrameFormat::Exr, _) => &["-compression", "zip1"],
+            (FrameFormat::Webp, _) => &["-quality", "90"],
+        };
+        args.extend(defaults.iter().map(|a| a.to_string()));
+        if let Some(pix_fmt) = self.pixel_format(depth, alpha) {
+            args.extend(["-pix_fmt".to_string(), pix_fmt.to_string()]);
+        }
+        args
+    }
+
+    /// Typical size of a frame per pixel at `depth` bits per channel, for
+    /// disk space estimates. Raw 8-bit RGB is 3 bytes; PNG and TIFF usually
+    /// compress film and graphics to a bit over half of that.
+    pub fn bytes_per_pixel(self, depth: u8) -> f64 {
+        match self {
+            FrameFormat::Png | FrameFormat::Tiff => 2.0 * depth as f64 / 8.0,
+            FrameFormat::Jpeg => 0.4,
+            FrameFormat::Exr => depth as f64 / 4.0,
+            FrameFormat::Dpx if depth <= 10 => 4.0,
+            FrameFormat::Dpx => 6.0,
+            FrameFormat::Webp => 0.5,
+        }
+    }
+}
+
+impl fmt::Display for FrameFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FrameFormat::Png => "PNG",
+            FrameFormat::Jpeg => "JPEG",
+            FrameFormat::Tiff => "TIFF",
+            FrameFormat::Exr => "EXR",
+            FrameFormat::Dpx => "DPX",
+            FrameFormat::Webp => "WebP",
+        })
+    }
+}
+
+impl FromStr for FrameFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<FrameFormat, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "png" => Ok(FrameFormat::Png),
+            "jpeg" | "jpg" => Ok(FrameFormat::Jpeg),
+            "tiff" | "tif" => Ok(FrameFormat::Tiff),
+            "exr" => Ok(FrameFormat::Exr),
+            "dpx" => Ok(FrameFormat::Dpx),
+            "webp" => Ok(FrameFormat::Webp),
+            _ => Err(format!("invalid frame format '{}', expected png, jpeg, tiff, exr, dpx or webp", text)),
+        }
+    }
+}
+
+/// What a job delivers: an image sequence, or one video file with the
+/// overlay burned in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Frames,
+    Video,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<OutputFormat, String> {
+        match text.trim() {
+            "frames" => Ok(OutputFormat::Frames),
+            "video" => Ok(OutputFormat::Video),
+            _ => Err(format!("invalid output format '{}', expected frames or video", text)),
+        }
+    }
+}
+
+/// Presets of x264 and x265, fastest first. AV1 takes the same names.
+const X26X_PRESETS: &[&str] =
+    &["ultrafast", "superfast", "veryfast", "faster", "fast", "medium", "slow", "slower", "veryslow", "placebo"];
+
+/// Encoder of AV1 video output.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Av1Encoder {
+    /// SVT-AV1, much faster than libaom at similar quality.
+    #[default]
+    Svt,
+    /// libaom, the reference encoder, for builds without SVT-AV1.
+    Aom,
+}
+
+impl Av1Encoder {
+    /// ffmpeg encoder name.
+    pub fn encoder(self) -> &'static str {
+        match self {
+            Av1Encoder::Svt => "libsvtav1",
+            Av1Encoder::Aom => "libaom-av1",
+        }
+    }
+
+    /// Encoder options passed after `-c:v`: `settings` with the x264 style
+    /// preset names mapped to SVT-AV1 presets or libaom `-cpu-used`, at
+    /// `depth` bits per channel.
+    pub fn encoder_args(self, depth: u8, settings: &VideoSettings) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut push = |option: &str, value: String| {
+            args.push(option.to_string());
+            args.push(value);
+        };
+        match (settings.bitrate, settings.crf) {
+            (Some(bitrate), _) => push("-b:v", bitrate.to_string()),
+            (None, crf) => {
+                push("-crf", crf.unwrap_or(VideoCodec::Av1.default_crf()).to_string());
+                if self == Av1Encoder::Aom {
+                    // Constant quality rather than constrained by a bitrate
+                    push("-b:v", "0".to_string());
+                }
+            }
+        }
+        // Both count down from slowest to fastest
+        let preset = X26X_PRESETS.iter().position(|p| Some(*p) == settings.preset.as_deref()).unwrap_or(5);
+        match self {
+            Av1Encoder::Svt => push("-preset", [12, 11, 10, 9, 8, 6, 5, 4, 2, 0][preset].to_string()),
+            Av1Encoder::Aom => {
+                push("-cpu-used", [8, 7, 6, 5, 5, 4, 3, 2, 1, 0][preset].to_string());
+                push("-row-mt", "1".to_string());
+            }
+        }
+        if let Some(profile) = &settings.profile {
+            push("-profile:v", profile.clone());
+        }
+        push("-pix_fmt", if depth == 10 { "yuv420p10le" } else { "yuv420p" }.to_string());
+        args
+    }
+}
+
+impl fmt::Display for Av1Encoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Av1Encoder::Svt => "SVT-AV1",
+            Av1Encoder::Aom => "libaom",
+        })
+    }
+}
+
+/// Rate control and compatibility settings of video output; `None` keeps
+/// the codec's default.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct VideoSettings {
+    /// Constant quality, 0 (lossless) to 51 (worst), or 63 for AV1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crf: Option<u8>,
+    /// Average bits per second instead of constant quality.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<u64>,
+    /// Peak bits per second the decoder's buffer is sized for (VBV); equal
+    /// to `bitrate` for constant bitrate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maxrate: Option<u64>,
+    /// Size of that buffer in bits; twice `maxrate` unless set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bufsize: Option<u64>,
+    /// Encoder speed/size trade-off, e.g. `slow`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// Codec profile, e.g. `high`, `main10` or ProRes `4444xq`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Codec level, e.g. `4.1`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+}
+
+/// Codec of video output.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoCodec {
+    /// H.264 in MP4.
+    #[default]
+    H264,
+    /// HEVC in MP4, tagged hvc1 for Apple players.
+    Hevc,
+    /// ProRes 422 HQ (4444 with alpha) in QuickTime.
+    Prores,
+    /// AV1 in MP4, for the smallest files.
+    Av1,
+    /// Avid DNxHR (HQ by default, HQX at 10 bits) in QuickTime.
+    Dnxhr,
+    /// JPEG 2000 (10-bit 4:2:2 by default) in MXF, for IMF mastering; the
+    /// cinema2k and cinema4k profiles encode 12-bit X'Y'Z' for DCPs.
+    J2k,
+}
+
+impl VideoCodec {
+    /// Container extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            VideoCodec::H264 | VideoCodec::Hevc | VideoCodec::Av1 => "mp4",
+            VideoCodec::Prores | VideoCodec::Dnxhr | VideoCodec::J2k => "mov",
+        }
+    }
+
+    /// Container video output is delivered in unless the job picks one.
+    pub fn container(self) -> Container {
+        match self {
+            VideoCodec::H264 | VideoCodec::Hevc | VideoCodec::Av1 => Container::Mp4,
+            VideoCodec::Prores | VideoCodec::Dnxhr => Container::Mov,
+            VideoCodec::J2k => Container::Mxf,
+        }
+    }
+
+    /// ffmpeg encoder for the codec; AV1 can also be encoded with
+    /// [`Av1Encoder::Aom`].
+    pub fn encoder(self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "libx264",
+            VideoCodec::Hevc => "libx265",
+            VideoCodec::Prores => "prores_ks",
+            VideoCodec::Av1 => Av1Encoder::Svt.encoder(),
+            VideoCodec::Dnxhr => "dnxhd",
+            VideoCodec::J2k => "libopenjpeg",
+        }
+    }
+
+    /// Constant quality the codec is encoded at unless the job sets one or
+    /// a bitrate: visually lossless for x264 and x265, and about as good
+    /// for AV1.
+    pub fn default_crf(self) -> u8 {
+        match self {
+            VideoCodec::H264 => 18,
+            VideoCodec::Av1 => 30,
+            _ => 20,
+        }
+    }
+
+    /// Highest (worst) constant quality the codec accepts.
+    pub fn max_crf(self) -> u8 {
+        if self == VideoCodec::Av1 {
+            63
+        } else {
+            51
+        }
+    }
+
+    /// Bits per channel the codec is encoded with in `profile` unless the
+    /// job sets them.
+    pub fn default_bit_depth(self, profile: Option<&str>) -> u8 {
+        match (self, profile) {
+            (VideoCodec::J2k, Some("cinema2k" | "cinema4k")) => 12,
+            (VideoCodec::Prores | VideoCodec::J2k, _) | (VideoCodec::Dnxhr, Some("hqx" | "444")) => 10,
+            _ => 8,
+        }
+    }
+
+    /// Bits per channel the codec can be encoded with.
+    pub fn bit_depths(self) -> &'static [u8] {
+        match self {
+            VideoCodec::H264 => &[8],
+            VideoCodec::Hevc | VideoCodec::Av1 | VideoCodec::Dnxhr => &[8, 10],
+            VideoCodec::Prores => &[10],
+            VideoCodec::J2k => &[8, 10, 12],
+        }
+    }
+
+    /// Fail unless the codec can be encoded with `depth` bits per channel.
+    pub fn check_bit_depth(self, depth: u8) -> std::result::Result<(), String> {
+        if self.bit_depths().contains(&depth) {
+            return Ok(());
+        }
+        let supported: Vec<String> = self.bit_depths().iter().map(|d| d.to_string()).collect();
+        Err(format!("{} can't be encoded with {} bits per channel, only {}", self, depth, supported.join(", ")))
+    }
+
+    /// Whether the codec can carry an alpha channel.
+    pub fn supports_alpha(self) -> bool {
+        self == VideoCodec::Prores
+    }
+
+    /// Whether the codec can be encoded to a target bitrate.
+    pub fn supports_bitrate(self) -> bool {
+        matches!(self, VideoCodec::H264 | VideoCodec::Hevc | VideoCodec::Av1)
+    }
+
+    /// Whether the codec can be encoded in two passes.
+    pub fn supports_two_pass(self) -> bool {
+        matches!(self, VideoCodec::H264 | VideoCodec::Hevc)
+    }
+
+    /// Whether the encoder takes a peak bitrate and buffer size.
+    pub fn supports_maxrate(self) -> bool {
+        matches!(self, VideoCodec::H264 | VideoCodec::Hevc)
+    }
+
+    /// Encoder presets the codec accepts, fastest first.
+    pub fn presets(self) -> &'static [&'static str] {
+        match self {
+            VideoCodec::H264 | VideoCodec::Hevc | VideoCodec::Av1 => X26X_PRESETS,
+            VideoCodec::Prores | VideoCodec::Dnxhr | VideoCodec::J2k => &[],
+        }
+    }
+
+    /// Profiles the codec can be encoded with.
+    pub fn profiles(self) -> &'static [&'static str] {
+        match self {
+            VideoCodec::H264 => &["baseline", "main", "high"],
+            VideoCodec::Hevc => &["main", "main10"],
+            VideoCodec::Prores => &["proxy", "lt", "standard", "hq", "4444", "4444xq"],
+            VideoCodec::Av1 => &["main"],
+            VideoCodec::Dnxhr => &["lb", "sq", "hq", "hqx", "444"],
+            VideoCodec::J2k => &["cinema2k", "cinema4k"],
+        }
+    }
+
+    /// Levels the codec can be encoded with.
+    pub fn levels(self) -> &'static [&'static str] {
+        match self {
+            VideoCodec::H264 => &[
+                "1", "1b", "1.1", "1.2", "1.3", "2", "2.1", "2.2", "3", "3.1", "3.2", "4", "4.1", "4.2", "5", "5.1",
+                "5.2", "6", "6.1", "6.2",
+            ],
+            VideoCodec::Hevc => &["1", "2", "2.1", "3", "3.1", "4", "4.1", "5", "5.1", "5.2", "6", "6.1", "6.2"],
+            VideoCodec::Prores | VideoCodec::Av1 | VideoCodec::Dnxhr | VideoCodec::J2k => &[],
+        }
+    }
+
+    /// Fail unless the codec can be encoded with `settings` at `depth` bits
+    /// per channel, with alpha if `alpha` is set.
+    pub fn check_settings(self, settings: &VideoSettings, depth: u8, alpha: bool) -> std::result::Result<(), String> {
+        let rate_control = [
+            ("--crf", settings.crf.is_some()),
+            ("--bitrate", settings.bitrate.is_some()),
+            ("--maxrate", settings.maxrate.is_some()),
+            ("--bufsize", settings.bufsize.is_some()),
+            ("--preset", settings.preset.is_some()),
+            ("--level", settings.level.is_some()),
+        ];
+        if !self.supports_bitrate() {
+            if let Some((flag, _)) = rate_control.iter().find(|(_, set)| *set) {
+                return Err(format!(
+                    "{} is encoded at a fixed quality per profile; {} needs --codec h264, hevc or av1",
+                    self, flag
+                ));
+            }
+        }
+        if settings.crf.is_some() && settings.bitrate.is_some() {
+            return Err("--crf and --bitrate can't be combined; pick constant quality or a bitrate".to_string());
+        }
+        if settings.crf.is_some_and(|crf| crf > self.max_crf()) {
+            return Err(format!(
+                "{} --crf must be 0 to {}, not {}",
+                self,
+                self.max_crf(),
+                settings.crf.unwrap_or_default()
+            ));
+        }
+        if !self.supports_maxrate() {
+            if let Some((flag, _)) = rate_control[2..4].iter().find(|(_, set)| *set) {
+                return Err(format!("{} video can't be capped with {}; use --bitrate or --crf", self, flag));
+            }
+        }
+        if settings.bufsize.is_some() && settings.maxrate.is_none() {
+            return Err("--bufsize needs --maxrate".to_string());
+        }
+        if let (Some(bitrate), Some(maxrate)) = (settings.bitrate, settings.maxrate) {
+            if maxrate < bitrate {
+                return Err(format!("--maxrate {} is below --bitrate {}", maxrate, bitrate));
+            }
+        }
+        for (flag, value, allowed) in [
+            ("--preset", &settings.preset, self.presets()),
+            ("--profile", &settings.profile, self.profiles()),
+            ("--level", &settings.level, self.levels()),
+        ] {
+            match value.as_deref().filter(|v| !allowed.contains(v)) {
+                Some(_) if allowed.is_empty() => return Err(format!("{} has no {} setting", self, flag)),
+                Some(value) => {
+                    let allowed = allowed.join(", ");
+                    return Err(format!("{} can't be encoded with {} {}, only {}", self, flag, value, allowed));
+                }
+                None => {}
+            }
+        }
+        match (self, settings.profile.as_deref(), depth) {
+            (VideoCodec::Hevc, Some(profile @ "main"), 10) | (VideoCodec::Hevc, Some(profile @ "main10"), 8) => {
+                Err(format!("HEVC profile {} doesn't match {} bits per channel", profile, depth))
+            }
+            (VideoCodec::Prores, Some(profile), _) if alpha && !profile.starts_with("4444") => {
+                Err(format!("ProRes {} has no alpha channel; use --profile 4444 or 4444xq", profile))
+            }
+            (VideoCodec::Dnxhr, Some(profile @ ("hqx" | "444")), 8) => {
+                Err(format!("DNxHR {} is 10-bit; use --bit-depth 10 or --profile hq", profile))
+            }
+            (VideoCodec::Dnxhr, Some(profile @ ("lb" | "sq" | "hq")), 10) => {
+                Err(format!("DNxHR {} is 8-bit; use --profile hqx or 444 for 10 bits", profile))
+            }
+            (VideoCodec::J2k, Some(profile), depth) if depth != 12 => {
+                Err(format!("JPEG 2000 {} is 12-bit X'Y'Z'; drop --bit-depth", profile))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Encoder options passed after `-c:v`: visually lossless quality for
+    /// delivery unless `settings` ask for a bitrate, with alpha if `alpha`
+    /// is set. `pass` is the pass (1 or 2) of a two-pass encode and the file
+    /// it keeps its statistics in. HEVC carries the HDR metadata of `hdr`.
+    pub fn encoder_args(
+        self,
+        depth: u8,
+        alpha: bool,
+        settings: &VideoSettings,
+        pass: Option<(u8, &Path)>,
+        hdr: Option<&SourceColor>,
+    ) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut push = |option: &str, value: String| {
+            args.push(option.to_string());
+            args.push(value);
+        };
+        if self == VideoCodec::Prores {
+            // 4444 profiles sample chroma fully, and only they carry alpha
+            let profile = settings.profile.as_deref().unwrap_or(if alpha { "4444" } else { "hq" });
+            let number = self.profiles().iter().position(|p| *p == profile).unwrap_or(3);
+            push("-profile:v", number.to_string());
+            let pix_fmt = match (profile.starts_with("4444"), alpha) {
+                (true, true) => "yuva444p10le",
+                (true, false) => "yuv444p10le",
+                (false, _) => "yuv422p10le",
+            };
+            push("-pix_fmt", pix_fmt.to_string());
+            return args;
+        }
+        if self == VideoCodec::Av1 {
+            return Av1Encoder::Svt.encoder_args(depth, settings);
+        }
+        if self == VideoCodec::Dnxhr {
+            let profile = settings.profile.as_deref().unwrap_or(if depth == 10 { "hqx" } else { "hq" });
+            push("-profile:v", format!("dnxhr_{}", profile));
+            let pix_fmt = match (profile, depth) {
+                ("444", _) => "yuv444p10le",
+                (_, 10) => "yuv422p10le",
+                _ => "yuv422p",
+            };
+            push("-pix_fmt", pix_fmt.to_string());
+            return args;
+        }
+        if let (VideoCodec::J2k, Some(profile)) = (self, &settings.profile) {
+            // DCI frames: X'Y'Z' within the per-frame size limit of 24 fps
+            push("-profile:v", profile.clone());
+            push("-cinema_mode", if profile == "cinema4k" { "4k_24" } else { "2k_24" }.to_string());
+            push("-pix_fmt", "xyz12le".to_string());
+            return args;
+        }
+        if self == VideoCodec::J2k {
+            let pix_fmt = match depth {
+                12 => "yuv422p12le",
+                10 => "yuv422p10le",
+                _ => "yuv422p",
+            };
+            push("-pix_fmt", pix_fmt.to_string());
+            return args;
+        }
+
+        match (settings.bitrate, settings.crf) {
+            (Some(bitrate), _) => push("-b:v", bitrate.to_string()),
+            (None, crf) => push("-crf", crf.unwrap_or(self.default_crf()).to_string()),
+        }
+        if let Some(maxrate) = settings.maxrate {
+            push("-maxrate", maxrate.to_string());
+            push("-bufsize", settings.bufsize.unwrap_or(maxrate * 2).to_string());
+        }
+        push("-preset", settings.preset.clone().unwrap_or_else(|| "medium".to_string()));
+        if let Some(profile) = &settings.profile {
+            push("-profile:v", profile.clone());
+        }
+        push("-pix_fmt", if depth == 10 { "yuv420p10le" } else { "yuv420p" }.to_string());
+
+        // Options ffmpeg has no flag for go through the encoder's own
+        // `:` separated key=value params, of which only one list is used
+        let mut params = Vec::new();
+        match (self, &settings.level) {
+            (VideoCodec::H264, Some(level)) => push("-level:v", level.clone()),
+            (VideoCodec::Hevc, Some(level)) => params.push(format!("level-idc={}", level)),
+            _ => {}
+        }
+        if let Some((pass, stats)) = pass {
+            let mut path = String::new();
+            for c in stats.to_string_lossy().chars() {
+                if matches!(c, '\\' | '\'' | ':' | '=') {
+                    path.push('\\');
+                }
+                path.push(c);
+            }
+            params.push(format!("pass={}", pass));
+            params.push(format!("stats={}", path));
+        }
+        if let (VideoCodec::Hevc, Some(hdr)) = (self, hdr) {
+            params.extend(hdr.x265_params());
+        }
+        if !params.is_empty() {
+            let option = if self == VideoCodec::H264 { "-x264-params" } else { "-x265-params" };
+            push(option, params.join(":"));
+        }
+        if self == VideoCodec::Hevc {
+            push("-tag:v", "hvc1".to_string());
+        }
+        args
+    }
+
+    /// Typical size of a frame per pixel, for disk space estimates.
+    pub fn bytes_per_pixel(self) -> f64 {
+        match self {
+            VideoCodec::H264 => 0.05,
+            VideoCodec::Hevc => 0.03,
+            VideoCodec::Prores => 0.5,
+            VideoCodec::Av1 => 0.02,
+            VideoCodec::Dnxhr => 0.45,
+            VideoCodec::J2k => 0.6,
+        }
+    }
+}
+
+impl fmt::Display for VideoCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VideoCodec::H264 => "H.264",
+            VideoCodec::Hevc => "HEVC",
+            VideoCodec::Prores => "ProRes",
+            VideoCodec::Av1 => "AV1",
+            VideoCodec::Dnxhr => "DNxHR",
+            VideoCodec::J2k => "JPEG 2000",
+        })
+    }
+}
+
+impl FromStr for VideoCodec {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<VideoCodec, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "h264" | "avc" => Ok(VideoCodec::H264),
+            "hevc" | "h265" => Ok(VideoCodec::Hevc),
+            "prores" => Ok(VideoCodec::Prores),
+            "av1" => Ok(VideoCodec::Av1),
+            "dnxhr" => Ok(VideoCodec::Dnxhr),
+            "j2k" | "jpeg2000" => Ok(VideoCodec::J2k),
+            _ => Err(format!("invalid codec '{}', expected h264, hevc, prores, av1, dnxhr or j2k", text)),
+        }
+    }
+}
+
+/// Container of video output. Segments are encoded to the codec's own
+/// container and joined into this one.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    Mp4,
+    /// QuickTime.
+    Mov,
+    /// MXF OP1a, for broadcast and Avid.
+    Mxf,
+}
+
+impl Container {
+    /// File extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Container::Mp4 => "mp4",
+            Container::Mov => "mov",
+            Container::Mxf => "mxf",
+        }
+    }
+
+    /// Fail unless the container can hold `codec` video.
+    pub fn check_codec(self, codec: VideoCodec) -> std::result::Result<(), String> {
+        let supported = match self {
+            Container::Mp4 => matches!(codec, VideoCodec::H264 | VideoCodec::Hevc | VideoCodec::Av1),
+            Container::Mov => codec != VideoCodec::Av1,
+            Container::Mxf => matches!(codec, VideoCodec::Prores | VideoCodec::Dnxhr | VideoCodec::J2k),
+        };
+        if supported {
+            Ok(())
+        } else {
+            Err(format!("{} video can't be delivered in {}", codec, self))
+        }
+    }
+}
+
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Container::Mp4 => "MP4",
+            Container::Mov => "QuickTime",
+            Container::Mxf => "MXF",
+        })
+    }
+}
+
+impl FromStr for Container {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Container, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "mp4" => Ok(Container::Mp4),
+            "mov" => Ok(Container::Mov),
+            "mxf" => Ok(Container::Mxf),
+            _ => Err(format!("invalid container '{}', expected mp4, mov or mxf", text)),
+        }
+    }
+}
+
+/// Lossless codec segments are encoded to instead of image sequences; the
+/// chunks are expanded to frames when the segments are combined.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IntermediateCodec {
+    /// FFV1 level 3, up to 16 bits per channel.
+    Ffv1,
+    /// Ut Video, 8 bits per channel only, faster than FFV1.
+    Utvideo,
+}
+
+impl IntermediateCodec {
+    /// Container extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        "mkv"
+    }
+
+    /// ffmpeg encoder for the codec.
+    pub fn encoder(self) -> &'static str {
+        match self {
+            IntermediateCodec::Ffv1 => "ffv1",
+            IntermediateCodec::Utvideo => "utvideo",
+        }
+    }
+
+    /// Bits per channel the codec stores losslessly.
+    pub fn bit_depths(self) -> &'static [u8] {
+        match self {
+            IntermediateCodec::Ffv1 => &[8, 10, 16],
+            IntermediateCodec::Utvideo => &[8],
+        }
+    }
+
+    /// Fail unless frames of `depth` bits per channel survive the codec
+    /// unchanged.
+    pub fn check_bit_depth(self, depth: u8) -> std::result::Result<(), String> {
+        if self.bit_depths().contains(&depth) {
+            return Ok(());
+        }
+        let supported: Vec<String> = self.bit_depths().iter().map(|d| d.to_string()).collect();
+        Err(format!(
+            "{} can't hold {} bits per channel losslessly, only {}; use a different --intermediate",
+            self,
+            depth,
+            supported.join(", ")
+        ))
+    }
+
+    /// Encoder options passed after `-c:v`: planar RGB(A) at `depth`, every
+    /// frame a keyframe so chunks can be cut and expanded anywhere.
+    pub fn encoder_args(self, depth: u8, alpha: bool) -> Vec<String> {
+        let pix_fmt = match (depth, alpha) {
+            (10, false) => "gbrp10le",
+            (10, true) => "gbrap10le",
+            (16, false) => "gbrp16le",
+            (16, true) => "gbrap16le",
+            (_, false) => "gbrp",
+            (_, true) => "gbrap",
+        };
+        let args: &[&str] = match self {
+            IntermediateCodec::Ffv1 => &["-level", "3", "-g", "1", "-slices", "16", "-slicecrc", "1"],
+            IntermediateCodec::Utvideo => &["-pred", "median"],
+        };
+        let mut args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        args.extend(["-pix_fmt".to_string(), pix_fmt.to_string()]);
+        args
+    }
+
+    /// Typical size of a frame per pixel, for disk space estimates: about
+    /// half of uncompressed RGB.
+    pub fn bytes_per_pixel(self, depth: u8) -> f64 {
+        let sample = if depth > 8 { 2.0 } else { 1.0 };
+        3.0 * sample * 0.5
+    }
+}
+
+impl fmt::Display for IntermediateCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IntermediateCodec::Ffv1 => "FFV1",
+            IntermediateCodec::Utvideo => "Ut Video",
+        })
+    }
+}
+
+impl FromStr for IntermediateCodec {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<IntermediateCodec, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "ffv1" => Ok(IntermediateCodec::Ffv1),
+            "utvideo" => Ok(IntermediateCodec::Utvideo),
+            _ => Err(format!("invalid intermediate codec '{}', expected ffv1 or utvideo", text)),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Colour the guides are drawn in.
+const GUIDE_COLOR: &str = "white@0.6";
+
+/// A broadcast guide drawn over review output.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Guide {
+    /// The action-safe area, 93% of the width and height (SMPTE ST 2046-1).
+    ActionSafe,
+    /// The title-safe area, 90% of the width and height (SMPTE ST 2046-1).
+    TitleSafe,
+    /// A cross marking the centre of the frame.
+    CenterCross,
+}
+
+impl Guide {
+    // drawbox filters drawing the guide. Lines are a pixel thick per 540
+    // lines of picture, and the cross's arms are as long as each other on
+    // screen whatever the pixel aspect ratio.
+    fn filters(self) -> Vec<String> {
+        let safe_area = |margin: &str, share: &str| {
+            format!("drawbox=x=iw*{0}:y=ih*{0}:w=iw*{1}:h=ih*{1}:color={2}:t=ceil(ih/540)", margin, share, GUIDE_COLOR)
+        };
+        match self {
+            Guide::ActionSafe => vec![safe_area("0.035", "0.93")],
+            Guide::TitleSafe => vec![safe_area("0.05", "0.9")],
+            Guide::CenterCross => vec![
+                format!("drawbox=x=(iw-w)/2:y=(ih-h)/2:w=ih*0.05/sar:h=ceil(ih/540):color={}:t=fill", GUIDE_COLOR),
+                format!("drawbox=x=(iw-w)/2:y=(ih-h)/2:w=ceil(ih/540)/sar:h=ih*0.05:color={}:t=fill", GUIDE_COLOR),
+            ],
+        }
+    }
+}
+
+impl FromStr for Guide {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Guide, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "action-safe" => Ok(Guide::ActionSafe),
+            "title-safe" => Ok(Guide::TitleSafe),
+            "center-cross" => Ok(Guide::CenterCross),
+            _ => Err(format!("invalid guide '{}', expected action-safe, title-safe or center-cross", text)),
+        }
+    }
+}
+
+impl fmt::Display for Guide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Guide::ActionSafe => "action-safe",
+            Guide::TitleSafe => "title-safe",
+            Guide::CenterCross => "center-cross",
+        })
+    }
+}
+
+/// Filters appended to a graph with one unlabeled output to draw `guides`
+/// over it. They are drawn relative to the frame, so they fit any size and
+/// aspect ratio.
+pub fn filter_suffix(guides: &[Guide]) -> String {
+    guides.iter().flat_map(|g| g.filters()).map(|f| format!(",{}", f)).collect()
+}
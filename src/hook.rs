@@ -0,0 +1,189 @@
+use crate::ffmpeg;
+use crate::segment::Segment;
+use crate::smpte::EditRate;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+/// Directory of a segment its frames are decoded to for the hook in
+/// [`HookMode::Files`].
+pub const INPUT_DIR: &str = "watermark_in";
+
+/// Directory of a segment the hook writes the watermarked frames to in
+/// [`HookMode::Files`].
+pub const OUTPUT_DIR: &str = "watermark_out";
+
+/// Names of the frames handed to the hook in [`HookMode::Files`].
+const FRAME_PATTERN: &str = "%06d.png";
+
+/// How frames are handed to a watermark hook.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HookMode {
+    /// Raw frames on the hook's stdin, watermarked frames on its stdout, one
+    /// after another at the source's size.
+    #[default]
+    Pipe,
+    /// A directory of PNG frames per segment, watermarked into another.
+    Files,
+}
+
+impl FromStr for HookMode {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<HookMode, String> {
+        match text.trim() {
+            "pipe" => Ok(HookMode::Pipe),
+            "files" => Ok(HookMode::Files),
+            _ => Err(format!("invalid hook mode '{}', expected pipe or files", text)),
+        }
+    }
+}
+
+impl fmt::Display for HookMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            HookMode::Pipe => "pipe",
+            HookMode::Files => "files",
+        })
+    }
+}
+
+/// An external command every segment's composited frames pass through
+/// between decoding and encoding, e.g. a forensic watermark vendor's tool.
+///
+/// It runs through the shell once per segment, told about the frames in
+/// `DELIVERY_*` environment variables: `WIDTH`, `HEIGHT`, `PIX_FMT` and
+/// `FRAME_RATE` of the frames, the `SEGMENT` id, `START` time in the source
+/// and number of `FRAMES` (if known), the `JOB_ID` and `RECIPIENT`, and in
+/// files mode the `INPUT_DIR` and `OUTPUT_DIR`. It must hand back as many
+/// frames as it was given, in the same size and format.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WatermarkHook {
+    pub command: String,
+    #[serde(default)]
+    pub mode: HookMode,
+    /// Size and rate of the source's frames, once probed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_rate: Option<f64>,
+}
+
+impl WatermarkHook {
+    /// `command`, handed frames in `mode`.
+    pub fn new(command: impl Into<String>, mode: HookMode) -> WatermarkHook {
+        WatermarkHook { command: command.into(), mode, width: None, height: None, frame_rate: None }
+    }
+
+    /// Whether the size and rate of the frames are still to be probed.
+    pub fn unprobed(&self) -> bool {
+        self.width.is_none() || self.height.is_none() || self.frame_rate.is_none()
+    }
+
+    /// Pixel format of the frames handed to the hook: RGB with `alpha` or
+    /// without, at 8 or 16 bits per channel for `bit_depth`. Raw frames are
+    /// little-endian; PNG stores big-endian.
+    pub fn pixel_format(&self, bit_depth: u8, alpha: bool) -> &'static str {
+        match (self.mode, bit_depth > 8, alpha) {
+            (_, false, false) => "rgb24",
+            (_, false, true) => "rgba",
+            (HookMode::Pipe, true, false) => "rgb48le",
+            (HookMode::Pipe, true, true) => "rgba64le",
+            (HookMode::Files, true, false) => "rgb48be",
+            (HookMode::Files, true, true) => "rgba64be",
+        }
+    }
+
+    /// Output options ending the decode of `segment`, writing its frames in
+    /// `pix_fmt` to stdout or to its input directory.
+    pub fn decode_output_args(&self, segments_dir: &Path, segment: &Segment, pix_fmt: &str) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec!["-pix_fmt".into(), pix_fmt.into()];
+        match self.mode {
+            HookMode::Pipe => args.extend(["-f", "rawvideo", "pipe:1"].map(OsString::from)),
+            HookMode::Files => {
+                let pattern = ffmpeg::sequence_pattern(&input_dir(segments_dir, segment), FRAME_PATTERN);
+                args.extend(["-y".into(), pattern]);
+            }
+        }
+        args
+    }
+
+    /// Input options of the encode of `segment`, reading the watermarked
+    /// frames in `pix_fmt` from stdin or from its output directory.
+    pub fn encode_input_args(&self, segments_dir: &Path, segment: &Segment, pix_fmt: &str) -> Vec<OsString> {
+        let rate = EditRate::from_fps(self.frame_rate.unwrap_or_default());
+        let rate = format!("{}/{}", rate.numerator, rate.denominator);
+        let mut args: Vec<OsString> = vec!["-framerate".into(), rate.into()];
+        match self.mode {
+            HookMode::Pipe => {
+                let size = format!("{}x{}", self.width.unwrap_or_default(), self.height.unwrap_or_default());
+                args.extend(["-f", "rawvideo", "-pix_fmt", pix_fmt, "-s", &size, "-i", "pipe:0"].map(OsString::from));
+            }
+            HookMode::Files => {
+                let pattern = ffmpeg::sequence_pattern(&output_dir(segments_dir, segment), FRAME_PATTERN);
+                args.extend(["-i".into(), pattern]);
+            }
+        }
+        args
+    }
+
+    /// The hook's invocation for `segment`, with frames in `pix_fmt`, for
+    /// the job `job_id` and `recipient`, if any.
+    pub fn command(
+        &self,
+        segments_dir: &Path,
+        segment: &Segment,
+        pix_fmt: &str,
+        job_id: &str,
+        recipient: Option<&str>,
+    ) -> Command {
+        let mut cmd = shell(&self.command);
+        let number = |n: Option<u32>| n.map(|n| n.to_string()).unwrap_or_default();
+        cmd.env("DELIVERY_WIDTH", number(self.width))
+            .env("DELIVERY_HEIGHT", number(self.height))
+            .env("DELIVERY_PIX_FMT", pix_fmt)
+            .env("DELIVERY_FRAME_RATE", self.frame_rate.map(|r| r.to_string()).unwrap_or_default())
+            .env("DELIVERY_SEGMENT", segment.id.to_string())
+            .env("DELIVERY_START", format!("{:.6}", segment.start))
+            .env("DELIVERY_FRAMES", segment.frames.map(|n| n.to_string()).unwrap_or_default())
+            .env("DELIVERY_JOB_ID", job_id)
+            .env("DELIVERY_RECIPIENT", recipient.unwrap_or_default());
+        if self.mode == HookMode::Files {
+            cmd.env("DELIVERY_INPUT_DIR", input_dir(segments_dir, segment))
+                .env("DELIVERY_OUTPUT_DIR", output_dir(segments_dir, segment));
+        }
+        cmd
+    }
+}
+
+/// Directory `segment`'s frames are decoded to for the hook in files mode.
+pub fn input_dir(segments_dir: &Path, segment: &Segment) -> PathBuf {
+    segment.dir(segments_dir).join(INPUT_DIR)
+}
+
+/// Directory the hook writes `segment`'s watermarked frames to in files
+/// mode.
+pub fn output_dir(segments_dir: &Path, segment: &Segment) -> PathBuf {
+    segment.dir(segments_dir).join(OUTPUT_DIR)
+}
+
+// `command` run by the system's shell.
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
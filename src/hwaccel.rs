@@ -0,0 +1,198 @@
+use crate::console::info;
+use crate::ffmpeg::Capabilities;
+use crate::format::{VideoCodec, VideoSettings};
+use crate::{DeliveryError, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Hardware the source is decoded and video output encoded with.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum HwAccel {
+    /// Whichever of the others this machine's ffmpeg supports, or none.
+    /// Resolved when the job runs.
+    Auto,
+    /// Apple VideoToolbox (macOS).
+    Videotoolbox,
+    /// NVIDIA NVDEC decoding and NVENC encoding.
+    Nvenc,
+    /// Intel Quick Sync Video.
+    Qsv,
+    /// Decode and encode on the CPU.
+    #[default]
+    None,
+}
+
+impl HwAccel {
+    /// Accelerations `Auto` tries on this platform, best first.
+    fn candidates() -> &'static [HwAccel] {
+        if cfg!(target_os = "macos") {
+            &[HwAccel::Videotoolbox]
+        } else {
+            &[HwAccel::Nvenc, HwAccel::Qsv]
+        }
+    }
+
+    /// Method passed to `-hwaccel` for decoding, as `ffmpeg -hwaccels` lists
+    /// it.
+    pub fn method(self) -> Option<&'static str> {
+        match self {
+            HwAccel::Videotoolbox => Some("videotoolbox"),
+            HwAccel::Nvenc => Some("cuda"),
+            HwAccel::Qsv => Some("qsv"),
+            HwAccel::Auto | HwAccel::None => None,
+        }
+    }
+
+    /// Options placed before the source's `-i` to decode it in hardware, on
+    /// GPU `device` if set. ffmpeg falls back to software for codecs the
+    /// hardware can't decode.
+    pub fn decode_args(self, device: Option<usize>) -> Vec<String> {
+        let Some(method) = self.method() else { return Vec::new() };
+        let mut args = vec!["-hwaccel".to_string(), method.to_string()];
+        if let Some(device) = device {
+            args.extend(["-hwaccel_device".to_string(), device.to_string()]);
+        }
+        args
+    }
+
+    /// Hardware encoder for `codec`, if there is one; ProRes is always
+    /// encoded in software.
+    pub fn encoder(self, codec: VideoCodec) -> Option<&'static str> {
+        match (self, codec) {
+            (HwAccel::Videotoolbox, VideoCodec::H264) => Some("h264_videotoolbox"),
+            (HwAccel::Videotoolbox, VideoCodec::Hevc) => Some("hevc_videotoolbox"),
+            (HwAccel::Nvenc, VideoCodec::H264) => Some("h264_nvenc"),
+            (HwAccel::Nvenc, VideoCodec::Hevc) => Some("hevc_nvenc"),
+            (HwAccel::Qsv, VideoCodec::H264) => Some("h264_qsv"),
+            (HwAccel::Qsv, VideoCodec::Hevc) => Some("hevc_qsv"),
+            _ => None,
+        }
+    }
+
+    /// Options of the hardware encoder for `codec` passed after `-c:v`: a
+    /// quality close to the software encoder's for the CRF in `settings`,
+    /// unless they ask for a bitrate, on NVIDIA GPU `device` if set.
+    pub fn encoder_args(
+        self,
+        codec: VideoCodec,
+        depth: u8,
+        settings: &VideoSettings,
+        device: Option<usize>,
+    ) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut push = |option: &str, value: String| {
+            args.push(option.to_string());
+            args.push(value);
+        };
+        if let (HwAccel::Nvenc, Some(device)) = (self, device) {
+            push("-gpu", device.to_string());
+        }
+        let crf = settings.crf.unwrap_or(codec.default_crf());
+        match (self, settings.bitrate) {
+            (_, Some(bitrate)) => push("-b:v", bitrate.to_string()),
+            (HwAccel::Nvenc, None) => {
+                push("-rc", "vbr".to_string());
+                push("-cq", crf.to_string());
+                push("-b:v", "0".to_string());
+            }
+            (HwAccel::Qsv, None) => push("-global_quality", crf.to_string()),
+            // VideoToolbox quality runs from 0 to 100, the other way round
+            (_, None) => push("-q:v", ((51 - crf.min(51) as u32) * 100 / 51).to_string()),
+        }
+        if let Some(maxrate) = settings.maxrate {
+            push("-maxrate", maxrate.to_string());
+            push("-bufsize", settings.bufsize.unwrap_or(maxrate * 2).to_string());
+        }
+        match self {
+            HwAccel::Nvenc => {
+                push("-preset", "p6".to_string());
+                push("-tune", "hq".to_string());
+            }
+            HwAccel::Qsv => push("-preset", "slow".to_string()),
+            _ => {}
+        }
+        if let Some(profile) = &settings.profile {
+            push("-profile:v", profile.clone());
+        }
+        if let Some(level) = &settings.level {
+            push("-level:v", level.clone());
+        }
+        let pix_fmt = match (self, depth) {
+            (_, 10) => "p010le",
+            (HwAccel::Qsv, _) => "nv12",
+            _ => "yuv420p",
+        };
+        push("-pix_fmt", pix_fmt.to_string());
+        if codec == VideoCodec::Hevc {
+            push("-tag:v", "hvc1".to_string());
+        }
+        args
+    }
+
+    /// The acceleration to use for this request with an ffmpeg of `caps`,
+    /// encoding `codec` if the job delivers video: `Auto` becomes the first
+    /// candidate the build supports, or `None`. Fails if an explicitly
+    /// requested one isn't supported.
+    pub fn resolve(self, caps: &Capabilities, codec: Option<VideoCodec>) -> Result<HwAccel> {
+        if self == HwAccel::Auto {
+            let found = HwAccel::candidates().iter().copied().find(|a| a.supported(caps, codec));
+            match found {
+                Some(accel) => info!("⚡ Using {} hardware acceleration", accel),
+                None => info!("ℹ️ No hardware acceleration available, using the CPU"),
+            }
+            return Ok(found.unwrap_or(HwAccel::None));
+        }
+        if self.method().is_some() && !self.supported(caps, None) {
+            return Err(DeliveryError::MissingCapability(format!(
+                "Your ffmpeg ({}) lacks {} hardware acceleration; `ffmpeg -hwaccels` lists {}",
+                caps.version,
+                self,
+                if caps.hwaccels.is_empty() {
+                    "none".to_string()
+                } else {
+                    let mut methods: Vec<&str> = caps.hwaccels.iter().map(String::as_str).collect();
+                    methods.sort_unstable();
+                    methods.join(", ")
+                }
+            )));
+        }
+        Ok(self)
+    }
+
+    // Whether the build can decode with this acceleration, and has its
+    // encoder for `codec`.
+    fn supported(self, caps: &Capabilities, codec: Option<VideoCodec>) -> bool {
+        let encoder = codec.and_then(|c| self.encoder(c));
+        self.method().is_some_and(|m| caps.hwaccels.contains(m))
+            && encoder.is_none_or(|e| caps.encoders.contains(e))
+    }
+}
+
+impl FromStr for HwAccel {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<HwAccel, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(HwAccel::Auto),
+            "videotoolbox" => Ok(HwAccel::Videotoolbox),
+            "nvenc" => Ok(HwAccel::Nvenc),
+            "qsv" => Ok(HwAccel::Qsv),
+            "none" => Ok(HwAccel::None),
+            _ => Err(format!("invalid hwaccel '{}', expected auto, videotoolbox, nvenc, qsv or none", text)),
+        }
+    }
+}
+
+impl fmt::Display for HwAccel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HwAccel::Auto => "auto",
+            HwAccel::Videotoolbox => "VideoToolbox",
+            HwAccel::Nvenc => "NVENC",
+            HwAccel::Qsv => "Quick Sync",
+            HwAccel::None => "none",
+        })
+    }
+}
@@ -0,0 +1,429 @@
+use crate::combine::segment_frames;
+use crate::format::FrameFormat;
+use crate::{DeliveryError, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// PNG file signature.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// PNG chunks describing the colour of the image, which an embedded profile
+/// replaces. cICP would take precedence over it, and the rest contradict it.
+const PNG_COLOR_CHUNKS: [&[u8]; 5] = [b"iCCP", b"sRGB", b"cICP", b"gAMA", b"cHRM"];
+
+/// TIFF tag holding an ICC profile.
+const TIFF_ICC_TAG: u16 = 34675;
+
+/// sRGB primaries adapted to the D50 profile connection space, and D50.
+const SRGB_RED: [f64; 3] = [0.436_074_7, 0.222_504_5, 0.013_932_2];
+const SRGB_GREEN: [f64; 3] = [0.385_064_9, 0.716_878_6, 0.097_104_5];
+const SRGB_BLUE: [f64; 3] = [0.143_080_4, 0.060_616_9, 0.714_173_3];
+const D50: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+/// ICC profile embedded in PNG and TIFF frames, so review and compositing
+/// apps don't have to guess their colour.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IccProfile {
+    /// sRGB IEC61966-2.1, built in.
+    Srgb,
+    /// A profile read from a file.
+    File(PathBuf),
+}
+
+impl IccProfile {
+    /// The profile with a relative file resolved against `base`.
+    pub fn relative_to(self, base: &Path) -> IccProfile {
+        match self {
+            IccProfile::File(path) => IccProfile::File(base.join(path)),
+            srgb => srgb,
+        }
+    }
+
+    /// The profile's bytes. Fails if a file can't be read or isn't a profile
+    /// for RGB images.
+    pub fn load(&self) -> Result<Vec<u8>> {
+        let path = match self {
+            IccProfile::Srgb => return Ok(srgb()),
+            IccProfile::File(path) => path,
+        };
+        let data =
+            fs::read(path).map_err(|e| DeliveryError::io(format!("Failed to read ICC profile {}", path.display()), e))?;
+        let declared = data.get(..4).map(|size| u32::from_be_bytes(size.try_into().unwrap()) as usize);
+        if data.len() < 128 || &data[36..40] != b"acsp" || declared != Some(data.len()) {
+            return Err(DeliveryError::Config(format!("{} is not an ICC profile", path.display())));
+        }
+        if &data[16..20] != b"RGB " {
+            return Err(DeliveryError::Config(format!(
+                "ICC profile {} is for {} images, frames are RGB",
+                path.display(),
+                String::from_utf8_lossy(&data[16..20]).trim()
+            )));
+        }
+        Ok(data)
+    }
+}
+
+impl FromStr for IccProfile {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<IccProfile, String> {
+        match text.trim() {
+            "" => Err("expected srgb or an ICC profile file".to_string()),
+            srgb if srgb.eq_ignore_ascii_case("srgb") => Ok(IccProfile::Srgb),
+            path => Ok(IccProfile::File(PathBuf::from(path))),
+        }
+    }
+}
+
+impl fmt::Display for IccProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IccProfile::Srgb => f.write_str("sRGB"),
+            IccProfile::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Embed `profile` in every `format` frame in `dir`, in place of the colour
+/// information the encoder wrote. Only PNG and TIFF frames can hold one.
+/// Returns the number of frames.
+pub fn embed_frames(dir: &Path, format: FrameFormat, profile: &[u8]) -> Result<usize> {
+    let frames = segment_frames(dir, format.extension())
+        .map_err(|e| DeliveryError::io(format!("Failed to read {}", dir.display()), e))?;
+    for frame in &frames {
+        let context = || format!("Failed to embed the ICC profile in {}", frame.display());
+        let data = fs::read(frame).map_err(|e| DeliveryError::io(context(), e))?;
+        let tagged = match format {
+            FrameFormat::Png => embed_png(&data, profile),
+            FrameFormat::Tiff => embed_tiff(&data, profile),
+            _ => None,
+        };
+        let tagged = tagged.ok_or_else(|| {
+            DeliveryError::io(context(), io::Error::new(io::ErrorKind::InvalidData, format!("not a {} file", format)))
+        })?;
+        fs::write(frame, tagged).map_err(|e| DeliveryError::io(context(), e))?;
+    }
+    Ok(frames.len())
+}
+
+// `png` with an iCCP chunk holding `profile` right after the header, and
+// none of the colour chunks it had. None if it isn't a PNG.
+fn embed_png(png: &[u8], profile: &[u8]) -> Option<Vec<u8>> {
+    let rest = png.strip_prefix(PNG_SIGNATURE)?;
+    let mut iccp = b"ICC profile\0\0".to_vec();
+    iccp.extend(zlib_stored(profile));
+
+    let mut out = Vec::with_capacity(png.len() + iccp.len() + 12);
+    out.extend_from_slice(PNG_SIGNATURE);
+    let mut pos = 0;
+    while pos < rest.len() {
+        let length = u32::from_be_bytes(rest.get(pos..pos + 4)?.try_into().unwrap()) as usize;
+        let chunk = rest.get(pos..pos + 12 + length)?;
+        let kind = &chunk[4..8];
+        if !PNG_COLOR_CHUNKS.contains(&kind) {
+            out.extend_from_slice(chunk);
+        }
+        if kind == b"IHDR" {
+            out.extend((iccp.len() as u32).to_be_bytes());
+            let start = out.len();
+            out.extend_from_slice(b"iCCP");
+            out.extend_from_slice(&iccp);
+            let crc = crc32(&out[start..]);
+            out.extend(crc.to_be_bytes());
+        }
+        pos += chunk.len();
+    }
+    Some(out)
+}
+
+// `data` as a zlib stream of stored deflate blocks. Profiles are a few
+// kilobytes, not worth a compressor.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    // Empty data still needs a final (empty) block
+    let mut rest = data;
+    loop {
+        let (block, tail) = rest.split_at(rest.len().min(0xffff));
+        out.push(tail.is_empty() as u8);
+        let length = block.len() as u16;
+        out.extend(length.to_le_bytes());
+        out.extend((!length).to_le_bytes());
+        out.extend_from_slice(block);
+        if tail.is_empty() {
+            break;
+        }
+        rest = tail;
+    }
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    out.extend(((b << 16) | a).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 })
+    })
+}
+
+// `tiff` with `profile` appended and a copy of its first IFD that points at
+// it, replacing a profile it had. None if it isn't a TIFF (or is a BigTIFF).
+fn embed_tiff(tiff: &[u8], profile: &[u8]) -> Option<Vec<u8>> {
+    let big_endian = match tiff.get(..4)? {
+        b"II*\0" => false,
+        b"MM\0*" => true,
+        _ => return None,
+    };
+    let u16_at = |pos: usize| {
+        let bytes = tiff.get(pos..pos + 2)?.try_into().unwrap();
+        Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    };
+    let u16_bytes = |value: u16| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+    let u32_bytes = |value: u32| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+
+    let ifd = {
+        let bytes = tiff.get(4..8)?.try_into().unwrap();
+        (if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }) as usize
+    };
+    let count = u16_at(ifd)? as usize;
+    let entries_end = ifd + 2 + 12 * count;
+    let next_ifd = tiff.get(entries_end..entries_end + 4)?;
+    let mut entries: Vec<Vec<u8>> = tiff[ifd + 2..entries_end]
+        .chunks(12)
+        .enumerate()
+        .filter(|&(i, _)| u16_at(ifd + 2 + 12 * i) != Some(TIFF_ICC_TAG))
+        .map(|(_, entry)| entry.to_vec())
+        .collect();
+
+    // Offsets into the file have to be on a word boundary
+    let mut out = tiff.to_vec();
+    out.resize(out.len().next_multiple_of(2), 0);
+    let profile_at = out.len() as u32;
+    out.extend_from_slice(profile);
+    out.resize(out.len().next_multiple_of(2), 0);
+
+    let mut icc = Vec::with_capacity(12);
+    icc.extend(u16_bytes(TIFF_ICC_TAG));
+    icc.extend(u16_bytes(7)); // UNDEFINED, bytes
+    icc.extend(u32_bytes(profile.len() as u32));
+    icc.extend(u32_bytes(profile_at));
+    entries.push(icc);
+    entries.sort_by_key(|entry| {
+        let tag = [entry[0], entry[1]];
+        if big_endian { u16::from_be_bytes(tag) } else { u16::from_le_bytes(tag) }
+    });
+
+    let new_ifd = out.len() as u32;
+    out.extend(u16_bytes(entries.len() as u16));
+    out.extend(entries.concat());
+    out.extend_from_slice(next_ifd);
+    out[4..8].copy_from_slice(&u32_bytes(new_ifd));
+    Some(out)
+}
+
+// sRGB IEC61966-2.1 as an ICC v2 display profile: the primaries adapted to
+// D50, and the standard's tone curve sampled at 1024 points.
+fn srgb() -> Vec<u8> {
+    let s15 = |xyz: [f64; 3]| -> Vec<u8> {
+        xyz.iter().flat_map(|v| ((v * 65536.0).round() as i32).to_be_bytes()).collect()
+    };
+    let element = |kind: &[u8], body: Vec<u8>| [kind, &[0; 4], &body].concat();
+
+    let description = b"sRGB IEC61966-2.1\0";
+    let mut desc = (description.len() as u32).to_be_bytes().to_vec();
+    desc.extend_from_slice(description);
+    desc.extend([0; 8 + 3 + 67]); // no Unicode or ScriptCode description
+    let curve: Vec<u8> = (1024u32).to_be_bytes().into_iter().chain((0..1024).flat_map(|i| {
+        let x = i as f64 / 1023.0;
+        let linear = if x <= 0.04045 { x / 12.92 } else { ((x + 0.055) / 1.055).powf(2.4) };
+        ((linear * 65535.0).round() as u16).to_be_bytes()
+    })).collect();
+
+    let elements = [
+        (b"desc", element(b"desc", desc)),
+        (b"cprt", element(b"text", b"No copyright, use freely\0".to_vec())),
+        (b"wtpt", element(b"XYZ ", s15(D50))),
+        (b"rXYZ", element(b"XYZ ", s15(SRGB_RED))),
+        (b"gXYZ", element(b"XYZ ", s15(SRGB_GREEN))),
+        (b"bXYZ", element(b"XYZ ", s15(SRGB_BLUE))),
+        (b"rTRC", element(b"curv", curve)),
+    ];
+    // The green and blue curves share the red one's data
+    let tags = elements.len() + 2;
+
+    let mut table = (tags as u32).to_be_bytes().to_vec();
+    let mut data = Vec::new();
+    let data_start = 128 + 4 + 12 * tags;
+    for (signature, element) in &elements {
+        let at = (data_start + data.len()) as u32;
+        let signatures: &[&[u8; 4]] = if *signature == b"rTRC" { &[b"rTRC", b"gTRC", b"bTRC"] } else { &[*signature] };
+        for signature in signatures {
+            table.extend_from_slice(*signature);
+            table.extend(at.to_be_bytes());
+            table.extend((element.len() as u32).to_be_bytes());
+        }
+        data.extend_from_slice(element);
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+
+    let mut header = vec![0; 128];
+    header[0..4].copy_from_slice(&((data_start + data.len()) as u32).to_be_bytes());
+    header[8..12].copy_from_slice(&[2, 0x10, 0, 0]);
+    header[12..16].copy_from_slice(b"mntr");
+    header[16..20].copy_from_slice(b"RGB ");
+    header[20..24].copy_from_slice(b"XYZ ");
+    // Created 2026-01-01, fixed so every frame gets the same bytes
+    let created: Vec<u8> = [2026u16, 1, 1, 0, 0, 0].iter().flat_map(|v| v.to_be_bytes()).collect();
+    header[24..36].copy_from_slice(&created);
+    header[36..40].copy_from_slice(b"acsp");
+    header[68..80].copy_from_slice(&s15(D50));
+    [header, table, data].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The data of a zlib stream of stored deflate blocks, checking its
+    // framing and checksum.
+    fn inflate_stored(zlib: &[u8]) -> Vec<u8> {
+        assert_eq!(u16::from_be_bytes([zlib[0], zlib[1]]) % 31, 0, "bad zlib header");
+        let mut data = Vec::new();
+        let mut pos = 2;
+        loop {
+            let header = zlib[pos];
+            assert_eq!(header & 0b110, 0, "not a stored block");
+            let length = u16::from_le_bytes([zlib[pos + 1], zlib[pos + 2]]);
+            assert_eq!(!length, u16::from_le_bytes([zlib[pos + 3], zlib[pos + 4]]));
+            pos += 5;
+            data.extend_from_slice(&zlib[pos..pos + length as usize]);
+            pos += length as usize;
+            if header & 1 == 1 {
+                break;
+            }
+        }
+        let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+            let a = (a + byte as u32) % 65521;
+            (a, (b + a) % 65521)
+        });
+        assert_eq!(zlib[pos..], ((b << 16) | a).to_be_bytes());
+        data
+    }
+
+    fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let body = [kind, data].concat();
+        [&(data.len() as u32).to_be_bytes()[..], &body, &crc32(&body).to_be_bytes()].concat()
+    }
+
+    // The type and data of each chunk of `png`, checking their CRCs.
+    fn png_chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut rest = png.strip_prefix(PNG_SIGNATURE).unwrap();
+        let mut chunks = Vec::new();
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let body = &rest[4..8 + length];
+            let crc = u32::from_be_bytes(rest[8 + length..12 + length].try_into().unwrap());
+            let kind = String::from_utf8_lossy(&body[..4]).into_owned();
+            assert_eq!(crc32(body), crc, "bad CRC in {}", kind);
+            chunks.push((kind, body[4..].to_vec()));
+            rest = &rest[12 + length..];
+        }
+        chunks
+    }
+
+    #[test]
+    fn crc32_matches_the_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn zlib_stored_inflates_back() {
+        let large: Vec<u8> = (0..150_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        for data in [&b""[..], b"ICC profile", &large[..0xffff], &large[..0x10000], &large] {
+            assert_eq!(inflate_stored(&zlib_stored(data)), data, "{} bytes", data.len());
+        }
+        // One block per 65535 bytes
+        assert_eq!(zlib_stored(&large).len(), 2 + 3 * 5 + large.len() + 4);
+    }
+
+    #[test]
+    fn embed_png_replaces_the_colour_chunks() {
+        let png = [
+            PNG_SIGNATURE.to_vec(),
+            png_chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0]),
+            png_chunk(b"sRGB", &[0]),
+            png_chunk(b"gAMA", &45455u32.to_be_bytes()),
+            png_chunk(b"IDAT", b"pixels"),
+            png_chunk(b"IEND", b""),
+        ]
+        .concat();
+        let profile = srgb();
+        let tagged = embed_png(&png, &profile).unwrap();
+
+        let chunks = png_chunks(&tagged);
+        let kinds: Vec<&str> = chunks.iter().map(|(kind, _)| kind.as_str()).collect();
+        assert_eq!(kinds, ["IHDR", "iCCP", "IDAT", "IEND"]);
+        let iccp = chunks[1].1.strip_prefix(b"ICC profile\0\0").unwrap();
+        assert_eq!(inflate_stored(iccp), profile);
+        assert_eq!(chunks[2].1, b"pixels");
+
+        assert_eq!(embed_png(b"GIF89a", &profile), None);
+        assert_eq!(embed_png(&png[..png.len() - 6], &profile), None);
+    }
+
+    // A little-endian TIFF header and the entry count of its first IFD.
+    fn tiff_header(entries: u16) -> Vec<u8> {
+        [&b"II*\0"[..], &8u32.to_le_bytes(), &entries.to_le_bytes()].concat()
+    }
+
+    fn tiff_entry(tag: u16, kind: u16, count: u32, value: u32) -> Vec<u8> {
+        [&tag.to_le_bytes()[..], &kind.to_le_bytes(), &count.to_le_bytes(), &value.to_le_bytes()].concat()
+    }
+
+    #[test]
+    fn embed_tiff_adds_the_profile_tag_in_order() {
+        let entries = [
+            tiff_entry(256, 3, 1, 1),
+            tiff_entry(257, 3, 1, 1),
+            tiff_entry(TIFF_ICC_TAG, 7, 4, 0),
+            tiff_entry(40961, 3, 1, 1),
+        ];
+        let tiff = [tiff_header(4), entries.concat(), vec![0; 4], vec![0xab]].concat();
+        let profile = srgb();
+        let tagged = embed_tiff(&tiff, &profile).unwrap();
+
+        // The old IFD is left in place, unreferenced
+        assert_eq!(tagged[8..tiff.len()], tiff[8..]);
+        let u16_at = |pos: usize| u16::from_le_bytes(tagged[pos..pos + 2].try_into().unwrap());
+        let u32_at = |pos: usize| u32::from_le_bytes(tagged[pos..pos + 4].try_into().unwrap());
+        let ifd = u32_at(4) as usize;
+        assert_eq!(ifd % 2, 0);
+        assert_eq!(u16_at(ifd), 4);
+        let tags: Vec<u16> = (0..4).map(|i| u16_at(ifd + 2 + 12 * i)).collect();
+        assert_eq!(tags, [256, 257, TIFF_ICC_TAG, 40961]);
+
+        let icc = ifd + 2 + 12 * 2;
+        assert_eq!(u16_at(icc + 2), 7);
+        assert_eq!(u32_at(icc + 4) as usize, profile.len());
+        let offset = u32_at(icc + 8) as usize;
+        assert_eq!(offset % 2, 0);
+        assert_eq!(tagged[offset..offset + profile.len()], profile[..]);
+        assert_eq!(u32_at(ifd + 2 + 12 * 4), 0);
+    }
+
+    #[test]
+    fn embed_tiff_rejects_truncated_files() {
+        let tiff = [tiff_header(2), tiff_entry(256, 3, 1, 1)].concat();
+        assert_eq!(embed_tiff(&tiff, b"profile"), None);
+        assert_eq!(embed_tiff(&tiff[..6], b"profile"), None);
+        assert_eq!(embed_tiff(b"PNG", b"profile"), None);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::console::info;
+use crate::package::{self, Package};
+use crate::plan::JobPlan;
+use crate::smpte::{self, Asset, EditRate, HashAlgorithm};
+use crate::{output, Result};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Edit rate of the audio track file: 48 kHz samples.
+pub const AUDIO_RATE: EditRate = EditRate { numerator: 48000, denominator: 1 };
+
+/// Digest the packing list records assets with.
+const HASH: HashAlgorithm = HashAlgorithm::Sha256;
+
+/// XML namespace of IMF packing lists (SMPTE ST 2067-2).
+pub const PKL_NAMESPACE: &str = "http://www.smpte-ra.org/schemas/2067-2/2016/PKL";
+
+/// One track file of a composition.
+#[derive(Debug, Clone)]
+pub struct TrackFile {
+    /// UUID of the track file.
+    pub id: String,
+    pub edit_rate: EditRate,
+    /// Length in edit units.
+    pub duration: u64,
+}
+
+/// Composition playlist (SMPTE ST 2067-3) `id` playing `video` and, if
+/// given, `audio` in one segment, starting at `timecode` if given.
+///
+/// The essence descriptors, which need the track files' MXF header metadata
+/// as RegXML, are left out, so strict validators report the CPL as
+/// incomplete; most ingest tools only follow the track file references.
+pub fn cpl(
+    id: &str,
+    title: &str,
+    issued: &str,
+    timecode: Option<&str>,
+    video: &TrackFile,
+    audio: Option<&TrackFile>,
+) -> String {
+    let title = smpte::escape(title);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<CompositionPlaylist xmlns=\"http://www.smpte-ra.org/schemas/2067-3/2016\"");
+    xml.push_str(" xmlns:cc=\"http://www.smpte-ra.org/schemas/2067-2/2016\"");
+    xml.push_str(" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n");
+    xml.push_str(&format!("  <Id>urn:uuid:{}</Id>\n  <Annotation>{}</Annotation>\n", id, title));
+    xml.push_str(&format!("  <IssueDate>{}</IssueDate>\n", issued));
+    xml.push_str(&format!("  <Issuer>{}</Issuer>\n  <Creator>{}</Creator>\n", smpte::CREATOR, smpte::CREATOR));
+    xml.push_str(&format!("  <ContentTitle>{}</ContentTitle>\n", title));
+    if let Some(timecode) = timecode {
+        let rate = (video.edit_rate.numerator as f64 / video.edit_rate.denominator as f64).round();
+        let drop_frame = timecode.contains([';', '.']);
+        xml.push_str("  <CompositionTimecode>\n");
+        xml.push_str(&format!("    <TimecodeDropFrame>{}</TimecodeDropFrame>\n", drop_frame));
+        xml.push_str(&format!("    <TimecodeRate>{}</TimecodeRate>\n", rate));
+        xml.push_str(&format!("    <TimecodeStartAddress>{}</TimecodeStartAddress>\n", timecode.replace('.', ";")));
+        xml.push_str("  </CompositionTimecode>\n");
+    }
+    xml.push_str(&format!("  <EditRate>{}</EditRate>\n", video.edit_rate));
+    xml.push_str("  <SegmentList>\n    <Segment>\n");
+    xml.push_str(&format!("      <Id>urn:uuid:{}</Id>\n      <SequenceList>\n", smpte::uuid()));
+    for (sequence, track) in [("MainImageSequence", Some(video)), ("MainAudioSequence", audio)] {
+        let Some(track) = track else {
+            continue;
+        };
+        xml.push_str(&format!("        <cc:{}>\n", sequence));
+        xml.push_str(&format!("          <Id>urn:uuid:{}</Id>\n", smpte::uuid()));
+        xml.push_str(&format!("          <TrackId>urn:uuid:{}</TrackId>\n", smpte::uuid()));
+        xml.push_str("          <ResourceList>\n");
+        xml.push_str("            <Resource xsi:type=\"TrackFileResourceType\">\n");
+        xml.push_str(&format!("              <Id>urn:uuid:{}</Id>\n", smpte::uuid()));
+        xml.push_str(&format!("              <EditRate>{}</EditRate>\n", track.edit_rate));
+        xml.push_str(&format!("              <IntrinsicDuration>{}</IntrinsicDuration>\n", track.duration));
+        xml.push_str(&format!("              <TrackFileId>urn:uuid:{}</TrackFileId>\n", track.id));
+        xml.push_str("            </Resource>\n          </ResourceList>\n");
+        xml.push_str(&format!("        </cc:{}>\n", sequence));
+    }
+    xml.push_str("      </SequenceList>\n    </Segment>\n  </SegmentList>\n</CompositionPlaylist>\n");
+    xml
+}
+
+/// Wrap the finished `video` (of `frames` frames) and the audio of the
+/// plan's source into a basic IMF package in the `imf/` subdirectory of
+/// `output_dir`, replacing an older package there: a video and an audio MXF
+/// track file, a CPL playing them, and the PKL, ASSETMAP and VOLINDEX that
+/// go with it. Returns the path of the asset map.
+pub fn package(ffmpeg: &Path, plan: &JobPlan, video: &Path, frames: u64, output_dir: &Path) -> Result<PathBuf> {
+    let _span = tracing::info_span!("package").entered();
+    info!("\n📦 Packaging as IMF...");
+    let started = Instant::now();
+
+    let dir = output_dir.join(Package::Imf.dir_name());
+    let staging = output::staging_dir(&dir);
+    output::prepare_staging(&staging)?;
+    let title = plan.input.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let issued = smpte::issue_date();
+
+    let edit_rate = EditRate::from_fps(plan.frame_rate);
+    let video_track = TrackFile { id: smpte::uuid(), edit_rate, duration: frames };
+    let video_file = format!("VIDEO_{}.mxf", video_track.id);
+    smpte::run(ffmpeg, smpte::video_track_command(ffmpeg, video, &staging.join(&video_file)))?;
+    let mut assets = vec![Asset::read(&staging, &video_track.id, video_file, "application/mxf", HASH)?];
+
+    let audio_track = if plan.audio_channels.is_empty() {
+        None
+    } else {
+        let samples = edit_rate.convert(frames, AUDIO_RATE);
+        let track = TrackFile { id: smpte::uuid(), edit_rate: AUDIO_RATE, duration: samples };
+        let file = format!("AUDIO_{}.mxf", track.id);
+        let seconds = samples as f64 / AUDIO_RATE.numerator as f64;
+        let cmd = smpte::audio_track_command(ffmpeg, &plan.input, &plan.audio_channels, seconds, &staging.join(&file));
+        smpte::run(ffmpeg, cmd)?;
+        assets.push(Asset::read(&staging, &track.id, file, "application/mxf", HASH)?);
+        Some(track)
+    };
+
+    let cpl_id = smpte::uuid();
+    let cpl_file = format!("CPL_{}.xml", cpl_id);
+    let xml = cpl(&cpl_id, &title, &issued, plan.timecode.as_deref(), &video_track, audio_track.as_ref());
+    smpte::write(&staging, &cpl_file, &xml)?;
+    assets.insert(0, Asset::read(&staging, &cpl_id, cpl_file, "text/xml", HASH)?);
+
+    let pkl_id = smpte::uuid();
+    let pkl_file = format!("PKL_{}.xml", pkl_id);
+    smpte::write(&staging, &pkl_file, &smpte::pkl(PKL_NAMESPACE, &pkl_id, &title, &issued, HASH, &assets))?;
+    let pkl_asset = Asset::read(&staging, &pkl_id, pkl_file, "text/xml", HASH)?;
+    let map = smpte::asset_map(&smpte::uuid(), &title, &issued, &pkl_asset, &assets);
+    smpte::write(&staging, Package::Imf.manifest(), &map)?;
+    smpte::write(&staging, smpte::VOLINDEX, &smpte::volume_index())?;
+
+    package::publish(&staging, &dir)?;
+    info!("✅ Packaged in {:.2} seconds", started.elapsed().as_secs_f32());
+    Ok(dir.join(Package::Imf.manifest()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video() -> TrackFile {
+        let edit_rate = EditRate { numerator: 24000, denominator: 1001 };
+        TrackFile { id: "video-id".to_string(), edit_rate, duration: 240 }
+    }
+
+    #[test]
+    fn cpl_references_every_track_file() {
+        let audio = TrackFile { id: "audio-id".to_string(), edit_rate: AUDIO_RATE, duration: 480480 };
+        let xml = cpl("cpl-id", "Tom & Jerry", "2026-10-16T00:00:00Z", None, &video(), Some(&audio));
+
+        for element in [
+            "<Id>urn:uuid:cpl-id</Id>",
+            "<Annotation>Tom &amp; Jerry</Annotation>",
+            "<ContentTitle>Tom &amp; Jerry</ContentTitle>",
+            "<IssueDate>2026-10-16T00:00:00Z</IssueDate>",
+            &format!("<Creator>{}</Creator>", smpte::CREATOR),
+            "<EditRate>24000 1001</EditRate>",
+            "<cc:MainImageSequence>",
+            "<TrackFileId>urn:uuid:video-id</TrackFileId>",
+            "<IntrinsicDuration>240</IntrinsicDuration>",
+            "<cc:MainAudioSequence>",
+            "<TrackFileId>urn:uuid:audio-id</TrackFileId>",
+            "<EditRate>48000 1</EditRate>",
+            "<IntrinsicDuration>480480</IntrinsicDuration>",
+        ] {
+            assert!(xml.contains(element), "missing {}", element);
+        }
+        assert!(!xml.contains("CompositionTimecode"));
+    }
+
+    #[test]
+    fn cpl_without_audio_has_only_the_image_sequence() {
+        let xml = cpl("cpl-id", "Title", "2026-10-16T00:00:00Z", None, &video(), None);
+        assert_eq!(xml.matches("<TrackFileId>").count(), 1);
+        assert!(!xml.contains("MainAudioSequence"));
+    }
+
+    #[test]
+    fn cpl_writes_the_start_timecode() {
+        let xml = cpl("cpl-id", "Title", "2026-10-16T00:00:00Z", Some("01:00:00:00"), &video(), None);
+        assert!(xml.contains("<TimecodeDropFrame>false</TimecodeDropFrame>"));
+        assert!(xml.contains("<TimecodeRate>24</TimecodeRate>"));
+        assert!(xml.contains("<TimecodeStartAddress>01:00:00:00</TimecodeStartAddress>"));
+
+        let xml = cpl("cpl-id", "Title", "2026-10-16T00:00:00Z", Some("01:00:00.00"), &video(), None);
+        assert!(xml.contains("<TimecodeDropFrame>true</TimecodeDropFrame>"));
+        assert!(xml.contains("<TimecodeStartAddress>01:00:00;00</TimecodeStartAddress>"));
+    }
+}
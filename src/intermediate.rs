@@ -0,0 +1,87 @@
+use crate::combine::segment_frames;
+use crate::console::{debug, info};
+use crate::segment::Segment;
+use crate::{ffmpeg, interrupt, openfiles, process, DeliveryError, EncodeJob, Result};
+use std::collections::VecDeque;
+use std::fs;
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+/// The ffmpeg invocation that expands the lossless chunk of `segment` into
+/// frames in the job's frame format, next to the chunk.
+pub fn expand_command(job: &EncodeJob, segment: &Segment, ext: &str) -> Command {
+    let dir = segment.dir(&job.segments_dir);
+    let threads = job.ffmpeg_threads().to_string();
+    let mut cmd = Command::new(&job.ffmpeg);
+    cmd.args(["-v", "error"])
+        .arg("-i").arg(ffmpeg::path_arg(&segment.chunk(&job.segments_dir, ext)))
+        .args(["-map", "0:v:0"])
+        .args(job.frame_encoder_args())
+        .args(["-threads", &threads])
+        .arg("-y").arg(ffmpeg::sequence_pattern(&dir, &format!("%05d.{}", job.frame_format.extension())));
+    cmd
+}
+
+// Expand one segment, replacing frames an interrupted expansion left.
+fn expand_segment(job: &EncodeJob, segment: &Segment, ext: &str) -> Result<()> {
+    let dir = segment.dir(&job.segments_dir);
+    let stale = segment_frames(&dir, job.frame_format.extension())
+        .map_err(|e| DeliveryError::io(format!("Failed to read {}", dir.display()), e))?;
+    for frame in stale {
+        fs::remove_file(&frame).map_err(|e| DeliveryError::io(format!("Failed to remove {}", frame.display()), e))?;
+    }
+
+    let mut cmd = expand_command(job, segment, ext);
+    process::contain(&mut cmd);
+    debug!("Command: {}", ffmpeg::display_command(&cmd));
+    let output = cmd.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(job.ffmpeg.clone()),
+        _ => DeliveryError::io("Failed to execute ffmpeg", e),
+    })?;
+    if !output.status.success() {
+        return Err(DeliveryError::SegmentFailed {
+            id: segment.id,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    job.embed_icc_profile(segment)
+}
+
+/// Expand the chunk (`chunk.<ext>`) of every segment into frames, `threads`
+/// segments at a time (fewer if the open file limit is low), so
+/// [`crate::combine::combine`] finds them where the segments would have
+/// written them directly.
+pub fn expand_all(job: &EncodeJob, segments: &[Segment], ext: &str) -> Result<()> {
+    let _span = tracing::info_span!("expand", segments = segments.len()).entered();
+    info!("\n📤 Expanding {} segments to {} frames...", segments.len(), job.frame_format);
+    let started = Instant::now();
+
+    let queue: Mutex<VecDeque<&Segment>> = Mutex::new(segments.iter().collect());
+    let failures: Mutex<Vec<(usize, DeliveryError)>> = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        let workers = job.threads.clamp(1, segments.len().max(1));
+        for _ in 0..openfiles::limit_workers(workers, openfiles::FILES_PER_WORKER, job.max_open_files) {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap().pop_front();
+                let Some(segment) = next else { break };
+                if interrupt::requested() {
+                    break;
+                }
+                if let Err(e) = expand_segment(job, segment, ext) {
+                    failures.lock().unwrap().push((segment.id, e));
+                }
+            });
+        }
+    });
+
+    interrupt::check()?;
+    let mut failures = failures.into_inner().unwrap();
+    if !failures.is_empty() {
+        failures.sort_by_key(|(id, _)| *id);
+        return Err(failures.remove(0).1);
+    }
+    info!("✅ Expanded {} segments in {:.2} seconds", segments.len(), started.elapsed().as_secs_f32());
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use crate::console::warning;
+use crate::{DeliveryError, Result};
+use std::collections::HashMap;
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Running ffmpeg processes by the key [`register`] gave them, killed when an
+/// interrupt arrives.
+static CHILDREN: Mutex<Option<HashMap<usize, Child>>> = Mutex::new(None);
+
+/// Key of the next registered process; segment ids repeat across jobs run at
+/// once.
+static NEXT_KEY: AtomicUsize = AtomicUsize::new(0);
+
+/// Handle Ctrl+C (and SIGTERM): kill every running ffmpeg process, and the
+/// encoder's process group on Unix, so the workers stop and the job returns [`DeliveryError::Interrupted`]. A second
+/// interrupt exits immediately.
+pub fn install() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+        warning!("\n⚠️ Interrupted, stopping FFmpeg processes (press Ctrl+C again to force quit)...");
+        if let Some(children) = CHILDREN.lock().unwrap().as_mut() {
+            for child in children.values_mut() {
+                let _ = child.kill();
+            }
+        }
+        crate::process::kill_group();
+    })
+    .map_err(|e| DeliveryError::Config(format!("Failed to install Ctrl+C handler: {}", e)))
+}
+
+/// Whether an interrupt has been received.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Fail with [`DeliveryError::Interrupted`] once an interrupt was received.
+pub fn check() -> Result<()> {
+    if requested() {
+        Err(DeliveryError::Interrupted)
+    } else {
+        Ok(())
+    }
+}
+
+/// Track a spawned process until [`release`] is called, returning the key it
+/// is tracked by. If an interrupt has already arrived, the process is killed
+/// straight away.
+pub fn register(mut child: Child) -> usize {
+    let key = NEXT_KEY.fetch_add(1, Ordering::Relaxed);
+    let mut children = CHILDREN.lock().unwrap();
+    if requested() {
+        let _ = child.kill();
+    }
+    children.get_or_insert_with(HashMap::new).insert(key, child);
+    key
+}
+
+/// Kill the process registered as `key`, if it is still running.
+pub fn kill(key: usize) {
+    if let Some(child) = CHILDREN.lock().unwrap().as_mut().and_then(|c| c.get_mut(&key)) {
+        let _ = child.kill();
+    }
+}
+
+/// Stop tracking the process registered as `key` and hand it back.
+pub fn release(key: usize) -> Option<Child> {
+    CHILDREN.lock().unwrap().as_mut()?.remove(&key)
+}
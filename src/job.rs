@@ -0,0 +1,2195 @@
+use crate::console::{self, debug, info, warning};
+use crate::events::{self, Event};
+use crate::checkpoint::Checkpoint;
+use crate::hwaccel::HwAccel;
+use crate::metrics::{self, QualityMetric};
+use crate::openfiles::MaxOpenFiles;
+use crate::timecode::{self, Timecode};
+use crate::audio::{AudioCodec, AudioTracks};
+use crate::format::{Av1Encoder, Container, FrameQuality, IntermediateCodec, OutputFormat, VideoCodec, VideoSettings};
+use crate::clock::UtcTime;
+use crate::burnin::{self, FrameNumberBurnIn, TextBurnIn, TextVars, TimecodeBurnIn};
+use crate::color::{self, OutputColor, SourceColor, Tonemap};
+use crate::crop::{self, Crop};
+use crate::guides::{self, Guide};
+use crate::hook::{HookMode, WatermarkHook};
+use crate::icc::{self, IccProfile};
+use crate::naming::{self, FrameNames, NameVars};
+use crate::overlay::{self, Clip, OverlayLayer, OverlaySettings, Playback};
+use crate::package::{self, Package};
+use crate::rendition::{self, Rendition};
+use crate::slate::{self, Slate};
+use crate::plan::{JobPlan, PLAN_FILE};
+use crate::probe::MediaInfo;
+use crate::segment::{Segment, SplitOn};
+use crate::shots::{self, ShotListFormat};
+use crate::stems::{self, StemFormat};
+use crate::svg::{self, Raster};
+use crate::twopass::{self, TwoPass};
+use crate::{
+    cleanup, combine, concat, crfsearch, dcp, diskspace, ffmpeg, imf, intermediate, loudness, memory, openfiles,
+    output, probe, process, qc, scenes, segment, split, verify, worker, DeliveryError, FrameFormat, Result,
+};
+use serde::{Deserialize, Serialize};
+use std::cell::OnceCell;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Name of the scratch directory for per-segment frames.
+pub const SEGMENTS_DIR: &str = "tmp_segments";
+
+/// Filter graph used when the job doesn't specify one.
+pub const DEFAULT_FILTER: &str = "[0:v][1:v]overlay";
+
+/// Timeline of a filter graph running on the whole source.
+const WHOLE_PROGRAM: Range<f64> = 0.0..f64::INFINITY;
+
+/// Format of the built-in overlay for more than 8 bits per channel: overlay
+/// composites in 8-bit 4:2:0 unless told otherwise.
+const HIGH_DEPTH_FORMAT: &str = "yuv444p10";
+
+/// Format of the built-in overlay keeping the source's alpha: overlay picks
+/// a format with alpha from its inputs.
+const ALPHA_FORMAT: &str = "auto";
+
+/// How inputs with a variable frame rate are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum VfrMode {
+    /// Warn and plan with the average frame rate.
+    #[default]
+    Warn,
+    /// Convert to this constant frame rate, duplicating or dropping frames.
+    Cfr(f64),
+}
+
+impl FromStr for VfrMode {
+    type Err = String;
+
+    /// Parse `warn` or `cfr:<fps>`, e.g. `cfr:25` or `cfr:30000/1001`.
+    fn from_str(text: &str) -> std::result::Result<VfrMode, String> {
+        match text.trim().split_once(':') {
+            None if text.trim() == "warn" => Ok(VfrMode::Warn),
+            Some(("cfr", rate)) => probe::parse_rate(rate)
+                .map(VfrMode::Cfr)
+                .ok_or_else(|| format!("invalid frame rate '{}' in '{}'", rate, text)),
+            _ => Err(format!("invalid VFR mode '{}', expected warn or cfr:<fps>", text)),
+        }
+    }
+}
+
+/// What happens to the source's alpha channel.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AlphaMode {
+    /// Composite onto the source and write opaque frames, dropping any alpha.
+    #[default]
+    Discard,
+    /// Keep the alpha channel through the filter graph and into the frames.
+    Preserve,
+    /// Matte the source against this color (an ffmpeg color such as `white`
+    /// or `#20252a`) before compositing.
+    FlattenOn(String),
+}
+
+/// What to do when the output directory already holds frames.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OnExisting {
+    /// Refuse to run rather than mix new frames with old ones.
+    #[default]
+    Fail,
+    /// Leave the output alone and don't encode.
+    Skip,
+    /// Delete the old frames first.
+    Overwrite,
+    /// Write to the next free `<output>_v002`, `<output>_v003`, ...
+    Version,
+}
+
+impl FromStr for OnExisting {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<OnExisting, String> {
+        match text.trim() {
+            "fail" => Ok(OnExisting::Fail),
+            "skip" => Ok(OnExisting::Skip),
+            "overwrite" => Ok(OnExisting::Overwrite),
+            "version" => Ok(OnExisting::Version),
+            _ => Err(format!("invalid policy '{}', expected fail, skip, overwrite or version", text)),
+        }
+    }
+}
+
+// `dir` with its `_vNNN` suffix, if any, removed.
+fn unversioned(dir: &Path) -> PathBuf {
+    let name = dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    match name.rsplit_once("_v") {
+        Some((base, n)) if !base.is_empty() && n.len() >= 3 && n.bytes().all(|b| b.is_ascii_digit()) => {
+            dir.with_file_name(base)
+        }
+        _ => dir.to_path_buf(),
+    }
+}
+
+/// A single overlay-and-export job.
+///
+/// Construct with [`EncodeJob::new`], adjust the public fields as needed and
+/// call [`EncodeJob::run`].
+#[derive(Debug, Clone)]
+pub struct EncodeJob {
+    /// ffmpeg executable; a bare name is looked up on `PATH`.
+    pub ffmpeg: PathBuf,
+    /// ffprobe executable; a bare name is looked up on `PATH`.
+    pub ffprobe: PathBuf,
+    pub input: PathBuf,
+    pub overlay: PathBuf,
+    /// Whether `overlay` loops, and its length if it is animated.
+    pub overlay_playback: Playback,
+    pub output_dir: PathBuf,
+    /// Scratch directory for per-segment frames, removed after a successful run
+    /// unless `keep_temp` is set.
+    pub segments_dir: PathBuf,
+    /// Keep `segments_dir` and its frames after a successful run.
+    pub keep_temp: bool,
+    /// Number of segments encoded in parallel.
+    pub threads: usize,
+    /// Threads each ffmpeg process may use for decoding, filtering and
+    /// encoding; `None` shares the CPU threads evenly between the workers.
+    pub ffmpeg_threads: Option<usize>,
+    /// Memory one worker's ffmpeg is assumed to need when deciding how many
+    /// run at once; `None` estimates it from the resolution.
+    pub memory_per_worker: Option<u64>,
+    /// How the open file limit is handled; `None` raises it only when it
+    /// would otherwise leave fewer than `threads` workers.
+    pub max_open_files: Option<MaxOpenFiles>,
+    /// Run ffmpeg at reduced scheduling priority.
+    pub background: bool,
+    /// Pin each worker's ffmpeg to its own set of `ffmpeg_threads()` CPUs.
+    pub pin_cpus: bool,
+    /// NVIDIA GPUs the workers decode and encode on, taken in turn; empty
+    /// leaves the choice to ffmpeg (the first GPU).
+    pub gpus: Vec<usize>,
+    /// Number of segments the input is split into; `None` picks
+    /// [`SEGMENTS_PER_THREAD`] per thread.
+    pub segments: Option<usize>,
+    /// Size segments by estimated encode cost instead of equal duration.
+    pub adaptive_segments: bool,
+    /// What to do when the input has a variable frame rate. `Cfr` converts
+    /// every input, constant or not, to that rate.
+    pub vfr_mode: VfrMode,
+    /// Copy the source into one piece per segment at keyframes before
+    /// encoding, so workers read their piece instead of seeking in the input.
+    pub presplit: bool,
+    /// Place the segment boundaries evenly or at scene cuts.
+    pub split_on: SplitOn,
+    /// Graph passed to `-filter_complex`; input 0 is the video, input 1 the overlay
+    /// and inputs 2 and up the overlay layers.
+    pub filter: String,
+    /// 3D LUT the video is graded with before anything is composited over
+    /// it.
+    pub lut: Option<PathBuf>,
+    /// Tone mapping of an HDR source to SDR, ahead of `lut`.
+    pub tonemap: Option<Tonemap>,
+    /// Colour of the source, once probed for video output, so an HDR source
+    /// is delivered as HDR.
+    pub source_color: Option<SourceColor>,
+    /// Colour the video is converted to (after tone mapping) and tagged
+    /// with, or with a LUT, only tagged with.
+    pub color: OutputColor,
+    /// Find the black bars around the picture and crop them off.
+    pub autocrop: bool,
+    /// Bars cropped off, once found for `autocrop`.
+    pub crop: Option<Crop>,
+    /// Where the overlay goes when `filter` is [`DEFAULT_FILTER`].
+    pub overlay_settings: OverlaySettings,
+    /// More overlays composited over `overlay` in order, as inputs 2 and up.
+    pub overlay_layers: Vec<OverlayLayer>,
+    /// Text burned in over the composited frames.
+    pub burn_in: Option<TextBurnIn>,
+    /// `{recipient}` in the burn-in text: who the copy is for.
+    pub recipient: Option<String>,
+    /// Running timecode burned in over the composited frames.
+    pub timecode_burn_in: Option<TimecodeBurnIn>,
+    /// Output frame number burned in over the composited frames.
+    pub frame_number_burn_in: Option<FrameNumberBurnIn>,
+    /// Broadcast guides drawn over the picture, under the burn-ins.
+    pub guides: Vec<Guide>,
+    /// Recipient's name burned in over the composited frames, in screener
+    /// mode. Unlike `burn_in` it is not a template.
+    pub watermark: Option<TextBurnIn>,
+    /// Slate (and 2-pop) put before the program in video output.
+    pub slate: Option<Slate>,
+    /// External command the composited frames of every segment pass through
+    /// before they are encoded, e.g. to embed a forensic watermark.
+    pub watermark_hook: Option<WatermarkHook>,
+    /// Precomputed plan to execute instead of probing the input.
+    pub plan: Option<JobPlan>,
+    /// Where to save the plan before encoding starts.
+    pub plan_out: Option<PathBuf>,
+    /// Keep the segments a previous run completed in `segments_dir` and only
+    /// encode the rest.
+    pub resume: bool,
+    /// Re-encode only these segments, reusing the other segments a previous
+    /// run completed in `segments_dir`.
+    pub only_segments: Option<Vec<usize>>,
+    /// Leave `segments_dir` in place when interrupted so the run can be resumed.
+    pub keep_on_interrupt: bool,
+    /// Kill a segment's ffmpeg after this long without progress or output.
+    pub stall_timeout: Option<Duration>,
+    /// Kill a segment's ffmpeg after this long in total.
+    pub segment_timeout: Option<Duration>,
+    /// How often a failed or timed out segment is re-encoded before giving up.
+    pub retries: usize,
+    /// Check for enough free disk space before encoding.
+    pub space_check: bool,
+    /// Image format the frames are exported in.
+    pub frame_format: FrameFormat,
+    /// Deliver an image sequence, or a single video file encoded with
+    /// `codec` in `output_dir`.
+    pub output_format: OutputFormat,
+    /// Codec of video output.
+    pub codec: VideoCodec,
+    /// Container of video output; `None` uses the codec's.
+    pub container: Option<Container>,
+    /// Start timecode of video output; `None` writes none.
+    pub timecode: Option<Timecode>,
+    /// Codec the source's audio is carried into video output in; `None`
+    /// uses the container's default.
+    pub audio: Option<AudioCodec>,
+    /// Audio channels and tracks of MXF output.
+    pub audio_tracks: AudioTracks,
+    /// Integrated loudness in LUFS the audio of video output is normalized
+    /// to, measured in a first pass over the source; `None` keeps its level.
+    pub loudness_target: Option<f64>,
+    /// Encoder of AV1 output; `None` picks SVT-AV1 if ffmpeg has it, or
+    /// libaom, when the job runs.
+    pub av1_encoder: Option<Av1Encoder>,
+    /// Encode frame output segments to this lossless codec and expand them
+    /// to frames when combining, instead of writing frames directly.
+    pub intermediate: Option<IntermediateCodec>,
+    /// Renditions video output is delivered in, each scaled from the same
+    /// composited frames; empty delivers one video at the source's size.
+    pub renditions: Vec<Rendition>,
+    /// Streaming packages made from video output after it is joined.
+    pub package: Vec<Package>,
+    /// Length of the packaged media segments in seconds; `None` uses
+    /// [`package::SEGMENT_DURATION`].
+    pub package_segment: Option<f64>,
+    /// Write each of the source's audio tracks to its own file in this
+    /// format next to the output.
+    pub extract_audio: Option<StemFormat>,
+    /// Write a list of the source's shots, found by scene detection, with a
+    /// thumbnail of each next to the output in this format.
+    pub shot_list: Option<ShotListFormat>,
+    /// Quality, bitrate, preset, profile and level of video output.
+    pub video_settings: VideoSettings,
+    /// Hardware the source is decoded and video output encoded with;
+    /// `HwAccel::Auto` is resolved when the job runs.
+    pub hwaccel: HwAccel,
+    /// Encode video output in two passes to hit its bitrate (or every
+    /// rendition's bitrate) closely.
+    pub two_pass: Option<TwoPass>,
+    /// VMAF score the CRF of video output is searched for on probe clips
+    /// before encoding, unless the job sets a CRF.
+    pub target_vmaf: Option<f64>,
+    /// Bits per channel of the frames; `None` uses the format's (or codec's)
+    /// default.
+    pub bit_depth: Option<u8>,
+    /// PNG compression level and JPEG quality.
+    pub quality: FrameQuality,
+    /// ICC profile embedded in PNG and TIFF frames.
+    pub icc_profile: Option<IccProfile>,
+    /// Keep, discard or flatten the source's alpha channel.
+    pub alpha: AlphaMode,
+    /// Template the output frames are named with; see
+    /// [`FrameNames::from_template`].
+    pub name_template: String,
+    /// Number of the first output frame.
+    pub start_frame: u64,
+    /// Digits frame numbers are zero-padded to, unless the template sets a width.
+    pub frame_padding: usize,
+    /// `{job}` in the name template; defaults to the output directory's name.
+    pub job_id: Option<String>,
+    /// Have [`EncodeJob::prepare_output`] move `output_dir` into a new
+    /// `<job>_<timestamp>` subdirectory of itself.
+    pub timestamped_output: bool,
+    /// Point the `latest` link next to `output_dir` at it after a successful
+    /// run; set by [`EncodeJob::prepare_output`] for timestamped outputs.
+    pub update_latest: bool,
+    /// What [`EncodeJob::prepare_output`] does when `output_dir` already
+    /// holds frames.
+    pub on_existing: OnExisting,
+    /// Only warn when the segments produce a different number of frames
+    /// than planned.
+    pub allow_frame_mismatch: bool,
+    /// Compare the frames at every join between segments for duplicates,
+    /// gaps and overlaps before combining.
+    pub check_boundaries: bool,
+    /// Decode the whole source before encoding and stop if it is damaged.
+    pub verify_source: bool,
+    /// QC checks reported on the finished delivery.
+    pub qc: qc::Checks,
+    /// Metrics video output is scored with against the source after it is
+    /// joined, segment by segment.
+    pub quality_metrics: Vec<QualityMetric>,
+}
+
+/// Segments created per thread by default. Smaller segments let threads that
+/// finish early pick up more work instead of idling.
+pub const SEGMENTS_PER_THREAD: usize = 4;
+
+/// Segments shorter than this aren't worth the seek and startup overhead.
+const MIN_SEGMENT_DURATION: f64 = 2.0;
+
+// Default segment count for `duration` seconds on `threads` threads: a few
+// segments per thread, unless that would make them very short.
+fn default_segments(duration: f64, threads: usize) -> usize {
+    let by_length = (duration / MIN_SEGMENT_DURATION) as usize;
+    (threads * SEGMENTS_PER_THREAD).min(by_length).max(threads)
+}
+
+/// Number of threads the system can run in parallel, falling back to 1.
+pub fn available_threads() -> usize {
+    match std::thread::available_parallelism() {
+        Ok(n) => {
+            let threads = n.get();
+            debug!("🧵 System reports {} available threads", threads);
+            threads
+        }
+        Err(e) => {
+            warning!("⚠️ Failed to get thread count: {}, using 1 thread", e);
+            1
+        }
+    }
+}
+
+impl EncodeJob {
+    pub fn new(
+        input: impl Into<PathBuf>,
+        overlay: impl Into<PathBuf>,
+        output_dir: impl Into<PathBuf>,
+    ) -> EncodeJob {
+        EncodeJob {
+            ffmpeg: PathBuf::from("ffmpeg"),
+            ffprobe: PathBuf::from("ffprobe"),
+            input: input.into(),
+            overlay: overlay.into(),
+            overlay_playback: Playback::default(),
+            output_dir: output_dir.into(),
+            segments_dir: PathBuf::from(SEGMENTS_DIR),
+            keep_temp: false,
+            threads: available_threads(),
+            ffmpeg_threads: None,
+            pin_cpus: false,
+            gpus: Vec::new(),
+            background: false,
+            memory_per_worker: None,
+            max_open_files: None,
+            segments: None,
+            adaptive_segments: false,
+            presplit: false,
+            split_on: SplitOn::Time,
+            vfr_mode: VfrMode::Warn,
+            filter: DEFAULT_FILTER.to_string(),
+            lut: None,
+            tonemap: None,
+            source_color: None,
+            color: OutputColor::default(),
+            autocrop: false,
+            crop: None,
+            overlay_settings: OverlaySettings::default(),
+            overlay_layers: Vec::new(),
+            burn_in: None,
+            recipient: None,
+            timecode_burn_in: None,
+            frame_number_burn_in: None,
+            guides: Vec::new(),
+            watermark: None,
+            slate: None,
+            watermark_hook: None,
+            plan: None,
+            plan_out: None,
+            resume: false,
+            only_segments: None,
+            keep_on_interrupt: false,
+            stall_timeout: None,
+            segment_timeout: None,
+            retries: 0,
+            space_check: true,
+            frame_format: FrameFormat::Png,
+            output_format: OutputFormat::Frames,
+            codec: VideoCodec::H264,
+            container: None,
+            timecode: None,
+            audio: None,
+            audio_tracks: AudioTracks::default(),
+            loudness_target: None,
+            av1_encoder: None,
+            intermediate: None,
+            renditions: Vec::new(),
+            package: Vec::new(),
+            package_segment: None,
+            extract_audio: None,
+            shot_list: None,
+            video_settings: VideoSettings::default(),
+            hwaccel: HwAccel::None,
+            two_pass: None,
+            target_vmaf: None,
+            bit_depth: None,
+            quality: FrameQuality::default(),
+            icc_profile: None,
+            alpha: AlphaMode::Discard,
+            name_template: naming::DEFAULT_TEMPLATE.to_string(),
+            start_frame: naming::DEFAULT_START_FRAME,
+            frame_padding: naming::DEFAULT_PADDING,
+            job_id: None,
+            timestamped_output: false,
+            update_latest: false,
+            on_existing: OnExisting::Fail,
+            allow_frame_mismatch: false,
+            check_boundaries: false,
+            verify_source: false,
+            qc: qc::Checks::default(),
+            quality_metrics: Vec::new(),
+        }
+    }
+
+    /// Frame rate the job's frames are produced at: the `--vfr-mode cfr` rate
+    /// if set, otherwise the input's (average) rate.
+    pub fn frame_rate(&self, media: &MediaInfo) -> f64 {
+        match (self.vfr_mode, &media.video) {
+            (VfrMode::Cfr(rate), _) => rate,
+            (VfrMode::Warn, Some(video)) => video.frame_rate,
+            (VfrMode::Warn, None) => 0.0,
+        }
+    }
+
+    /// Bits per channel the frames are written with.
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth.unwrap_or_else(|| match self.output_format {
+            OutputFormat::Frames => self.frame_format.default_bit_depth(),
+            OutputFormat::Video => self.codec.default_bit_depth(self.video_settings.profile.as_deref()),
+        })
+    }
+
+    /// `-c:v` and the encoder options segments are encoded with: those of
+    /// the frame format, the intermediate codec or the video codec.
+    pub fn encoder_args(&self) -> Vec<String> {
+        let alpha = self.alpha == AlphaMode::Preserve;
+        let (encoder, options) = match (self.output_format, self.intermediate) {
+            (OutputFormat::Frames, None) => return self.frame_encoder_args(),
+            (OutputFormat::Frames, Some(codec)) => (codec.encoder(), codec.encoder_args(self.bit_depth(), alpha)),
+            (OutputFormat::Video, _) => return self.video_encoder_args(self.video_settings.bitrate, None, None),
+        };
+        let mut args = vec!["-c:v".to_string(), encoder.to_string()];
+        args.extend(options);
+        args
+    }
+
+    /// `-c:v` and the encoder options of the video codec, at an average of
+    /// `bitrate` bits per second if set, for `pass` of a two-pass encode
+    /// with its statistics file, on `gpu` if it is encoded in hardware.
+    pub fn video_encoder_args(
+        &self,
+        bitrate: Option<u64>,
+        pass: Option<(u8, &Path)>,
+        gpu: Option<usize>,
+    ) -> Vec<String> {
+        let alpha = self.alpha == AlphaMode::Preserve;
+        let settings = VideoSettings { bitrate, ..self.video_settings.clone() };
+        let mut args = vec!["-c:v".to_string(), self.video_encoder().to_string()];
+        match (self.hwaccel.encoder(self.codec), self.codec) {
+            (Some(_), _) => args.extend(self.hwaccel.encoder_args(self.codec, self.bit_depth(), &settings, gpu)),
+            (None, VideoCodec::Av1) => {
+                args.extend(self.av1_encoder.unwrap_or_default().encoder_args(self.bit_depth(), &settings))
+            }
+            (None, _) => {
+                args.extend(self.codec.encoder_args(self.bit_depth(), alpha, &settings, pass, self.hdr_source()))
+            }
+        }
+        args
+    }
+
+    /// Colour of the source if it is HDR video delivered as such: not tone
+    /// mapped, and to video output.
+    pub fn hdr_source(&self) -> Option<&SourceColor> {
+        let hdr_transfer = self.color.transfer.as_deref().is_none_or(|t| t == color::PQ || t == color::HLG);
+        let hdr_output = self.output_format == OutputFormat::Video && self.tonemap.is_none() && hdr_transfer;
+        self.source_color.as_ref().filter(|c| hdr_output && c.is_hdr())
+    }
+
+    /// Output options tagging the output's colour: SDR BT.709 when tone
+    /// mapped, the source's when it is HDR, with what the job sets in place.
+    /// None if the job leaves the colour alone.
+    pub fn color_tag_args(&self) -> Vec<String> {
+        let base = match (&self.tonemap, self.hdr_source()) {
+            (Some(_), _) => SourceColor::sdr(),
+            (None, Some(hdr)) => hdr.with_hdr_defaults(),
+            (None, None) if self.color.is_set() => self.source_color.clone().unwrap_or_default(),
+            (None, None) => return Vec::new(),
+        };
+        self.color.applied_to(&base).tag_args()
+    }
+
+    /// ffmpeg encoder of video output: the hardware encoder of `hwaccel`
+    /// for the codec if it has one, otherwise the software encoder.
+    pub fn video_encoder(&self) -> &'static str {
+        match (self.hwaccel.encoder(self.codec), self.codec) {
+            (Some(encoder), _) => encoder,
+            (None, VideoCodec::Av1) => self.av1_encoder.unwrap_or_default().encoder(),
+            (None, codec) => codec.encoder(),
+        }
+    }
+
+    /// Average bitrate every output of a segment is encoded to, one per
+    /// rendition: the job's bitrate, or in two passes each rendition's
+    /// bitrate. `None` encodes at constant quality.
+    pub fn target_bitrates(&self) -> Vec<Option<u64>> {
+        if self.renditions.is_empty() {
+            return vec![self.video_settings.bitrate];
+        }
+        self.renditions.iter().map(|r| r.bitrate.filter(|_| self.two_pass.is_some())).collect()
+    }
+
+    /// `-c:v` and the encoder options of the frame format.
+    pub fn frame_encoder_args(&self) -> Vec<String> {
+        let alpha = self.alpha == AlphaMode::Preserve;
+        let mut args = vec!["-c:v".to_string(), self.frame_format.encoder().to_string()];
+        args.extend(self.frame_format.encoder_args(self.bit_depth(), alpha, &self.quality));
+        args
+    }
+
+    /// Embed the job's ICC profile, if any, in the frames `segment` wrote.
+    pub fn embed_icc_profile(&self, segment: &Segment) -> Result<()> {
+        let Some(profile) = &self.icc_profile else { return Ok(()) };
+        let frames = icc::embed_frames(&segment.dir(&self.segments_dir), self.frame_format, &profile.load()?)?;
+        debug!("[Thread {}] Embedded the {} ICC profile in {} frames", segment.id, profile, frames);
+        Ok(())
+    }
+
+    /// Length of the packaged media segments, in seconds.
+    pub fn package_segment(&self) -> f64 {
+        self.package_segment.unwrap_or(package::SEGMENT_DURATION)
+    }
+
+    /// Extension of the video file each segment is encoded to (that of the
+    /// first rendition, if several), or `None` if segments write frames.
+    pub fn chunk_extension(&self) -> Option<String> {
+        self.video_chunks("").into_iter().next().map(|(ext, _)| ext)
+    }
+
+    /// The chunk extension and output file name of every video the segments
+    /// are encoded to, with output files named after `base`: one per
+    /// rendition, or just `base`. Empty if segments write frames.
+    pub fn video_chunks(&self, base: &str) -> Vec<(String, String)> {
+        match (self.output_format, self.intermediate) {
+            (OutputFormat::Frames, None) => Vec::new(),
+            (OutputFormat::Frames, Some(codec)) => vec![(codec.extension().to_string(), base.to_string())],
+            (OutputFormat::Video, _) if self.renditions.is_empty() => {
+                vec![(self.codec.extension().to_string(), base.to_string())]
+            }
+            (OutputFormat::Video, _) => self
+                .renditions
+                .iter()
+                .map(|r| (format!("{}.{}", r.name, self.codec.extension()), r.file_name(base)))
+                .collect(),
+        }
+    }
+
+    /// Container of video output.
+    pub fn container(&self) -> Container {
+        self.container.unwrap_or(self.codec.container())
+    }
+
+    /// Codec of the audio of video output.
+    pub fn audio_codec(&self) -> AudioCodec {
+        self.audio.unwrap_or(AudioCodec::default_for(self.container()))
+    }
+
+    /// File name of video output: that of the job's plan, or the job id (or
+    /// the input's name) with the container's extension.
+    pub fn video_name(&self) -> String {
+        if let Some(name) = self.plan.as_ref().and_then(|p| p.video_name.clone()) {
+            return name;
+        }
+        format!("{}.{}", self.output_stem(), self.container().extension())
+    }
+
+    /// Name files written next to the output are based on: the job id, or
+    /// the input's name.
+    pub fn output_stem(&self) -> String {
+        self.job_id.clone().unwrap_or_else(|| {
+            self.input.file_stem().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| "video".to_string())
+        })
+    }
+
+    /// The filter graph ffmpeg runs on the stretch of the source `timeline`:
+    /// `filter`, with the default overlay compositing the layers shown in
+    /// it at 10 bits when the frames have more than 8, or in a format with
+    /// alpha when it is preserved, and their windows moved onto the
+    /// timeline. When flattening, the graph reads the matted source instead
+    /// of `[0:v]`, and when tone mapping, converting the colour or with a
+    /// LUT, the graded one. The guides and burn-ins go over the result. With
+    /// renditions, its output is forked and scaled into one labeled output
+    /// per rendition.
+    pub fn filter_graph(&self, timeline: &Range<f64>) -> String {
+        let graph = if self.filter == DEFAULT_FILTER {
+            let format = match &self.alpha {
+                AlphaMode::Preserve => Some(ALPHA_FORMAT),
+                _ if self.bit_depth() > 8 => Some(HIGH_DEPTH_FORMAT),
+                _ => None,
+            };
+            let layers: Vec<&OverlaySettings> = self.overlay_inputs(timeline).iter().map(|&(_, _, s)| s).collect();
+            overlay::filter_graph(&layers, format, timeline.start)
+        } else {
+            self.filter.clone()
+        };
+        let mut graph = match &self.alpha {
+            AlphaMode::FlattenOn(color) => format!(
+                "[0:v]split[alpha_src][alpha_fg];[alpha_src]drawbox=c={}:t=fill:replace=1[alpha_bg];\
+                [alpha_bg][alpha_fg]overlay{}[flat];{}",
+                ffmpeg::escape_filter_value(color),
+                if self.bit_depth() > 8 { "=format=yuv444p10" } else { "" },
+                graph.replace("[0:v]", "[flat]")
+            ),
+            _ => graph.to_string(),
+        };
+        let alpha = self.alpha == AlphaMode::Preserve;
+        let lut = |lut: &PathBuf| format!("lut3d=file={}", ffmpeg::escape_filter_value(&lut.to_string_lossy()));
+        let tonemap = self.tonemap.iter().map(|t| t.filters(alpha));
+        // The LUT sets the colour itself, so it is only tagged
+        let convert = (self.color.is_set() && self.lut.is_none()).then(|| self.color.filter(&self.graded_color()));
+        let crop = self.crop.filter(|c| !c.is_empty()).map(|c| c.filter());
+        let grade: Vec<String> =
+            crop.into_iter().chain(tonemap).chain(self.lut.iter().map(lut)).chain(convert).collect();
+        if !grade.is_empty() {
+            graph = format!("[0:v]{}[graded];{}", grade.join(","), graph.replace("[0:v]", "[graded]"));
+        }
+        graph.push_str(&guides::filter_suffix(&self.guides));
+        if let (Some(burn_in), Ok(Some(text))) = (&self.burn_in, self.burn_in_text()) {
+            graph.push_str(&burn_in.filter_suffix(&text));
+        }
+        if let Some(watermark) = &self.watermark {
+            graph.push_str(&watermark.filter_suffix(&watermark.text));
+        }
+        // The timeline starts on a frame or half a frame before it
+        let first_frame = |fps: Option<f64>| (timeline.start * fps.unwrap_or_default() + 0.25).round() as u64;
+        if let Some(burn_in) = &self.timecode_burn_in {
+            graph.push_str(&burn_in.filter_suffix(first_frame(burn_in.frame_rate)));
+        }
+        if let Some(burn_in) = &self.frame_number_burn_in {
+            let names = self.frame_names().unwrap_or_default();
+            graph.push_str(&burn_in.filter_suffix(names.start_frame + first_frame(burn_in.frame_rate), names.padding));
+        }
+        if self.output_format == OutputFormat::Video && !self.renditions.is_empty() {
+            graph.push_str(&rendition::filter_suffix(&self.renditions));
+        }
+        if self.output_format == OutputFormat::Video && self.package.contains(&Package::Dcp) {
+            graph.push_str(&dcp::filter_suffix(self.video_settings.profile.as_deref().unwrap_or_default()));
+        }
+        graph
+    }
+
+    // Colour of the video the colour conversion starts from: SDR BT.709 when
+    // tone mapped, else the source's, which for HDR is BT.2020 where it
+    // doesn't say.
+    fn graded_color(&self) -> SourceColor {
+        match (&self.tonemap, &self.source_color) {
+            (Some(_), _) => SourceColor::sdr(),
+            (None, Some(source)) if source.is_hdr() => source.with_hdr_defaults(),
+            (None, source) => source.clone().unwrap_or_default(),
+        }
+    }
+
+    // Fail if the frame format, codec or filter graph can't do what `alpha`
+    // asks.
+    fn check_alpha(&self) -> Result<()> {
+        match (&self.alpha, self.output_format) {
+            (AlphaMode::Preserve, OutputFormat::Frames) if !self.frame_format.supports_alpha() => {
+                Err(DeliveryError::Config(format!(
+                    "{} frames can't store alpha; use --frame-format png, tiff, exr, dpx or webp",
+                    self.frame_format
+                )))
+            }
+            (AlphaMode::Preserve, OutputFormat::Video) if !self.codec.supports_alpha() => Err(DeliveryError::Config(
+                format!("{} video can't store alpha; use --codec prores", self.codec),
+            )),
+            (AlphaMode::FlattenOn(_), _) if !self.filter.contains("[0:v]") => Err(DeliveryError::Config(
+                "--flatten-on needs a filter graph that reads the video as [0:v]".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Threads each ffmpeg process may use, so the workers together don't
+    /// oversubscribe the CPU.
+    pub fn ffmpeg_threads(&self) -> usize {
+        self.ffmpeg_threads.unwrap_or_else(|| {
+            let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+            cpus / self.threads.max(1)
+        }).max(1)
+    }
+
+    /// CPUs the ffmpeg processes of worker `worker` are pinned to, or none if
+    /// `pin_cpus` is off. Workers get consecutive, non-overlapping sets as long
+    /// as there are enough CPUs, then wrap around.
+    pub fn worker_cpus(&self, worker: usize) -> Vec<usize> {
+        if !self.pin_cpus {
+            return Vec::new();
+        }
+        let cpus = process::available_cpus();
+        let per_worker = self.ffmpeg_threads().min(cpus.len());
+        (0..per_worker).map(|i| cpus[(worker * per_worker + i) % cpus.len()]).collect()
+    }
+
+    /// GPU the ffmpeg processes of worker `worker` run on, or `None` if no
+    /// `gpus` were given. Workers take the GPUs in turn.
+    pub fn worker_gpu(&self, worker: usize) -> Option<usize> {
+        (!self.gpus.is_empty()).then(|| self.gpus[worker % self.gpus.len()])
+    }
+
+    /// Fail if the job's options contradict each other or are out of range.
+    /// Runs before the source is probed, so a bad command line fails fast.
+    pub fn validate(&self) -> Result<()> {
+        if self.output_format == OutputFormat::Video && self.intermediate.is_some() {
+            return Err(DeliveryError::Config("--intermediate only applies to --output-format frames".to_string()));
+        }
+        if self.output_format == OutputFormat::Video {
+            self.container().check_codec(self.codec).map_err(DeliveryError::Config)?;
+        }
+        if (self.container.is_some() || self.timecode.is_some()) && self.output_format == OutputFormat::Frames {
+            return Err(DeliveryError::Config("--container and --timecode need --output-format video".to_string()));
+        }
+        if self.audio.is_some() && self.output_format == OutputFormat::Frames {
+            return Err(DeliveryError::Config("--audio needs --output-format video".to_string()));
+        }
+        if self.output_format == OutputFormat::Video {
+            self.audio_codec().check_container(self.container(), &[]).map_err(DeliveryError::Config)?;
+        }
+        if self.loudness_target.is_some()
+            && (self.output_format == OutputFormat::Frames
+                || matches!(self.audio_codec(), AudioCodec::None | AudioCodec::Copy))
+        {
+            return Err(DeliveryError::Config(
+                "--loudness-target needs video output with encoded audio; use --audio aac or pcm".to_string(),
+            ));
+        }
+        if self.audio_tracks != AudioTracks::default()
+            && (self.output_format == OutputFormat::Frames
+                || self.container() != Container::Mxf
+                || self.audio_codec() == AudioCodec::None)
+        {
+            return Err(DeliveryError::Config(
+                "--audio-layout, --audio-channels, --audio-tracks and --audio-labels need --container mxf".to_string(),
+            ));
+        }
+        if !self.renditions.is_empty() && self.output_format == OutputFormat::Frames {
+            return Err(DeliveryError::Config("--rendition needs --output-format video".to_string()));
+        }
+        if !self.quality_metrics.is_empty() && self.output_format == OutputFormat::Frames {
+            return Err(DeliveryError::Config("--quality-metrics needs --output-format video".to_string()));
+        }
+        rendition::check(&self.renditions).map_err(DeliveryError::Config)?;
+        if !self.package.is_empty() && self.output_format == OutputFormat::Frames {
+            return Err(DeliveryError::Config("--package needs --output-format video".to_string()));
+        }
+        for package in &self.package {
+            package.check_codec(self.codec).map_err(DeliveryError::Config)?;
+        }
+        if let Some(package) = self.package.iter().find(|p| !p.is_streaming()) {
+            if !self.renditions.is_empty() {
+                let message = format!("--package {} takes a single video; drop --rendition", package.dir_name());
+                return Err(DeliveryError::Config(message));
+            }
+        }
+        if self.package.contains(&Package::Dcp)
+            && !matches!(self.video_settings.profile.as_deref(), Some("cinema2k" | "cinema4k"))
+        {
+            return Err(DeliveryError::Config(
+                "--package dcp needs DCI frames; use --profile cinema2k or cinema4k (or --delivery-preset dcp-2k)"
+                    .to_string(),
+            ));
+        }
+        if self.package_segment().is_nan() || self.package_segment() <= 0.0 {
+            return Err(DeliveryError::Config("--package-segment must be more than 0 seconds".to_string()));
+        }
+        if let Some(slate) = &self.slate {
+            if self.output_format == OutputFormat::Frames {
+                return Err(DeliveryError::Config("--slate needs --output-format video".to_string()));
+            }
+            if !self.renditions.is_empty() {
+                return Err(DeliveryError::Config("--slate takes a single video; drop --rendition".to_string()));
+            }
+            if self.audio_codec() == AudioCodec::Copy {
+                return Err(DeliveryError::Config(
+                    "--slate delays the audio to follow it, so it can't be copied; use --audio aac or pcm".to_string(),
+                ));
+            }
+            if slate.duration.is_nan() || slate.duration <= 0.0 {
+                return Err(DeliveryError::Config("--slate-duration must be more than 0 seconds".to_string()));
+            }
+        }
+        if let Some(hook) = &self.watermark_hook {
+            if hook.command.trim().is_empty() {
+                return Err(DeliveryError::Config("--watermark-hook needs a command".to_string()));
+            }
+            if !self.renditions.is_empty() {
+                let message = "--watermark-hook takes a single video; drop --rendition";
+                return Err(DeliveryError::Config(message.to_string()));
+            }
+            if self.two_pass.is_some() {
+                return Err(DeliveryError::Config(
+                    "--watermark-hook would run on both passes of --two-pass; use --crf or a single-pass bitrate"
+                        .to_string(),
+                ));
+            }
+            if self.package.contains(&Package::Dcp) {
+                return Err(DeliveryError::Config(
+                    "--package dcp converts the frames to XYZ before encoding, which the hook's RGB frames can't \
+                    carry; drop --watermark-hook"
+                        .to_string(),
+                ));
+            }
+        }
+        if self.filter != DEFAULT_FILTER && self.overlay_settings != OverlaySettings::default() {
+            return Err(DeliveryError::Config(
+                "--overlay-position, --overlay-margin, --overlay-x, --overlay-y, --overlay-scale, \
+                --overlay-opacity, --overlay-key and --overlay-window set up the built-in overlay; do the same in \
+                --filter instead"
+                    .to_string(),
+            ));
+        }
+        let layers_set_up = self.overlay_layers.iter().any(|l| l.settings != OverlaySettings::default());
+        if self.filter != DEFAULT_FILTER && layers_set_up {
+            return Err(DeliveryError::Config(
+                "The settings of [[overlays]] layers and --overlay-schedule apply to the built-in overlay; with \
+                --filter, composite inputs 2 and up there instead"
+                    .to_string(),
+            ));
+        }
+        let graded = self.lut.is_some() || self.tonemap.is_some() || self.color.is_set();
+        if (graded || self.autocrop) && !self.filter.contains("[0:v]") {
+            return Err(DeliveryError::Config(
+                "--lut, --tonemap, --color-* and --autocrop need a filter graph that reads the video as [0:v]"
+                    .to_string(),
+            ));
+        }
+        if graded && (!self.quality_metrics.is_empty() || self.target_vmaf.is_some()) {
+            warning!("⚠️ Quality is measured against the untouched source, so grading lowers the scores");
+        }
+        self.burn_in_text()?;
+        self.check_rate_control()?;
+        self.check_alpha()?;
+        let frames = self.output_format == OutputFormat::Frames;
+        if self.quality.png_compression.is_some() && !(frames && self.frame_format == FrameFormat::Png) {
+            warning!("⚠️ --png-compression only applies to png frames");
+        }
+        if self.quality.jpeg_quality.is_some() && !(frames && self.frame_format == FrameFormat::Jpeg) {
+            warning!("⚠️ --jpeg-quality only applies to jpeg frames");
+        }
+        if let Some(profile) = &self.icc_profile {
+            if !(frames && matches!(self.frame_format, FrameFormat::Png | FrameFormat::Tiff)) {
+                return Err(DeliveryError::Config("--icc-profile embeds a profile in png and tiff frames".to_string()));
+            }
+            profile.load()?;
+        }
+        Ok(())
+    }
+
+    // Fail unless the options fit what resolving the job picked and probed:
+    // the bit depth (10 bits for HDR video), the hardware acceleration and
+    // the source's colour.
+    fn check_resolved(&self) -> Result<()> {
+        let depth = self.bit_depth();
+        match self.output_format {
+            OutputFormat::Frames => {
+                self.frame_format.check_bit_depth(depth).map_err(DeliveryError::Config)?;
+                if let Some(codec) = self.intermediate {
+                    codec.check_bit_depth(depth).map_err(DeliveryError::Config)?;
+                }
+            }
+            OutputFormat::Video => self.codec.check_bit_depth(depth).map_err(DeliveryError::Config)?,
+        }
+        if self.output_format == OutputFormat::Video && self.rate_controlled() {
+            let alpha = self.alpha == AlphaMode::Preserve;
+            self.codec.check_settings(&self.video_settings, depth, alpha).map_err(DeliveryError::Config)?;
+        }
+        if !self.gpus.is_empty() && self.hwaccel != HwAccel::Nvenc {
+            return Err(DeliveryError::Config(format!(
+                "--gpus selects NVIDIA GPUs and needs --hwaccel nvenc (hardware acceleration is {})",
+                self.hwaccel
+            )));
+        }
+        if self.output_format == OutputFormat::Video && self.hwaccel.encoder(self.codec).is_some() {
+            if self.two_pass.is_some() {
+                return Err(DeliveryError::Config(format!(
+                    "--two-pass needs software encoding; {} encodes in one pass",
+                    self.hwaccel
+                )));
+            }
+            if self.video_settings.preset.is_some() {
+                return Err(DeliveryError::Config(format!(
+                    "--preset picks an x264/x265 preset; {} encoding uses its own",
+                    self.hwaccel
+                )));
+            }
+        }
+        let converted = self.color.is_set() && self.lut.is_none() && self.tonemap.is_none();
+        if let Some(source) = self.source_color.as_ref().filter(|s| converted && !s.is_hdr()) {
+            let tags = [("primaries", &source.primaries), ("transfer", &source.transfer), ("matrix", &source.space)];
+            let untagged: Vec<&str> = tags.iter().filter(|(_, tag)| tag.is_none()).map(|(name, _)| *name).collect();
+            if !untagged.is_empty() {
+                warning!("⚠️ The input doesn't tag its {}; converting it as BT.709", untagged.join(", "));
+            }
+        }
+        if let Some(hdr) = self.hdr_source() {
+            let transfer = self.color.transfer.clone().or(hdr.transfer.clone()).unwrap_or_default();
+            if depth < 10 {
+                return Err(DeliveryError::Config(format!(
+                    "The input is HDR ({}) and needs at least 10 bits; drop --bit-depth or tone map it with \
+                    --tonemap sdr",
+                    transfer
+                )));
+            }
+            if self.codec == VideoCodec::Hevc && self.hwaccel.encoder(self.codec).is_some() {
+                warning!(
+                    "⚠️ {} doesn't write the mastering display or content light levels; use --hwaccel none",
+                    self.video_encoder()
+                );
+            }
+            info!("🌈 Delivering HDR ({})", transfer);
+        }
+        if let Some(profile) = &self.icc_profile {
+            let delivered = self.color.applied_to(&self.graded_color());
+            let wide_gamut = delivered.primaries.as_deref().is_some_and(|p| p != "bt709");
+            if *profile == IccProfile::Srgb && (wide_gamut || delivered.is_hdr()) {
+                warning!("⚠️ The frames aren't sRGB; pass --icc-profile a profile of their colour space");
+            }
+        }
+        Ok(())
+    }
+
+    /// Verify once, before any worker starts, that the ffmpeg build supports
+    /// every filter and encoder this job uses.
+    pub fn check_capabilities(&self) -> Result<ffmpeg::Capabilities> {
+        let _span = tracing::info_span!("preflight", ffmpeg = %self.ffmpeg.display()).entered();
+        let mut encoders = match self.output_format {
+            OutputFormat::Frames => [Some(self.frame_format.encoder()), self.intermediate.map(|c| c.encoder())]
+                .into_iter()
+                .flatten()
+                .collect(),
+            OutputFormat::Video => vec![self.video_encoder()],
+        };
+        info!("\n🔍 Checking FFmpeg capabilities...");
+        let caps = ffmpeg::Capabilities::query(&self.ffmpeg)?;
+        debug!("ℹ️ {}", caps.version);
+        self.hwaccel.resolve(&caps, None)?;
+        let mut filters = ffmpeg::filter_names(&self.filter_graph(&WHOLE_PROGRAM));
+        filters.extend(self.quality_metrics.iter().map(|m| m.filter().to_string()));
+        if self.searches_crf() {
+            filters.push(QualityMetric::Vmaf.filter().to_string());
+        }
+        if (self.split_on == SplitOn::Scenes || self.shot_list.is_some()) && self.plan.is_none() {
+            filters.extend(["scale", "select", "showinfo"].map(String::from));
+        }
+        if let Some(slate) = &self.slate {
+            filters.extend(["color", "drawtext", "adelay"].map(String::from));
+            if slate.two_pop {
+                filters.push("aeval".to_string());
+            }
+        }
+        if self.watermark_hook.as_ref().is_some_and(|h| h.mode == HookMode::Files) {
+            encoders.push(FrameFormat::Png.encoder());
+        }
+        caps.require(&filters, &encoders)?;
+        info!("✅ FFmpeg supports the required filters and encoders");
+        Ok(caps)
+    }
+
+    // Whether the job sets how the video is encoded: its video settings, a
+    // two-pass encode or a VMAF target.
+    fn rate_controlled(&self) -> bool {
+        self.video_settings != VideoSettings::default() || self.two_pass.is_some() || self.target_vmaf.is_some()
+    }
+
+    // Fail unless the video settings apply to the codec, and a two-pass
+    // encode has a target for every output.
+    fn check_rate_control(&self) -> Result<()> {
+        if !self.rate_controlled() {
+            return Ok(());
+        }
+        if self.output_format == OutputFormat::Frames {
+            return Err(DeliveryError::Config(
+                "--crf, --target-vmaf, --bitrate, --maxrate, --preset, --profile, --level and --two-pass need \
+                --output-format video"
+                    .to_string(),
+            ));
+        }
+        if let Some(target) = self.target_vmaf {
+            crfsearch::parse_target(&target.to_string()).map_err(DeliveryError::Config)?;
+            if !matches!(self.codec, VideoCodec::H264 | VideoCodec::Hevc | VideoCodec::Av1) {
+                return Err(DeliveryError::Config(format!(
+                    "{} has no CRF to search; --target-vmaf needs --codec h264, hevc or av1",
+                    self.codec
+                )));
+            }
+            if !self.renditions.is_empty() {
+                return Err(DeliveryError::Config(
+                    "--target-vmaf searches the CRF of a single video; drop --rendition".to_string(),
+                ));
+            }
+            if self.video_settings.bitrate.is_some() || self.two_pass.is_some() {
+                return Err(DeliveryError::Config("--target-vmaf picks a CRF, not a --bitrate".to_string()));
+            }
+        }
+        if self.two_pass.is_some() && !self.codec.supports_two_pass() {
+            return Err(DeliveryError::Config(format!(
+                "{} is encoded in one pass; --two-pass needs --codec h264 or hevc",
+                self.codec
+            )));
+        }
+        let rates = self.video_settings.bitrate.is_some() || self.video_settings.maxrate.is_some();
+        if rates && !self.renditions.is_empty() {
+            return Err(DeliveryError::Config(
+                "--bitrate and --maxrate don't apply to renditions; give each rendition its bitrate".to_string(),
+            ));
+        }
+        if self.two_pass.is_some() && self.video_settings.crf.is_some() {
+            return Err(DeliveryError::Config("--two-pass encodes to a bitrate, not --crf".to_string()));
+        }
+        if self.two_pass.is_some() && self.target_bitrates().contains(&None) {
+            return Err(DeliveryError::Config(if self.renditions.is_empty() {
+                "--two-pass needs a target --bitrate".to_string()
+            } else {
+                "--two-pass needs a bitrate for every rendition".to_string()
+            }));
+        }
+        Ok(())
+    }
+
+    /// Fail if the input video, an overlay or the LUT is missing.
+    pub fn validate_inputs(&self) -> Result<()> {
+        info!("\n🔍 Validating input files:");
+        let layers = self.overlay_layers.iter().map(|l| ("Overlay layer", &l.path));
+        let lut = self.lut.iter().map(|path| ("LUT", path));
+        for (name, path) in [("Video", &self.input), ("Overlay", &self.overlay)].into_iter().chain(layers).chain(lut) {
+            let exists = if matches!(name, "Video" | "LUT") { path.exists() } else { overlay::exists(path) };
+            debug!("- {}: {} -> {}", name, path.display(), exists);
+            if !exists {
+                return Err(DeliveryError::MissingInput { name, path: path.clone() });
+            }
+        }
+        Ok(())
+    }
+
+    /// Names of the output frames: those of the job's plan, or resolved from
+    /// `name_template`.
+    pub fn frame_names(&self) -> Result<FrameNames> {
+        if let Some(plan) = &self.plan {
+            return Ok(plan.frame_names.clone());
+        }
+        let file_name = |path: &Path| path.file_stem().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let vars = NameVars {
+            basename: file_name(&self.input),
+            job: self.job_id.clone().unwrap_or_else(|| file_name(&self.output_dir)),
+            date: UtcTime::now().date(),
+            ext: self.frame_format.extension().to_string(),
+        };
+        FrameNames::from_template(&self.name_template, &vars, self.start_frame, self.frame_padding)
+            .map_err(DeliveryError::Config)
+    }
+
+    /// The burn-in text with its variables filled in: that of the job's
+    /// plan, or resolved from `burn_in`.
+    pub fn burn_in_text(&self) -> Result<Option<String>> {
+        if let Some(plan) = &self.plan {
+            return Ok(plan.burn_in_text.clone());
+        }
+        let Some(burn_in) = &self.burn_in else {
+            return Ok(None);
+        };
+        let vars = TextVars {
+            filename: self.input.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+            date: UtcTime::now().date(),
+            jobid: self.job_id.clone().unwrap_or_else(|| {
+                self.output_dir.file_stem().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+            }),
+            recipient: self.recipient.clone(),
+        };
+        burnin::resolve(&burn_in.text, &vars).map(Some).map_err(DeliveryError::Config)
+    }
+
+    /// The slate with its title and date filled in: that of the job's plan,
+    /// or the job id (or the input's name) and today.
+    pub fn slate(&self) -> Option<Slate> {
+        if let Some(plan) = &self.plan {
+            return plan.slate.clone();
+        }
+        let slate = self.slate.clone()?;
+        Some(Slate {
+            title: slate.title.or_else(|| Some(self.output_stem())),
+            date: slate.date.or_else(|| Some(UtcTime::now().date())),
+            ..slate
+        })
+    }
+
+    // Frames named like this job's in `dir`, or its video file.
+    fn existing_frames(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        if self.output_format == OutputFormat::Video {
+            let videos = self.video_chunks(&self.video_name()).into_iter().map(|(_, name)| dir.join(name));
+            return Ok(videos.filter(|video| video.exists()).collect());
+        }
+        combine::output_frames(dir, &self.frame_names()?)
+            .map_err(|e| DeliveryError::io(format!("Failed to read {}", dir.display()), e))
+    }
+
+    // `output_dir`, or the subdirectory a timestamped run started now writes to.
+    fn run_output_dir(&self) -> PathBuf {
+        if !self.timestamped_output {
+            return self.output_dir.clone();
+        }
+        let job = self.job_id.clone().unwrap_or_else(|| {
+            self.input.file_stem().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| "job".to_string())
+        });
+        self.output_dir.join(output::run_dir_name(&job, &UtcTime::now()))
+    }
+
+    /// Switch to a new timestamped subdirectory if `timestamped_output` is
+    /// set, then apply `on_existing` if `output_dir` already holds frames:
+    /// fail, delete them, or switch to a new versioned directory. Returns
+    /// `false` if the job should be skipped.
+    pub fn prepare_output(&mut self) -> Result<bool> {
+        if self.timestamped_output {
+            self.output_dir = self.run_output_dir();
+            self.timestamped_output = false;
+            self.update_latest = true;
+            info!("📂 Writing this run to {}", self.output_dir.display());
+        }
+        let existing = self.existing_frames(&self.output_dir)?;
+        if existing.is_empty() {
+            return Ok(true);
+        }
+        match self.on_existing {
+            OnExisting::Fail => Err(self.existing_output_error(existing.len())),
+            OnExisting::Skip => {
+                info!("⏭ {} already holds {} frames, skipping", self.output_dir.display(), existing.len());
+                Ok(false)
+            }
+            OnExisting::Overwrite => {
+                info!("🧹 Removing {} frames from {}", existing.len(), self.output_dir.display());
+                for frame in existing {
+                    fs::remove_file(&frame)
+                        .map_err(|e| DeliveryError::io(format!("Failed to remove {}", frame.display()), e))?;
+                }
+                Ok(true)
+            }
+            OnExisting::Version => {
+                let base = unversioned(&self.output_dir);
+                let name = base.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+                let mut version = 2;
+                let dir = loop {
+                    let dir = base.with_file_name(format!("{}_v{:03}", name, version));
+                    if self.existing_frames(&dir)?.is_empty() {
+                        break dir;
+                    }
+                    version += 1;
+                };
+                info!("📂 {} already holds frames, writing to {}", self.output_dir.display(), dir.display());
+                self.output_dir = dir;
+                Ok(true)
+            }
+        }
+    }
+
+    fn existing_output_error(&self, frames: usize) -> DeliveryError {
+        if self.output_format == OutputFormat::Video {
+            return DeliveryError::Config(format!(
+                "{} already holds {} from a previous run; use --on-existing skip, overwrite or version",
+                self.output_dir.display(),
+                self.video_chunks(&self.video_name()).into_iter().map(|(_, name)| name).collect::<Vec<_>>().join(", ")
+            ));
+        }
+        DeliveryError::Config(format!(
+            "{} already holds {} frames from a previous run; use --on-existing skip, overwrite or version",
+            self.output_dir.display(),
+            frames
+        ))
+    }
+
+    /// Probe the input and split it into segments, or return the job's
+    /// precomputed plan. The plan is saved to `plan_out` if set.
+    pub fn plan(&self) -> Result<JobPlan> {
+        let plan = match &self.plan {
+            Some(plan) => {
+                info!("📄 Using precomputed plan with {} segments", plan.segments.len());
+                plan.clone()
+            }
+            None => {
+                let media = probe::media_info(&self.ffprobe, &self.input)?;
+
+                let num_threads = self.threads.max(1);
+                let num_segments = self.segments.unwrap_or_else(|| default_segments(media.duration, num_threads)).max(1);
+                info!("🧵 Using {} threads for {} segments", num_threads, num_segments);
+                let frame_rate = self.frame_rate(&media);
+                match (self.vfr_mode, &media.video) {
+                    (VfrMode::Cfr(rate), _) => info!("🎞 Converting to a constant {:.3} fps", rate),
+                    (VfrMode::Warn, Some(video)) if video.variable_frame_rate => warning!(
+                        "⚠️ Input has a variable frame rate (~{:.3} fps on average); frame counts and segment \
+                        boundaries may be off. Use --vfr-mode cfr:<fps> to convert it",
+                        video.frame_rate
+                    ),
+                    _ => {}
+                }
+                if let (AlphaMode::Preserve, Some(video)) = (&self.alpha, &media.video) {
+                    if !video.has_alpha() {
+                        warning!("⚠️ Input ({}) has no alpha channel; the frames will be opaque", video.pix_fmt);
+                    }
+                }
+                if self.package.contains(&Package::Dcp) && (frame_rate - dcp::FRAME_RATE).abs() > 1e-6 {
+                    return Err(DeliveryError::Config(format!(
+                        "A DCP plays at {} fps and the input is {:.3} fps; convert it with --vfr-mode cfr:{}",
+                        dcp::FRAME_RATE,
+                        frame_rate,
+                        dcp::FRAME_RATE
+                    )));
+                }
+                if self.slate.is_some() && frame_rate <= 0.0 {
+                    return Err(DeliveryError::Config(
+                        "The input's frame rate is unknown, so the slate can't be timed; set it with --vfr-mode \
+                        cfr:<fps>"
+                            .to_string(),
+                    ));
+                }
+                if self.slate.as_ref().is_some_and(|s| s.two_pop) && media.audio.is_empty() {
+                    warning!("⚠️ The input has no audio; the 2-pop will be silent");
+                }
+                if self.extract_audio.is_some() && media.audio.is_empty() {
+                    warning!("⚠️ The input has no audio tracks to extract");
+                }
+                if self.loudness_target.is_some() && media.audio.is_empty() {
+                    warning!("⚠️ The input has no audio to normalize");
+                }
+                if self.output_format == OutputFormat::Video {
+                    let codecs: Vec<&str> = media.audio.iter().map(|a| a.codec.as_str()).collect();
+                    self.audio_codec().check_container(self.container(), &codecs).map_err(DeliveryError::Config)?;
+                }
+                if self.output_format == OutputFormat::Video && self.container() == Container::Mxf {
+                    let streams: Vec<u32> = media.audio.iter().map(|a| a.channels).collect();
+                    self.audio_tracks.check(&streams).map_err(DeliveryError::Config)?;
+                }
+                if self.bit_depth() > 8 && self.filter != DEFAULT_FILTER && !self.filter.contains("format=") {
+                    warning!(
+                        "⚠️ The filter graph may reduce the frames to 8 bits per channel; composite at a higher \
+                        depth with e.g. overlay=format=yuv444p10"
+                    );
+                }
+                let cuts = if self.split_on == SplitOn::Scenes || self.shot_list.is_some() {
+                    scenes::detect(&self.ffmpeg, &self.input)?
+                } else {
+                    Vec::new()
+                };
+                let segments = if self.split_on == SplitOn::Scenes {
+                    if cuts.is_empty() {
+                        warning!("⚠️ No scene cuts found; splitting the input evenly");
+                    }
+                    segment::plan_by_scenes(media.duration, frame_rate, num_segments, &cuts)
+                } else if self.adaptive_segments {
+                    let packets = probe::video_packets(&self.ffprobe, &self.input)?;
+                    segment::plan_by_cost(media.duration, frame_rate, num_segments, &packets)
+                } else {
+                    segment::plan(media.duration, frame_rate, num_segments)
+                };
+                let mut plan = JobPlan::new(self, &media, &segments)?;
+                plan.scene_cuts = cuts;
+                plan
+            }
+        };
+        if let Some(path) = &self.plan_out {
+            plan.save(path)?;
+            info!("📄 Plan saved to {}", path.display());
+        }
+        Ok(plan)
+    }
+
+    /// Probe and plan the job, then print every ffmpeg invocation and the
+    /// expected output layout without creating or modifying any files.
+    pub fn dry_run(&self) -> Result<JobPlan> {
+        self.validate()?;
+        if self.unresolved() {
+            return self.resolve()?.dry_run_resolved();
+        }
+        self.dry_run_resolved()
+    }
+
+    fn dry_run_resolved(&self) -> Result<JobPlan> {
+        let _span = tracing::info_span!("dry_run", input = %self.input.display()).entered();
+        self.validate_inputs()?;
+        self.check_resolved()?;
+        self.check_capabilities()?;
+        let plan = self.plan()?;
+
+        console::line("\n📝 Planned FFmpeg commands:".to_string());
+        for (path, playback, _) in self.overlay_inputs(&WHOLE_PROGRAM) {
+            if let Some(raster) = &playback.raster {
+                console::line(format!("# rasterize {} at the size it is composited at", path.display()));
+                console::line(ffmpeg::display_command(&raster.command(&self.ffmpeg, path)));
+            }
+        }
+        if self.verify_source {
+            console::line("# decode the source to check it, one slice per segment".to_string());
+            for segment in plan.segments() {
+                console::line(ffmpeg::display_command(&verify::decode_command(&self.ffmpeg, &self.input, &segment)));
+            }
+        }
+        if let Some(target) = self.target_vmaf.filter(|_| self.searches_crf()) {
+            let crfs = crfsearch::candidates(self);
+            let duration = plan.segments.iter().map(|s| s.duration).sum();
+            for clip in crfsearch::clips(duration) {
+                for &crf in &crfs {
+                    console::line(format!(
+                        "# probe clip {}: {:.3}s + {:.3}s at CRF {}, scored for VMAF {}",
+                        clip.id, clip.start, clip.duration, crf, target
+                    ));
+                    let (encode, score) = crfsearch::probe_commands(self, &plan, &clip, crf);
+                    console::line(ffmpeg::display_command(&encode));
+                    console::line(ffmpeg::display_command(&score));
+                }
+            }
+            console::line("# the segments are encoded at the highest CRF that scores the target".to_string());
+        }
+        if self.presplit {
+            console::line("# split the source at the keyframes nearest the segment boundaries".to_string());
+            console::line(ffmpeg::display_command(&split::split_command(self, &plan.segments())));
+        }
+        for (segment, planned) in plan.segments().iter().zip(&plan.segments) {
+            console::line(format!(
+                "# segment {}: {:.3}s + {:.3}s, ~{} frames -> {}",
+                segment.id,
+                segment.start,
+                segment.duration,
+                planned.expected_frames,
+                segment.dir(&self.segments_dir).display()
+            ));
+            if self.two_pass.is_some() {
+                console::line("# first pass".to_string());
+                let targets = self.target_bitrates();
+                console::line(ffmpeg::display_command(&worker::encode_command(self, segment, Some(1), &targets, None)));
+            }
+            let gpu = self.worker_gpu(segment.id);
+            if let (Some(hook), Some(decode), Some(command)) =
+                (&self.watermark_hook, worker::decode_command(self, segment, gpu), worker::hook_command(self, segment))
+            {
+                console::line(format!("# decode into the watermark hook ({} mode), then encode its frames", hook.mode));
+                console::line(ffmpeg::display_command(&decode));
+                console::line(ffmpeg::display_command(&command));
+            }
+            console::line(ffmpeg::display_command(&worker::segment_command(self, segment)));
+        }
+        if self.two_pass == Some(TwoPass::Global) {
+            console::line("# the second passes share the target bitrate by first pass complexity".to_string());
+        }
+        if let (OutputFormat::Frames, Some(ext)) = (self.output_format, self.chunk_extension()) {
+            for segment in plan.segments() {
+                console::line(format!("# expand segment {} to {} frames", segment.id, self.frame_format));
+                console::line(ffmpeg::display_command(&intermediate::expand_command(self, &segment, &ext)));
+            }
+        }
+        if let Some(slate) = plan.slate.as_ref().filter(|_| plan.output_format == OutputFormat::Video) {
+            console::line(format!("# encode the {:.1}s slate put before the program", slate.pre_roll()));
+            console::line(ffmpeg::display_command(&slate.encode_command(
+                &self.ffmpeg,
+                &self.slate_lines(slate, &plan),
+                (plan.width, plan.height),
+                plan.frame_rate,
+                &self.slate_encoder_args(),
+                &self.slate_chunk(),
+            )));
+        }
+        if let Some(target) = self.loudness_target.filter(|_| !plan.audio_channels.is_empty()) {
+            for stream in 0..plan.audio_channels.len() {
+                console::line(format!("# measure audio stream {} to normalize it to {} LUFS", stream + 1, target));
+                let cmd = loudness::measure_command(&self.ffmpeg, &self.input, stream, target);
+                console::line(ffmpeg::display_command(&cmd));
+            }
+        }
+
+        console::line("\n📂 Expected output layout:".to_string());
+        console::line(format!("{}/  (temporary, one subdirectory per segment)", self.segments_dir.display()));
+        match plan.output_format {
+            OutputFormat::Frames => console::line(format!(
+                "{}/{}  (~{} frames, numbered continuously from {} in segment order)",
+                self.run_output_dir().display(),
+                plan.frame_names.pattern(),
+                plan.expected_frames(),
+                plan.frame_names.start_frame
+            )),
+            OutputFormat::Video => {
+                for (_, name) in self.video_chunks(&self.video_name()) {
+                    console::line(format!(
+                        "{}/{}  (~{} frames of {}, joined from the segment chunks)",
+                        self.run_output_dir().display(),
+                        name,
+                        plan.expected_frames(),
+                        plan.codec
+                    ));
+                }
+            }
+        }
+        if let Some(format) = self.extract_audio {
+            for track in 0..plan.audio_channels.len() {
+                console::line(format!(
+                    "{}/{}  (audio track {} of the source)",
+                    self.run_output_dir().display(),
+                    stems::file_name(&self.output_stem(), track, format),
+                    track + 1
+                ));
+            }
+        }
+        if let Some(format) = self.shot_list {
+            console::line(format!(
+                "{}/{}  ({} shots, thumbnails in {}/)",
+                self.run_output_dir().display(),
+                shots::file_name(&self.output_stem(), format),
+                plan.scene_cuts.len() + 1,
+                shots::thumbnail_dir(&self.output_stem())
+            ));
+        }
+        for package in &self.package {
+            let from = if package.is_streaming() {
+                format!("packaged from the video in {}s segments", self.package_segment())
+            } else {
+                format!("{} track files, CPL and PKL wrapped from the video", package.dir_name().to_uppercase())
+            };
+            console::line(format!(
+                "{}/{}/{}  ({})",
+                self.run_output_dir().display(),
+                package.dir_name(),
+                package.manifest(),
+                from
+            ));
+        }
+        Ok(plan)
+    }
+
+    // Whether the CRF of video output is still to be searched for.
+    fn searches_crf(&self) -> bool {
+        self.target_vmaf.is_some() && self.video_settings.crf.is_none() && self.output_format == OutputFormat::Video
+    }
+
+    /// The overlay inputs of a graph running on the stretch of the source
+    /// `timeline`, in order from input 1: the overlay and the layers shown
+    /// in it. A custom `filter` gets every layer, as it picks its inputs by
+    /// number.
+    pub fn overlay_inputs(&self, timeline: &Range<f64>) -> Vec<(&Path, &Playback, &OverlaySettings)> {
+        let custom = self.filter != DEFAULT_FILTER;
+        let layers = self
+            .overlay_layers
+            .iter()
+            .filter(|l| custom || l.settings.window.is_none_or(|w| w.overlaps(timeline.start, timeline.end)))
+            .map(|l| (l.path.as_path(), &l.playback, &l.settings));
+        let overlay = (self.overlay.as_path(), &self.overlay_playback, &self.overlay_settings);
+        std::iter::once(overlay).chain(layers).collect()
+    }
+
+    // Whether the job leaves a choice to this machine's ffmpeg, or has an
+    // animated overlay still to be probed.
+    fn unresolved(&self) -> bool {
+        let av1 = self.output_format == OutputFormat::Video && self.codec == VideoCodec::Av1;
+        let unprobed = self.overlay_inputs(&WHOLE_PROGRAM).iter().any(|(path, playback, _)| playback.unprobed(path));
+        let hook_unprobed = self.watermark_hook.as_ref().is_some_and(|h| h.unprobed()) && self.input.exists();
+        let tonemap_unprobed = self.tonemap.as_ref().is_some_and(|t| t.unprobed()) && self.input.exists();
+        self.hwaccel == HwAccel::Auto
+            || (av1 && self.av1_encoder.is_none())
+            || unprobed
+            || self.untimed()
+            || hook_unprobed
+            || tonemap_unprobed
+            || self.colorless()
+            || self.uncropped()
+    }
+
+    // Whether the source's colour is still to be probed, for video output
+    // to carry HDR through or for converting it.
+    fn colorless(&self) -> bool {
+        let video = self.output_format == OutputFormat::Video && self.tonemap.is_none();
+        (video || self.color.is_set()) && self.source_color.is_none() && self.input.exists()
+    }
+
+    // Whether the source's black bars are still to be found for `autocrop`.
+    fn uncropped(&self) -> bool {
+        self.autocrop && self.crop.is_none() && self.input.exists()
+    }
+
+    // Whether the source's timecode or frame rate is still to be probed for
+    // the burn-ins.
+    fn untimed(&self) -> bool {
+        let timecode = self.timecode_burn_in.as_ref().is_some_and(|b| b.start.is_none());
+        let frame_number = self.frame_number_burn_in.as_ref().is_some_and(|b| b.frame_rate.is_none());
+        (timecode || frame_number) && self.input.exists()
+    }
+
+    // A copy of the job with `HwAccel::Auto` replaced by the acceleration
+    // this machine's ffmpeg supports, the AV1 encoder picked, the source's
+    // black bars found for `autocrop`, the animated overlays probed and the
+    // SVG overlays sized to the cropped picture, the burn-ins given the
+    // frame rate and the timecode burn-in the source's timecode (or the
+    // output's, if set), the watermark hook the size and rate of the
+    // source's frames, the tone mapping the source's transfer, and video
+    // output and colour conversion the source's colour (at 10 bits for HDR,
+    // unless set).
+    fn resolve(&self) -> Result<EncodeJob> {
+        let caps = ffmpeg::Capabilities::query(&self.ffmpeg)?;
+        // The source is probed on first use, once for every feature below
+        let probed = OnceCell::new();
+        let media_info = || -> Result<&MediaInfo> {
+            if let Some(media) = probed.get() {
+                return Ok(media);
+            }
+            let media = probe::media_info(&self.ffprobe, &self.input)?;
+            Ok(probed.get_or_init(|| media))
+        };
+        let codec = (self.output_format == OutputFormat::Video).then_some(self.codec);
+        let mut job = self.clone();
+        job.hwaccel = self.hwaccel.resolve(&caps, codec)?;
+        if codec == Some(VideoCodec::Av1) && self.av1_encoder.is_none() {
+            // A build with neither fails the capability check on SVT-AV1
+            let aom = !caps.encoders.contains(Av1Encoder::Svt.encoder())
+                && caps.encoders.contains(Av1Encoder::Aom.encoder());
+            let encoder = if aom { Av1Encoder::Aom } else { Av1Encoder::Svt };
+            debug!("ℹ️ Encoding AV1 with {}", encoder);
+            job.av1_encoder = Some(encoder);
+        }
+        if self.uncropped() {
+            let media = media_info()?;
+            let size = media.video.as_ref().map_or((0, 0), |v| (v.width, v.height));
+            job.crop = Some(crop::detect(&self.ffmpeg, &self.input, size, media.duration)?);
+        }
+        if self.overlay_inputs(&WHOLE_PROGRAM).iter().any(|(path, playback, _)| playback.unprobed(path)) {
+            let (program_rate, size) = match &self.plan {
+                Some(plan) => (plan.frame_rate, (plan.width, plan.height)),
+                None => {
+                    let media = media_info()?;
+                    let size = media.video.as_ref().map_or((0, 0), |v| (v.width, v.height));
+                    (self.frame_rate(media), job.crop.unwrap_or_default().apply(size))
+                }
+            };
+            let probe = |path: &Path, settings: &OverlaySettings, playback: &mut Playback| -> Result<()> {
+                if playback.unprobed(path) && svg::is_svg(path) {
+                    playback.raster = Some(Raster::new(path, settings.scale, size, &self.segments_dir));
+                } else if playback.unprobed(path) {
+                    playback.clip = Some(Clip::probe(&self.ffprobe, path, program_rate)?);
+                }
+                Ok(())
+            };
+            probe(&self.overlay, &self.overlay_settings, &mut job.overlay_playback)?;
+            for layer in &mut job.overlay_layers {
+                probe(&layer.path, &layer.settings, &mut layer.playback)?;
+            }
+        }
+        if self.untimed() {
+            let media = media_info()?;
+            let frame_rate = Some(self.frame_rate(media));
+            if let Some(burn_in) = &mut job.timecode_burn_in {
+                let start = match (&self.timecode, &media.timecode) {
+                    (Some(Timecode::At(timecode)), _) | (_, Some(timecode)) => timecode.clone(),
+                    (_, None) => {
+                        warning!("⚠️ The input has no timecode; the burned-in timecode starts at 00:00:00:00");
+                        "00:00:00:00".to_string()
+                    }
+                };
+                burn_in.start = Some(start);
+                burn_in.frame_rate = frame_rate;
+            }
+            if let Some(burn_in) = &mut job.frame_number_burn_in {
+                burn_in.frame_rate = frame_rate;
+            }
+        }
+        if let Some(hook) = job.watermark_hook.as_mut().filter(|h| h.unprobed() && self.input.exists()) {
+            let media = media_info()?;
+            let Some(video) = &media.video else {
+                let message = format!("{} has no video stream to hand the watermark hook", self.input.display());
+                return Err(DeliveryError::ProbeFailed(message));
+            };
+            hook.width = Some(video.width);
+            hook.height = Some(video.height);
+            hook.frame_rate = Some(self.frame_rate(media));
+        }
+        if let Some(tonemap) = job.tonemap.as_mut().filter(|t| t.unprobed() && self.input.exists()) {
+            let media = media_info()?;
+            let transfer = media.video.as_ref().and_then(|v| v.color_transfer.clone());
+            tonemap.transfer = Some(match transfer {
+                Some(transfer) if transfer == color::PQ || transfer == color::HLG => transfer,
+                Some(transfer) => {
+                    return Err(DeliveryError::Config(format!(
+                        "--tonemap maps HDR (PQ or HLG) video to SDR, and the input's transfer is {}",
+                        transfer
+                    )))
+                }
+                None => {
+                    warning!("⚠️ The input's transfer isn't tagged; tone mapping it as PQ");
+                    color::PQ.to_string()
+                }
+            });
+        }
+        if self.colorless() {
+            let media = media_info()?;
+            let mut color = media.video.as_ref().map_or_else(SourceColor::default, |v| SourceColor {
+                primaries: v.color_primaries.clone(),
+                transfer: v.color_transfer.clone(),
+                space: v.color_space.clone(),
+                range: v.color_range.clone(),
+                ..SourceColor::default()
+            });
+            job.source_color = Some(color.clone());
+            if job.hdr_source().is_some() {
+                (color.mastering_display, color.content_light) = probe::hdr_metadata(&self.ffprobe, &self.input)?;
+                if self.bit_depth.is_none() && self.bit_depth() < 10 {
+                    debug!("ℹ️ Encoding the HDR source at 10 bits");
+                    job.bit_depth = Some(10);
+                }
+            }
+            job.source_color = Some(color);
+        }
+        Ok(job)
+    }
+
+    /// Run the full pipeline: probe, encode segments in parallel, combine and
+    /// clean up. Returns the number of frames written to `output_dir`.
+    pub fn run(&self) -> Result<usize> {
+        self.validate()?;
+        if self.unresolved() {
+            return self.resolve()?.run_resolved();
+        }
+        self.run_resolved()
+    }
+
+    fn run_resolved(&self) -> Result<usize> {
+        let started = Instant::now();
+        let _span = tracing::info_span!(
+            "job",
+            input = %self.input.display(),
+            output_dir = %self.output_dir.display(),
+        )
+        .entered();
+        self.validate_inputs()?;
+        self.check_resolved()?;
+        self.check_capabilities()?;
+        let mut plan = self.plan()?;
+
+        if self.verify_source {
+            verify::source(&self.ffmpeg, &self.input, &plan.segments(), self.threads)?;
+        }
+        // Continue with the CRF found; the saved plan keeps it for resumed runs
+        if let Some(target) = self.target_vmaf.filter(|_| self.searches_crf()) {
+            self.rasterize_overlays()?;
+            let mut job = self.clone();
+            job.video_settings.crf = Some(crfsearch::search(self, &plan, target)?);
+            plan.video_settings = job.video_settings.clone();
+            return job.execute(plan, started);
+        }
+        self.execute(plan, started)
+    }
+
+    // Encode, verify and combine the segments of `plan`, which the job's
+    // checks have passed, and clean up.
+    fn execute(&self, mut plan: JobPlan, started: Instant) -> Result<usize> {
+        // Never mix new frames with those of an earlier run; resumed runs
+        // rewrite the same frames
+        if !self.resume && self.only_segments.is_none() {
+            let existing = self.existing_frames(&self.output_dir)?;
+            if !existing.is_empty() {
+                return Err(self.existing_output_error(existing.len()));
+            }
+        }
+
+        // Create output directory
+        info!("\n📂 Creating output directory: {}", self.output_dir.display());
+        if !self.output_dir.exists() {
+            fs::create_dir_all(&self.output_dir)
+                .map_err(|e| DeliveryError::io("Failed to create output directory", e))?;
+            info!("✅ Created output directory");
+        } else {
+            info!("ℹ️ Output directory already exists");
+        }
+
+        let mut segments = plan.segments();
+        let mut pending = self.pending_segments(&segments)?;
+        self.rasterize_overlays()?;
+        let presplit = self.presplit && !self.resume && self.only_segments.is_none()
+            && segments.iter().all(|s| s.source.is_none());
+        if self.space_check {
+            self.check_disk_space(&plan, &pending, presplit)?;
+        }
+        if presplit {
+            segments = split::split_source(self, &segments)?;
+            plan.set_segments(self, &segments);
+            pending = segments.clone();
+        }
+        plan.save(&self.segments_dir.join(PLAN_FILE))?;
+
+        events::emit(Event::JobStarted {
+            input: self.input.display().to_string(),
+            output_dir: self.output_dir.display().to_string(),
+            segments: segments.len(),
+            duration: segments.iter().map(|s| s.duration).sum(),
+        });
+
+        let workers = self.workers(&plan, pending.len());
+        let encoded = twopass::prepare(self, &segments, &pending)
+            .and_then(|bitrates| worker::run_all(self, &pending, &bitrates, workers));
+        if let Err(e) = encoded {
+            if matches!(e, DeliveryError::Interrupted) {
+                if self.keep_on_interrupt {
+                    info!("ℹ️ Completed segments kept in {} for resume", self.segments_dir.display());
+                } else {
+                    cleanup::remove_segments_dir(&self.segments_dir);
+                }
+            }
+            return Err(e);
+        }
+
+        let encoded = verify::frame_counts(&self.segments_dir, &plan, &self.ffprobe, self.allow_frame_mismatch)
+            .inspect_err(|_| {
+                info!("ℹ️ Segments kept in {} for inspection", self.segments_dir.display());
+            })?;
+        if self.check_boundaries {
+            verify::boundaries(&self.segments_dir, &plan, &self.ffprobe)?;
+        }
+        let frames = self.combine_into_output(&plan, &segments, encoded)?;
+
+        self.finish_segments_dir();
+        events::emit(Event::JobDone { frames, elapsed: started.elapsed().as_secs_f64() });
+        Ok(frames)
+    }
+
+    // Draw the SVG overlays at the size they are composited at.
+    fn rasterize_overlays(&self) -> Result<()> {
+        for (path, playback, _) in self.overlay_inputs(&WHOLE_PROGRAM) {
+            if let Some(raster) = &playback.raster {
+                raster.draw(&self.ffmpeg, path)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Number of segments to encode at once: `threads`, but no more than there
+    // are segments, than fit into the available memory or than may keep
+    // their files open at once.
+    fn workers(&self, plan: &JobPlan, pending: usize) -> usize {
+        let workers = self.threads.clamp(1, pending.max(1));
+        let workers = openfiles::limit_workers(workers, openfiles::FILES_PER_WORKER, self.max_open_files);
+        let per_worker = match self.memory_per_worker {
+            Some(bytes) => bytes,
+            None if plan.width == 0 || plan.height == 0 => return workers,
+            None => memory::estimate_worker_bytes(plan.width, plan.height, self.ffmpeg_threads()),
+        };
+        memory::limit_workers(workers, per_worker)
+    }
+
+    // Estimate what the pending segments and the combined output will take
+    // and make sure it fits on the temp and output volumes.
+    fn check_disk_space(&self, plan: &JobPlan, pending: &[Segment], presplit: bool) -> Result<()> {
+        if plan.width == 0 || plan.height == 0 {
+            debug!("⚠️ Unknown resolution, skipping disk space check");
+            return Ok(());
+        }
+        let frames_of = |ids: &mut dyn Iterator<Item = usize>| -> u64 {
+            ids.filter_map(|id| plan.segments.iter().find(|s| s.id == id))
+                .map(|s| s.expected_frames)
+                .sum()
+        };
+        let depth = self.bit_depth();
+        let estimate = |frames| match self.output_format {
+            OutputFormat::Frames => {
+                diskspace::estimate_frame_bytes(frames, plan.width, plan.height, self.frame_format, depth)
+            }
+            OutputFormat::Video if self.renditions.is_empty() => {
+                match self.video_settings.bitrate.or(self.video_settings.maxrate) {
+                    Some(bitrate) if plan.frame_rate > 0.0 => {
+                        diskspace::estimate_bitrate_bytes(frames, plan.frame_rate, bitrate)
+                    }
+                    _ => diskspace::estimate_video_bytes(frames, plan.width, plan.height, self.codec),
+                }
+            }
+            OutputFormat::Video => self
+                .renditions
+                .iter()
+                .map(|r| match r.bitrate {
+                    Some(bitrate) if plan.frame_rate > 0.0 => {
+                        diskspace::estimate_bitrate_bytes(frames, plan.frame_rate, bitrate)
+                    }
+                    _ => {
+                        let width = (plan.width as u64 * r.height as u64 / plan.height as u64) as u32;
+                        diskspace::estimate_video_bytes(frames, width, r.height, self.codec)
+                    }
+                })
+                .sum(),
+        };
+        let pending_frames = frames_of(&mut pending.iter().map(|s| s.id));
+        let mut temp = estimate(pending_frames);
+        // Chunks are expanded next to themselves before combining
+        if let (OutputFormat::Frames, Some(codec)) = (self.output_format, self.intermediate) {
+            temp = diskspace::estimate_intermediate_bytes(pending_frames, plan.width, plan.height, codec, depth)
+                + estimate(plan.expected_frames());
+        }
+        // Splitting copies the video stream into the segments directory
+        if presplit {
+            temp += fs::metadata(&self.input).map(|m| m.len()).unwrap_or(0);
+        }
+        let mut output = estimate(plan.expected_frames());
+        // On a shared volume frames are renamed into the output for free,
+        // unless the segments are kept and the frames copied instead. Joined
+        // video is always a new file.
+        if self.output_format == OutputFormat::Frames
+            && !self.keep_temp
+            && diskspace::same_volume(&self.segments_dir, &self.output_dir) {
+            output = 0;
+        }
+        diskspace::check(&[(&self.segments_dir, temp), (&self.output_dir, output)])
+    }
+
+    // Prepare `segments_dir` and pick the segments this run has to encode.
+    fn pending_segments(&self, segments: &[Segment]) -> Result<Vec<Segment>> {
+        if let Some(ids) = &self.only_segments {
+            let mut checkpoint = Checkpoint::load(&self.segments_dir)?;
+            if let Some(id) = ids.iter().find(|id| !segments.iter().any(|s| s.id == **id)) {
+                return Err(DeliveryError::Config(format!(
+                    "Segment {} is not part of the plan ({} segments)",
+                    id,
+                    segments.len()
+                )));
+            }
+            let incomplete: Vec<String> = segments
+                .iter()
+                .filter(|s| !ids.contains(&s.id) && !checkpoint.is_done(s.id))
+                .map(|s| s.id.to_string())
+                .collect();
+            if !incomplete.is_empty() {
+                return Err(DeliveryError::Config(format!(
+                    "Segments {} of the previous run are not complete; include them or use resume",
+                    incomplete.join(",")
+                )));
+            }
+
+            let pending: Vec<Segment> = segments.iter().filter(|s| ids.contains(&s.id)).cloned().collect();
+            info!("♻️ Re-encoding segments {} and reusing the other {}",
+                ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(","),
+                segments.len() - pending.len());
+            for segment in &pending {
+                checkpoint.completed.remove(&segment.id);
+            }
+            cleanup::prepare_resume(&self.segments_dir, &pending)?;
+            checkpoint.save(&self.segments_dir)?;
+            Ok(pending)
+        } else if self.resume {
+            let checkpoint = Checkpoint::load(&self.segments_dir)?;
+            let pending: Vec<Segment> = segments.iter().filter(|s| !checkpoint.is_done(s.id)).cloned().collect();
+            info!("♻️ Resuming: {} of {} segments already completed", segments.len() - pending.len(), segments.len());
+            cleanup::prepare_resume(&self.segments_dir, &pending)?;
+            Ok(pending)
+        } else {
+            cleanup::prepare_segments_dir(&self.segments_dir)?;
+            Ok(segments.to_vec())
+        }
+    }
+
+    /// Re-run only the merging step on the segments a previous run left in
+    /// `segments_dir`. Returns the number of frames written to `output_dir`.
+    pub fn recombine(&self) -> Result<usize> {
+        let _span = tracing::info_span!("job", output_dir = %self.output_dir.display()).entered();
+        let plan = JobPlan::load_saved(&self.segments_dir)?;
+        fs::create_dir_all(&self.output_dir)
+            .map_err(|e| DeliveryError::io("Failed to create output directory", e))?;
+        let encoded = verify::frame_counts(&self.segments_dir, &plan, &self.ffprobe, self.allow_frame_mismatch)?;
+        let frames = self.combine_into_output(&plan, &plan.segments(), encoded)?;
+        self.finish_segments_dir();
+        Ok(frames)
+    }
+
+    /// Join the video chunks a previous run with `intermediate` or video
+    /// output left in `segments_dir` into the single file `video` with the
+    /// concat demuxer, without re-encoding, instead of expanding them to
+    /// frames. Returns the number of frames in the file.
+    pub fn join_video(&self, video: &Path) -> Result<usize> {
+        let _span = tracing::info_span!("job", output = %video.display()).entered();
+        let plan = JobPlan::load_saved(&self.segments_dir)?;
+        let (Some(name), Some(dir)) = (video.file_name(), video.parent()) else {
+            return Err(DeliveryError::Config(format!("{} is not a file name", video.display())));
+        };
+        let chunks = self.video_chunks(&name.to_string_lossy());
+        if chunks.is_empty() {
+            return Err(DeliveryError::Config(
+                "The previous run wrote frames, not video chunks; encode with --intermediate or --output-format \
+                video to join the segments into one file"
+                    .to_string(),
+            ));
+        }
+        let encoded = verify::frame_counts(&self.segments_dir, &plan, &self.ffprobe, self.allow_frame_mismatch)?;
+        let mux = self.mux(&plan)?;
+        let staging = output::staging_dir(video);
+        output::prepare_staging(&staging)?;
+        let mut frames = 0;
+        for (ext, name) in &chunks {
+            frames = self.join_chunks(&plan, &plan.segments(), ext, encoded, &mux, &staging.join(name))?;
+        }
+        for (_, name) in &chunks {
+            output::publish_file(&staging.join(name), dir)?;
+        }
+        self.finish_segments_dir();
+        Ok(frames)
+    }
+
+    // The timecode, source audio and slate video output is joined with,
+    // measuring the audio's loudness first if it is to be normalized and
+    // encoding the slate. The audio is delayed to follow the slate.
+    fn mux(&self, plan: &JobPlan) -> Result<concat::Mux> {
+        let mut mux = concat::Mux { timecode: plan.timecode.clone(), ..Default::default() };
+        let slate = plan.slate.as_ref().filter(|_| plan.frame_rate > 0.0);
+        if let Some(slate) = slate {
+            let (chunk, frames) = (self.slate_chunk(), slate.frames(plan.frame_rate));
+            slate.encode(
+                &self.ffmpeg,
+                &self.slate_lines(slate, plan),
+                (plan.width, plan.height),
+                plan.frame_rate,
+                &self.slate_encoder_args(),
+                &chunk,
+            )?;
+            mux.timecode = mux.timecode.map(|t| timecode::subtract_frames(&t, frames, plan.frame_rate));
+            mux.lead = Some((chunk, frames));
+        }
+        let audio = plan.audio_codec();
+        if audio != AudioCodec::None && !plan.audio_channels.is_empty() {
+            let mut filters = match plan.loudness_target {
+                Some(target) => loudness::measure(&self.ffmpeg, &plan.input, plan.audio_channels.len(), target)?,
+                None => Vec::new(),
+            };
+            if let Some(slate) = slate {
+                let delay = slate.audio_filter(plan.frame_rate);
+                filters.resize(plan.audio_channels.len(), String::new());
+                for filter in &mut filters {
+                    *filter = if filter.is_empty() { delay.clone() } else { format!("{},{}", filter, delay) };
+                }
+            }
+            mux.audio_source = Some(plan.input.clone());
+            mux.audio_args = match plan.container() {
+                Container::Mxf => plan.audio_tracks.mux_args(1, &plan.audio_channels, &filters),
+                _ => audio.mux_args(1, &filters),
+            };
+        }
+        Ok(mux)
+    }
+
+    // The lines of `slate` for the program of `plan`.
+    fn slate_lines(&self, slate: &Slate, plan: &JobPlan) -> Vec<String> {
+        let trt = timecode::from_seconds(plan.expected_frames() as f64 / plan.frame_rate, plan.frame_rate);
+        slate.lines(&trt, &audio_layout(plan))
+    }
+
+    // Encoder options of the slate: those of the video, at its bitrate.
+    fn slate_encoder_args(&self) -> Vec<String> {
+        self.video_encoder_args(self.target_bitrates().first().copied().flatten(), None, self.worker_gpu(0))
+    }
+
+    // File the slate is encoded to, in the segments directory.
+    fn slate_chunk(&self) -> PathBuf {
+        let ext = self.chunk_extension().unwrap_or_default();
+        self.segments_dir.join(slate::SLATE_DIR).join(format!("chunk.{}", ext))
+    }
+
+    // Join the `ext` chunks of `segments` into `video` with `mux`, which must
+    // then hold the `encoded` frames the chunks did, and those of its lead.
+    fn join_chunks(
+        &self,
+        plan: &JobPlan,
+        segments: &[Segment],
+        ext: &str,
+        encoded: u64,
+        mux: &concat::Mux,
+        video: &Path,
+    ) -> Result<usize> {
+        let (dir, rate) = (&self.segments_dir, plan.frame_rate);
+        concat::join(&self.ffmpeg, &self.ffprobe, dir, segments, ext, rate, mux, video)?;
+        let frames = probe::count_frames(&self.ffprobe, video)?;
+        let expected = encoded + mux.lead.as_ref().map_or(0, |(_, frames)| *frames);
+        if frames != expected {
+            if let Some(dir) = video.parent() {
+                info!("ℹ️ Joined video kept in {} for inspection", dir.display());
+            }
+            return Err(DeliveryError::FrameCountMismatch { expected, actual: frames });
+        }
+        Ok(frames as usize)
+    }
+
+    // Combine the segments into a staging directory and publish it as
+    // `output_dir` once every frame is there. Video chunks are joined into
+    // one file, which must hold the `encoded` frames the chunks did.
+    fn combine_into_output(&self, plan: &JobPlan, segments: &[Segment], encoded: u64) -> Result<usize> {
+        let staging = output::staging_dir(&self.output_dir);
+        output::prepare_staging(&staging)?;
+        if plan.output_format == OutputFormat::Video {
+            // Join every rendition before publishing any
+            let mut frames = 0;
+            let mux = self.mux(plan)?;
+            let chunks = self.video_chunks(&self.video_name());
+            for (ext, name) in &chunks {
+                frames = self.join_chunks(plan, segments, ext, encoded, &mux, &staging.join(name))?;
+            }
+            let mut videos = Vec::new();
+            for (_, name) in chunks {
+                output::publish_file(&staging.join(&name), &self.output_dir)?;
+                videos.push(self.output_dir.join(name));
+            }
+            self.extract_stems(plan)?;
+            self.export_shot_list(plan)?;
+            self.score_videos(plan, segments, &videos);
+            for package in &plan.package {
+                let (video, joined) = (&videos[0], frames as u64);
+                let manifest = match package {
+                    Package::Imf => imf::package(&self.ffmpeg, plan, video, joined, &self.output_dir)?,
+                    Package::Dcp => dcp::package(&self.ffmpeg, plan, video, joined, &self.output_dir)?,
+                    _ => package::package(
+                        &self.ffmpeg,
+                        *package,
+                        plan.codec,
+                        self.package_segment(),
+                        &self.renditions,
+                        &videos,
+                        &self.output_dir,
+                        self.max_open_files,
+                    )?,
+                };
+                info!("📦 {}", manifest.display());
+            }
+            if self.update_latest {
+                output::point_latest(&self.output_dir);
+            }
+            return Ok(frames);
+        }
+        if let Some(codec) = plan.intermediate {
+            intermediate::expand_all(self, segments, codec.extension())?;
+        }
+        let frames = combine::combine(
+            &self.segments_dir,
+            segments,
+            &staging,
+            &plan.frame_names,
+            plan.frame_format,
+            self.keep_temp,
+        )?;
+        output::publish(&staging, &self.output_dir, &plan.frame_names, frames)?;
+        self.extract_stems(plan)?;
+        self.export_shot_list(plan)?;
+        if self.update_latest {
+            output::point_latest(&self.output_dir);
+        }
+        Ok(frames)
+    }
+
+    // Score the joined `videos` against the source with the job's quality
+    // metrics. Failures are reported but don't fail the delivery.
+    fn score_videos(&self, plan: &JobPlan, segments: &[Segment], videos: &[PathBuf]) {
+        if self.quality_metrics.is_empty() {
+            return;
+        }
+        let (size, crop) = ((plan.width, plan.height), plan.crop.unwrap_or_default());
+        for video in videos {
+            let scored = metrics::compare(
+                &self.ffmpeg,
+                video,
+                &plan.input,
+                crop,
+                segments,
+                size,
+                &self.quality_metrics,
+                self.threads,
+            );
+            if let Err(e) = scored {
+                warning!("⚠️ Couldn't score {}: {}", video.display(), e);
+            }
+        }
+    }
+
+    // Write each of the source's audio tracks to its own file next to the
+    // output, if the job asks for it.
+    fn extract_stems(&self, plan: &JobPlan) -> Result<()> {
+        let Some(format) = plan.extract_audio.filter(|_| !plan.audio_channels.is_empty()) else {
+            return Ok(());
+        };
+        let tracks = plan.audio_channels.len();
+        for stem in stems::extract(&self.ffmpeg, &plan.input, tracks, format, &self.output_stem(), &self.output_dir)? {
+            info!("🎧 {}", stem.display());
+        }
+        Ok(())
+    }
+
+    // Write the shot list next to the output, if the job asks for it.
+    fn export_shot_list(&self, plan: &JobPlan) -> Result<()> {
+        let Some(format) = plan.shot_list else {
+            return Ok(());
+        };
+        if plan.frame_rate <= 0.0 {
+            warning!("⚠️ The input's frame rate is unknown; no shot list written");
+            return Ok(());
+        }
+        let ranges = shots::frame_ranges(&plan.scene_cuts, plan.duration, plan.frame_rate);
+        let stem = self.output_stem();
+        let list = shots::export(&self.ffmpeg, &plan.input, &ranges, plan.frame_rate, format, &stem, &self.output_dir)?;
+        info!("🎞 {}", list.display());
+        Ok(())
+    }
+
+    fn finish_segments_dir(&self) {
+        if self.keep_temp {
+            info!("\nℹ️ Temporary segments kept in {}", self.segments_dir.display());
+        } else {
+            cleanup::remove_segments_dir(&self.segments_dir);
+        }
+    }
+}
+
+// The audio of `plan`'s output as the slate lists it, e.g. `stereo + 5.1`.
+fn audio_layout(plan: &JobPlan) -> String {
+    if plan.audio_codec() == AudioCodec::None || plan.audio_channels.is_empty() {
+        return "none".to_string();
+    }
+    let layouts: Vec<String> = match plan.container() {
+        Container::Mxf => plan.audio_tracks.mapped(&plan.audio_channels).iter().map(|t| t.layout.to_string()).collect(),
+        _ => plan
+            .audio_channels
+            .iter()
+            .map(|&channels| match channels {
+                1 => "mono".to_string(),
+                2 => "stereo".to_string(),
+                6 => "5.1".to_string(),
+                n => format!("{} channels", n),
+            })
+            .collect(),
+    };
+    layouts.join(" + ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unversioned_strips_only_the_suffixes_versioning_writes() {
+        let cases = [
+            ("renders", "renders"),
+            ("/out/renders_v002", "/out/renders"),
+            ("renders_v1000", "renders"),
+            ("renders_v2", "renders_v2"),
+            ("renders_v10", "renders_v10"),
+            ("renders_vx", "renders_vx"),
+            ("renders_v00x", "renders_v00x"),
+            ("_v002", "_v002"),
+            ("take_v1_v003", "take_v1"),
+        ];
+        for (dir, base) in cases {
+            assert_eq!(unversioned(Path::new(dir)), PathBuf::from(base), "{}", dir);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Parallel overlay compositing on top of FFmpeg.
+//!
+//! The source video is split into time segments, every segment is composited
+//! and exported to images by its own ffmpeg process, and the resulting frames are
+//! merged back into a single numbered sequence.
+//!
+//! Diagnostics are emitted as [`tracing`] events inside a span per pipeline
+//! stage (`job`, `preflight`, `prepare`, `probe`, `scenes`, `crf_search`, `first_pass`, `encode` with
+//! one `segment` span per worker, `expand`, `loudness`, `combine`, `slate`, `package`,
+//! `stems`, `shots`, `metrics`, `qc`, `cleanup`, `screeners` with one
+//! `screener` span per recipient, and `batch` with one `batch_job` span per
+//! job of a batch), so embedding programs can install
+//! whichever subscriber they like.
+//!
+//! ```no_run
+//! use delivery_encoder::EncodeJob;
+//!
+//! let job = EncodeJob::new("master.mov", "logo.png", "renders");
+//! let frames = job.run().unwrap();
+//! println!("{} frames written", frames);
+//! ```
+
+pub mod audio;
+pub mod batch;
+pub mod burnin;
+pub mod checkpoint;
+pub mod cleanup;
+pub mod clock;
+pub mod color;
+pub mod combine;
+pub mod concat;
+pub mod config;
+pub mod console;
+pub mod crfsearch;
+pub mod crop;
+pub mod dcp;
+pub mod diskspace;
+mod error;
+pub mod events;
+pub mod ffmpeg;
+pub mod fetch;
+pub mod format;
+pub mod guides;
+pub mod hook;
+pub mod hwaccel;
+pub mod icc;
+pub mod imf;
+pub mod intermediate;
+pub mod interrupt;
+mod job;
+pub mod logfile;
+pub mod loudness;
+pub mod memory;
+pub mod metrics;
+pub mod naming;
+pub mod openfiles;
+pub mod output;
+pub mod overlay;
+pub mod package;
+pub mod plan;
+pub mod preset;
+pub mod probe;
+pub mod process;
+pub mod progress;
+pub mod qc;
+pub mod rendition;
+pub mod scenes;
+pub mod schedule;
+pub mod screener;
+pub mod segment;
+pub mod slate;
+pub mod shots;
+pub mod smpte;
+pub mod split;
+pub mod stems;
+pub mod svg;
+pub mod timecode;
+pub mod twopass;
+pub mod units;
+pub mod verify;
+pub mod worker;
+
+pub use error::{DeliveryError, Result};
+pub use format::{Av1Encoder, Container, FrameFormat, IntermediateCodec, OutputFormat, VideoCodec};
+pub use hwaccel::HwAccel;
+pub use job::{available_threads, AlphaMode, EncodeJob, OnExisting, VfrMode, DEFAULT_FILTER, SEGMENTS_DIR};
+pub use package::Package;
+pub use preset::DeliveryPreset;
+pub use rendition::Rendition;
+pub use timecode::Timecode;
+pub use twopass::TwoPass;
@@ -0,0 +1,131 @@
+use crate::clock::UtcTime;
+use crate::console::Level;
+use serde::Serialize;
+use std::cell::Cell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+static LOG: Mutex<Option<LogFile>> = Mutex::new(None);
+
+thread_local! {
+    /// Segment the current thread is working on, recorded with each entry.
+    static SEGMENT: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Size-rotated JSON-lines log file receiving every console message down to
+/// debug level, whatever the console verbosity.
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    keep: usize,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    timestamp: String,
+    level: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    segment: Option<usize>,
+    message: &'a str,
+}
+
+/// Start logging to `path`. When the file grows past `max_size` bytes it is
+/// renamed to `path.1` (older files shift to `.2`, `.3`, ...) and at most
+/// `keep` rotated files are kept.
+pub fn open(path: &Path, max_size: u64, keep: usize) -> io::Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    *LOG.lock().unwrap() = Some(LogFile {
+        path: path.to_path_buf(),
+        file,
+        size,
+        max_size: max_size.max(1),
+        keep,
+    });
+    Ok(())
+}
+
+pub fn active() -> bool {
+    LOG.lock().unwrap().is_some()
+}
+
+/// Tag entries written from the current thread with `segment`.
+pub fn set_segment(segment: Option<usize>) {
+    SEGMENT.with(|s| s.set(segment));
+}
+
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Error => "ERROR",
+        Level::Warn => "WARN",
+        Level::Info => "INFO",
+        Level::Debug => "DEBUG",
+        Level::Trace => "TRACE",
+    }
+}
+
+/// Append one entry per non-blank line of `message`.
+pub fn write(level: Level, message: &str) {
+    let mut guard = LOG.lock().unwrap();
+    let Some(log) = guard.as_mut() else {
+        return;
+    };
+    let segment = SEGMENT.with(|s| s.get());
+    for line in message.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let entry = Entry {
+            timestamp: UtcTime::now().rfc3339(),
+            level: level_name(level),
+            segment,
+            message: line,
+        };
+        if let Ok(json) = serde_json::to_string(&entry) {
+            log.append(&json);
+        }
+    }
+}
+
+impl LogFile {
+    fn append(&mut self, json: &str) {
+        let len = json.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_size {
+            if let Err(e) = self.rotate() {
+                eprintln!("Failed to rotate log file {}: {}", self.path.display(), e);
+            }
+        }
+        if writeln!(self.file, "{}", json).is_ok() {
+            self.size += len;
+        }
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated(self.keep));
+            for n in (1..self.keep).rev() {
+                let from = self.rotated(n);
+                if from.exists() {
+                    fs::rename(&from, self.rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+            self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+use crate::console::{debug, info, warning};
+use crate::{ffmpeg, probe, process, DeliveryError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Command;
+use std::time::Instant;
+
+/// Maximum true peak, in dBTP, the normalized audio is limited to (EBU R128).
+pub const TRUE_PEAK: f64 = -1.0;
+
+/// Loudness range, in LU, loudnorm aims for. Sources with a wider range are
+/// compressed instead of just gained.
+pub const LOUDNESS_RANGE: f64 = 11.0;
+
+/// Integrated loudness of EBU R128 broadcast deliveries, in LUFS.
+pub const EBU_R128: f64 = -23.0;
+
+/// Parse an integrated loudness target such as `-23LUFS`, `-14 LUFS` or `-23`.
+pub fn parse_target(text: &str) -> std::result::Result<f64, String> {
+    let trimmed = text.trim();
+    let number = trimmed
+        .get(..trimmed.len().saturating_sub(4))
+        .filter(|_| trimmed.to_ascii_uppercase().ends_with("LUFS"))
+        .unwrap_or(trimmed);
+    match number.trim().parse::<f64>() {
+        Ok(target) if (-70.0..=-5.0).contains(&target) => Ok(target),
+        _ => Err(format!("invalid loudness target '{}', expected -70 to -5 LUFS, e.g. -23LUFS", text)),
+    }
+}
+
+/// What the measurement pass of loudnorm found in one audio stream, as the
+/// strings it prints.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Measurement {
+    pub input_i: String,
+    pub input_tp: String,
+    pub input_lra: String,
+    pub input_thresh: String,
+    pub target_offset: String,
+}
+
+impl Measurement {
+    /// The measured levels as numbers; `-inf` for silence.
+    pub fn levels(&self) -> Levels {
+        let number = |text: &str| text.parse().unwrap_or(f64::NEG_INFINITY);
+        Levels {
+            integrated: number(&self.input_i),
+            true_peak: number(&self.input_tp),
+            range: number(&self.input_lra),
+        }
+    }
+
+    /// The second loudnorm pass bringing the stream to `target` LUFS with the
+    /// measured values, resampled back to 48 kHz (loudnorm works at 192 kHz).
+    /// `None` for a silent stream, which has no loudness to correct.
+    pub fn filter(&self, target: f64) -> Option<String> {
+        let integrated: f64 = self.input_i.parse().ok()?;
+        if !integrated.is_finite() {
+            return None;
+        }
+        Some(format!(
+            "loudnorm=I={}:TP={}:LRA={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}\
+            :linear=true,aresample=48000",
+            target,
+            TRUE_PEAK,
+            LOUDNESS_RANGE,
+            self.input_i,
+            self.input_tp,
+            self.input_lra,
+            self.input_thresh,
+            self.target_offset
+        ))
+    }
+}
+
+/// Loudness of one audio stream, as QC checks it.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Levels {
+    /// Integrated loudness in LUFS.
+    pub integrated: f64,
+    /// Maximum true peak in dBTP.
+    pub true_peak: f64,
+    /// Loudness range in LU.
+    pub range: f64,
+}
+
+/// The ffmpeg invocation measuring the loudness of the `stream`th audio
+/// stream of `source` against `target`, printing the result as JSON.
+pub fn measure_command(ffmpeg: &Path, source: &Path, stream: usize, target: f64) -> Command {
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-hide_banner", "-nostats", "-i"]).arg(ffmpeg::path_arg(source));
+    cmd.args(["-map", &format!("0:a:{}", stream), "-vn", "-af"]);
+    cmd.arg(format!("loudnorm=I={}:TP={}:LRA={}:print_format=json", target, TRUE_PEAK, LOUDNESS_RANGE));
+    cmd.args(["-f", "null", "-"]);
+    cmd
+}
+
+/// Pull the JSON block loudnorm prints at the end of its log out of `stderr`.
+pub fn parse_measurement(stderr: &str) -> Option<Measurement> {
+    let start = stderr.rfind('{')?;
+    let end = start + stderr[start..].find('}')?;
+    serde_json::from_str(&stderr[start..=end]).ok()
+}
+
+/// Measure each of the `streams` audio streams of `source` and return the
+/// filter normalizing it to `target` LUFS, empty for silent streams.
+pub fn measure(ffmpeg: &Path, source: &Path, streams: usize, target: f64) -> Result<Vec<String>> {
+    let _span = tracing::info_span!("loudness", streams).entered();
+    info!("\n🔊 Measuring the loudness of {} audio stream(s)...", streams);
+    let started = Instant::now();
+
+    let mut filters = Vec::with_capacity(streams);
+    for stream in 0..streams {
+        let measurement = run(ffmpeg, measure_command(ffmpeg, source, stream, target), stream)?;
+        info!(
+            "   Stream {}: {} LUFS, {} dBTP, {} LU",
+            stream + 1,
+            measurement.input_i,
+            measurement.input_tp,
+            measurement.input_lra
+        );
+        match measurement.filter(target) {
+            Some(filter) => filters.push(filter),
+            None => {
+                warning!("⚠️ Audio stream {} is silent; leaving it as it is", stream + 1);
+                filters.push(String::new());
+            }
+        }
+    }
+    info!("✅ Measured the loudness in {:.2} seconds", started.elapsed().as_secs_f32());
+    Ok(filters)
+}
+
+/// Measure the loudness of every audio stream of `file`, such as a finished
+/// delivery; empty if it has no audio.
+pub fn levels(ffmpeg: &Path, ffprobe: &Path, file: &Path) -> Result<Vec<Levels>> {
+    let _span = tracing::info_span!("loudness", file = %file.display()).entered();
+    let streams = probe::media_info(ffprobe, file)?.audio.len();
+    (0..streams)
+        .map(|stream| Ok(run(ffmpeg, measure_command(ffmpeg, file, stream, EBU_R128), stream)?.levels()))
+        .collect()
+}
+
+// Run the measurement `cmd` of the `stream`th audio stream and parse what
+// loudnorm found.
+fn run(ffmpeg: &Path, mut cmd: Command, stream: usize) -> Result<Measurement> {
+    process::contain(&mut cmd);
+    debug!("Command: {}", ffmpeg::display_command(&cmd));
+    let result = cmd.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffmpeg.to_path_buf()),
+        _ => DeliveryError::io("Failed to execute ffmpeg", e),
+    })?;
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    if !result.status.success() {
+        return Err(DeliveryError::AudioFailed(format!("stream {}: {}", stream + 1, stderr.trim())));
+    }
+    parse_measurement(&stderr)
+        .ok_or_else(|| DeliveryError::AudioFailed(format!("stream {}: loudnorm printed no measurement", stream + 1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = "\
+[Parsed_loudnorm_0 @ 0x6000] 
+{
+\t\"input_i\" : \"-27.61\",
+\t\"input_tp\" : \"-4.47\",
+\t\"input_lra\" : \"18.06\",
+\t\"input_thresh\" : \"-39.20\",
+\t\"output_i\" : \"-22.58\",
+\t\"output_tp\" : \"-1.50\",
+\t\"output_lra\" : \"14.78\",
+\t\"output_thresh\" : \"-34.71\",
+\t\"normalization_type\" : \"dynamic\",
+\t\"target_offset\" : \"0.58\"
+}
+[out#0/null @ 0x7000] video:0kB audio:1kB subtitle:0kB other streams:0kB global headers:0kB
+";
+
+    #[test]
+    fn parse_target_takes_lufs() {
+        assert_eq!(parse_target("-23LUFS"), Ok(-23.0));
+        assert_eq!(parse_target(" -14 lufs "), Ok(-14.0));
+        assert_eq!(parse_target("-24"), Ok(-24.0));
+        for invalid in ["-80", "-3LUFS", "loud", "LUFS"] {
+            assert!(parse_target(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn measurement_becomes_the_second_pass() {
+        let measurement = parse_measurement(LOG).unwrap();
+        assert_eq!(
+            measurement.filter(-23.0).unwrap(),
+            "loudnorm=I=-23:TP=-1:LRA=11:measured_I=-27.61:measured_TP=-4.47:measured_LRA=18.06\
+             :measured_thresh=-39.20:offset=0.58:linear=true,aresample=48000"
+        );
+    }
+
+    #[test]
+    fn silent_streams_are_left_alone() {
+        let log = LOG.replace("\"-27.61\"", "\"-inf\"").replace("\"-4.47\"", "\"-inf\"");
+        assert_eq!(parse_measurement(&log).unwrap().filter(-23.0), None);
+    }
+
+    #[test]
+    fn measurement_reports_the_input_levels() {
+        let measurement = parse_measurement(LOG).unwrap();
+        assert_eq!(measurement.levels(), Levels { integrated: -27.61, true_peak: -4.47, range: 18.06 });
+        let silent = parse_measurement(&LOG.replace("\"-27.61\"", "\"-inf\"")).unwrap();
+        assert_eq!(silent.levels().integrated, f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn logs_without_a_measurement_parse_to_nothing() {
+        assert!(parse_measurement("[aist#0:0/pcm_s24le @ 0x6000] Decoding error").is_none());
+        assert!(parse_measurement("{ \"input_i\" : \"-20\" }").is_none());
+    }
+}
@@ -1,124 +1,1047 @@
+mod cli;
 
+use clap::Parser;
+use cli::{CleanArgs, Cli, CombineArgs, Command, EncodeArgs, FetchArgs, OutputArgs, ProbeArgs, ProgressFormat, ResumeArgs, RunArgs, TempArgs, TempJobArgs, TempLocation, ToolArgs};
+use delivery_encoder::burnin::{FrameNumberBurnIn, TextBurnIn, TimecodeBurnIn};
+use delivery_encoder::color::{self, OutputColor, ToneOperator, ToneTarget, Tonemap};
+use delivery_encoder::config::JobConfig;
+use delivery_encoder::console::{Level, Verbosity};
+use delivery_encoder::events::{self, Event};
+use delivery_encoder::metrics::{self, QualityReport};
+use delivery_encoder::overlay::Position;
+use delivery_encoder::plan::JobPlan;
+use delivery_encoder::hook::{HookMode, WatermarkHook};
+use delivery_encoder::slate::Slate;
+use delivery_encoder::{batch, burnin, cleanup, console, fetch, ffmpeg, interrupt, logfile, loudness, overlay, probe, qc, schedule, screener, units, AlphaMode, DeliveryError, DeliveryPreset, EncodeJob, OutputFormat, Result, DEFAULT_FILTER, SEGMENTS_DIR};
 use std::env;
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
+// Console output goes through the library's console so verbosity, progress
+// bars and JSON events are respected.
+macro_rules! error {
+    ($($arg:tt)*) => { console::log(Level::Error, format!($($arg)*)) };
+}
+macro_rules! info {
+    ($($arg:tt)*) => { console::log(Level::Info, format!($($arg)*)) };
+}
+macro_rules! debug {
+    ($($arg:tt)*) => { console::log(Level::Debug, format!($($arg)*)) };
+}
+/// The final summary is printed even with --quiet.
+macro_rules! summary {
+    ($($arg:tt)*) => { console::line(format!($($arg)*)) };
+}
+
+// Resolve a user supplied path against the directory the tool was launched from,
+// since the working directory is moved to the project root below.
+fn resolve_arg(path: Option<PathBuf>, launch_dir: &Path, default: &str) -> PathBuf {
+    match path {
+        Some(p) if p.is_absolute() => p,
+        Some(p) => launch_dir.join(p),
+        None => PathBuf::from(default),
+    }
+}
+
+/// Process exit code for each kind of failure, so wrapping scripts can tell
+/// them apart.
+fn exit_code(error: &DeliveryError) -> i32 {
+    match error {
+        DeliveryError::Config(_) => 2,
+        DeliveryError::MissingInput { .. } => 3,
+        DeliveryError::FfmpegNotFound(_) => 4,
+        DeliveryError::ProbeFailed(_) => 5,
+        DeliveryError::SegmentFailed { .. }
+        | DeliveryError::SegmentTimedOut { .. }
+        | DeliveryError::SplitFailed(_)
+        | DeliveryError::JoinFailed(_)
+        | DeliveryError::PackageFailed(_)
+        | DeliveryError::CrfSearchFailed(_)
+        | DeliveryError::AudioFailed(_) => 6,
+        DeliveryError::Io { .. } => 7,
+        DeliveryError::FetchFailed(_) => 8,
+        DeliveryError::MissingCapability(_) => 9,
+        DeliveryError::InsufficientSpace { .. } => 10,
+        DeliveryError::FrameCountMismatch { .. } => 11,
+        DeliveryError::QcFailed(_) => 12,
+        DeliveryError::DamagedSource(_) => 13,
+        DeliveryError::BatchFailed { .. } => 14,
+        DeliveryError::Interrupted => 130,
+    }
+}
+
+// Like resolve_arg, but a bare executable name is left alone so it can be
+// looked up on PATH.
+fn resolve_tool_arg(path: Option<PathBuf>, launch_dir: &Path) -> Option<PathBuf> {
+    path.map(|p| {
+        if p.is_relative() && p.components().count() > 1 {
+            launch_dir.join(p)
+        } else {
+            p
+        }
+    })
+}
+
 fn main() {
+    let cli = Cli::parse();
+    setup_output(&cli.output);
+    if let Err(e) = interrupt::install() {
+        error!("⚠️ {}", e);
+    }
+    // Keep stdout for the JSON document; console output moves to stderr
+    if let Some(Command::Probe(ProbeArgs { json: true, .. })) = &cli.command {
+        events::enable();
+    }
+
     let start_time = Instant::now();
-    println!("🚀 Starting delivery encoder\n---------------------------");
+    info!("🚀 Starting delivery encoder\n---------------------------");
 
-    // Get executable path and derive project root
-    let exe_path = env::current_exe().unwrap_or_else(|e| {
-        println!("❌ Failed to get executable path: {}", e);
-        std::process::exit(1);
+    let result = match cli.command.unwrap_or(Command::Encode(Box::new(cli.encode))) {
+        Command::Encode(args) => run_encode(*args),
+        Command::Probe(args) => run_probe(args),
+        Command::Combine(args) => run_combine(args),
+        Command::Clean(args) => run_clean(args),
+        Command::Resume(args) => run_resume(*args),
+        Command::FetchFfmpeg(args) => run_fetch(args),
+    };
+    if let Err(e) = result {
+        error!("\n❌ {}", e);
+        events::emit(Event::Error { message: e.to_string(), exit_code: exit_code(&e) });
+        std::process::exit(exit_code(&e));
+    }
+
+    // Final statistics
+    let total_duration = start_time.elapsed();
+    summary!("\n🏁 Total execution time: {:.2} seconds\n✨ Process completed", 
+        total_duration.as_secs_f32()
+    );
+}
+
+fn setup_output(args: &OutputArgs) {
+    console::set_verbosity(match (args.quiet, args.verbose) {
+        (true, _) => Verbosity::Quiet,
+        (false, 0) => Verbosity::Normal,
+        (false, 1) => Verbosity::Verbose,
+        (false, _) => Verbosity::Trace,
     });
-    println!("✅ Executable path: {}", exe_path.display());
+    console::set_plain(args.plain || console::plain_by_environment());
+    if args.progress_format == ProgressFormat::Json {
+        events::enable();
+    }
+    if let Some(path) = &args.log_file {
+        if let Err(e) = logfile::open(path, args.log_max_size, args.log_keep) {
+            let e = DeliveryError::io(format!("Failed to open log file {}", path.display()), e);
+            error!("❌ {}", e);
+            std::process::exit(exit_code(&e));
+        }
+    }
+}
+
+// Move into the project root so the bundled assets/ paths resolve.
+fn enter_project_root() -> Result<()> {
+    // Get executable path and derive project root
+    let exe_path = env::current_exe()
+        .map_err(|e| DeliveryError::io("Failed to get executable path", e))?;
+    debug!("✅ Executable path: {}", exe_path.display());
 
     let project_root = exe_path
         .parent()  // bin/<os>
         .and_then(|p| p.parent())  // bin
         .and_then(|p| p.parent())  // project root
-        .unwrap_or_else(|| {
-            println!("❌ Failed to derive project root");
-            std::process::exit(1);
-        });
+        .ok_or_else(|| DeliveryError::Config("Failed to derive project root".to_string()))?;
 
-    println!("📂 Project root: {}", project_root.display());
+    debug!("📂 Project root: {}", project_root.display());
 
     // Set working directory
-    if let Err(e) = env::set_current_dir(project_root) {
-        println!("❌ Failed to set working directory: {}", e);
-        std::process::exit(1);
+    env::set_current_dir(project_root)
+        .map_err(|e| DeliveryError::io("Failed to set working directory", e))?;
+    debug!("📂 Working directory set to project root");
+    Ok(())
+}
+
+fn current_dir() -> Result<PathBuf> {
+    env::current_dir().map_err(|e| DeliveryError::io("Failed to get current directory", e))
+}
+
+// Locate ffmpeg and ffprobe; call after enter_project_root so the bundled
+// copies are found.
+fn locate_tools(ffmpeg_override: Option<PathBuf>, ffprobe_override: Option<PathBuf>) -> Result<(PathBuf, PathBuf)> {
+    debug!("✅ Platform: {}", env::consts::OS);
+    let ffmpeg_path = ffmpeg::locate("ffmpeg", ffmpeg_override.as_deref())?;
+    let ffprobe_path = ffmpeg::locate("ffprobe", ffprobe_override.as_deref())?;
+    info!("🔍 FFmpeg path: {}", ffmpeg_path.display());
+    debug!("🔍 FFprobe path: {}", ffprobe_path.display());
+    Ok((ffmpeg_path, ffprobe_path))
+}
+
+fn apply_run_args(encode_job: &mut EncodeJob, args: &RunArgs) {
+    if let Some(n) = args.threads {
+        encode_job.threads = n as usize;
+    }
+    if let Some(n) = args.ffmpeg_threads {
+        encode_job.ffmpeg_threads = Some(n as usize);
+    }
+    encode_job.pin_cpus = args.pin_cpus;
+    encode_job.background = args.background;
+    if let Some(accel) = args.hwaccel {
+        encode_job.hwaccel = accel;
     }
-    println!("📂 Working directory set to project root");
+    if !args.gpus.is_empty() {
+        encode_job.gpus = args.gpus.clone();
+    }
+    encode_job.memory_per_worker = args.max_mem_per_worker;
+    if let Some(max) = args.max_open_files {
+        encode_job.max_open_files = Some(max);
+    }
+    encode_job.stall_timeout = args.stall_timeout;
+    encode_job.segment_timeout = args.segment_timeout;
+    encode_job.retries = args.retries;
+    encode_job.keep_on_interrupt = args.keep_on_interrupt;
+    encode_job.keep_temp = args.keep_temp;
+    encode_job.space_check = !args.no_space_check;
+    encode_job.allow_frame_mismatch = args.allow_frame_mismatch;
+    encode_job.check_boundaries = args.check_boundaries;
+    encode_job.verify_source = args.verify_source;
+    encode_job.qc.silence = args.qc_silence.map(|duration| qc::SilenceCheck {
+        threshold: args.qc_silence_threshold.unwrap_or(qc::SILENCE_THRESHOLD),
+        duration: duration.as_secs_f64(),
+    });
+    encode_job.qc.black = args.qc_black.map(|duration| qc::BlackCheck {
+        duration: duration.as_secs_f64(),
+        head: args.qc_black_head.map_or(0.0, |d| d.as_secs_f64()),
+        tail: args.qc_black_tail.map_or(0.0, |d| d.as_secs_f64()),
+    });
+    encode_job.qc.freeze = args.qc_freeze.map(|duration| qc::FreezeCheck { duration: duration.as_secs_f64() });
+    encode_job.qc.fail = args.qc_fail;
+    if !args.quality_metrics.is_empty() {
+        encode_job.quality_metrics = args.quality_metrics.clone();
+    }
+}
 
-    // Determine FFmpeg path
-    let ffmpeg_path = match () {
-        _ if cfg!(target_os = "macos") => "assets/bin/macos/ffmpeg",
-        _ if cfg!(target_os = "windows") => "assets/bin/windows/ffmpeg.exe",
-        _ => {
-            println!("❌ Unsupported operating system");
-            std::process::exit(1);
+// Segments directory of the job `job` (see `temp_job`). Called from the
+// project root.
+fn segments_dir(
+    temp_dir: Option<PathBuf>,
+    temp: Option<TempLocation>,
+    launch_dir: &Path,
+    job: &str,
+) -> Result<PathBuf> {
+    let shared = match (temp, temp_dir) {
+        (Some(TempLocation::System), _) => env::temp_dir(),
+        (Some(TempLocation::InMemory), _) => cleanup::ram_disk()?,
+        (Some(TempLocation::Project), _) | (None, None) => return Ok(PathBuf::from(SEGMENTS_DIR)),
+        (None, Some(dir)) => launch_dir.join(dir),
+    };
+    Ok(shared.join(cleanup::shared_segments_dir_name(job)))
+}
+
+// What tells the segments of jobs sharing a temp directory apart: the job id,
+// or the absolute path of the input. Called from the project root.
+fn temp_job(job_id: Option<&str>, input: &Path) -> Result<String> {
+    match job_id {
+        Some(id) => Ok(id.to_string()),
+        None => std::path::absolute(input)
+            .map(|path| path.to_string_lossy().into_owned())
+            .map_err(|e| DeliveryError::io(format!("Failed to resolve {}", input.display()), e)),
+    }
+}
+
+fn conversion_summary(frames: usize, encode_job: &EncodeJob) -> Result<()> {
+    summary!("\n✅ Conversion successful!");
+    match encode_job.output_format {
+        OutputFormat::Frames => {
+            summary!("📸 {} {} frames saved to: {}", frames, encode_job.frame_format, encode_job.output_dir.display())
+        }
+        OutputFormat::Video => {
+            for (_, name) in encode_job.video_chunks(&encode_job.video_name()) {
+                summary!("🎬 {} frames of {} saved to: {}", frames, encode_job.codec, encode_job.output_dir.join(name).display());
+            }
+        }
+    }
+    if encode_job.output_format == OutputFormat::Video {
+        for (_, name) in encode_job.video_chunks(&encode_job.video_name()) {
+            let video = encode_job.output_dir.join(name);
+            audio_summary(&video, encode_job);
+            if !encode_job.quality_metrics.is_empty() {
+                quality_summary(&video);
+            }
+        }
+    }
+    for package in &encode_job.package {
+        summary!("📦 Package: {}", encode_job.output_dir.join(package.dir_name()).join(package.manifest()).display());
+    }
+    if !encode_job.qc.any() {
+        return Ok(());
+    }
+    let files = match encode_job.output_format {
+        OutputFormat::Frames => vec![encode_job.input.clone()],
+        OutputFormat::Video => {
+            let chunks = encode_job.video_chunks(&encode_job.video_name());
+            chunks.into_iter().map(|(_, name)| encode_job.output_dir.join(name)).collect()
         }
     };
+    let mut problems = Vec::new();
+    for file in files {
+        match qc_summary(&file, encode_job) {
+            Ok(0) => {}
+            Ok(found) => problems.push(format!("{} problem(s) in {}", found, file.display())),
+            Err(e) if encode_job.qc.fail => return Err(e),
+            Err(e) => console::log(Level::Warn, format!("⚠️ Couldn't check {}: {}", file.display(), e)),
+        }
+    }
+    if encode_job.qc.fail && !problems.is_empty() {
+        return Err(DeliveryError::QcFailed(problems.join("\n")));
+    }
+    Ok(())
+}
 
-    println!("🔍 FFmpeg path: {}\n✅ Platform: {}", 
-        ffmpeg_path,
-        if cfg!(windows) { "Windows" } else { "macOS" }
-    );
+// Report the loudness of each audio stream of the delivered `video` for QC.
+fn audio_summary(video: &Path, encode_job: &EncodeJob) {
+    match loudness::levels(&encode_job.ffmpeg, &encode_job.ffprobe, video) {
+        Ok(levels) => {
+            for (stream, levels) in levels.iter().enumerate() {
+                summary!(
+                    "🔊 Audio {}: {:.1} LUFS integrated, {:.1} dBTP true peak, {:.1} LU range",
+                    stream + 1,
+                    levels.integrated,
+                    levels.true_peak,
+                    levels.range
+                );
+                events::emit(Event::AudioLevels {
+                    file: video.display().to_string(),
+                    stream: stream + 1,
+                    integrated: levels.integrated,
+                    true_peak: levels.true_peak,
+                    range: levels.range,
+                });
+            }
+        }
+        Err(e) => {
+            console::log(Level::Warn, format!("⚠️ Couldn't measure the loudness of {}: {}", video.display(), e))
+        }
+    }
+}
 
-    // Define and validate paths
-    let assets = [
-        ("Video", "assets/video.mov"),
-        ("Overlay", "assets/overlay.png"),
-        ("FFmpeg", ffmpeg_path),
-    ];
-
-    println!("\n🔍 Validating input files:");
-    for (name, path) in &assets {
-        let exists = Path::new(path).exists();
-        println!("- {}: {} -> {}", name, path, exists);
-        if !exists {
-            println!("❌ {} not found: {}", name, path);
-            std::process::exit(1);
-        }
-    }
-
-    // Create output directory
-    let output_dir = "output";
-    println!("\n📂 Creating output directory: {}", output_dir);
-    if !Path::new(output_dir).exists() {
-        if let Err(e) = std::fs::create_dir(output_dir) {
-            println!("❌ Failed to create output directory: {}", e);
-            std::process::exit(1);
-        }
-        println!("✅ Created output directory");
-    } else {
-        println!("ℹ️ Output directory already exists");
-    }
-
-    // Prepare FFmpeg command
-    let output_pattern = format!("{}/video%05d.png", output_dir);
-    let args = [
-        "-i", "assets/video.mov",
-        "-i", "assets/overlay.png",
-        "-filter_complex", "[0:v][1:v]overlay",
-        "-y", &output_pattern
-    ];
-
-    println!("\n⚙️ FFmpeg command:\n{} {}", 
-        ffmpeg_path,
-        args.join(" ")
-    );
+// Report the overall scores saved for the delivered `video`, if scoring it
+// worked.
+fn quality_summary(video: &Path) {
+    let path = metrics::report_path(video);
+    let report = std::fs::read(&path).ok().and_then(|json| serde_json::from_slice::<QualityReport>(&json).ok());
+    if let Some(report) = report {
+        summary!("📈 Quality: {} ({})", report.overall, path.display());
+    }
+}
 
-    println!("\n⏳ Starting video processing...");
-    let ffmpeg_start = Instant::now();
-
-    // Execute FFmpeg command
-    let status = Command::new(ffmpeg_path)
-        .args(&args)
-        .status();
-
-    // Handle execution result
-    match status {
-        Ok(exit_status) if exit_status.success() => {
-            let duration = ffmpeg_start.elapsed();
-            println!("\n✅ Conversion successful!");
-            println!("⏱️ FFmpeg processing time: {:.2} seconds", duration.as_secs_f32());
-            println!("📸 PNG frames saved to: {}", output_pattern);
-        },
-        Ok(exit_status) => {
-            println!("\n❌ FFmpeg failed with exit code: {:?}", exit_status.code());
+// Report what the QC checks find in `file`, the delivered video or the
+// source of delivered frames. Returns the number of findings.
+fn qc_summary(file: &Path, encode_job: &EncodeJob) -> Result<usize> {
+    let report = qc::inspect(&encode_job.ffmpeg, &encode_job.ffprobe, file, &encode_job.qc)?;
+    if report.findings.is_empty() {
+        summary!("🔍 QC: nothing found in {}", file.display());
+    }
+    for finding in &report.findings {
+        summary!("⚠️ QC: {} in {}", finding.describe(report.frame_rate), file.display());
+        events::emit(Event::QcFinding {
+            file: file.display().to_string(),
+            check: finding.check,
+            start: finding.start,
+            end: finding.end,
+        });
+    }
+    Ok(report.findings.len())
+}
+
+fn run_encode(args: EncodeArgs) -> Result<()> {
+    if let Some(ids) = args.only_segments {
+        let job = TempJobArgs { job_id: args.job_id, input: args.input };
+        let mut encode_job = previous_job(args.output_dir, args.temp, job, Some(args.tools))?;
+        encode_job.only_segments = Some(ids);
+        apply_run_args(&mut encode_job, &args.run);
+        let frames = encode_job.run()?;
+        return conversion_summary(frames, &encode_job);
+    }
+
+    let launch_dir = current_dir()?;
+    if let Some(path) = &args.jobs {
+        let path = launch_dir.join(path);
+        return run_batch(&path, args, &launch_dir);
+    }
+
+    let job = match &args.config {
+        Some(path) => {
+            let job = JobConfig::load(path)?;
+            info!("📄 Loaded job config: {}", path.display());
+            job
+        }
+        None => JobConfig::default(),
+    };
+    execute_encode(build_encode(args, job, &launch_dir)?).map(|_| ())
+}
+
+// An encode job as the command line and job config describe it, with what
+// else `execute_encode` needs to run it.
+struct Encode {
+    job: EncodeJob,
+    screeners: Option<PathBuf>,
+    watermark: screener::Watermark,
+    screener_jobs: usize,
+    dry_run: bool,
+}
+
+// The position, margin, font and size every kind of burn-in has.
+trait BurnIn {
+    fn placement(&mut self) -> (&mut Position, &mut u32, &mut Option<String>, &mut u32);
+}
+
+impl BurnIn for TextBurnIn {
+    fn placement(&mut self) -> (&mut Position, &mut u32, &mut Option<String>, &mut u32) {
+        (&mut self.position, &mut self.margin, &mut self.font, &mut self.size)
+    }
+}
+
+impl BurnIn for TimecodeBurnIn {
+    fn placement(&mut self) -> (&mut Position, &mut u32, &mut Option<String>, &mut u32) {
+        (&mut self.position, &mut self.margin, &mut self.font, &mut self.size)
+    }
+}
+
+impl BurnIn for FrameNumberBurnIn {
+    fn placement(&mut self) -> (&mut Position, &mut u32, &mut Option<String>, &mut u32) {
+        (&mut self.position, &mut self.margin, &mut self.font, &mut self.size)
+    }
+}
+
+// The margin, font and size the burn-ins share, from the flags or else the
+// job config.
+struct BurnInStyle {
+    margin: Option<u32>,
+    font: Option<String>,
+    size: Option<u32>,
+}
+
+impl BurnInStyle {
+    // Place `burn_in` at `position` (its flag) or else `key` (its config
+    // key) and set it in the shared style, keeping the defaults of what
+    // neither gives.
+    fn apply(&self, burn_in: &mut impl BurnIn, position: Option<Position>, key: Option<&str>) -> Result<()> {
+        let (at, margin, font, size) = burn_in.placement();
+        if let Some(position) = position {
+            *at = position;
+        } else if let Some(key) = key {
+            *at = key.parse().map_err(DeliveryError::Config)?;
+        }
+        if let Some(value) = self.margin {
+            *margin = value;
+        }
+        font.clone_from(&self.font);
+        if let Some(value) = self.size {
+            *size = value;
+        }
+        Ok(())
+    }
+}
+
+// Build the encode job of `job`, a job config, with the flags of `args` on
+// top, resolving relative paths against `launch_dir`. Moves into the
+// project root.
+fn build_encode(args: EncodeArgs, job: JobConfig, launch_dir: &Path) -> Result<Encode> {
+    let video_path = resolve_arg(args.input.or(job.input), launch_dir, "assets/video.mov");
+    let overlay_path = resolve_arg(args.overlay.or(job.overlay), launch_dir, "assets/overlay.png");
+    // A plan keeps its own output directory unless -o is given
+    let output_given = args.output_dir.is_some();
+    let output_dir = resolve_arg(args.output_dir.or(job.output_dir), launch_dir, "output");
+    let ffmpeg_override = resolve_tool_arg(args.tools.ffmpeg_path.or(job.ffmpeg_path), launch_dir);
+    let ffprobe_override = resolve_tool_arg(args.tools.ffprobe_path.or(job.ffprobe_path), launch_dir);
+    let threads = job.threads;
+    let segments = args.segments.map(|n| n as usize).or(job.segments);
+    let plan_in = args.plan_in.map(|p| launch_dir.join(p));
+    let plan_out = args.plan_out.map(|p| launch_dir.join(p));
+    let filter = args.filter.or(job.filter).unwrap_or_else(|| DEFAULT_FILTER.to_string());
+    let lut = args.lut.map(|p| launch_dir.join(p)).or(job.lut);
+
+    enter_project_root()?;
+    let temp_job = temp_job(args.job_id.as_deref().or(job.job_id.as_deref()), &video_path)?;
+    let segments_dir = segments_dir(args.temp.temp_dir.or(job.temp_dir), args.temp.temp, launch_dir, &temp_job)?;
+    let (ffmpeg_path, ffprobe_path) = locate_tools(ffmpeg_override, ffprobe_override)?;
+
+    let mut encode_job = EncodeJob::new(video_path, overlay_path, output_dir);
+    encode_job.ffmpeg = ffmpeg_path;
+    encode_job.ffprobe = ffprobe_path;
+    encode_job.filter = filter;
+    encode_job.lut = lut;
+    let tone_target = match (args.tonemap, &job.tonemap) {
+        (Some(target), _) => Some(target),
+        (None, Some(target)) => Some(target.parse().map_err(DeliveryError::Config)?),
+        (None, None) => None,
+    };
+    if let Some(ToneTarget::Sdr) = tone_target {
+        let operator = match (args.tonemap_operator, &job.tonemap_operator) {
+            (Some(operator), _) => operator,
+            (None, Some(operator)) => operator.parse().map_err(DeliveryError::Config)?,
+            (None, None) => ToneOperator::default(),
+        };
+        encode_job.tonemap = Some(Tonemap::new(operator));
+    }
+    let tag = |arg: Option<String>, job: &Option<String>, parse: fn(&str) -> std::result::Result<String, String>| {
+        match (arg, job) {
+            (Some(tag), _) => Ok(Some(tag)),
+            (None, Some(tag)) => parse(tag).map(Some).map_err(DeliveryError::Config),
+            (None, None) => Ok(None),
+        }
+    };
+    encode_job.color = OutputColor {
+        primaries: tag(args.color_primaries, &job.color_primaries, color::parse_primaries)?,
+        transfer: tag(args.color_trc, &job.color_trc, color::parse_transfer)?,
+        space: tag(args.colorspace, &job.colorspace, color::parse_matrix)?,
+        range: match (args.color_range, &job.color_range) {
+            (Some(range), _) => Some(range),
+            (None, Some(range)) => Some(range.parse().map_err(DeliveryError::Config)?),
+            (None, None) => None,
         },
-        Err(e) => {
-            println!("\n❌ Failed to execute FFmpeg command: {}", e);
+    };
+    encode_job.autocrop = args.autocrop || job.autocrop.unwrap_or(false);
+    if let Some(position) = args.overlay_position {
+        encode_job.overlay_settings.position = position;
+    } else if let Some(position) = &job.overlay_position {
+        encode_job.overlay_settings.position = position.parse().map_err(DeliveryError::Config)?;
+    }
+    encode_job.overlay_settings.margin = args.overlay_margin.or(job.overlay_margin).unwrap_or(0);
+    encode_job.overlay_settings.x = args.overlay_x.or(job.overlay_x);
+    encode_job.overlay_settings.y = args.overlay_y.or(job.overlay_y);
+    if let Some(scale) = args.overlay_scale {
+        encode_job.overlay_settings.scale = Some(scale);
+    } else if let Some(scale) = &job.overlay_scale {
+        encode_job.overlay_settings.scale = Some(scale.parse().map_err(DeliveryError::Config)?);
+    }
+    if let Some(opacity) = args.overlay_opacity {
+        encode_job.overlay_settings.opacity = Some(opacity);
+    } else if let Some(opacity) = job.overlay_opacity {
+        let opacity = overlay::parse_opacity(&opacity.to_string()).map_err(DeliveryError::Config)?;
+        encode_job.overlay_settings.opacity = Some(opacity);
+    }
+    if let Some(key) = args.overlay_key {
+        encode_job.overlay_settings.key = Some(key);
+    } else if let Some(key) = &job.overlay_key {
+        encode_job.overlay_settings.key = Some(key.parse().map_err(DeliveryError::Config)?);
+    }
+    if let Some(window) = args.overlay_window {
+        encode_job.overlay_settings.window = Some(window);
+    } else if let Some(window) = &job.overlay_window {
+        encode_job.overlay_settings.window = Some(window.parse().map_err(DeliveryError::Config)?);
+    }
+    encode_job.overlay_playback.looped = args.overlay_loop || job.overlay_loop.unwrap_or(false);
+    if let Some(layers) = &job.overlays {
+        encode_job.overlay_layers = layers.iter().map(|l| l.to_layer()).collect::<Result<_>>()?;
+    }
+    if let Some(path) = args.overlay_schedule.map(|p| launch_dir.join(p)).or(job.overlay_schedule) {
+        let looped = encode_job.overlay_playback.looped;
+        encode_job.overlay_layers.extend(schedule::load(&path, &encode_job.overlay_settings, looped)?);
+    }
+    // A font file, unlike a family name, is resolved like any other path
+    let font = match args.burn_in_font.or(job.burn_in_font.clone()) {
+        Some(font) if burnin::is_font_file(&font) => Some(launch_dir.join(font).to_string_lossy().into_owned()),
+        font => font,
+    };
+    let style = BurnInStyle {
+        margin: args.burn_in_margin.or(job.burn_in_margin),
+        font,
+        size: args.burn_in_size.or(job.burn_in_size),
+    };
+    if args.burn_timecode || job.burn_timecode.unwrap_or(false) {
+        let mut burn_in = TimecodeBurnIn::default();
+        style.apply(&mut burn_in, args.burn_timecode_position, job.burn_timecode_position.as_deref())?;
+        encode_job.timecode_burn_in = Some(burn_in);
+    }
+    if args.burn_frame_number || job.burn_frame_number.unwrap_or(false) {
+        let mut burn_in = FrameNumberBurnIn::default();
+        style.apply(&mut burn_in, args.burn_frame_number_position, job.burn_frame_number_position.as_deref())?;
+        encode_job.frame_number_burn_in = Some(burn_in);
+    }
+    if !args.guides.is_empty() {
+        encode_job.guides = args.guides.clone();
+    } else if let Some(guides) = &job.guides {
+        encode_job.guides =
+            guides.iter().map(|g| g.parse()).collect::<std::result::Result<_, _>>().map_err(DeliveryError::Config)?;
+    }
+    if let Some(text) = args.burn_in.or(job.burn_in) {
+        let mut burn_in = TextBurnIn::new(text);
+        style.apply(&mut burn_in, args.burn_in_position, job.burn_in_position.as_deref())?;
+        if let Some(color) = args.burn_in_color.or(job.burn_in_color) {
+            burn_in.color = color;
+        }
+        burn_in.box_color = args.burn_in_box.or(job.burn_in_box);
+        encode_job.burn_in = Some(burn_in);
+    }
+    encode_job.recipient = args.recipient.or(job.recipient);
+    if args.slate || job.slate.unwrap_or(false) {
+        let mut slate = Slate {
+            title: args.slate_title.or(job.slate_title),
+            episode: args.slate_episode.or(job.slate_episode),
+            version: args.slate_version.or(job.slate_version),
+            date: args.slate_date.or(job.slate_date),
+            two_pop: args.two_pop || job.two_pop.unwrap_or(false),
+            ..Slate::default()
+        };
+        if let Some(duration) = args.slate_duration {
+            slate.duration = duration.as_secs_f64();
+        } else if let Some(duration) = &job.slate_duration {
+            slate.duration = units::parse_duration(duration).map_err(DeliveryError::Config)?.as_secs_f64();
+        }
+        encode_job.slate = Some(slate);
+    }
+    // The config's list is already absolute, next to the config
+    let screeners = args.screeners.or(job.screeners).map(|p| launch_dir.join(p));
+    let mut watermark = screener::Watermark::default();
+    if let Some(position) = args.screener_position {
+        watermark.position = position;
+    } else if let Some(position) = &job.screener_position {
+        watermark.position = position.parse().map_err(DeliveryError::Config)?;
+    }
+    if let Some(opacity) = args.screener_opacity {
+        watermark.opacity = opacity;
+    } else if let Some(opacity) = job.screener_opacity {
+        watermark.opacity = overlay::parse_opacity(&opacity.to_string()).map_err(DeliveryError::Config)?;
+    }
+    if let Some(size) = args.screener_size.or(job.screener_size) {
+        watermark.size = size;
+    }
+    watermark.font = style.font;
+    if let Some(command) = args.watermark_hook.or(job.watermark_hook) {
+        let mode = match (args.watermark_hook_mode, &job.watermark_hook_mode) {
+            (Some(mode), _) => mode,
+            (None, Some(mode)) => mode.parse().map_err(DeliveryError::Config)?,
+            (None, None) => HookMode::default(),
+        };
+        encode_job.watermark_hook = Some(WatermarkHook::new(command, mode));
+    }
+    let screener_jobs =
+        args.screener_jobs.map(|n| n as usize).or(job.screener_jobs).unwrap_or(screener::DEFAULT_AT_ONCE).max(1);
+    encode_job.segments_dir = segments_dir;
+    if let Some(n) = threads {
+        encode_job.threads = n;
+    }
+    if let Some(accel) = &job.hwaccel {
+        encode_job.hwaccel = accel.parse().map_err(DeliveryError::Config)?;
+    }
+    if let Some(gpus) = job.gpus {
+        encode_job.gpus = gpus;
+    }
+    if let Some(max) = &job.max_open_files {
+        encode_job.max_open_files = Some(max.parse().map_err(DeliveryError::Config)?);
+    }
+    encode_job.segments = segments;
+    encode_job.adaptive_segments = args.adaptive_segments || job.adaptive_segments.unwrap_or(false);
+    encode_job.presplit = args.presplit || job.presplit.unwrap_or(false);
+    if let Some(mode) = args.split_on {
+        encode_job.split_on = mode;
+    } else if let Some(mode) = &job.split_on {
+        encode_job.split_on = mode.parse().map_err(DeliveryError::Config)?;
+    }
+    if let Some(mode) = args.vfr_mode {
+        encode_job.vfr_mode = mode;
+    } else if let Some(mode) = &job.vfr_mode {
+        encode_job.vfr_mode = mode.parse().map_err(DeliveryError::Config)?;
+    }
+    if let Some(format) = args.frame_format {
+        encode_job.frame_format = format;
+    } else if let Some(format) = &job.frame_format {
+        encode_job.frame_format = format.parse().map_err(DeliveryError::Config)?;
+    }
+    if let Some(preset) = args.delivery_preset {
+        preset.apply(&mut encode_job);
+    } else if let Some(preset) = &job.delivery_preset {
+        preset.parse::<DeliveryPreset>().map_err(DeliveryError::Config)?.apply(&mut encode_job);
+    }
+    if let Some(format) = args.output_format {
+        encode_job.output_format = format;
+    } else if let Some(format) = &job.output_format {
+        encode_job.output_format = format.parse().map_err(DeliveryError::Config)?;
+    }
+    if let Some(codec) = args.codec {
+        encode_job.codec = codec;
+    } else if let Some(codec) = &job.codec {
+        encode_job.codec = codec.parse().map_err(DeliveryError::Config)?;
+    }
+    if let Some(container) = args.container {
+        encode_job.container = Some(container);
+    } else if let Some(container) = &job.container {
+        encode_job.container = Some(container.parse().map_err(DeliveryError::Config)?);
+    }
+    if let Some(timecode) = args.timecode {
+        encode_job.timecode = Some(timecode);
+    } else if let Some(timecode) = &job.timecode {
+        encode_job.timecode = Some(timecode.parse().map_err(DeliveryError::Config)?);
+    }
+    if let Some(audio) = args.audio {
+        encode_job.audio = Some(audio);
+    } else if let Some(audio) = &job.audio {
+        encode_job.audio = Some(audio.parse().map_err(DeliveryError::Config)?);
+    }
+    if let Some(layout) = args.audio_layout {
+        encode_job.audio_tracks.layout = layout;
+    } else if let Some(layout) = &job.audio_layout {
+        encode_job.audio_tracks.layout = layout.parse().map_err(DeliveryError::Config)?;
+    }
+    if let Some(channels) = args.audio_channels.or(job.audio_channels) {
+        encode_job.audio_tracks.channels = channels;
+    }
+    if let Some(tracks) = args.audio_tracks {
+        encode_job.audio_tracks.tracks = tracks;
+    } else if let Some(tracks) = &job.audio_tracks {
+        encode_job.audio_tracks.tracks =
+            tracks.iter().map(|t| t.parse()).collect::<std::result::Result<_, _>>().map_err(DeliveryError::Config)?;
+    }
+    if let Some(labels) = args.audio_labels.or(job.audio_labels) {
+        encode_job.audio_tracks.labels = labels;
+    }
+    if let Some(target) = args.loudness_target {
+        encode_job.loudness_target = Some(target);
+    } else if let Some(target) = &job.loudness_target {
+        encode_job.loudness_target = Some(loudness::parse_target(target).map_err(DeliveryError::Config)?);
+    }
+    if let Some(codec) = args.intermediate {
+        encode_job.intermediate = Some(codec);
+    } else if let Some(codec) = &job.intermediate {
+        encode_job.intermediate = Some(codec.parse().map_err(DeliveryError::Config)?);
+    }
+    if let Some(packages) = args.package {
+        encode_job.package = packages;
+    } else if let Some(packages) = &job.package {
+        encode_job.package =
+            packages.iter().map(|p| p.parse()).collect::<std::result::Result<_, _>>().map_err(DeliveryError::Config)?;
+    }
+    encode_job.package_segment = args.package_segment.or(job.package_segment);
+    if let Some(format) = args.extract_audio {
+        encode_job.extract_audio = Some(format);
+    } else if let Some(format) = &job.extract_audio {
+        encode_job.extract_audio = Some(format.parse().map_err(DeliveryError::Config)?);
+    }
+    if let Some(format) = args.shot_list {
+        encode_job.shot_list = Some(format);
+    } else if let Some(format) = &job.shot_list {
+        encode_job.shot_list = Some(format.parse().map_err(DeliveryError::Config)?);
+    }
+    let config_rate =
+        |rate: &Option<String>| rate.as_deref().map(units::parse_bitrate).transpose().map_err(DeliveryError::Config);
+    encode_job.video_settings.crf = args.crf.or(job.crf);
+    encode_job.target_vmaf = args.target_vmaf.or(job.target_vmaf);
+    encode_job.video_settings.bitrate = args.bitrate.or(config_rate(&job.bitrate)?);
+    encode_job.video_settings.maxrate = args.maxrate.or(config_rate(&job.maxrate)?);
+    encode_job.video_settings.bufsize = args.bufsize.or(config_rate(&job.bufsize)?);
+    encode_job.video_settings.preset = args.preset.or(job.preset);
+    if let Some(profile) = args.profile.or(job.profile) {
+        encode_job.video_settings.profile = Some(profile);
+    }
+    encode_job.video_settings.level = args.level.or(job.level);
+    if let Some(mode) = args.two_pass {
+        encode_job.two_pass = Some(mode);
+    } else if let Some(mode) = &job.two_pass {
+        encode_job.two_pass = Some(mode.parse().map_err(DeliveryError::Config)?);
+    }
+    if let Some(renditions) = args.rendition {
+        encode_job.renditions = renditions;
+    } else if let Some(renditions) = &job.renditions {
+        encode_job.renditions = renditions.iter().map(|r| r.to_rendition()).collect::<Result<_>>()?;
+    }
+    if let Some(depth) = args.bit_depth.or(job.bit_depth) {
+        encode_job.bit_depth = Some(depth);
+    }
+    encode_job.quality.png_compression = args.png_compression.or(job.png_compression);
+    encode_job.quality.jpeg_quality = args.jpeg_quality.or(job.jpeg_quality);
+    let icc_profile = match args.icc_profile {
+        Some(profile) => Some(profile),
+        None => job.icc_profile.as_deref().map(str::parse).transpose().map_err(DeliveryError::Config)?,
+    };
+    encode_job.icc_profile = icc_profile.map(|profile| profile.relative_to(launch_dir));
+    if let Some(color) = args.flatten_on.or(job.flatten_on) {
+        encode_job.alpha = AlphaMode::FlattenOn(color);
+    } else if args.preserve_alpha || job.preserve_alpha.unwrap_or(false) {
+        encode_job.alpha = AlphaMode::Preserve;
+    }
+    if let Some(template) = args.name_template.or(job.name_template) {
+        encode_job.name_template = template;
+    }
+    if let Some(n) = args.start_frame.or(job.start_frame) {
+        encode_job.start_frame = n;
+    }
+    if let Some(n) = args.frame_padding.map(|n| n as usize).or(job.frame_padding) {
+        encode_job.frame_padding = n;
+    }
+    encode_job.job_id = args.job_id.or(job.job_id);
+    encode_job.timestamped_output = args.timestamped || job.timestamped_output.unwrap_or(false);
+    if let Some(policy) = args.on_existing {
+        encode_job.on_existing = policy;
+    } else if let Some(policy) = &job.on_existing {
+        encode_job.on_existing = policy.parse().map_err(DeliveryError::Config)?;
+    }
+    encode_job.plan_out = plan_out;
+    apply_run_args(&mut encode_job, &args.run);
+    if let Some(path) = plan_in {
+        let output_dir = encode_job.output_dir.clone();
+        JobPlan::load(&path)?.apply(&mut encode_job);
+        info!("📄 Loaded plan: {}", path.display());
+        if output_given {
+            encode_job.output_dir = output_dir;
         }
     }
+    Ok(Encode { job: encode_job, screeners, watermark, screener_jobs, dry_run: args.dry_run })
+}
 
-    // Final statistics
-    let total_duration = start_time.elapsed();
-    println!("\n🏁 Total execution time: {:.2} seconds\n✨ Process completed", 
-        total_duration.as_secs_f32()
-    );
-}
\ No newline at end of file
+// Run `encode` (or dry-run it) and summarize what it delivered. Returns the
+// number of frames written, or `None` if nothing was encoded.
+fn execute_encode(encode: Encode) -> Result<Option<usize>> {
+    let Encode { job: mut encode_job, screeners, watermark, screener_jobs, dry_run } = encode;
+    if let Some(list) = screeners {
+        let recipients = screener::load(&list)?;
+        let jobs = screener::jobs(&encode_job, &recipients, &watermark, screener_jobs);
+        if dry_run {
+            for job in &jobs {
+                console::line(format!("\n🎟 Screener for {}", job.recipient.as_deref().unwrap_or_default()));
+                job.dry_run()?;
+            }
+            summary!("\n📝 Dry run complete, nothing was written");
+            return Ok(None);
+        }
+        let mut total = 0;
+        for (job, frames) in screener::run_all(jobs, screener_jobs)? {
+            conversion_summary(frames, &job)?;
+            total += frames;
+        }
+        return Ok(Some(total));
+    }
+    if dry_run {
+        encode_job.dry_run()?;
+        summary!("\n📝 Dry run complete, nothing was written");
+        return Ok(None);
+    }
+    if !encode_job.prepare_output()? {
+        summary!("\n⏭ Output already exists, nothing was encoded");
+        return Ok(None);
+    }
+    let frames = encode_job.run()?;
+    conversion_summary(frames, &encode_job)?;
+    Ok(Some(frames))
+}
+
+// Run every job of the jobs file at `path`, each built like a --config job
+// with the flags of `args` on top, `--job-concurrency` at a time, and write
+// the batch report.
+fn run_batch(path: &Path, args: EncodeArgs, launch_dir: &Path) -> Result<()> {
+    let entries = batch::load(path)?;
+    info!("📄 Loaded {} job(s): {}", entries.len(), path.display());
+    let report_path = match &args.batch_report {
+        Some(report) => launch_dir.join(report),
+        None => batch::report_path(path),
+    };
+    let at_once = args.job_concurrency.map_or(1, |n| n as usize).clamp(1, entries.len());
+    let jobs = entries
+        .into_iter()
+        .map(|entry| {
+            let input = entry.config.as_ref().ok().and_then(|config| config.input.clone());
+            let encode = entry.config.and_then(|config| build_encode(args.clone(), config, launch_dir));
+            (entry.line, input, encode.and_then(|encode| batch_encode(encode, at_once)))
+        })
+        .collect();
+    let report = batch::run_all(jobs, at_once, execute_encode);
+    report.save(&report_path)?;
+    summary!("\n🗂 Batch report: {}", report_path.display());
+    interrupt::check()?;
+    if report.failed > 0 {
+        return Err(DeliveryError::BatchFailed { failed: report.failed, total: report.jobs.len(), report: report_path });
+    }
+    Ok(())
+}
+
+// Adapt `encode` to run as one of `at_once` jobs of a batch: it gets that
+// share of the workers, and its own segments directory in the project.
+fn batch_encode(mut encode: Encode, at_once: usize) -> Result<Encode> {
+    let job = &mut encode.job;
+    if job.segments_dir == Path::new(SEGMENTS_DIR) {
+        let temp_job = temp_job(job.job_id.as_deref(), &job.input)?;
+        job.segments_dir = job.segments_dir.join(cleanup::shared_segments_dir_name(&temp_job));
+    }
+    job.ffmpeg_threads = Some(job.ffmpeg_threads());
+    job.threads = (job.threads / at_once).max(1);
+    // Jobs run at once would pin their workers to the same CPUs
+    job.pin_cpus = job.pin_cpus && at_once == 1;
+    Ok(encode)
+}
+
+fn run_probe(args: ProbeArgs) -> Result<()> {
+    let launch_dir = current_dir()?;
+    let input = resolve_arg(args.input, &launch_dir, "assets/video.mov");
+    let ffprobe_override = resolve_tool_arg(args.tools.ffprobe_path, &launch_dir);
+    enter_project_root()?;
+    let ffprobe = ffmpeg::locate("ffprobe", ffprobe_override.as_deref())?;
+
+    if !input.exists() {
+        return Err(DeliveryError::MissingInput { name: "Video", path: input });
+    }
+    let media = probe::media_info(&ffprobe, &input)?;
+    if args.json {
+        let json = serde_json::to_string_pretty(&media)
+            .map_err(|e| DeliveryError::Config(format!("Failed to serialize probe result: {}", e)))?;
+        println!("{}", json);
+        return Ok(());
+    }
+    summary!("\n🎬 {}", input.display());
+    for line in media.summary() {
+        summary!("   {}", line);
+    }
+    Ok(())
+}
+
+// A job that picks up the plan and intermediates a previous run left in the
+// temporary directory.
+fn previous_job(
+    output_dir: Option<PathBuf>,
+    temp: TempArgs,
+    job: TempJobArgs,
+    tools: Option<ToolArgs>,
+) -> Result<EncodeJob> {
+    let launch_dir = current_dir()?;
+    let output_dir = output_dir.map(|p| launch_dir.join(p));
+    let input = resolve_arg(job.input, &launch_dir, "assets/video.mov");
+    let tools = tools.map(|t| {
+        (resolve_tool_arg(t.ffmpeg_path, &launch_dir), resolve_tool_arg(t.ffprobe_path, &launch_dir))
+    });
+    enter_project_root()?;
+
+    let temp_job = temp_job(job.job_id.as_deref(), &input)?;
+    let mut encode_job = EncodeJob::new("", "", "");
+    encode_job.segments_dir = segments_dir(temp.temp_dir, temp.temp, &launch_dir, &temp_job)?;
+    let plan = JobPlan::load_saved(&encode_job.segments_dir)?;
+    info!("📄 Found previous run with {} segments", plan.segments.len());
+    plan.apply(&mut encode_job);
+    if let Some(output_dir) = output_dir {
+        encode_job.output_dir = output_dir;
+    }
+    if let Some((ffmpeg_override, ffprobe_override)) = tools {
+        (encode_job.ffmpeg, encode_job.ffprobe) = locate_tools(ffmpeg_override, ffprobe_override)?;
+    }
+    Ok(encode_job)
+}
+
+fn run_combine(args: CombineArgs) -> Result<()> {
+    let to_video = args.to_video.map(|p| current_dir().map(|dir| dir.join(p))).transpose()?;
+    let mut encode_job = previous_job(args.output_dir, args.temp, args.temp_job, Some(args.tools))?;
+    encode_job.keep_temp = args.keep_temp;
+    encode_job.allow_frame_mismatch = args.allow_frame_mismatch;
+    if let Some(video) = to_video {
+        let frames = encode_job.join_video(&video)?;
+        summary!("\n✅ Conversion successful!");
+        let name = video.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        for (_, name) in encode_job.video_chunks(&name) {
+            summary!("🎬 {} frames saved to: {}", frames, video.with_file_name(name).display());
+        }
+        return Ok(());
+    }
+    let frames = encode_job.recombine()?;
+    conversion_summary(frames, &encode_job)
+}
+
+fn run_resume(args: ResumeArgs) -> Result<()> {
+    let mut encode_job = previous_job(args.output_dir, args.temp, args.temp_job, Some(args.tools))?;
+    encode_job.resume = true;
+    apply_run_args(&mut encode_job, &args.run);
+    let frames = encode_job.run()?;
+    conversion_summary(frames, &encode_job)
+}
+
+fn run_clean(args: CleanArgs) -> Result<()> {
+    let launch_dir = current_dir()?;
+    let output_dir = resolve_arg(args.output_dir, &launch_dir, "output");
+    let input = resolve_arg(args.temp_job.input, &launch_dir, "assets/video.mov");
+    enter_project_root()?;
+
+    let temp_job = temp_job(args.temp_job.job_id.as_deref(), &input)?;
+    let mut targets = vec![segments_dir(args.temp.temp_dir, args.temp.temp, &launch_dir, &temp_job)?];
+    if args.output {
+        targets.push(output_dir);
+    }
+    for dir in targets {
+        if cleanup::remove_dir(&dir)? {
+            summary!("🧹 Removed {}", dir.display());
+        } else {
+            info!("ℹ️ Nothing to remove at {}", dir.display());
+        }
+    }
+    Ok(())
+}
+
+fn run_fetch(args: FetchArgs) -> Result<()> {
+    let launch_dir = current_dir()?;
+    let pins = resolve_arg(Some(args.pins), &launch_dir, "");
+    enter_project_root()?;
+
+    let dest_dir = ffmpeg::bundled_dir().ok_or_else(|| {
+        DeliveryError::Config(format!("No bundled FFmpeg location for {}", env::consts::OS))
+    })?;
+    let installed = dest_dir.join(format!("ffmpeg{}", env::consts::EXE_SUFFIX));
+    if installed.exists() && !args.force {
+        info!("ℹ️ FFmpeg already installed at {} (use --force to replace)", installed.display());
+        return Ok(());
+    }
+
+    let archives = match (args.url, args.sha256) {
+        (Some(url), Some(sha256)) => vec![fetch::PinnedArchive { url, sha256 }],
+        _ => fetch::load_pins(&pins)?,
+    };
+    fetch::fetch_ffmpeg(&archives, &dest_dir)?;
+    info!("✅ FFmpeg installed into {}", dest_dir.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn exit_code_tells_each_kind_of_failure_apart() {
+        let text = || "failed".to_string();
+        let failed = |code, errors: Vec<DeliveryError>| errors.into_iter().map(move |e| (e, code));
+        let cases: Vec<(DeliveryError, i32)> = [
+            failed(2, vec![DeliveryError::Config(text())]),
+            failed(3, vec![DeliveryError::MissingInput { name: "input", path: PathBuf::from("in.mov") }]),
+            failed(4, vec![DeliveryError::FfmpegNotFound(PathBuf::from("ffmpeg"))]),
+            failed(5, vec![DeliveryError::ProbeFailed(text())]),
+            failed(
+                6,
+                vec![
+                    DeliveryError::SegmentFailed { id: 1, stderr: text() },
+                    DeliveryError::SegmentTimedOut { id: 1, reason: text() },
+                    DeliveryError::SplitFailed(text()),
+                    DeliveryError::JoinFailed(text()),
+                    DeliveryError::PackageFailed(text()),
+                    DeliveryError::CrfSearchFailed(text()),
+                    DeliveryError::AudioFailed(text()),
+                ],
+            ),
+            failed(7, vec![DeliveryError::io("Failed to read", io::Error::other("gone"))]),
+            failed(8, vec![DeliveryError::FetchFailed(text())]),
+            failed(9, vec![DeliveryError::MissingCapability(text())]),
+            failed(10, vec![DeliveryError::InsufficientSpace { path: PathBuf::from("/"), required: 2, available: 1 }]),
+            failed(11, vec![DeliveryError::FrameCountMismatch { expected: 2, actual: 1 }]),
+            failed(12, vec![DeliveryError::QcFailed(text())]),
+            failed(13, vec![DeliveryError::DamagedSource(text())]),
+            failed(14, vec![DeliveryError::BatchFailed { failed: 1, total: 2, report: PathBuf::from("r.json") }]),
+            failed(130, vec![DeliveryError::Interrupted]),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        for (error, code) in &cases {
+            assert_eq!(exit_code(error), *code, "{:?}", error);
+        }
+        // One code per kind, none of them success
+        let mut codes: Vec<i32> = cases.iter().map(|(_, code)| *code).collect();
+        codes.dedup();
+        let kinds = codes.len();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), kinds);
+        assert!(!codes.contains(&0));
+    }
+}
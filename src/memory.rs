@@ -0,0 +1,73 @@
+use crate::console::{debug, info};
+use crate::units::format_size;
+use std::io;
+
+/// Memory an ffmpeg process needs regardless of resolution (code, codec
+/// contexts, I/O buffers).
+const BASE_WORKER_BYTES: u64 = 256 << 20;
+
+/// Decoded frames an ffmpeg process keeps in flight, plus a few more for every
+/// thread it runs.
+const FRAMES_IN_FLIGHT: u64 = 16;
+const FRAMES_PER_THREAD: u64 = 4;
+
+/// Bytes per pixel of a decoded frame, assuming 16-bit RGBA as the worst case.
+const BYTES_PER_PIXEL: u64 = 8;
+
+/// Estimated peak memory of one worker's ffmpeg decoding, compositing and
+/// encoding `width` x `height` frames on `threads` threads.
+pub fn estimate_worker_bytes(width: u32, height: u32, threads: usize) -> u64 {
+    let frame = width as u64 * height as u64 * BYTES_PER_PIXEL;
+    BASE_WORKER_BYTES + frame * (FRAMES_IN_FLIGHT + FRAMES_PER_THREAD * threads as u64)
+}
+
+/// Physical memory available to new processes without swapping.
+#[cfg(target_os = "linux")]
+pub fn available_memory() -> io::Result<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo")?;
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))
+        .and_then(|rest| rest.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map(|kib| kib * 1024)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no MemAvailable in /proc/meminfo"))
+}
+
+/// Physical memory available to new processes without swapping.
+#[cfg(windows)]
+pub fn available_memory() -> io::Result<u64> {
+    use windows_sys::Win32::System::SystemInformation::{GlobalMemoryStatusEx, MEMORYSTATUSEX};
+
+    let mut status = MEMORYSTATUSEX { dwLength: std::mem::size_of::<MEMORYSTATUSEX>() as u32, ..Default::default() };
+    // SAFETY: `status` is a valid, correctly sized out parameter.
+    if unsafe { GlobalMemoryStatusEx(&mut status) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(status.ullAvailPhys)
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn available_memory() -> io::Result<u64> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "available memory query not supported"))
+}
+
+/// How many of `workers` fit into the available memory when each needs
+/// `per_worker` bytes, but at least one. If the available memory can't be
+/// determined, `workers` is returned unchanged.
+pub fn limit_workers(workers: usize, per_worker: u64) -> usize {
+    let available = match available_memory() {
+        Ok(available) => available,
+        Err(e) => {
+            debug!("⚠️ Could not determine available memory: {}", e);
+            return workers;
+        }
+    };
+    debug!("🧠 {} available, ~{} per worker", format_size(available), format_size(per_worker));
+    let fits = (available / per_worker.max(1)).max(1) as usize;
+    if fits < workers {
+        info!("🧠 Limiting to {} parallel segments: {} of memory available, ~{} needed per segment",
+            fits, format_size(available), format_size(per_worker));
+        return fits;
+    }
+    workers
+}
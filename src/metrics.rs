@@ -0,0 +1,318 @@
+use crate::console::{debug, info};
+use crate::crop::Crop;
+use crate::segment::Segment;
+use crate::{ffmpeg, interrupt, process, DeliveryError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+/// A full-reference quality metric comparing video output to the source.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QualityMetric {
+    /// Netflix's VMAF, 0-100.
+    Vmaf,
+    /// Peak signal-to-noise ratio in dB.
+    Psnr,
+    /// Structural similarity, 0-1.
+    Ssim,
+}
+
+impl QualityMetric {
+    /// The ffmpeg filter computing the metric.
+    pub fn filter(self) -> &'static str {
+        match self {
+            QualityMetric::Vmaf => "libvmaf",
+            QualityMetric::Psnr => "psnr",
+            QualityMetric::Ssim => "ssim",
+        }
+    }
+
+    // What precedes the score in the summary the filter logs at the end.
+    fn log_key(self) -> &'static str {
+        match self {
+            QualityMetric::Vmaf => "VMAF score:",
+            QualityMetric::Psnr => "average:",
+            QualityMetric::Ssim => "All:",
+        }
+    }
+}
+
+impl FromStr for QualityMetric {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<QualityMetric, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "vmaf" => Ok(QualityMetric::Vmaf),
+            "psnr" => Ok(QualityMetric::Psnr),
+            "ssim" => Ok(QualityMetric::Ssim),
+            _ => Err(format!("invalid quality metric '{}', expected vmaf, psnr or ssim", text)),
+        }
+    }
+}
+
+impl fmt::Display for QualityMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            QualityMetric::Vmaf => "VMAF",
+            QualityMetric::Psnr => "PSNR",
+            QualityMetric::Ssim => "SSIM",
+        })
+    }
+}
+
+/// Scores of one stretch of video; metrics that weren't computed are `None`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Scores {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vmaf: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub psnr: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssim: Option<f64>,
+}
+
+impl Scores {
+    pub fn get(&self, metric: QualityMetric) -> Option<f64> {
+        match metric {
+            QualityMetric::Vmaf => self.vmaf,
+            QualityMetric::Psnr => self.psnr,
+            QualityMetric::Ssim => self.ssim,
+        }
+    }
+
+    fn set(&mut self, metric: QualityMetric, score: f64) {
+        match metric {
+            QualityMetric::Vmaf => self.vmaf = Some(score),
+            QualityMetric::Psnr => self.psnr = Some(score),
+            QualityMetric::Ssim => self.ssim = Some(score),
+        }
+    }
+}
+
+impl fmt::Display for Scores {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        for metric in [QualityMetric::Vmaf, QualityMetric::Psnr, QualityMetric::Ssim] {
+            match (metric, self.get(metric)) {
+                (QualityMetric::Ssim, Some(score)) => parts.push(format!("{} {:.4}", metric, score)),
+                (_, Some(score)) => parts.push(format!("{} {:.2}", metric, score)),
+                (_, None) => {}
+            }
+        }
+        f.write_str(&parts.join(", "))
+    }
+}
+
+/// Scores of one segment of the video.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SegmentScores {
+    pub segment: usize,
+    pub start: f64,
+    pub duration: f64,
+    #[serde(flatten)]
+    pub scores: Scores,
+}
+
+/// Per-segment and overall scores of a video against its source, as saved
+/// next to the video.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QualityReport {
+    pub video: PathBuf,
+    pub reference: PathBuf,
+    /// Duration-weighted mean of the segment scores.
+    pub overall: Scores,
+    pub segments: Vec<SegmentScores>,
+}
+
+/// File the quality report of `video` is saved to: `<video>.quality.json`.
+pub fn report_path(video: &Path) -> PathBuf {
+    let mut name = video.file_name().unwrap_or_default().to_os_string();
+    name.push(".quality.json");
+    video.with_file_name(name)
+}
+
+/// The ffmpeg invocation scoring `segment`'s stretch of `video` against the
+/// same stretch of `reference` with `crop` cut off, with `video` scaled to
+/// the cropped reference's `width` x `height`.
+pub fn compare_command(
+    ffmpeg: &Path,
+    video: &Path,
+    reference: &Path,
+    crop: Crop,
+    segment: &Segment,
+    size: (u32, u32),
+    metrics: &[QualityMetric],
+) -> Command {
+    command(ffmpeg, video, Some(segment.start), reference, crop, segment, size, metrics)
+}
+
+/// The ffmpeg invocation scoring the whole of `clip`, encoded from
+/// `segment`'s stretch of `reference` with `crop` cut off, against that
+/// stretch.
+pub fn clip_command(
+    ffmpeg: &Path,
+    clip: &Path,
+    reference: &Path,
+    crop: Crop,
+    segment: &Segment,
+    size: (u32, u32),
+    metrics: &[QualityMetric],
+) -> Command {
+    command(ffmpeg, clip, None, reference, crop, segment, size, metrics)
+}
+
+// Compare `video`, from `video_start` if it is a whole delivery, with
+// `segment`'s stretch of `reference`, cropped like the delivery.
+#[allow(clippy::too_many_arguments)]
+fn command(
+    ffmpeg: &Path,
+    video: &Path,
+    video_start: Option<f64>,
+    reference: &Path,
+    crop: Crop,
+    segment: &Segment,
+    size: (u32, u32),
+    metrics: &[QualityMetric],
+) -> Command {
+    let (start, duration) = (format!("{:.6}", segment.start), format!("{:.6}", segment.duration));
+    let count = metrics.len();
+    let labels = |prefix: &str| -> String { (0..count).map(|i| format!("[{}{}]", prefix, i)).collect() };
+    let crop = if crop.is_empty() { String::new() } else { format!("{},", crop.filter()) };
+    let mut graph = format!(
+        "[0:v]scale={}:{}:flags=bicubic,setpts=PTS-STARTPTS,split={}{};[1:v]{}setpts=PTS-STARTPTS,split={}{}",
+        size.0,
+        size.1,
+        count,
+        labels("d"),
+        crop,
+        count,
+        labels("r")
+    );
+    for (index, metric) in metrics.iter().enumerate() {
+        graph.push_str(&format!(";[d{}][r{}]{}", index, index, metric.filter()));
+    }
+
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-hide_banner", "-nostats", "-nostdin"]);
+    if let Some(video_start) = video_start {
+        cmd.args(["-ss", &format!("{:.6}", video_start)]);
+    }
+    cmd.args(["-t", &duration, "-i"]).arg(ffmpeg::path_arg(video));
+    cmd.args(["-ss", &start, "-t", &duration, "-i"]).arg(ffmpeg::path_arg(reference));
+    cmd.arg("-filter_complex").arg(graph);
+    cmd.args(["-f", "null", "-"]);
+    cmd
+}
+
+/// Pull the scores of `metrics` out of the summaries their filters log.
+pub fn parse_scores(log: &str, metrics: &[QualityMetric]) -> Scores {
+    let mut scores = Scores::default();
+    for &metric in metrics {
+        let key = metric.log_key();
+        let Some(at) = log.rfind(key) else {
+            continue;
+        };
+        let rest = log[at + key.len()..].trim_start();
+        let end = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+        if let Ok(score) = rest[..end].parse() {
+            scores.set(metric, score);
+        }
+    }
+    scores
+}
+
+/// Score `video` against `reference`, with `crop` cut off as it was for the
+/// video, with `metrics`, one segment at a time and `threads` segments at
+/// once, and save the report next to the video. The overlay is part of the
+/// video but not of the reference, so it lowers the scores where it covers
+/// the picture.
+#[allow(clippy::too_many_arguments)]
+pub fn compare(
+    ffmpeg: &Path,
+    video: &Path,
+    reference: &Path,
+    crop: Crop,
+    segments: &[Segment],
+    size: (u32, u32),
+    metrics: &[QualityMetric],
+    threads: usize,
+) -> Result<QualityReport> {
+    let _span = tracing::info_span!("metrics", segments = segments.len()).entered();
+    let names: Vec<String> = metrics.iter().map(|m| m.to_string()).collect();
+    info!("\n📈 Measuring {} of {}...", names.join(", "), video.display());
+    let started = Instant::now();
+
+    let queue: Mutex<VecDeque<&Segment>> = Mutex::new(segments.iter().collect());
+    let results: Mutex<Vec<SegmentScores>> = Mutex::new(Vec::new());
+    let failures: Mutex<Vec<DeliveryError>> = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, segments.len().max(1)) {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap().pop_front();
+                let Some(segment) = next else { break };
+                if interrupt::requested() {
+                    break;
+                }
+                let cmd = compare_command(ffmpeg, video, reference, crop, segment, size, metrics);
+                match run(ffmpeg, cmd) {
+                    Ok(log) => results.lock().unwrap().push(SegmentScores {
+                        segment: segment.id,
+                        start: segment.start,
+                        duration: segment.duration,
+                        scores: parse_scores(&log, metrics),
+                    }),
+                    Err(e) => failures.lock().unwrap().push(e),
+                }
+            });
+        }
+    });
+
+    interrupt::check()?;
+    if let Some(e) = failures.into_inner().unwrap().into_iter().next() {
+        return Err(e);
+    }
+    let mut segments = results.into_inner().unwrap();
+    segments.sort_by_key(|s| s.segment);
+    let mut overall = Scores::default();
+    for &metric in metrics {
+        let scored: Vec<(f64, f64)> =
+            segments.iter().filter_map(|s| s.scores.get(metric).map(|score| (score, s.duration))).collect();
+        let weight: f64 = scored.iter().map(|(_, duration)| duration).sum();
+        if weight > 0.0 {
+            overall.set(metric, scored.iter().map(|(score, duration)| score * duration).sum::<f64>() / weight);
+        }
+    }
+    for segment in &segments {
+        info!("   Segment {}: {}", segment.segment, segment.scores);
+    }
+
+    let report = QualityReport { video: video.to_path_buf(), reference: reference.to_path_buf(), overall, segments };
+    let path = report_path(video);
+    let json = serde_json::to_string_pretty(&report).map_err(|e| DeliveryError::QcFailed(e.to_string()))?;
+    fs::write(&path, json).map_err(|e| DeliveryError::io(format!("Failed to write {}", path.display()), e))?;
+    info!("✅ {} in {:.2} seconds", report.overall, started.elapsed().as_secs_f32());
+    Ok(report)
+}
+
+/// Run the comparison `cmd` and return what it logged.
+pub fn run(ffmpeg: &Path, mut cmd: Command) -> Result<String> {
+    process::contain(&mut cmd);
+    debug!("Command: {}", ffmpeg::display_command(&cmd));
+    let result = cmd.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffmpeg.to_path_buf()),
+        _ => DeliveryError::io("Failed to execute ffmpeg", e),
+    })?;
+    let log = String::from_utf8_lossy(&result.stderr).into_owned();
+    if !result.status.success() {
+        return Err(DeliveryError::QcFailed(log.trim().to_string()));
+    }
+    Ok(log)
+}
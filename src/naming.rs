@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+
+/// Template the output frames are named with unless the job sets one.
+pub const DEFAULT_TEMPLATE: &str = "video{frame}.{ext}";
+
+/// Number given to the first output frame by default.
+pub const DEFAULT_START_FRAME: u64 = 1;
+
+/// Digits `{frame}` is zero-padded to unless the template sets a width.
+pub const DEFAULT_PADDING: usize = 5;
+
+/// Values substituted into a name template.
+#[derive(Debug, Clone, Default)]
+pub struct NameVars {
+    /// `{basename}`: file name of the source without its extension.
+    pub basename: String,
+    /// `{job}`: the job id.
+    pub job: String,
+    /// `{date}`: the day the job was planned, `YYYY-MM-DD`.
+    pub date: String,
+    /// `{ext}`: extension of the frame format.
+    pub ext: String,
+}
+
+/// Output frame names resolved from a template: everything around `{frame}`
+/// is fixed for the job, so a run, its resume and a later `combine` all name
+/// frames identically.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FrameNames {
+    pub prefix: String,
+    pub suffix: String,
+    pub start_frame: u64,
+    pub padding: usize,
+}
+
+impl Default for FrameNames {
+    fn default() -> FrameNames {
+        FrameNames {
+            prefix: "video".to_string(),
+            suffix: ".png".to_string(),
+            start_frame: DEFAULT_START_FRAME,
+            padding: DEFAULT_PADDING,
+        }
+    }
+}
+
+impl FrameNames {
+    /// Resolve `template`, e.g. `{basename}.{frame:04}.{ext}`. Variables are
+    /// `{basename}`, `{job}`, `{date}`, `{ext}` and `{frame}`, which must
+    /// appear exactly once and takes an optional width (`{frame:06}`)
+    /// overriding `padding`.
+    pub fn from_template(template: &str, vars: &NameVars, start_frame: u64, padding: usize) -> Result<FrameNames, String> {
+        let mut prefix = String::new();
+        let mut suffix = String::new();
+        let mut frame_width = None;
+        let mut rest = template;
+        while let Some(open) = rest.find(['{', '}']) {
+            let out = if frame_width.is_some() { &mut suffix } else { &mut prefix };
+            out.push_str(&rest[..open]);
+            if rest[open..].starts_with('}') {
+                return Err(format!("unmatched '}}' in name template '{}'", template));
+            }
+            let close = rest[open..]
+                .find('}')
+                .map(|i| open + i)
+                .ok_or_else(|| format!("unclosed '{{' in name template '{}'", template))?;
+            let (name, width) = match rest[open + 1..close].split_once(':') {
+                Some((name, width)) => (name, Some(width)),
+                None => (&rest[open + 1..close], None),
+            };
+            match (name, width) {
+                ("frame", _) if frame_width.is_some() => {
+                    return Err(format!("name template '{}' contains {{frame}} more than once", template));
+                }
+                ("frame", None) => frame_width = Some(padding),
+                ("frame", Some(width)) => {
+                    let width = width
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|w| *w <= 20)
+                        .ok_or_else(|| format!("invalid frame width '{}' in name template '{}'", width, template))?;
+                    frame_width = Some(width);
+                }
+                ("basename", None) => out.push_str(&vars.basename),
+                ("job", None) => out.push_str(&vars.job),
+                ("date", None) => out.push_str(&vars.date),
+                ("ext", None) => out.push_str(&vars.ext),
+                _ => return Err(format!("unknown variable '{{{}}}' in name template '{}'", &rest[open + 1..close], template)),
+            }
+            rest = &rest[close + 1..];
+        }
+        let Some(padding) = frame_width else {
+            return Err(format!("name template '{}' must contain {{frame}}", template));
+        };
+        suffix.push_str(rest);
+
+        if prefix.contains(['/', '\\']) || suffix.contains(['/', '\\']) {
+            return Err(format!("name template '{}' must not contain path separators", template));
+        }
+        if suffix.is_empty() || suffix.ends_with('.') {
+            return Err(format!("name template '{}' must end with a file extension", template));
+        }
+        Ok(FrameNames { prefix, suffix, start_frame, padding })
+    }
+
+    /// File name of the frame at `index` (0-based) in the output sequence.
+    pub fn name(&self, index: usize) -> String {
+        format!("{}{:0width$}{}", self.prefix, self.start_frame + index as u64, self.suffix, width = self.padding)
+    }
+
+    /// Whether `file_name` is a frame named by these rules.
+    pub fn matches(&self, file_name: &str) -> bool {
+        file_name
+            .strip_prefix(self.prefix.as_str())
+            .and_then(|n| n.strip_suffix(self.suffix.as_str()))
+            .is_some_and(|n| n.len() >= self.padding.max(1) && n.bytes().all(|b| b.is_ascii_digit()))
+    }
+
+    /// The names as a printf-style sequence pattern, e.g. `video%05d.png`.
+    pub fn pattern(&self) -> String {
+        format!("{}%0{}d{}", self.prefix, self.padding, self.suffix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> NameVars {
+        NameVars { basename: "clip".into(), job: "ep101".into(), date: "2026-10-16".into(), ext: "exr".into() }
+    }
+
+    fn error(template: &str) -> String {
+        FrameNames::from_template(template, &vars(), 1, DEFAULT_PADDING).unwrap_err()
+    }
+
+    #[test]
+    fn frame_width_overrides_padding() {
+        let names = FrameNames::from_template("{basename}.{frame:04}.{ext}", &vars(), 1001, 7).unwrap();
+        assert_eq!(names.name(0), "clip.1001.exr");
+        assert_eq!(names.pattern(), "clip.%04d.exr");
+
+        let names = FrameNames::from_template("{job}_{date}_{frame}.{ext}", &vars(), 1, 7).unwrap();
+        assert_eq!(names.name(41), "ep101_2026-10-16_0000042.exr");
+        assert!(names.matches("ep101_2026-10-16_0000042.exr"));
+        assert!(!names.matches("ep101_2026-10-16_42.exr"));
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        assert!(error("{basename}.{ext}").contains("must contain {frame}"));
+        assert!(error("{frame}_{frame}.{ext}").contains("more than once"));
+        assert!(error("{frame:x}.{ext}").contains("invalid frame width 'x'"));
+        assert!(error("{frame:21}.{ext}").contains("invalid frame width '21'"));
+        assert!(error("{shot}_{frame}.{ext}").contains("unknown variable '{shot}'"));
+        assert!(error("{frame.{ext}").contains("unknown variable"));
+        assert!(error("{frame}}.{ext}").contains("unmatched '}'"));
+        assert!(error("{frame}.{ext").contains("unclosed '{'"));
+        assert!(error("out/{frame}.{ext}").contains("path separators"));
+        assert!(error("{basename}{frame}").contains("file extension"));
+        assert!(error("{frame}.").contains("file extension"));
+    }
+}
@@ -0,0 +1,171 @@
+use crate::console::{debug, info};
+use std::str::FromStr;
+
+/// Files the encoder keeps open besides its workers': the standard streams,
+/// the log file, the checkpoint and the pipes of one-off ffmpeg runs.
+const RESERVED_FILES: u64 = 64;
+
+/// Files one worker holds open at most: the pipes of its decode, watermark
+/// hook and encode processes, and those std opens while spawning them.
+pub const FILES_PER_WORKER: u64 = 16;
+
+/// Files ffmpeg opens besides its inputs when packaging: its standard
+/// streams, the manifests and the media segments being written.
+pub const PACKAGE_FILES: u64 = 16;
+
+/// Highest open file limit macOS accepts, whatever the hard limit says
+/// (`OPEN_MAX`).
+#[cfg(target_os = "macos")]
+const MACOS_OPEN_MAX: u64 = 10240;
+
+/// How the process's open file limit is handled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaxOpenFiles {
+    /// Never raise the soft limit; work within it.
+    Keep,
+    /// Raise the soft limit up to this many files (as far as the hard limit
+    /// allows) and never plan for more.
+    Files(u64),
+}
+
+impl FromStr for MaxOpenFiles {
+    type Err = String;
+
+    /// Parse `keep` or a number of files such as `4096`.
+    fn from_str(text: &str) -> std::result::Result<MaxOpenFiles, String> {
+        match text.trim() {
+            "keep" => Ok(MaxOpenFiles::Keep),
+            n => match n.parse::<u64>() {
+                Ok(files) if files > 0 => Ok(MaxOpenFiles::Files(files)),
+                _ => Err(format!("invalid open file limit '{}', expected keep or a number of files", text)),
+            },
+        }
+    }
+}
+
+/// How many files the encoder may have open at once with `max` applied, or
+/// `None` if there is no such limit. Without `max` the soft limit is raised
+/// (Unix) to make room for `wanted` files if it is lower; with
+/// [`MaxOpenFiles::Files`] it is raised to that many. The ffmpeg processes
+/// the encoder starts inherit the raised limit.
+pub fn limit(max: Option<MaxOpenFiles>, wanted: u64) -> Option<u64> {
+    let (soft, hard) = query()?;
+    let target = match max {
+        Some(MaxOpenFiles::Keep) => return Some(soft),
+        Some(MaxOpenFiles::Files(files)) => files,
+        None => wanted,
+    };
+    let target = target.min(hard);
+    #[cfg(target_os = "macos")]
+    let target = target.min(MACOS_OPEN_MAX);
+    let limit = if target > soft { raise(soft, target, hard) } else { soft };
+    match max {
+        Some(MaxOpenFiles::Files(files)) => Some(limit.min(files)),
+        _ => Some(limit),
+    }
+}
+
+// The soft and hard open file limits; `None` if the soft limit is
+// unlimited or can't be queried.
+#[cfg(unix)]
+// rlim_t is signed on some BSDs
+#[allow(clippy::unnecessary_cast)]
+fn query() -> Option<(u64, u64)> {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    // SAFETY: getrlimit only writes into the struct we pass.
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        debug!("⚠️ Could not query the open file limit: {}", std::io::Error::last_os_error());
+        return None;
+    }
+    if limit.rlim_cur == libc::RLIM_INFINITY {
+        return None;
+    }
+    let hard = if limit.rlim_max == libc::RLIM_INFINITY { u64::MAX } else { limit.rlim_max as u64 };
+    Some((limit.rlim_cur as u64, hard))
+}
+
+#[cfg(not(unix))]
+fn query() -> Option<(u64, u64)> {
+    None
+}
+
+// Raise the soft limit from `soft` to `target`, returning the limit now in
+// effect.
+#[cfg(unix)]
+fn raise(soft: u64, target: u64, hard: u64) -> u64 {
+    let raised = libc::rlimit {
+        rlim_cur: target as libc::rlim_t,
+        rlim_max: if hard == u64::MAX { libc::RLIM_INFINITY } else { hard as libc::rlim_t },
+    };
+    // SAFETY: setrlimit only reads the struct we pass.
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &raised) } != 0 {
+        debug!("⚠️ Could not raise the open file limit of {}: {}", soft, std::io::Error::last_os_error());
+        return soft;
+    }
+    info!("📂 Raised the open file limit from {} to {}", soft, target);
+    target
+}
+
+#[cfg(not(unix))]
+fn raise(soft: u64, _target: u64, _hard: u64) -> u64 {
+    soft
+}
+
+/// How many of `workers` fit into an open file `limit` when each holds up to
+/// `per_worker` files open, but at least one. Without a limit, `workers` is
+/// returned unchanged.
+pub fn fits(limit: Option<u64>, workers: usize, per_worker: u64) -> usize {
+    let Some(limit) = limit else {
+        return workers;
+    };
+    let fits = (limit.saturating_sub(RESERVED_FILES) / per_worker.max(1)).max(1);
+    workers.min(usize::try_from(fits).unwrap_or(usize::MAX))
+}
+
+/// How many of `workers` fit into the open file limit, `max` applied, when
+/// each holds up to `per_worker` files open. The soft limit is only raised
+/// when `max` asks for it or when it would leave fewer than `workers`.
+pub fn limit_workers(workers: usize, per_worker: u64, max: Option<MaxOpenFiles>) -> usize {
+    let wanted = RESERVED_FILES + workers as u64 * per_worker;
+    let limit = limit(max, wanted);
+    let fits = fits(limit, workers, per_worker);
+    if let (true, Some(limit)) = (fits < workers, limit) {
+        info!("📂 Limiting to {} parallel segments: {} files may be open at once", fits, limit);
+    }
+    fits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_without_a_limit_keeps_the_workers() {
+        assert_eq!(fits(None, 12, FILES_PER_WORKER), 12);
+    }
+
+    #[test]
+    fn fits_at_least_one_worker() {
+        assert_eq!(fits(Some(0), 12, FILES_PER_WORKER), 1);
+        assert_eq!(fits(Some(RESERVED_FILES - 1), 12, FILES_PER_WORKER), 1);
+        assert_eq!(fits(Some(RESERVED_FILES), 12, FILES_PER_WORKER), 1);
+    }
+
+    #[test]
+    fn fits_workers_up_to_the_exact_boundary() {
+        let limit = RESERVED_FILES + 4 * FILES_PER_WORKER;
+        assert_eq!(fits(Some(limit), 8, FILES_PER_WORKER), 4);
+        assert_eq!(fits(Some(limit - 1), 8, FILES_PER_WORKER), 3);
+        assert_eq!(fits(Some(limit), 4, FILES_PER_WORKER), 4);
+        assert_eq!(fits(Some(limit), 2, FILES_PER_WORKER), 2);
+        assert_eq!(fits(Some(limit), 8, 0), 8);
+    }
+
+    #[test]
+    fn max_open_files_parses_keep_or_a_count() {
+        assert_eq!("keep".parse(), Ok(MaxOpenFiles::Keep));
+        assert_eq!("4096".parse(), Ok(MaxOpenFiles::Files(4096)));
+        assert!("0".parse::<MaxOpenFiles>().is_err());
+        assert!("lots".parse::<MaxOpenFiles>().is_err());
+    }
+}
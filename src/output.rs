@@ -0,0 +1,183 @@
+use crate::clock::UtcTime;
+use crate::console::{debug, info, warning};
+use crate::naming::FrameNames;
+use crate::{combine, DeliveryError, Result};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the link to the newest run inside a timestamped output directory.
+pub const LATEST_LINK: &str = "latest";
+
+/// Name of a run's subdirectory of a timestamped output directory:
+/// `<job>_<YYYYMMDD>_<HHMMSS>`.
+pub fn run_dir_name(job: &str, time: &UtcTime) -> String {
+    format!(
+        "{}_{:04}{:02}{:02}_{:02}{:02}{:02}",
+        job, time.year, time.month, time.day, time.hour, time.minute, time.second
+    )
+}
+
+/// Point `<parent of run_dir>/latest` at `run_dir` (a symlink on Unix, a
+/// symlink or else a junction on Windows). A `latest` that is a real
+/// directory is left alone. Failure is reported but not fatal.
+pub fn point_latest(run_dir: &Path) {
+    let (Some(parent), Some(name)) = (run_dir.parent(), run_dir.file_name()) else {
+        return;
+    };
+    let link = parent.join(LATEST_LINK);
+    if fs::symlink_metadata(&link).is_ok_and(|m| m.is_dir()) {
+        warning!("⚠️ {} is a directory, not pointing it at the new run", link.display());
+        return;
+    }
+    match replace_link(&link, Path::new(name), run_dir) {
+        Ok(()) => info!("🔗 {} -> {}", link.display(), name.to_string_lossy()),
+        Err(e) => warning!("⚠️ Failed to update {}: {}", link.display(), e),
+    }
+}
+
+// Swap the link in with a rename so readers never see it missing.
+#[cfg(unix)]
+fn replace_link(link: &Path, relative: &Path, _target: &Path) -> io::Result<()> {
+    let staged = link.with_extension("new");
+    let _ = fs::remove_file(&staged);
+    std::os::unix::fs::symlink(relative, &staged)?;
+    fs::rename(&staged, link)
+}
+
+// Symlinks need Developer Mode or admin rights on Windows; junctions don't,
+// but need an absolute target and can't be swapped in atomically.
+#[cfg(windows)]
+fn replace_link(link: &Path, relative: &Path, target: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(link).is_ok() {
+        fs::remove_dir(link).or_else(|_| fs::remove_file(link))?;
+    }
+    if std::os::windows::fs::symlink_dir(relative, link).is_ok() {
+        return Ok(());
+    }
+    let target = std::path::absolute(target)?;
+    let status = std::process::Command::new("cmd")
+        .arg("/C")
+        .arg("mklink")
+        .arg("/J")
+        .arg(link)
+        .arg(target)
+        .stdout(std::process::Stdio::null())
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("mklink /J exited with {}", status)))
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn replace_link(_link: &Path, _relative: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "links not supported"))
+}
+
+/// Hidden directory next to `output_dir` that frames are combined into before
+/// they are published: `.<name>.staging`.
+pub fn staging_dir(output_dir: &Path) -> PathBuf {
+    let name = output_dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    output_dir.with_file_name(format!(".{}.staging", name))
+}
+
+/// Create an empty staging directory, removing one an interrupted run left.
+pub fn prepare_staging(staging: &Path) -> Result<()> {
+    if staging.exists() {
+        debug!("🧹 Removing stale staging directory {}", staging.display());
+        fs::remove_dir_all(staging)
+            .map_err(|e| DeliveryError::io(format!("Failed to remove {}", staging.display()), e))?;
+    }
+    fs::create_dir_all(staging)
+        .map_err(|e| DeliveryError::io(format!("Failed to create {}", staging.display()), e))?;
+    hide(staging);
+    Ok(())
+}
+
+/// Check that `staging` holds all `frames` frames, then move them to
+/// `output_dir` in one rename, so tools watching `output_dir` never see a
+/// partial set. If `output_dir` already holds other files the frames are
+/// renamed into it one by one instead. A staging directory that fails the
+/// check is kept for inspection.
+pub fn publish(staging: &Path, output_dir: &Path, names: &FrameNames, frames: usize) -> Result<()> {
+    let staged = combine::output_frames(staging, names)
+        .map_err(|e| DeliveryError::io(format!("Failed to read {}", staging.display()), e))?;
+    if staged.len() != frames {
+        info!("ℹ️ Staged frames kept in {} for inspection", staging.display());
+        return Err(DeliveryError::FrameCountMismatch { expected: frames as u64, actual: staged.len() as u64 });
+    }
+
+    let publish_err = |e| DeliveryError::io(format!("Failed to move frames into {}", output_dir.display()), e);
+    let empty = fs::read_dir(output_dir).map(|mut d| d.next().is_none()).unwrap_or(true);
+    if empty {
+        // Unix renames over an empty directory; Windows needs it gone first
+        if fs::rename(staging, output_dir).is_err() {
+            if output_dir.exists() {
+                fs::remove_dir(output_dir).map_err(publish_err)?;
+            }
+            fs::rename(staging, output_dir).map_err(publish_err)?;
+        }
+        unhide(output_dir);
+    } else {
+        debug!("ℹ️ {} holds other files, moving frames individually", output_dir.display());
+        for frame in staged {
+            if let Some(name) = frame.file_name() {
+                fs::rename(&frame, output_dir.join(name)).map_err(publish_err)?;
+            }
+        }
+        fs::remove_dir_all(staging).map_err(publish_err)?;
+    }
+    debug!("✅ Published {} frames to {}", frames, output_dir.display());
+    Ok(())
+}
+
+/// Move the finished file `staged` from its staging directory into
+/// `output_dir` (created if needed), removing the staging directory once it
+/// is empty.
+pub fn publish_file(staged: &Path, output_dir: &Path) -> Result<()> {
+    let publish_err = |e| DeliveryError::io(format!("Failed to move the video into {}", output_dir.display()), e);
+    fs::create_dir_all(output_dir).map_err(publish_err)?;
+    if let (Some(name), Some(staging)) = (staged.file_name(), staged.parent()) {
+        fs::rename(staged, output_dir.join(name)).map_err(publish_err)?;
+        if fs::read_dir(staging).is_ok_and(|mut d| d.next().is_none()) {
+            fs::remove_dir(staging).map_err(publish_err)?;
+        }
+    }
+    debug!("✅ Published {}", output_dir.display());
+    Ok(())
+}
+
+// The dot prefix hides the staging directory on Unix; Windows needs the
+// attribute.
+#[cfg(windows)]
+fn set_hidden(path: &Path, hidden: bool) {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::{
+        GetFileAttributesW, SetFileAttributesW, FILE_ATTRIBUTE_HIDDEN, INVALID_FILE_ATTRIBUTES,
+    };
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    // SAFETY: `wide` is NUL terminated.
+    unsafe {
+        let attributes = GetFileAttributesW(wide.as_ptr());
+        if attributes != INVALID_FILE_ATTRIBUTES {
+            let attributes = if hidden { attributes | FILE_ATTRIBUTE_HIDDEN } else { attributes & !FILE_ATTRIBUTE_HIDDEN };
+            SetFileAttributesW(wide.as_ptr(), attributes);
+        }
+    }
+}
+
+fn hide(path: &Path) {
+    #[cfg(windows)]
+    set_hidden(path, true);
+    #[cfg(not(windows))]
+    let _ = path;
+}
+
+pub(crate) fn unhide(path: &Path) {
+    #[cfg(windows)]
+    set_hidden(path, false);
+    #[cfg(not(windows))]
+    let _ = path;
+}
@@ -0,0 +1,665 @@
+use crate::svg::{self, Raster};
+use crate::{ffmpeg, probe, Result};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Corner, edge or centre of the frame the overlay is anchored to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Position {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Position {
+    const ALL: [Position; 9] = [
+        Position::TopLeft,
+        Position::Top,
+        Position::TopRight,
+        Position::Left,
+        Position::Center,
+        Position::Right,
+        Position::BottomLeft,
+        Position::Bottom,
+        Position::BottomRight,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Position::TopLeft => "top-left",
+            Position::Top => "top",
+            Position::TopRight => "top-right",
+            Position::Left => "left",
+            Position::Center => "center",
+            Position::Right => "right",
+            Position::BottomLeft => "bottom-left",
+            Position::Bottom => "bottom",
+            Position::BottomRight => "bottom-right",
+        }
+    }
+
+    /// `x` expression placing something `margin` pixels from the anchored
+    /// edge, given `free`, the expression of the width left beside it (e.g.
+    /// `W-w` for overlay).
+    pub fn x_within(self, margin: u32, free: &str) -> String {
+        match self {
+            Position::TopLeft | Position::Left | Position::BottomLeft => margin.to_string(),
+            Position::Top | Position::Center | Position::Bottom => format!("({})/2", free),
+            Position::TopRight | Position::Right | Position::BottomRight => far_edge(free, margin),
+        }
+    }
+
+    /// `y` expression, as [`Position::x_within`].
+    pub fn y_within(self, margin: u32, free: &str) -> String {
+        match self {
+            Position::TopLeft | Position::Top | Position::TopRight => margin.to_string(),
+            Position::Left | Position::Center | Position::Right => format!("({})/2", free),
+            Position::BottomLeft | Position::Bottom | Position::BottomRight => far_edge(free, margin),
+        }
+    }
+
+    // overlay's `x` and `y` expressions.
+    fn x(self, margin: u32) -> String {
+        self.x_within(margin, "W-w")
+    }
+
+    fn y(self, margin: u32) -> String {
+        self.y_within(margin, "H-h")
+    }
+}
+
+// `edge`, the expression placing something against the right or bottom
+// edge, moved `margin` pixels in.
+fn far_edge(edge: &str, margin: u32) -> String {
+    if margin > 0 {
+        format!("{}-{}", edge, margin)
+    } else {
+        edge.to_string()
+    }
+}
+
+impl FromStr for Position {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Position, String> {
+        let name = text.trim().to_ascii_lowercase().replace('_', "-");
+        let name = if name == "centre" { "center".to_string() } else { name };
+        Position::ALL.into_iter().find(|p| p.name() == name).ok_or_else(|| {
+            format!(
+                "invalid overlay position '{}', expected {}",
+                text,
+                Position::ALL.map(|p| p.name()).join(", ")
+            )
+        })
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Size of the overlay as a share of the video's width or height; the other
+/// side follows the overlay's aspect ratio.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum OverlayScale {
+    /// Overlay width as a share of the video width.
+    Width(f64),
+    /// Overlay height as a share of the video height.
+    Height(f64),
+}
+
+impl OverlayScale {
+    /// scale2ref's `w` and `h` options sizing the overlay against the video.
+    fn options(self) -> String {
+        match self {
+            OverlayScale::Width(share) => format!("w=main_w*{}:h=ow/a", share),
+            OverlayScale::Height(share) => format!("w=oh*a:h=main_h*{}", share),
+        }
+    }
+}
+
+impl FromStr for OverlayScale {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<OverlayScale, String> {
+        let lower = text.trim().to_ascii_lowercase();
+        let parsed = match lower.split_at(lower.len().saturating_sub(1)) {
+            (share, "w") => share.trim().parse().ok().map(OverlayScale::Width),
+            (share, "h") => share.trim().parse().ok().map(OverlayScale::Height),
+            _ => None,
+        };
+        match parsed {
+            Some(scale @ (OverlayScale::Width(share) | OverlayScale::Height(share))) if share > 0.0 && share <= 1.0 => {
+                Ok(scale)
+            }
+            _ => Err(format!(
+                "invalid overlay scale '{}', expected a share of the video width or height, e.g. 0.15w or 0.1h",
+                text
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OverlayScale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverlayScale::Width(share) => write!(f, "{}w", share),
+            OverlayScale::Height(share) => write!(f, "{}h", share),
+        }
+    }
+}
+
+/// Stretch of the program, in seconds, the overlay is shown in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Window {
+    /// overlay's `enable` expression showing the overlay during the window
+    /// on a timeline that starts `origin` seconds into the program.
+    fn enable(self, origin: f64) -> String {
+        format!("between(t,{:.3},{:.3})", self.start - origin, self.end - origin)
+    }
+
+    /// Whether any of the window falls between `start` and `end` seconds
+    /// into the program.
+    pub fn overlaps(self, start: f64, end: f64) -> bool {
+        self.start < end && self.end > start
+    }
+}
+
+// Seconds in a clock time such as `00:01:05`, `01:05.5` or `65`.
+fn parse_clock(text: &str) -> Option<f64> {
+    let fields: Vec<&str> = text.trim().split(':').collect();
+    if fields.len() > 3 {
+        return None;
+    }
+    let mut seconds = 0.0;
+    for (index, field) in fields.iter().enumerate() {
+        let value: f64 = field.trim().parse().ok()?;
+        let last = index + 1 == fields.len();
+        if value < 0.0 || !value.is_finite() || (!last && value.fract() != 0.0) || (index > 0 && value >= 60.0) {
+            return None;
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Some(seconds)
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Window, String> {
+        let invalid = || format!("invalid overlay window '{}', expected START-END, e.g. 00:00:05-00:00:15", text);
+        let (start, end) = text.split_once('-').ok_or_else(invalid)?;
+        let (start, end) = (parse_clock(start).ok_or_else(invalid)?, parse_clock(end).ok_or_else(invalid)?);
+        if end <= start {
+            return Err(format!("overlay window '{}' ends before it starts", text));
+        }
+        Ok(Window { start, end })
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}s-{}s", self.start, self.end)
+    }
+}
+
+/// Parse an overlay opacity from 0 (invisible) to 1 (as it is), e.g. `0.4`.
+pub fn parse_opacity(text: &str) -> std::result::Result<f64, String> {
+    match text.trim().parse::<f64>() {
+        Ok(opacity) if (0.0..=1.0).contains(&opacity) => Ok(opacity),
+        _ => Err(format!("invalid overlay opacity '{}', expected 0 to 1, e.g. 0.4", text)),
+    }
+}
+
+/// How close to the key colour a pixel must be to be keyed out by default.
+pub const DEFAULT_KEY_SIMILARITY: f64 = 0.15;
+
+/// How gradually pixels just outside the similarity are keyed out by default.
+pub const DEFAULT_KEY_BLEND: f64 = 0.05;
+
+/// Keying of an overlay delivered over a green (or blue) screen instead of
+/// with alpha, such as a lower-third animation, with chromakey.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChromaKey {
+    /// Colour of the screen, e.g. `0x00B140` or `green`.
+    pub color: String,
+    /// How close to `color` a pixel must be to turn transparent, above 0 to 1.
+    pub similarity: f64,
+    /// How gradually the pixels just outside `similarity` turn transparent,
+    /// 0 (a hard edge) to 1.
+    pub blend: f64,
+}
+
+impl ChromaKey {
+    /// chromakey taking the overlay's stream `input` to `output`.
+    fn filter(&self, input: &str, output: &str) -> String {
+        format!(
+            "{}chromakey=color={}:similarity={}:blend={}{}",
+            input,
+            ffmpeg::escape_filter_value(&self.color),
+            self.similarity,
+            self.blend,
+            output
+        )
+    }
+}
+
+impl FromStr for ChromaKey {
+    type Err = String;
+
+    /// Parse `COLOR[:SIMILARITY[:BLEND]]`, e.g. `0x00B140` or `green:0.2:0.1`.
+    fn from_str(text: &str) -> std::result::Result<ChromaKey, String> {
+        let mut fields = text.trim().split(':').map(str::trim);
+        let color = fields.next().filter(|c| !c.is_empty()).ok_or_else(|| {
+            format!("invalid overlay key '{}', expected COLOR[:SIMILARITY[:BLEND]], e.g. 0x00B140:0.15", text)
+        })?;
+        // A share up to 1, above 0 unless `zero` is allowed
+        let mut share = |name: &str, default: f64, zero: bool| match fields.next() {
+            None => Ok(default),
+            Some(field) => match field.parse::<f64>() {
+                Ok(value) if value <= 1.0 && (value > 0.0 || (zero && value == 0.0)) => Ok(value),
+                _ => Err(format!(
+                    "invalid key {} '{}' in '{}', expected {} to 1",
+                    name,
+                    field,
+                    text,
+                    if zero { "0" } else { "above 0" }
+                )),
+            },
+        };
+        let similarity = share("similarity", DEFAULT_KEY_SIMILARITY, false)?;
+        let blend = share("blend", DEFAULT_KEY_BLEND, true)?;
+        if fields.next().is_some() {
+            return Err(format!("invalid overlay key '{}', expected COLOR[:SIMILARITY[:BLEND]]", text));
+        }
+        Ok(ChromaKey { color: color.to_string(), similarity, blend })
+    }
+}
+
+impl fmt::Display for ChromaKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.color, self.similarity, self.blend)
+    }
+}
+
+/// Where the built-in overlay filter composites the overlay.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct OverlaySettings {
+    /// Anchor of the overlay.
+    #[serde(default)]
+    pub position: Position,
+    /// Distance in pixels from the anchored edges.
+    #[serde(default)]
+    pub margin: u32,
+    /// overlay `x` expression, e.g. `W-w-48`, replacing the position's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    /// overlay `y` expression, replacing the position's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+    /// Scale the overlay relative to the video before compositing it, so
+    /// one asset suits every resolution.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<OverlayScale>,
+    /// Multiply the overlay's alpha by this, 0 to 1, for faint watermarks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f64>,
+    /// Only show the overlay during this stretch of the program.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window: Option<Window>,
+    /// Key out the overlay's green screen before compositing it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<ChromaKey>,
+}
+
+/// Extensions of the video files an animated overlay is read from, such as
+/// ProRes 4444 or VP9 with alpha.
+const VIDEO_EXTENSIONS: [&str; 7] = ["mov", "mp4", "m4v", "mkv", "webm", "mxf", "gif"];
+
+/// Start numbers ffmpeg looks for the first frame of an image sequence at.
+const SEQUENCE_START_NUMBERS: u32 = 5;
+
+// Frame `number` of the image sequence `path`, e.g. `logo_0001.png` for 1 in
+// `logo_%04d.png`, or `None` if `path` isn't a sequence pattern.
+fn sequence_frame(path: &Path, number: u32) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let start = name.find('%')?;
+    let end = start + 1 + name[start + 1..].find('d')?;
+    let width: usize = match &name[start + 1..end] {
+        "" => 0,
+        width => width.parse().ok()?,
+    };
+    Some(path.with_file_name(format!("{}{:0width$}{}", &name[..start], number, &name[end + 1..], width = width)))
+}
+
+/// Whether `path` is an image sequence pattern such as `logo_%04d.png`.
+pub fn is_sequence(path: &Path) -> bool {
+    sequence_frame(path, 0).is_some()
+}
+
+/// Whether the overlay at `path` moves: an image sequence or a video file.
+pub fn is_animated(path: &Path) -> bool {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    is_sequence(path) || VIDEO_EXTENSIONS.contains(&extension.as_str())
+}
+
+/// Whether the overlay at `path` exists; for an image sequence, its first
+/// frame.
+pub fn exists(path: &Path) -> bool {
+    match sequence_frame(path, 0) {
+        Some(_) => (0..SEQUENCE_START_NUMBERS).filter_map(|n| sequence_frame(path, n)).any(|frame| frame.exists()),
+        None => path.exists(),
+    }
+}
+
+/// Length and rate of an animated overlay, probed before encoding.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Clip {
+    /// Seconds the animation plays for.
+    pub duration: f64,
+    pub frame_rate: f64,
+    /// Decoder to read it with instead of ffmpeg's default: libvpx, as the
+    /// native VP8 and VP9 decoders drop the alpha channel.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decoder: Option<String>,
+}
+
+impl Clip {
+    /// Probe the animated overlay at `path`. An image sequence has no rate
+    /// of its own and plays at `program_rate`, a frame per frame.
+    pub fn probe(ffprobe: &Path, path: &Path, program_rate: f64) -> Result<Clip> {
+        let media = probe::media_info(ffprobe, path)?;
+        let video = media.require_video()?;
+        let frames = video.frame_count.unwrap_or_else(|| (media.duration * video.frame_rate).round() as u64).max(1);
+        let frame_rate = if is_sequence(path) { program_rate } else { video.frame_rate };
+        let decoder = match video.codec.as_str() {
+            "vp8" => Some("libvpx".to_string()),
+            "vp9" => Some("libvpx-vp9".to_string()),
+            _ => None,
+        };
+        Ok(Clip { duration: frames as f64 / frame_rate, frame_rate, decoder })
+    }
+}
+
+/// How an overlay file is read over the program.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Playback {
+    /// Start an animated overlay over whenever it ends, instead of holding
+    /// its last frame.
+    #[serde(default)]
+    pub looped: bool,
+    /// The animation, once probed; `None` for a still image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clip: Option<Clip>,
+    /// The PNG an SVG overlay is read from instead, once its size is known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raster: Option<Raster>,
+}
+
+impl Playback {
+    /// Whether the overlay at `path` still has to be probed, or sized if it
+    /// is an SVG.
+    pub fn unprobed(&self, path: &Path) -> bool {
+        let unsized_svg = svg::is_svg(path) && self.raster.is_none();
+        (unsized_svg || (self.clip.is_none() && is_animated(path))) && exists(path)
+    }
+}
+
+/// The ffmpeg input options reading the overlay at `path` (or its raster)
+/// for a segment that starts `origin` seconds into the program. An animation is seeked to
+/// the frame showing at `origin`, so it carries on across the joins, and is
+/// looped or trimmed to the segment by the encode's own length.
+pub fn input_args(path: &Path, playback: &Playback, origin: f64) -> Vec<OsString> {
+    let mut args: Vec<OsString> = Vec::new();
+    if let Some(clip) = &playback.clip {
+        if is_sequence(path) {
+            args.extend(["-framerate".into(), clip.frame_rate.to_string().into()]);
+        }
+        let offset = if playback.looped {
+            args.extend(["-stream_loop".into(), "-1".into()]);
+            origin.rem_euclid(clip.duration)
+        } else {
+            // Past its end, show the last frame, which overlay then holds
+            origin.min(clip.duration - 1.0 / clip.frame_rate).max(0.0)
+        };
+        if offset > 0.0 {
+            args.extend(["-ss".into(), format!("{:.6}", offset).into()]);
+        }
+        if let Some(decoder) = &clip.decoder {
+            args.extend(["-c:v".into(), decoder.into()]);
+        }
+    }
+    let path = playback.raster.as_ref().map_or(path, |r| r.path.as_path());
+    args.extend(["-i".into(), ffmpeg::path_arg(path)]);
+    args
+}
+
+/// An extra overlay composited over the main overlay and the layers
+/// declared before it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OverlayLayer {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub settings: OverlaySettings,
+    #[serde(default)]
+    pub playback: Playback,
+}
+
+/// The filter graph compositing the overlay inputs onto input 0 in order,
+/// input 1 with the first of `layers` and so on, in `format` if set (see
+/// overlay's `format` option), for input 0 starting `origin` seconds into
+/// the program. A single layer of default settings gives
+/// [`crate::DEFAULT_FILTER`].
+pub fn filter_graph(layers: &[&OverlaySettings], format: Option<&str>, origin: f64) -> String {
+    let mut graph = String::new();
+    for (index, layer) in layers.iter().enumerate() {
+        let input = index + 1;
+        let base = if index == 0 { "[0:v]".to_string() } else { format!("[layered{}]", index) };
+        graph.push_str(&layer.composite(&base, input, format, origin));
+        if input < layers.len() {
+            graph.push_str(&format!("[layered{}];", input));
+        }
+    }
+    graph
+}
+
+impl OverlaySettings {
+    // Chain compositing input `input` onto `base`, with an unlabeled output.
+    fn composite(&self, base: &str, input: usize, format: Option<&str>, origin: f64) -> String {
+        let mut options = Vec::new();
+        let anchored = self.position != Position::TopLeft || self.margin > 0;
+        match &self.x {
+            Some(x) => options.push(format!("x={}", ffmpeg::escape_filter_value(x))),
+            None if anchored => options.push(format!("x={}", self.position.x(self.margin))),
+            None => {}
+        }
+        match &self.y {
+            Some(y) => options.push(format!("y={}", ffmpeg::escape_filter_value(y))),
+            None if anchored => options.push(format!("y={}", self.position.y(self.margin))),
+            None => {}
+        }
+        if let Some(format) = format {
+            options.push(format!("format={}", format));
+        }
+        if let Some(window) = self.window {
+            options.push(format!("enable={}", ffmpeg::escape_filter_value(&window.enable(origin))));
+        }
+        // Key, fade and scale the overlay on the way in; scale2ref passes
+        // the video through as its second output
+        let mut graph = String::new();
+        let mut overlay = format!("[{}:v]", input);
+        if let Some(key) = &self.key {
+            graph.push_str(&key.filter(&overlay, &format!("[keyed{}];", input)));
+            overlay = format!("[keyed{}]", input);
+        }
+        if let Some(opacity) = self.opacity.filter(|&opacity| opacity < 1.0) {
+            graph.push_str(&format!("{}format=rgba,colorchannelmixer=aa={}[faded{}];", overlay, opacity, input));
+            overlay = format!("[faded{}]", input);
+        }
+        match self.scale {
+            Some(scale) => graph.push_str(&format!(
+                "{}{}scale2ref={}[scaled{}][base{}];[base{}][scaled{}]",
+                overlay,
+                base,
+                scale.options(),
+                input,
+                input,
+                input,
+                input
+            )),
+            None => graph.push_str(&format!("{}{}", base, overlay)),
+        }
+        graph.push_str("overlay");
+        if !options.is_empty() {
+            graph.push('=');
+            graph.push_str(&options.join(":"));
+        }
+        graph
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[OsString]) -> Vec<&str> {
+        args.iter().map(|a| a.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn default_settings_give_the_default_filter() {
+        assert_eq!(filter_graph(&[&OverlaySettings::default()], None, 0.0), crate::DEFAULT_FILTER);
+    }
+
+    #[test]
+    fn anchored_overlay_is_placed_from_its_edges() {
+        let settings = OverlaySettings { position: Position::BottomRight, margin: 24, ..OverlaySettings::default() };
+        assert_eq!(
+            filter_graph(&[&settings], Some("yuva444p10"), 0.0),
+            "[0:v][1:v]overlay=x=W-w-24:y=H-h-24:format=yuva444p10"
+        );
+        let settings = OverlaySettings { position: Position::Center, x: Some("W-w".to_string()), ..settings };
+        assert_eq!(filter_graph(&[&settings], None, 0.0), "[0:v][1:v]overlay=x=W-w:y=(H-h)/2");
+    }
+
+    #[test]
+    fn layers_are_composited_in_order() {
+        let top = OverlaySettings { position: Position::Top, ..OverlaySettings::default() };
+        assert_eq!(
+            filter_graph(&[&OverlaySettings::default(), &top], None, 0.0),
+            "[0:v][1:v]overlay[layered1];[layered1][2:v]overlay=x=(W-w)/2:y=0"
+        );
+    }
+
+    #[test]
+    fn overlay_is_keyed_faded_and_scaled_on_the_way_in() {
+        let settings = OverlaySettings {
+            key: Some("green".parse().unwrap()),
+            opacity: Some(0.5),
+            scale: Some(OverlayScale::Width(0.2)),
+            window: Some(Window { start: 5.0, end: 15.0 }),
+            ..OverlaySettings::default()
+        };
+        assert_eq!(
+            filter_graph(&[&settings], None, 2.0),
+            "[1:v]chromakey=color=green:similarity=0.15:blend=0.05[keyed1];\
+             [keyed1]format=rgba,colorchannelmixer=aa=0.5[faded1];\
+             [faded1][0:v]scale2ref=w=main_w*0.2:h=ow/a[scaled1][base1];\
+             [base1][scaled1]overlay=enable=between(t\\,3.000\\,13.000)"
+        );
+    }
+
+    #[test]
+    fn positions_parse() {
+        assert_eq!("Bottom_Right".parse::<Position>(), Ok(Position::BottomRight));
+        assert_eq!("centre".parse::<Position>(), Ok(Position::Center));
+        assert!("middle".parse::<Position>().is_err());
+    }
+
+    #[test]
+    fn scales_parse_as_a_share_of_the_video() {
+        assert_eq!("0.15W".parse::<OverlayScale>(), Ok(OverlayScale::Width(0.15)));
+        assert_eq!("0.1h".parse::<OverlayScale>(), Ok(OverlayScale::Height(0.1)));
+        for invalid in ["1.5h", "0h", "0.2", "w"] {
+            assert!(invalid.parse::<OverlayScale>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn opacity_parses_from_zero_to_one() {
+        assert_eq!(parse_opacity(" 0.4 "), Ok(0.4));
+        assert!(parse_opacity("1.2").is_err());
+    }
+
+    #[test]
+    fn windows_parse_clock_times() {
+        assert_eq!("00:00:05-00:00:15".parse::<Window>(), Ok(Window { start: 5.0, end: 15.0 }));
+        assert_eq!("1:05.5-120".parse::<Window>(), Ok(Window { start: 65.5, end: 120.0 }));
+        for invalid in ["10-5", "00:61-01:30", "5", "1.5:00-2:00", "a-b"] {
+            assert!(invalid.parse::<Window>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn windows_overlap_the_stretches_they_share() {
+        let window = Window { start: 5.0, end: 15.0 };
+        assert!(window.overlaps(0.0, 6.0) && window.overlaps(14.0, 20.0));
+        assert!(!window.overlaps(15.0, 20.0) && !window.overlaps(0.0, 5.0));
+    }
+
+    #[test]
+    fn keys_parse_with_defaults() {
+        let key: ChromaKey = "0x00B140".parse().unwrap();
+        assert_eq!((key.similarity, key.blend), (DEFAULT_KEY_SIMILARITY, DEFAULT_KEY_BLEND));
+        let key: ChromaKey = "green:0.2:0".parse().unwrap();
+        assert_eq!((key.color.as_str(), key.similarity, key.blend), ("green", 0.2, 0.0));
+        for invalid in ["", ":0.2", "green:0", "green:0.2:1.5", "green:0.2:0.1:0"] {
+            assert!(invalid.parse::<ChromaKey>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn sequences_and_videos_are_animated() {
+        let sequence = Path::new("logo/logo_%04d.png");
+        assert!(is_sequence(sequence) && is_animated(sequence));
+        assert_eq!(sequence_frame(sequence, 1), Some(PathBuf::from("logo/logo_0001.png")));
+        assert_eq!(sequence_frame(Path::new("%d.png"), 7), Some(PathBuf::from("7.png")));
+        assert!(is_animated(Path::new("sting.MOV")));
+        assert!(!is_animated(Path::new("logo.png")));
+    }
+
+    #[test]
+    fn animations_are_seeked_to_the_segment() {
+        let clip = Clip { duration: 4.0, frame_rate: 25.0, decoder: Some("libvpx-vp9".to_string()) };
+        let looped = Playback { looped: true, clip: Some(clip.clone()), raster: None };
+        assert_eq!(
+            args(&input_args(Path::new("sting.webm"), &looped, 10.0)),
+            ["-stream_loop", "-1", "-ss", "2.000000", "-c:v", "libvpx-vp9", "-i", "sting.webm"]
+        );
+        // Held on its last frame past the end
+        let held = Playback { looped: false, clip: Some(clip), raster: None };
+        assert_eq!(
+            args(&input_args(Path::new("sting.webm"), &held, 10.0)),
+            ["-ss", "3.960000", "-c:v", "libvpx-vp9", "-i", "sting.webm"]
+        );
+        assert_eq!(args(&input_args(Path::new("logo.png"), &Playback::default(), 10.0)), ["-i", "logo.png"]);
+    }
+}
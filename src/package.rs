@@ -0,0 +1,248 @@
+use crate::console::{debug, info};
+use crate::format::VideoCodec;
+use crate::openfiles::MaxOpenFiles;
+use crate::rendition::Rendition;
+use crate::segment::Segment;
+use crate::{ffmpeg, openfiles, output, process, DeliveryError, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Length of the packaged media segments, in seconds, unless the job sets
+/// one.
+pub const SEGMENT_DURATION: f64 = 6.0;
+
+/// Streaming or mastering package produced from video output.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Package {
+    /// HTTP Live Streaming: media segments, a variant playlist and a master
+    /// playlist in `hls/`.
+    Hls,
+    /// MPEG-DASH: fragmented MP4 segments and `manifest.mpd` in `dash/`.
+    Dash,
+    /// Interoperable Master Format: MXF track files, a CPL, a PKL and an
+    /// `ASSETMAP.xml` in `imf/`. See [`crate::imf`].
+    Imf,
+    /// Digital Cinema Package: MXF track files, a CPL, a PKL and an
+    /// `ASSETMAP.xml` in `dcp/`. See [`crate::dcp`].
+    Dcp,
+}
+
+impl Package {
+    /// Subdirectory of the output directory the package is written to.
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            Package::Hls => "hls",
+            Package::Dash => "dash",
+            Package::Imf => "imf",
+            Package::Dcp => "dcp",
+        }
+    }
+
+    /// Entry point of the package, relative to its directory.
+    pub fn manifest(self) -> &'static str {
+        match self {
+            Package::Hls => "master.m3u8",
+            Package::Dash => "manifest.mpd",
+            Package::Imf | Package::Dcp => "ASSETMAP.xml",
+        }
+    }
+
+    /// Whether the package is cut into media segments for streaming.
+    pub fn is_streaming(self) -> bool {
+        !matches!(self, Package::Imf | Package::Dcp)
+    }
+
+    /// Fail unless video of `codec` can be packaged.
+    pub fn check_codec(self, codec: VideoCodec) -> std::result::Result<(), String> {
+        match (self, codec) {
+            (Package::Imf, VideoCodec::Prores | VideoCodec::J2k) => Ok(()),
+            (Package::Imf, _) => Err(format!("{} video can't be packaged as IMF; use --codec prores or j2k", codec)),
+            (Package::Dcp, VideoCodec::J2k) => Ok(()),
+            (Package::Dcp, _) => Err(format!("{} video can't be packaged as a DCP; use --delivery-preset dcp-2k", codec)),
+            (_, VideoCodec::H264 | VideoCodec::Hevc | VideoCodec::Av1) => Ok(()),
+            _ => Err(format!("{} video can't be packaged for streaming; use --codec h264, hevc or av1", codec)),
+        }
+    }
+}
+
+impl FromStr for Package {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Package, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "hls" => Ok(Package::Hls),
+            "dash" => Ok(Package::Dash),
+            "imf" => Ok(Package::Imf),
+            "dcp" => Ok(Package::Dcp),
+            _ => Err(format!("invalid package '{}', expected hls, dash, imf or dcp", text)),
+        }
+    }
+}
+
+/// Times (relative to the segment's start) at which `segment` needs a
+/// keyframe so the packager can cut media segments every `interval` seconds
+/// of the source, as a `-force_key_frames` list.
+pub fn keyframe_times(segment: &Segment, interval: f64) -> String {
+    let first = (segment.start / interval).ceil() as u64;
+    let mut times = vec!["0".to_string()];
+    let mut n = first;
+    loop {
+        let time = n as f64 * interval - segment.start;
+        if time >= segment.duration {
+            break;
+        }
+        if time > 0.0 {
+            times.push(format!("{:.6}", time));
+        }
+        n += 1;
+    }
+    times.join(",")
+}
+
+/// The ffmpeg invocation that packages `videos` into `dir` in media segments
+/// of `segment_duration` seconds, without re-encoding. Several videos are
+/// the `renditions` of one ladder: HLS gets a variant playlist per
+/// rendition in a subdirectory named after it, DASH a representation each.
+pub fn package_command(
+    ffmpeg: &Path,
+    package: Package,
+    codec: VideoCodec,
+    segment_duration: f64,
+    renditions: &[Rendition],
+    videos: &[PathBuf],
+    dir: &Path,
+) -> Command {
+    let segment_duration = segment_duration.to_string();
+    let ladder = videos.len() > 1;
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-v", "error"]);
+    for video in videos {
+        cmd.arg("-i").arg(ffmpeg::path_arg(video));
+    }
+    for input in 0..videos.len() {
+        cmd.args(["-map", &format!("{}:v:0", input)]);
+    }
+    cmd.args(["-c", "copy"]);
+    match package {
+        Package::Hls => {
+            cmd.args(["-f", "hls", "-hls_time", &segment_duration, "-hls_playlist_type", "vod"]);
+            // HEVC (for Apple players) and AV1 only go in fragmented MP4
+            let mut segments = if codec != VideoCodec::H264 {
+                cmd.args(["-hls_segment_type", "fmp4", "-hls_fmp4_init_filename", "init.mp4"]);
+                "stream_%05d.m4s".to_string()
+            } else {
+                "stream_%05d.ts".to_string()
+            };
+            let mut playlist = "stream.m3u8".to_string();
+            if ladder {
+                let map: Vec<String> =
+                    renditions.iter().enumerate().map(|(i, r)| format!("v:{},name:{}", i, r.name)).collect();
+                cmd.args(["-var_stream_map", &map.join(" ")]);
+                segments = format!("%v/{}", segments);
+                playlist = format!("%v/{}", playlist);
+            }
+            cmd.arg("-hls_segment_filename").arg(ffmpeg::sequence_pattern(dir, &segments))
+                .args(["-master_pl_name", package.manifest()])
+                .arg("-y").arg(ffmpeg::sequence_pattern(dir, &playlist));
+        }
+        Package::Dash => {
+            if ladder {
+                cmd.args(["-adaptation_sets", "id=0,streams=v"]);
+            }
+            cmd.args(["-f", "dash", "-seg_duration", &segment_duration, "-use_template", "1", "-use_timeline", "1"])
+                .args(["-init_seg_name", "init-$RepresentationID$.m4s"])
+                .args(["-media_seg_name", "chunk-$RepresentationID$-$Number%05d$.m4s"])
+                .arg("-y").arg(ffmpeg::path_arg(&dir.join(package.manifest())));
+        }
+        Package::Imf | Package::Dcp => unreachable!("{:?} packages aren't made by ffmpeg alone", package),
+    }
+    cmd
+}
+
+// Make sure ffmpeg can open all `videos` at once within the open file `limit`.
+fn check_open_files(videos: usize, limit: Option<u64>) -> Result<()> {
+    match limit {
+        Some(limit) if videos as u64 + openfiles::PACKAGE_FILES > limit => Err(DeliveryError::PackageFailed(format!(
+            "{} videos are more than ffmpeg can open at once with the open file limit of {}; \
+             raise it with --max-open-files or ulimit -n",
+            videos, limit
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Package the finished `videos` (one, or one per rendition) as `package`
+/// into its subdirectory of `output_dir`, replacing an older package there.
+/// ffmpeg reads every video at once, so they have to fit into the open file
+/// limit, `max_open_files` applied. Returns the path of the package's
+/// manifest.
+#[allow(clippy::too_many_arguments)]
+pub fn package(
+    ffmpeg: &Path,
+    package: Package,
+    codec: VideoCodec,
+    segment_duration: f64,
+    renditions: &[Rendition],
+    videos: &[PathBuf],
+    output_dir: &Path,
+    max_open_files: Option<MaxOpenFiles>,
+) -> Result<PathBuf> {
+    let _span = tracing::info_span!("package").entered();
+    info!("\n📦 Packaging for {}...", package.dir_name().to_uppercase());
+    let started = Instant::now();
+    let wanted = videos.len() as u64 + openfiles::PACKAGE_FILES;
+    check_open_files(videos.len(), openfiles::limit(max_open_files, wanted))?;
+
+    let dir = output_dir.join(package.dir_name());
+    let staging = output::staging_dir(&dir);
+    output::prepare_staging(&staging)?;
+    let mut cmd = package_command(ffmpeg, package, codec, segment_duration, renditions, videos, &staging);
+    process::contain(&mut cmd);
+    debug!("Command: {}", ffmpeg::display_command(&cmd));
+    let result = cmd.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffmpeg.to_path_buf()),
+        _ => DeliveryError::io("Failed to execute ffmpeg", e),
+    })?;
+    if !result.status.success() {
+        return Err(DeliveryError::PackageFailed(String::from_utf8_lossy(&result.stderr).trim().to_string()));
+    }
+
+    publish(&staging, &dir)?;
+    info!("✅ Packaged in {:.2} seconds", started.elapsed().as_secs_f32());
+    Ok(dir.join(package.manifest()))
+}
+
+/// Move the package built in `staging` to `dir`, replacing an older one.
+pub fn publish(staging: &Path, dir: &Path) -> Result<()> {
+    let publish_err = |e| DeliveryError::io(format!("Failed to move the package into {}", dir.display()), e);
+    if dir.exists() {
+        fs::remove_dir_all(dir).map_err(publish_err)?;
+    }
+    fs::rename(staging, dir).map_err(publish_err)?;
+    output::unhide(dir);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_open_files_fails_when_the_videos_do_not_fit() {
+        let limit = 4 + openfiles::PACKAGE_FILES;
+        assert!(check_open_files(4, None).is_ok());
+        assert!(check_open_files(4, Some(limit)).is_ok());
+        match check_open_files(5, Some(limit)) {
+            Err(DeliveryError::PackageFailed(msg)) => {
+                let expected = format!("5 videos are more than ffmpeg can open at once with the open file limit of {}", limit);
+                assert!(msg.starts_with(&expected), "{}", msg);
+            }
+            other => panic!("expected PackageFailed, got {:?}", other),
+        }
+    }
+}
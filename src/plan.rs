@@ -0,0 +1,502 @@
+use crate::job::{AlphaMode, VfrMode};
+use crate::audio::{AudioCodec, AudioTracks};
+use crate::format::{Container, FrameQuality, IntermediateCodec, OutputFormat, VideoCodec, VideoSettings};
+use crate::naming::FrameNames;
+use crate::burnin::{FrameNumberBurnIn, TextBurnIn, TimecodeBurnIn};
+use crate::color::{OutputColor, SourceColor, Tonemap};
+use crate::crop::Crop;
+use crate::overlay::{OverlayLayer, OverlaySettings, Playback};
+use crate::package::Package;
+use crate::rendition::Rendition;
+use crate::probe::MediaInfo;
+use crate::guides::Guide;
+use crate::hook::WatermarkHook;
+use crate::icc::IccProfile;
+use crate::slate::Slate;
+use crate::segment::Segment;
+use crate::shots::ShotListFormat;
+use crate::stems::StemFormat;
+use crate::timecode::Timecode;
+use crate::twopass::TwoPass;
+use crate::{ffmpeg, worker, DeliveryError, EncodeJob, FrameFormat, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Format version written to plan files; bumped on incompatible changes.
+pub const PLAN_VERSION: u32 = 1;
+
+/// Name of the copy of the plan kept in the segments directory, which lets
+/// `combine` and `resume` work on a previous run's intermediates.
+pub const PLAN_FILE: &str = "plan.json";
+
+/// A computed job plan that can be saved with `--plan-out`, reviewed or
+/// diffed, and executed later (possibly elsewhere) with `--plan-in`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct JobPlan {
+    pub version: u32,
+    pub input: PathBuf,
+    pub overlay: PathBuf,
+    /// Whether the overlay loops, and its length if it is animated.
+    #[serde(default)]
+    pub overlay_playback: Playback,
+    pub output_dir: PathBuf,
+    pub filter: String,
+    /// 3D LUT the video is graded with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lut: Option<PathBuf>,
+    /// Tone mapping of an HDR source to SDR.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tonemap: Option<Tonemap>,
+    /// Colour of the source, for HDR deliveries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_color: Option<SourceColor>,
+    /// Colour the output is converted to and tagged with.
+    #[serde(default)]
+    pub color: OutputColor,
+    /// Black bars cropped off the video.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crop: Option<Crop>,
+    /// Where the built-in overlay goes.
+    #[serde(default)]
+    pub overlay_settings: OverlaySettings,
+    /// Overlays composited over `overlay`, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overlay_layers: Vec<OverlayLayer>,
+    /// Text burned in over the composited frames, and its text as resolved
+    /// when the plan was made.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burn_in: Option<TextBurnIn>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burn_in_text: Option<String>,
+    /// Running timecode burned in over the composited frames.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timecode_burn_in: Option<TimecodeBurnIn>,
+    /// Output frame number burned in over the composited frames.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_number_burn_in: Option<FrameNumberBurnIn>,
+    /// Broadcast guides drawn over the composited frames.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub guides: Vec<Guide>,
+    /// Recipient's name burned in, in screener mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark: Option<TextBurnIn>,
+    /// Slate put before the program, its title and date filled in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slate: Option<Slate>,
+    /// Command the composited frames pass through before encoding, with
+    /// their size and rate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watermark_hook: Option<WatermarkHook>,
+    /// Source duration in seconds.
+    pub duration: f64,
+    pub frame_rate: f64,
+    /// Frames in the input as recorded by its container, unless they are
+    /// converted to a different rate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_frames: Option<u64>,
+    /// Set when the frames are converted to the constant `frame_rate`.
+    #[serde(default)]
+    pub constant_frame_rate: bool,
+    /// Resolution of the delivered picture: the source's, less the bars
+    /// cropped off; 0 if unknown.
+    #[serde(default)]
+    pub width: u32,
+    #[serde(default)]
+    pub height: u32,
+    /// Set when `output_dir` is the parent of the run's timestamped
+    /// subdirectory, which is created when the plan is executed.
+    #[serde(default)]
+    pub timestamped_output: bool,
+    /// Set when the `latest` link next to `output_dir` follows this run.
+    #[serde(default)]
+    pub update_latest: bool,
+    /// Image format the frames are exported in.
+    #[serde(default)]
+    pub frame_format: FrameFormat,
+    /// Whether the job delivers frames or a video.
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    /// Codec of video output.
+    #[serde(default)]
+    pub codec: VideoCodec,
+    /// Lossless codec frame output segments are encoded to, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intermediate: Option<IntermediateCodec>,
+    /// Renditions of video output.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub renditions: Vec<Rendition>,
+    /// Streaming packages made from video output.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub package: Vec<Package>,
+    /// Length of the packaged media segments, if not the default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package_segment: Option<f64>,
+    /// Format the source's audio tracks are extracted to, if they are.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extract_audio: Option<StemFormat>,
+    /// Format of the shot list written next to the output, if one is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shot_list: Option<ShotListFormat>,
+    /// Scene cuts of the source in seconds, if they were detected.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scene_cuts: Vec<f64>,
+    /// Quality, bitrate, preset, profile and level of video output.
+    #[serde(default)]
+    pub video_settings: VideoSettings,
+    /// Two-pass mode of video output, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub two_pass: Option<TwoPass>,
+    /// File name of video output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_name: Option<String>,
+    /// Container of video output, if not the codec's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<Container>,
+    /// Start timecode of video output, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timecode: Option<String>,
+    /// Channels of each of the source's audio streams.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub audio_channels: Vec<u32>,
+    /// Codec of the audio of video output, if not the container's default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<AudioCodec>,
+    /// Audio channels and tracks of MXF output.
+    #[serde(default)]
+    pub audio_tracks: AudioTracks,
+    /// Integrated loudness in LUFS the audio is normalized to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loudness_target: Option<f64>,
+    /// Bits per channel of the frames, if not the format's default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bit_depth: Option<u8>,
+    /// PNG compression level and JPEG quality.
+    #[serde(default)]
+    pub quality: FrameQuality,
+    /// ICC profile embedded in the frames.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icc_profile: Option<IccProfile>,
+    /// What happens to the source's alpha channel.
+    #[serde(default)]
+    pub alpha: AlphaMode,
+    /// How the output frames are named.
+    #[serde(default)]
+    pub frame_names: FrameNames,
+    pub segments: Vec<PlannedSegment>,
+}
+
+/// One segment of a [`JobPlan`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PlannedSegment {
+    pub id: usize,
+    pub start: f64,
+    pub duration: f64,
+    pub expected_frames: u64,
+    /// Exact frame count the segment is cut to, for frame-accurate plans.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frames: Option<u64>,
+    /// Piece of the split source the segment encodes, relative to the
+    /// segments directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<PathBuf>,
+    /// The ffmpeg invocation as planned, for review only; it is rebuilt from
+    /// the plan's settings on the executing machine.
+    pub command: String,
+}
+
+fn planned_segments(job: &EncodeJob, segments: &[Segment], frame_rate: f64) -> Vec<PlannedSegment> {
+    segments
+        .iter()
+        .map(|segment| PlannedSegment {
+            id: segment.id,
+            start: segment.start,
+            duration: segment.duration,
+            expected_frames: segment.frames.unwrap_or((segment.duration * frame_rate).round() as u64),
+            frames: segment.frames,
+            source: segment.source.clone(),
+            command: ffmpeg::display_command(&worker::segment_command(job, segment)),
+        })
+        .collect()
+}
+
+impl JobPlan {
+    pub fn new(job: &EncodeJob, media: &MediaInfo, segments: &[Segment]) -> Result<JobPlan> {
+        let video = media.require_video()?;
+        let frame_rate = job.frame_rate(media);
+        let (width, height) = job.crop.unwrap_or_default().apply((video.width, video.height));
+        Ok(JobPlan {
+            version: PLAN_VERSION,
+            input: job.input.clone(),
+            overlay: job.overlay.clone(),
+            overlay_playback: job.overlay_playback.clone(),
+            output_dir: job.output_dir.clone(),
+            filter: job.filter.clone(),
+            lut: job.lut.clone(),
+            tonemap: job.tonemap.clone(),
+            source_color: job.source_color.clone(),
+            color: job.color.clone(),
+            crop: job.crop,
+            overlay_settings: job.overlay_settings.clone(),
+            overlay_layers: job.overlay_layers.clone(),
+            burn_in: job.burn_in.clone(),
+            burn_in_text: job.burn_in_text()?,
+            timecode_burn_in: job.timecode_burn_in.clone(),
+            frame_number_burn_in: job.frame_number_burn_in.clone(),
+            guides: job.guides.clone(),
+            watermark: job.watermark.clone(),
+            slate: job.slate(),
+            watermark_hook: job.watermark_hook.clone(),
+            duration: media.duration,
+            frame_rate,
+            source_frames: video.frame_count.filter(|_| job.vfr_mode == VfrMode::Warn),
+            constant_frame_rate: matches!(job.vfr_mode, VfrMode::Cfr(_)),
+            width,
+            height,
+            timestamped_output: job.timestamped_output,
+            update_latest: job.update_latest,
+            frame_format: job.frame_format,
+            output_format: job.output_format,
+            codec: job.codec,
+            intermediate: job.intermediate,
+            renditions: job.renditions.clone(),
+            package: job.package.clone(),
+            package_segment: job.package_segment,
+            extract_audio: job.extract_audio,
+            shot_list: job.shot_list,
+            scene_cuts: Vec::new(),
+            video_settings: job.video_settings.clone(),
+            two_pass: job.two_pass,
+            video_name: (job.output_format == OutputFormat::Video).then(|| job.video_name()),
+            container: job.container,
+            timecode: job.timecode.as_ref().and_then(|t| t.resolve(media)),
+            audio_channels: media.audio.iter().map(|a| a.channels).collect(),
+            audio: job.audio,
+            audio_tracks: job.audio_tracks.clone(),
+            loudness_target: job.loudness_target,
+            bit_depth: job.bit_depth,
+            quality: job.quality,
+            icc_profile: job.icc_profile.clone(),
+            alpha: job.alpha.clone(),
+            frame_names: job.frame_names()?,
+            segments: planned_segments(job, segments, frame_rate),
+        })
+    }
+
+    /// Replace the plan's segments, e.g. with the pieces the source was split into.
+    pub fn set_segments(&mut self, job: &EncodeJob, segments: &[Segment]) {
+        self.segments = planned_segments(job, segments, self.frame_rate);
+    }
+
+    pub fn load(path: &Path) -> Result<JobPlan> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| DeliveryError::io(format!("Failed to read plan {}", path.display()), e))?;
+        let plan: JobPlan = serde_json::from_str(&text)
+            .map_err(|e| DeliveryError::Config(format!("Failed to parse plan {}: {}", path.display(), e)))?;
+        if plan.version != PLAN_VERSION {
+            return Err(DeliveryError::Config(format!(
+                "Plan {} has version {}, expected {}",
+                path.display(),
+                plan.version,
+                PLAN_VERSION
+            )));
+        }
+        if plan.segments.is_empty() {
+            return Err(DeliveryError::Config(format!("Plan {} contains no segments", path.display())));
+        }
+        let mut ids: Vec<usize> = plan.segments.iter().map(|s| s.id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        if ids.len() != plan.segments.len() {
+            return Err(DeliveryError::Config(format!("Plan {} has duplicate segment ids", path.display())));
+        }
+        Ok(plan)
+    }
+
+    /// The plan saved in `segments_dir` by a previous run.
+    pub fn load_saved(segments_dir: &Path) -> Result<JobPlan> {
+        let path = segments_dir.join(PLAN_FILE);
+        if !path.exists() {
+            return Err(DeliveryError::Config(format!(
+                "No previous run found: {} does not exist",
+                path.display()
+            )));
+        }
+        JobPlan::load(&path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut json = serde_json::to_string_pretty(self)
+            .map_err(|e| DeliveryError::Config(format!("Failed to serialize plan: {}", e)))?;
+        json.push('\n');
+        fs::write(path, json)
+            .map_err(|e| DeliveryError::io(format!("Failed to write plan {}", path.display()), e))
+    }
+
+    /// Make `job` execute this plan: copy its inputs and filter and use its
+    /// segment boundaries instead of probing.
+    pub fn apply(self, job: &mut EncodeJob) {
+        job.input = self.input.clone();
+        job.overlay = self.overlay.clone();
+        job.overlay_playback = self.overlay_playback.clone();
+        job.output_dir = self.output_dir.clone();
+        job.filter = self.filter.clone();
+        job.lut = self.lut.clone();
+        job.tonemap = self.tonemap.clone();
+        job.source_color = self.source_color.clone();
+        job.color = self.color.clone();
+        job.autocrop = self.crop.is_some();
+        job.crop = self.crop;
+        job.overlay_settings = self.overlay_settings.clone();
+        job.overlay_layers = self.overlay_layers.clone();
+        job.burn_in = self.burn_in.clone();
+        job.timecode_burn_in = self.timecode_burn_in.clone();
+        job.frame_number_burn_in = self.frame_number_burn_in.clone();
+        job.guides = self.guides.clone();
+        job.watermark = self.watermark.clone();
+        job.slate = self.slate.clone();
+        job.watermark_hook = self.watermark_hook.clone();
+        job.frame_format = self.frame_format;
+        job.output_format = self.output_format;
+        job.codec = self.codec;
+        job.intermediate = self.intermediate;
+        job.renditions = self.renditions.clone();
+        job.package = self.package.clone();
+        job.package_segment = self.package_segment;
+        job.extract_audio = self.extract_audio;
+        job.shot_list = self.shot_list;
+        job.video_settings = self.video_settings.clone();
+        job.two_pass = self.two_pass;
+        job.container = self.container;
+        job.audio = self.audio;
+        job.audio_tracks = self.audio_tracks.clone();
+        job.loudness_target = self.loudness_target;
+        job.timecode = self.timecode.clone().map(Timecode::At);
+        job.bit_depth = self.bit_depth;
+        job.quality = self.quality;
+        job.icc_profile = self.icc_profile.clone();
+        job.alpha = self.alpha.clone();
+        job.timestamped_output = self.timestamped_output;
+        job.update_latest = self.update_latest;
+        if self.constant_frame_rate {
+            job.vfr_mode = VfrMode::Cfr(self.frame_rate);
+        }
+        job.plan = Some(self);
+    }
+
+    pub fn segments(&self) -> Vec<Segment> {
+        self.segments
+            .iter()
+            .map(|s| Segment {
+                id: s.id,
+                start: s.start,
+                duration: s.duration,
+                frames: s.frames,
+                source: s.source.clone(),
+            })
+            .collect()
+    }
+
+    /// Container of video output.
+    pub fn container(&self) -> Container {
+        self.container.unwrap_or(self.codec.container())
+    }
+
+    /// Codec of the audio of video output.
+    pub fn audio_codec(&self) -> AudioCodec {
+        self.audio.unwrap_or(AudioCodec::default_for(self.container()))
+    }
+
+    /// Extension of the video file each segment is encoded to (that of the
+    /// first rendition, if several), or `None` if segments write frames.
+    pub fn chunk_extension(&self) -> Option<String> {
+        match (self.output_format, self.intermediate, self.renditions.first()) {
+            (OutputFormat::Frames, None, _) => None,
+            (OutputFormat::Frames, Some(codec), _) => Some(codec.extension().to_string()),
+            (OutputFormat::Video, _, None) => Some(self.codec.extension().to_string()),
+            (OutputFormat::Video, _, Some(rendition)) => Some(format!("{}.{}", rendition.name, self.codec.extension())),
+        }
+    }
+
+    /// Total frames the plan expects across all segments.
+    pub fn expected_frames(&self) -> u64 {
+        self.segments.iter().map(|s| s.expected_frames).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    const PLAN: &str = r#"{
+        "version": 1,
+        "input": "in.mov",
+        "overlay": "overlay.png",
+        "output_dir": "out",
+        "filter": "[0:v][1:v]overlay",
+        "duration": 20.0,
+        "frame_rate": 25.0,
+        "segments": [
+            {"id": 0, "start": 0.0, "duration": 10.0, "expected_frames": 250, "command": "ffmpeg"},
+            {"id": 1, "start": 10.0, "duration": 10.0, "expected_frames": 250, "frames": 250, "command": "ffmpeg"}
+        ]
+    }"#;
+
+    // Write `json` to a plan file of its own and load it.
+    fn load(name: &str, json: &str) -> Result<JobPlan> {
+        let path = env::temp_dir().join(format!("delivery_encoder_plan_{}_{}.json", std::process::id(), name));
+        fs::write(&path, json).unwrap();
+        let plan = JobPlan::load(&path);
+        fs::remove_file(&path).unwrap();
+        plan
+    }
+
+    #[test]
+    fn load_fills_in_defaults() {
+        let plan = load("defaults", PLAN).unwrap();
+        assert_eq!(plan.output_format, OutputFormat::Frames);
+        assert_eq!(plan.frame_format, FrameFormat::default());
+        assert!(!plan.constant_frame_rate && plan.package.is_empty());
+        assert_eq!(plan.expected_frames(), 500);
+        assert_eq!(plan.chunk_extension(), None);
+
+        let segments = plan.segments();
+        assert_eq!(segments.len(), 2);
+        assert_eq!((segments[1].id, segments[1].start, segments[1].frames), (1, 10.0, Some(250)));
+        assert_eq!(segments[0].frames, None);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut plan = load("original", PLAN).unwrap();
+        plan.output_format = OutputFormat::Video;
+        plan.timecode = Some("01:00:00:00".to_string());
+        plan.scene_cuts = vec![4.2, 13.0];
+
+        let path = env::temp_dir().join(format!("delivery_encoder_plan_{}_saved.json", std::process::id()));
+        plan.save(&path).unwrap();
+        let loaded = JobPlan::load(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.output_format, OutputFormat::Video);
+        assert_eq!(loaded.timecode.as_deref(), Some("01:00:00:00"));
+        assert_eq!(loaded.scene_cuts, [4.2, 13.0]);
+        assert_eq!(loaded.chunk_extension(), Some(plan.codec.extension().to_string()));
+        assert_eq!(loaded.container(), plan.codec.container());
+        assert_eq!(loaded.expected_frames(), plan.expected_frames());
+    }
+
+    #[test]
+    fn load_rejects_bad_plans() {
+        assert!(load("version", &PLAN.replace("\"version\": 1", "\"version\": 2")).is_err());
+        assert!(load("unknown", &PLAN.replace("\"version\": 1", "\"version\": 1, \"speed\": 2")).is_err());
+        assert!(load("duplicate", &PLAN.replace("\"id\": 1", "\"id\": 0")).is_err());
+        let empty = PLAN.split("\"segments\"").next().unwrap().to_string() + "\"segments\": []}";
+        assert!(load("empty", &empty).is_err());
+        assert!(load("text", "not a plan").is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::audio::AudioCodec;
+use crate::format::{Container, OutputFormat, VideoCodec};
+use crate::package::Package;
+use crate::timecode::Timecode;
+use crate::EncodeJob;
+use std::fmt;
+use std::str::FromStr;
+
+/// Named delivery specification that sets the codec, profile, bit depth,
+/// container, timecode and package of video output in one go. Options
+/// given alongside it override its choices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryPreset {
+    /// Avid DNxHR LB (offline proxies), 8-bit 4:2:2 MXF.
+    DnxhrLb,
+    /// Avid DNxHR SQ, 8-bit 4:2:2 MXF.
+    DnxhrSq,
+    /// Avid DNxHR HQ, 8-bit 4:2:2 MXF.
+    DnxhrHq,
+    /// Avid DNxHR HQX, 10-bit 4:2:2 MXF.
+    DnxhrHqx,
+    /// Avid DNxHR 444, 10-bit 4:4:4 MXF.
+    Dnxhr444,
+    /// 2K DCP: 12-bit X'Y'Z' JPEG 2000 at 24 fps, flat or scope.
+    Dcp2k,
+    /// 4K DCP: 12-bit X'Y'Z' JPEG 2000 at 24 fps, flat or scope.
+    Dcp4k,
+}
+
+/// Every preset, in the order they are listed.
+pub const PRESETS: &[DeliveryPreset] = &[
+    DeliveryPreset::DnxhrLb,
+    DeliveryPreset::DnxhrSq,
+    DeliveryPreset::DnxhrHq,
+    DeliveryPreset::DnxhrHqx,
+    DeliveryPreset::Dnxhr444,
+    DeliveryPreset::Dcp2k,
+    DeliveryPreset::Dcp4k,
+];
+
+impl DeliveryPreset {
+    /// Profile of the preset's codec.
+    pub fn profile(self) -> &'static str {
+        match self {
+            DeliveryPreset::DnxhrLb => "lb",
+            DeliveryPreset::DnxhrSq => "sq",
+            DeliveryPreset::DnxhrHq => "hq",
+            DeliveryPreset::DnxhrHqx => "hqx",
+            DeliveryPreset::Dnxhr444 => "444",
+            DeliveryPreset::Dcp2k => "cinema2k",
+            DeliveryPreset::Dcp4k => "cinema4k",
+        }
+    }
+
+    /// Configure `job` for the preset: DNxHR video in MXF (at the profile's
+    /// bit depth) with PCM audio starting at the source's timecode, or JPEG
+    /// 2000 in MXF packaged as a DCP.
+    pub fn apply(self, job: &mut EncodeJob) {
+        job.output_format = OutputFormat::Video;
+        job.video_settings.profile = Some(self.profile().to_string());
+        job.container = Some(Container::Mxf);
+        match self {
+            DeliveryPreset::Dcp2k | DeliveryPreset::Dcp4k => {
+                job.codec = VideoCodec::J2k;
+                if !job.package.contains(&Package::Dcp) {
+                    job.package.push(Package::Dcp);
+                }
+            }
+            _ => {
+                job.codec = VideoCodec::Dnxhr;
+                job.audio = Some(AudioCodec::Pcm);
+                job.timecode = Some(Timecode::Source);
+            }
+        }
+    }
+}
+
+impl FromStr for DeliveryPreset {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<DeliveryPreset, String> {
+        let name = text.trim().to_ascii_lowercase();
+        PRESETS.iter().copied().find(|p| p.to_string() == name).ok_or_else(|| {
+            let names: Vec<String> = PRESETS.iter().map(|p| p.to_string()).collect();
+            format!("invalid delivery preset '{}', expected {}", text, names.join(", "))
+        })
+    }
+}
+
+impl fmt::Display for DeliveryPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeliveryPreset::Dcp2k => f.write_str("dcp-2k"),
+            DeliveryPreset::Dcp4k => f.write_str("dcp-4k"),
+            _ => write!(f, "dnxhr-{}", self.profile()),
+        }
+    }
+}
@@ -0,0 +1,525 @@
+use crate::color::{ContentLight, MasteringDisplay};
+use crate::console::{debug, info};
+use crate::{ffmpeg, DeliveryError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Command;
+
+/// Summary of a media file's container and streams.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MediaInfo {
+    /// Container format as reported by ffprobe, e.g. `mov,mp4,m4a,3gp,3g2,mj2`.
+    pub format: String,
+    /// Duration in seconds.
+    pub duration: f64,
+    /// The first video stream.
+    pub video: Option<VideoStream>,
+    pub audio: Vec<AudioStream>,
+    /// Start timecode of the container or its video or timecode track.
+    pub timecode: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct VideoStream {
+    pub index: u32,
+    pub codec: String,
+    pub width: u32,
+    pub height: u32,
+    /// Average frame rate in frames per second.
+    pub frame_rate: f64,
+    /// Frames aren't evenly spaced (screen recordings, phone footage), so
+    /// `frame_rate` is only an average.
+    pub variable_frame_rate: bool,
+    /// Number of frames, if the container records it.
+    pub frame_count: Option<u64>,
+    pub pix_fmt: String,
+    /// Colour tags as ffprobe names them, e.g. `bt2020`, `smpte2084`,
+    /// `bt2020nc` and `tv`, if the stream carries them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_primaries: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_transfer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_space: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_range: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AudioStream {
+    pub index: u32,
+    pub codec: String,
+    pub channels: u32,
+    /// e.g. `stereo` or `5.1(side)`; empty if ffprobe doesn't know.
+    pub channel_layout: String,
+    pub sample_rate: u32,
+}
+
+// The parts of `ffprobe -show_streams -show_format -of json` we use. ffprobe
+// reports most numbers as strings.
+#[derive(Deserialize)]
+struct RawProbe {
+    #[serde(default)]
+    streams: Vec<RawStream>,
+    format: Option<RawFormat>,
+}
+
+#[derive(Deserialize)]
+struct RawFormat {
+    #[serde(default)]
+    format_name: String,
+    duration: Option<String>,
+    #[serde(default)]
+    tags: RawTags,
+}
+
+#[derive(Deserialize, Default)]
+struct RawTags {
+    timecode: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawStream {
+    index: u32,
+    #[serde(default)]
+    codec_type: String,
+    #[serde(default)]
+    codec_name: String,
+    width: Option<u32>,
+    height: Option<u32>,
+    avg_frame_rate: Option<String>,
+    r_frame_rate: Option<String>,
+    pix_fmt: Option<String>,
+    color_primaries: Option<String>,
+    color_transfer: Option<String>,
+    color_space: Option<String>,
+    color_range: Option<String>,
+    channels: Option<u32>,
+    channel_layout: Option<String>,
+    sample_rate: Option<String>,
+    duration: Option<String>,
+    nb_frames: Option<String>,
+    #[serde(default)]
+    tags: RawTags,
+}
+
+/// Parse an ffprobe rate such as `30000/1001` or `25`.
+pub fn parse_rate(rate: &str) -> Option<f64> {
+    let value = match rate.split_once('/') {
+        Some((num, den)) => num.trim().parse::<f64>().ok()? / den.trim().parse::<f64>().ok()?,
+        None => rate.trim().parse().ok()?,
+    };
+    (value.is_finite() && value > 0.0).then_some(value)
+}
+
+// ffprobe's `r_frame_rate` is the lowest rate that represents every timestamp
+// of the stream; for variable frame rate footage it differs from the average.
+fn is_variable_rate(stream: &RawStream) -> bool {
+    match (
+        stream.r_frame_rate.as_deref().and_then(parse_rate),
+        stream.avg_frame_rate.as_deref().and_then(parse_rate),
+    ) {
+        (Some(r), Some(avg)) => (r - avg).abs() / r > 0.01,
+        _ => false,
+    }
+}
+
+// A colour tag ffprobe reports, unless it says the stream doesn't set it.
+fn color_tag(tag: &Option<String>) -> Option<String> {
+    tag.clone().filter(|t| !matches!(t.as_str(), "" | "unknown" | "unspecified" | "reserved"))
+}
+
+/// Build a [`MediaInfo`] from ffprobe's JSON output.
+pub fn parse_media_info(json: &str) -> Result<MediaInfo> {
+    let raw: RawProbe = serde_json::from_str(json)
+        .map_err(|e| DeliveryError::ProbeFailed(format!("Failed to parse ffprobe output: {}", e)))?;
+
+    let video_raw = raw.streams.iter().find(|s| s.codec_type == "video");
+    let video = video_raw.map(|s| VideoStream {
+        index: s.index,
+        codec: s.codec_name.clone(),
+        width: s.width.unwrap_or(0),
+        height: s.height.unwrap_or(0),
+        frame_rate: [&s.avg_frame_rate, &s.r_frame_rate]
+            .into_iter()
+            .flatten()
+            .find_map(|r| parse_rate(r))
+            .unwrap_or(0.0),
+        variable_frame_rate: is_variable_rate(s),
+        frame_count: s.nb_frames.as_deref().and_then(|n| n.parse().ok()),
+        pix_fmt: s.pix_fmt.clone().unwrap_or_default(),
+        color_primaries: color_tag(&s.color_primaries),
+        color_transfer: color_tag(&s.color_transfer),
+        color_space: color_tag(&s.color_space),
+        color_range: color_tag(&s.color_range),
+    });
+    let audio = raw
+        .streams
+        .iter()
+        .filter(|s| s.codec_type == "audio")
+        .map(|s| AudioStream {
+            index: s.index,
+            codec: s.codec_name.clone(),
+            channels: s.channels.unwrap_or(0),
+            channel_layout: s.channel_layout.clone().unwrap_or_default(),
+            sample_rate: s.sample_rate.as_deref().and_then(|r| r.parse().ok()).unwrap_or(0),
+        })
+        .collect();
+
+    // Some containers only carry a duration on the stream
+    let duration = raw
+        .format
+        .as_ref()
+        .and_then(|f| f.duration.as_deref())
+        .or_else(|| video_raw.and_then(|s| s.duration.as_deref()))
+        .and_then(|d| d.trim().parse::<f64>().ok())
+        .ok_or_else(|| DeliveryError::ProbeFailed("ffprobe reported no duration".to_string()))?;
+
+    let timecode = raw
+        .format
+        .as_ref()
+        .and_then(|f| f.tags.timecode.clone())
+        .or_else(|| raw.streams.iter().find_map(|s| s.tags.timecode.clone()));
+
+    Ok(MediaInfo {
+        format: raw.format.map(|f| f.format_name).unwrap_or_default(),
+        duration,
+        video,
+        audio,
+        timecode,
+    })
+}
+
+/// Container and stream information for `input`, as reported by ffprobe.
+pub fn media_info(ffprobe: &Path, input: &Path) -> Result<MediaInfo> {
+    let _span = tracing::info_span!("probe", input = %input.display()).entered();
+    info!("\n⏱ Probing input with FFprobe...");
+    debug!("🔍 FFprobe path: {}", ffprobe.display());
+
+    let output = Command::new(ffprobe)
+        .args(["-v", "error", "-show_streams", "-show_format", "-of", "json"])
+        .arg(ffmpeg::path_arg(input))
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffprobe.to_path_buf()),
+            _ => DeliveryError::io("Failed to execute ffprobe", e),
+        })?;
+
+    if !output.status.success() {
+        let error_msg = String::from_utf8_lossy(&output.stderr);
+        return Err(DeliveryError::ProbeFailed(error_msg.trim().to_string()));
+    }
+
+    let media = parse_media_info(&String::from_utf8_lossy(&output.stdout))?;
+    info!("⏱ Total video duration: {:.2} seconds", media.duration);
+    Ok(media)
+}
+
+// The side data of the first frame, from `ffprobe -show_entries
+// frame=side_data_list -of json`. ffprobe reports the ratios as `n/d`.
+#[derive(Deserialize)]
+struct RawFrames {
+    #[serde(default)]
+    frames: Vec<RawFrame>,
+}
+
+#[derive(Deserialize)]
+struct RawFrame {
+    #[serde(default)]
+    side_data_list: Vec<RawSideData>,
+}
+
+#[derive(Deserialize)]
+struct RawSideData {
+    #[serde(default)]
+    side_data_type: String,
+    #[serde(flatten)]
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+// A side data field, a number or an `n/d` ratio.
+fn side_data_number(side_data: &RawSideData, name: &str) -> Option<f64> {
+    match side_data.fields.get(name)? {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(text) => match text.split_once('/') {
+            Some((num, den)) => Some(num.trim().parse::<f64>().ok()? / den.trim().parse::<f64>().ok()?),
+            None => text.trim().parse().ok(),
+        },
+        _ => None,
+    }
+}
+
+/// The mastering display and content light level metadata of an HDR
+/// `input`, read from the side data of its first frame.
+pub fn hdr_metadata(ffprobe: &Path, input: &Path) -> Result<(Option<MasteringDisplay>, Option<ContentLight>)> {
+    let _span = tracing::info_span!("probe", input = %input.display()).entered();
+    let output = Command::new(ffprobe)
+        .args(["-v", "error", "-select_streams", "v:0", "-read_intervals", "%+#1"])
+        .args(["-show_entries", "frame=side_data_list", "-of", "json"])
+        .arg(ffmpeg::path_arg(input))
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffprobe.to_path_buf()),
+            _ => DeliveryError::io("Failed to execute ffprobe", e),
+        })?;
+
+    if !output.status.success() {
+        let error_msg = String::from_utf8_lossy(&output.stderr);
+        return Err(DeliveryError::ProbeFailed(error_msg.trim().to_string()));
+    }
+
+    let raw: RawFrames = serde_json::from_slice(&output.stdout)
+        .map_err(|e| DeliveryError::ProbeFailed(format!("Failed to parse ffprobe output: {}", e)))?;
+    let side_data = raw.frames.into_iter().next().map(|f| f.side_data_list).unwrap_or_default();
+    let find = |kind: &str| side_data.iter().find(|s| s.side_data_type == kind);
+    let mastering_display = find("Mastering display metadata").and_then(|s| {
+        let xy = |name: &str| {
+            Some([side_data_number(s, &format!("{}_x", name))?, side_data_number(s, &format!("{}_y", name))?])
+        };
+        Some(MasteringDisplay {
+            red: xy("red")?,
+            green: xy("green")?,
+            blue: xy("blue")?,
+            white_point: xy("white_point")?,
+            min_luminance: side_data_number(s, "min_luminance")?,
+            max_luminance: side_data_number(s, "max_luminance")?,
+        })
+    });
+    let content_light = find("Content light level metadata").and_then(|s| {
+        Some(ContentLight {
+            max_content: side_data_number(s, "max_content")? as u32,
+            max_average: side_data_number(s, "max_average")? as u32,
+        })
+    });
+    debug!("🔍 Mastering display: {:?}, content light level: {:?}", mastering_display, content_light);
+    Ok((mastering_display, content_light))
+}
+
+/// Presentation time (seconds from the first packet) and size in bytes of
+/// every packet of the first video stream, in time order. Only the container
+/// is read, nothing is decoded.
+pub fn video_packets(ffprobe: &Path, input: &Path) -> Result<Vec<(f64, u64)>> {
+    let _span = tracing::info_span!("probe", input = %input.display()).entered();
+    info!("\n⏱ Reading packet sizes with FFprobe...");
+
+    let output = Command::new(ffprobe)
+        .args(["-v", "error", "-select_streams", "v:0", "-show_entries", "packet=pts_time,size", "-of", "csv=p=0"])
+        .arg(ffmpeg::path_arg(input))
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffprobe.to_path_buf()),
+            _ => DeliveryError::io("Failed to execute ffprobe", e),
+        })?;
+
+    if !output.status.success() {
+        let error_msg = String::from_utf8_lossy(&output.stderr);
+        return Err(DeliveryError::ProbeFailed(error_msg.trim().to_string()));
+    }
+
+    // Packets without a timestamp ("N/A") are skipped
+    let mut packets: Vec<(f64, u64)> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (time, size) = line.trim().split_once(',')?;
+            Some((time.parse().ok()?, size.parse().ok()?))
+        })
+        .collect();
+    packets.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if let Some(&(first, _)) = packets.first() {
+        for packet in &mut packets {
+            packet.0 -= first;
+        }
+    }
+    debug!("🔍 Read {} video packets", packets.len());
+    Ok(packets)
+}
+
+/// Number of frames in the first video stream of `file`, counted from its
+/// packets (nothing is decoded).
+pub fn count_frames(ffprobe: &Path, file: &Path) -> Result<u64> {
+    let output = Command::new(ffprobe)
+        .args(["-v", "error", "-select_streams", "v:0", "-count_packets", "-show_entries", "stream=nb_read_packets", "-of", "csv=p=0"])
+        .arg(ffmpeg::path_arg(file))
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffprobe.to_path_buf()),
+            _ => DeliveryError::io("Failed to execute ffprobe", e),
+        })?;
+
+    if !output.status.success() {
+        let error_msg = String::from_utf8_lossy(&output.stderr);
+        return Err(DeliveryError::ProbeFailed(error_msg.trim().to_string()));
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.trim()
+        .parse()
+        .map_err(|_| DeliveryError::ProbeFailed(format!("Unexpected frame count '{}' for {}", text.trim(), file.display())))
+}
+
+impl VideoStream {
+    /// Whether the pixel format carries an alpha channel (ProRes 4444,
+    /// PNG/TIFF sequences with transparency, ...).
+    pub fn has_alpha(&self) -> bool {
+        let f = self.pix_fmt.as_str();
+        f.starts_with("yuva") || f.starts_with("gbrap") || f.starts_with("ya")
+            || ["rgba", "bgra", "argb", "abgr"].iter().any(|a| f.starts_with(a))
+    }
+}
+
+impl MediaInfo {
+    /// The video stream, or an error if the input has none.
+    pub fn require_video(&self) -> Result<&VideoStream> {
+        self.video
+            .as_ref()
+            .ok_or_else(|| DeliveryError::ProbeFailed("Input has no video stream".to_string()))
+    }
+
+    /// Human-readable summary, one line per fact.
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Format:      {}", self.format),
+            format!("Duration:    {:.3} s", self.duration),
+        ];
+        match &self.video {
+            Some(v) => {
+                lines.push(format!("Video:       #{} {} {}x{} {}", v.index, v.codec, v.width, v.height, v.pix_fmt));
+                lines.push(format!("Frame rate:  {:.3} fps{} (~{} frames)",
+                    v.frame_rate,
+                    if v.variable_frame_rate { " average, variable" } else { "" },
+                    v.frame_count.unwrap_or((self.duration * v.frame_rate).round() as u64)));
+                let tags = [&v.color_primaries, &v.color_transfer, &v.color_space, &v.color_range];
+                if tags.iter().any(|t| t.is_some()) {
+                    let tags: Vec<&str> = tags.iter().map(|t| t.as_deref().unwrap_or("?")).collect();
+                    lines.push(format!("Color:       {}", tags.join(" / ")));
+                }
+            }
+            None => lines.push("Video:       none".to_string()),
+        }
+        if let Some(timecode) = &self.timecode {
+            lines.push(format!("Timecode:    {}", timecode));
+        }
+        if self.audio.is_empty() {
+            lines.push("Audio:       none".to_string());
+        }
+        for a in &self.audio {
+            let layout = if a.channel_layout.is_empty() {
+                format!("{} ch", a.channels)
+            } else {
+                a.channel_layout.clone()
+            };
+            lines.push(format!("Audio:       #{} {} {} {} Hz", a.index, a.codec, layout, a.sample_rate));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROBE: &str = r#"{
+        "streams": [
+            {
+                "index": 0, "codec_name": "prores", "codec_type": "video", "width": 1920, "height": 1080,
+                "pix_fmt": "yuv422p10le", "color_range": "tv", "color_space": "bt709",
+                "color_transfer": "unknown", "r_frame_rate": "24000/1001", "avg_frame_rate": "24000/1001",
+                "nb_frames": "1438", "duration": "59.976583"
+            },
+            {
+                "index": 1, "codec_name": "pcm_s24le", "codec_type": "audio", "sample_rate": "48000",
+                "channels": 2, "channel_layout": "stereo"
+            },
+            {"index": 2, "codec_type": "data", "tags": {"timecode": "01:00:00:00"}}
+        ],
+        "format": {"format_name": "mov,mp4,m4a,3gp,3g2,mj2", "duration": "60.000000"}
+    }"#;
+
+    #[test]
+    fn parse_media_info_reads_streams_and_format() {
+        let media = parse_media_info(PROBE).unwrap();
+        assert_eq!(media.format, "mov,mp4,m4a,3gp,3g2,mj2");
+        assert_eq!(media.duration, 60.0);
+        assert_eq!(media.timecode.as_deref(), Some("01:00:00:00"));
+
+        let video = media.require_video().unwrap();
+        assert_eq!((video.index, video.codec.as_str(), video.width, video.height), (0, "prores", 1920, 1080));
+        assert!((video.frame_rate - 23.976).abs() < 0.001);
+        assert_eq!(video.frame_count, Some(1438));
+        // Tags ffprobe reports as unknown aren't set
+        assert_eq!(video.color_transfer, None);
+        assert_eq!(video.color_space.as_deref(), Some("bt709"));
+
+        assert_eq!(
+            media.audio,
+            [AudioStream {
+                index: 1,
+                codec: "pcm_s24le".to_string(),
+                channels: 2,
+                channel_layout: "stereo".to_string(),
+                sample_rate: 48000
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_media_info_falls_back_to_the_stream_duration() {
+        let json = r#"{
+            "streams": [{"index": 0, "codec_type": "video", "codec_name": "vp9", "r_frame_rate": "60/1",
+                         "avg_frame_rate": "30/1", "pix_fmt": "yuva420p", "duration": "12.5"}],
+            "format": {"format_name": "matroska,webm", "tags": {"timecode": "10:00:00:00"}}
+        }"#;
+        let media = parse_media_info(json).unwrap();
+        assert_eq!(media.duration, 12.5);
+        assert_eq!(media.timecode.as_deref(), Some("10:00:00:00"));
+        assert!(media.audio.is_empty());
+        assert_eq!(media.require_video().unwrap().frame_rate, 30.0);
+    }
+
+    #[test]
+    fn parse_media_info_needs_a_duration() {
+        assert!(parse_media_info(r#"{"streams": [], "format": {"format_name": "wav"}}"#).is_err());
+        assert!(parse_media_info("ffprobe: not json").is_err());
+        let media = parse_media_info(r#"{"format": {"format_name": "wav", "duration": "3.5"}}"#).unwrap();
+        assert!(media.require_video().is_err());
+    }
+
+    #[test]
+    fn variable_frame_rate_is_flagged() {
+        let video = |r: &str, avg: &str| {
+            let json = format!(
+                r#"{{"streams": [{{"index": 0, "codec_type": "video", "r_frame_rate": "{}", "avg_frame_rate": "{}"}}],
+                    "format": {{"duration": "10"}}}}"#,
+                r, avg
+            );
+            parse_media_info(&json).unwrap().video.unwrap()
+        };
+        assert!(video("60/1", "30/1").variable_frame_rate);
+        assert!(!video("24000/1001", "24000/1001").variable_frame_rate);
+        // Within 1% of each other
+        assert!(!video("25/1", "2497/100").variable_frame_rate);
+        assert!(!video("0/0", "25/1").variable_frame_rate);
+    }
+
+    #[test]
+    fn alpha_is_read_from_the_pixel_format() {
+        let video = |pix_fmt: &str| {
+            let json = PROBE.replace("yuv422p10le", pix_fmt);
+            parse_media_info(&json).unwrap().video.unwrap()
+        };
+        for alpha in ["yuva444p10le", "gbrap12le", "ya16be", "rgba64le", "bgra"] {
+            assert!(video(alpha).has_alpha(), "{}", alpha);
+        }
+        for opaque in ["yuv422p10le", "gbrp", "rgb48le", "gray"] {
+            assert!(!video(opaque).has_alpha(), "{}", opaque);
+        }
+    }
+
+    #[test]
+    fn parse_rate_takes_fractions_and_numbers() {
+        assert_eq!(parse_rate("25"), Some(25.0));
+        assert_eq!(parse_rate("30000/1001"), Some(30000.0 / 1001.0));
+        assert_eq!(parse_rate("0/0"), None);
+        assert_eq!(parse_rate("0"), None);
+        assert_eq!(parse_rate("N/A"), None);
+    }
+}
@@ -0,0 +1,274 @@
+use std::process::{Child, Command};
+
+/// Set up `cmd` so the process it spawns cannot outlive the encoder, even if
+/// the encoder is killed outright (e.g. by a render farm scheduler).
+///
+/// On Unix the process joins the encoder's dedicated process group (see
+/// [`kill_group`]), which a small guard process leads: it kills the group
+/// when the encoder exits, however it dies, and the interrupt handler kills
+/// the group on Ctrl+C or SIGTERM. On Linux the kernel also sends the child
+/// SIGKILL when the spawning thread exits, as a backstop.
+pub fn contain(cmd: &mut Command) {
+    #[cfg(unix)]
+    if let Some(group) = group::id() {
+        use std::os::unix::process::CommandExt;
+        // SAFETY: setpgid is async-signal-safe. If the group is gone (after
+        // an interrupt killed it) the process stays in the encoder's group.
+        unsafe {
+            cmd.pre_exec(move || {
+                libc::setpgid(0, group);
+                Ok(())
+            });
+        }
+    }
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::process::CommandExt;
+        let parent = std::process::id() as libc::pid_t;
+        // SAFETY: only async-signal-safe libc calls run between fork and exec.
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                // The encoder may have died before prctl took effect
+                if libc::getppid() != parent {
+                    libc::_exit(1);
+                }
+                Ok(())
+            });
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = cmd;
+}
+
+/// Kill every process [`contain`] put in the encoder's process group (Unix).
+pub fn kill_group() {
+    #[cfg(unix)]
+    if let Some(group) = group::started() {
+        // SAFETY: killpg only sends a signal.
+        unsafe {
+            libc::killpg(group, libc::SIGKILL);
+        }
+    }
+}
+
+#[cfg(unix)]
+mod group {
+    use crate::console::warning;
+    use std::io;
+    use std::sync::OnceLock;
+
+    // Process group id of the guard, or None if it couldn't be started.
+    static GROUP: OnceLock<Option<libc::pid_t>> = OnceLock::new();
+
+    /// The encoder's process group for its children, started on first use.
+    pub fn id() -> Option<libc::pid_t> {
+        *GROUP.get_or_init(|| {
+            let group = start_guard();
+            if group.is_none() {
+                warning!("⚠️ Failed to create a process group; FFmpeg may outlive the encoder if it is killed");
+            }
+            group
+        })
+    }
+
+    /// The group, if a process has been put in it yet.
+    pub fn started() -> Option<libc::pid_t> {
+        GROUP.get().copied().flatten()
+    }
+
+    // Fork the guard that leads the group, so it exists for as long as the
+    // encoder runs. It waits for the encoder's end of a pipe to close, which
+    // the kernel does when the encoder exits, then kills the group and itself.
+    fn start_guard() -> Option<libc::pid_t> {
+        let mut fds = [0; 2];
+        // SAFETY: the forked guard only makes async-signal-safe calls before
+        // it exits.
+        unsafe {
+            if libc::pipe(fds.as_mut_ptr()) == -1 {
+                return None;
+            }
+            let [read_end, write_end] = fds;
+            // ffmpeg must not hold the encoder's end open
+            libc::fcntl(write_end, libc::F_SETFD, libc::FD_CLOEXEC);
+            match libc::fork() {
+                -1 => {
+                    libc::close(read_end);
+                    libc::close(write_end);
+                    None
+                }
+                0 => {
+                    libc::close(write_end);
+                    libc::setpgid(0, 0);
+                    let mut byte = 0u8;
+                    while libc::read(read_end, &mut byte as *mut u8 as *mut libc::c_void, 1) == -1
+                        && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted
+                    {}
+                    libc::kill(0, libc::SIGKILL);
+                    libc::_exit(0)
+                }
+                guard => {
+                    libc::close(read_end);
+                    // Also set here, so the group exists once fork returns
+                    libc::setpgid(guard, guard);
+                    Some(guard)
+                }
+            }
+        }
+    }
+}
+
+/// Niceness ffmpeg runs at with [`lower_priority`] on Unix.
+#[cfg(unix)]
+const BACKGROUND_NICENESS: libc::c_int = 10;
+
+/// Run the process `cmd` spawns at reduced scheduling priority (nice 10 on
+/// Unix, below normal on Windows) so the machine stays responsive.
+pub fn lower_priority(cmd: &mut Command) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // SAFETY: nice is async-signal-safe. Failing to lower the priority is
+        // harmless, so errors are ignored.
+        unsafe {
+            cmd.pre_exec(|| {
+                libc::nice(BACKGROUND_NICENESS);
+                Ok(())
+            });
+        }
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        use windows_sys::Win32::System::Threading::BELOW_NORMAL_PRIORITY_CLASS;
+        cmd.creation_flags(BELOW_NORMAL_PRIORITY_CLASS);
+    }
+    #[cfg(not(any(unix, windows)))]
+    let _ = cmd;
+}
+
+/// Whether [`pin`] and [`pin_spawned`] can restrict processes to given CPUs
+/// on this platform.
+pub const PINNING_SUPPORTED: bool = cfg!(any(target_os = "linux", windows));
+
+/// CPUs this process may run on, in ascending order.
+pub fn available_cpus() -> Vec<usize> {
+    #[cfg(target_os = "linux")]
+    {
+        // SAFETY: sched_getaffinity only writes into the set we pass.
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) == 0 {
+                let cpus: Vec<usize> = (0..libc::CPU_SETSIZE as usize).filter(|&c| libc::CPU_ISSET(c, &set)).collect();
+                if !cpus.is_empty() {
+                    return cpus;
+                }
+            }
+        }
+    }
+    (0..std::thread::available_parallelism().map_or(1, |n| n.get())).collect()
+}
+
+/// Restrict the process `cmd` spawns to `cpus` (Linux). Every thread ffmpeg
+/// starts inherits the mask. Does nothing if `cpus` is empty.
+pub fn pin(cmd: &mut Command, cpus: &[usize]) {
+    #[cfg(target_os = "linux")]
+    if !cpus.is_empty() {
+        use std::os::unix::process::CommandExt;
+        // SAFETY: the set is built here; only sched_setaffinity runs after fork.
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            for &cpu in cpus {
+                libc::CPU_SET(cpu, &mut set);
+            }
+            cmd.pre_exec(move || {
+                if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (cmd, cpus);
+}
+
+/// Restrict a spawned process to `cpus` (Windows, CPUs 0-63 only). Does
+/// nothing if `cpus` is empty.
+pub fn pin_spawned(child: &Child, cpus: &[usize]) {
+    #[cfg(windows)]
+    if !cpus.is_empty() {
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::System::Threading::SetProcessAffinityMask;
+        let mask = cpus.iter().filter(|&&c| c < usize::BITS as usize).fold(0usize, |m, &c| m | 1 << c);
+        // SAFETY: the handle stays valid for the duration of the call.
+        if unsafe { SetProcessAffinityMask(child.as_raw_handle() as _, mask) } == 0 {
+            crate::console::warning!("⚠️ Failed to pin FFmpeg process {} to CPUs {:?}", child.id(), cpus);
+        }
+    }
+    #[cfg(not(windows))]
+    let _ = (child, cpus);
+}
+
+/// Tie a spawned process to the encoder's lifetime. On Windows it joins a Job
+/// Object that is closed, killing every member, when the encoder exits.
+pub fn adopt(child: &Child) {
+    #[cfg(windows)]
+    job_object::assign(child);
+    #[cfg(not(windows))]
+    let _ = child;
+}
+
+#[cfg(windows)]
+mod job_object {
+    use crate::console::warning;
+    use std::os::windows::io::AsRawHandle;
+    use std::process::Child;
+    use std::sync::OnceLock;
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+
+    // The handle is deliberately never closed: Windows closes it when the
+    // encoder exits, which is what kills the members.
+    static JOB: OnceLock<Option<usize>> = OnceLock::new();
+
+    fn create() -> Option<usize> {
+        // SAFETY: plain Win32 calls on a handle we own.
+        unsafe {
+            let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if job.is_null() {
+                return None;
+            }
+            let mut info = JOBOBJECT_EXTENDED_LIMIT_INFORMATION::default();
+            info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            let ok = SetInformationJobObject(
+                job,
+                JobObjectExtendedLimitInformation,
+                &info as *const _ as *const core::ffi::c_void,
+                std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            );
+            if ok == 0 {
+                CloseHandle(job);
+                return None;
+            }
+            Some(job as usize)
+        }
+    }
+
+    pub fn assign(child: &Child) {
+        let Some(job) = *JOB.get_or_init(create) else {
+            warning!("⚠️ Failed to create a Job Object; FFmpeg may outlive the encoder if it is killed");
+            return;
+        };
+        // SAFETY: both handles stay valid for the duration of the call.
+        let ok = unsafe { AssignProcessToJobObject(job as HANDLE, child.as_raw_handle() as HANDLE) };
+        if ok == 0 {
+            warning!("⚠️ Failed to add FFmpeg process {} to the Job Object", child.id());
+        }
+    }
+}
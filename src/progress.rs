@@ -0,0 +1,341 @@
+use crate::console::{self, info, Verbosity};
+use crate::events;
+use crate::segment::Segment;
+use crate::units::format_size;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Minimum time between progress lines when stdout is not a terminal.
+const LINE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A snapshot of one ffmpeg process's `-progress` output.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProgressUpdate {
+    /// Frames written so far.
+    pub frame: u64,
+    /// Position in the output timeline, in seconds.
+    pub out_time: f64,
+    /// ffmpeg reported `progress=end`.
+    pub done: bool,
+}
+
+/// Incremental parser for the `key=value` blocks ffmpeg writes with
+/// `-progress pipe:1`. Each block ends with a `progress=continue|end` line.
+#[derive(Debug, Default)]
+pub struct ProgressParser {
+    current: ProgressUpdate,
+}
+
+impl ProgressParser {
+    /// Feed one line; returns a complete update when a block ends.
+    pub fn feed(&mut self, line: &str) -> Option<ProgressUpdate> {
+        let (key, value) = line.trim().split_once('=')?;
+        match key {
+            "frame" => self.current.frame = value.trim().parse().unwrap_or(self.current.frame),
+            // Both keys are in microseconds; out_time_ms is misnamed upstream.
+            "out_time_us" | "out_time_ms" => {
+                if let Ok(us) = value.trim().parse::<i64>() {
+                    self.current.out_time = us.max(0) as f64 / 1_000_000.0;
+                }
+            }
+            "progress" => {
+                self.current.done = value.trim() == "end";
+                return Some(self.current);
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Aggregates per-segment progress into overall completion.
+#[derive(Debug, Clone)]
+pub struct Tracker {
+    durations: Vec<f64>,
+    updates: Vec<ProgressUpdate>,
+}
+
+impl Tracker {
+    /// `durations[i]` is the expected length of segment `i` in seconds.
+    pub fn new(durations: Vec<f64>) -> Tracker {
+        let updates = vec![ProgressUpdate::default(); durations.len()];
+        Tracker { durations, updates }
+    }
+
+    pub fn update(&mut self, id: usize, update: ProgressUpdate) {
+        if let Some(slot) = self.updates.get_mut(id) {
+            *slot = update;
+        }
+    }
+
+    pub fn latest(&self, id: usize) -> ProgressUpdate {
+        self.updates.get(id).copied().unwrap_or_default()
+    }
+
+    /// Completion of one segment, 0.0 to 100.0.
+    pub fn segment_percent(&self, id: usize) -> f64 {
+        let update = self.latest(id);
+        let duration = self.durations.get(id).copied().unwrap_or(0.0);
+        if update.done {
+            100.0
+        } else if duration > 0.0 {
+            (update.out_time / duration * 100.0).clamp(0.0, 100.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Completion of the whole job weighted by segment length, 0.0 to 100.0.
+    pub fn overall_percent(&self) -> f64 {
+        let total: f64 = self.durations.iter().sum();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        let done: f64 = (0..self.durations.len())
+            .map(|id| self.segment_percent(id) / 100.0 * self.durations[id])
+            .sum();
+        done / total * 100.0
+    }
+
+    /// Frames written across all segments.
+    pub fn frames(&self) -> u64 {
+        self.updates.iter().map(|u| u.frame).sum()
+    }
+}
+
+/// Live progress display: one bar per segment plus an overall bar with ETA on
+/// a terminal, periodic summary lines otherwise (logs, CI, pipes).
+pub struct ProgressView {
+    bars: Option<Bars>,
+    started: Instant,
+    last_line: Instant,
+}
+
+struct Bars {
+    multi: MultiProgress,
+    overall: ProgressBar,
+    /// Indexed by segment id.
+    segments: Vec<Option<ProgressBar>>,
+}
+
+// Bars count milliseconds of output timeline so indicatif's rate estimate
+// yields a meaningful ETA.
+fn millis(seconds: f64) -> u64 {
+    (seconds * 1000.0).max(0.0) as u64
+}
+
+static BARS: AtomicBool = AtomicBool::new(true);
+
+/// Allow progress bars, or print lines even on a terminal, e.g. while
+/// several jobs share it.
+pub fn set_bars(bars: bool) {
+    BARS.store(bars, Ordering::Relaxed);
+}
+
+// Whether progress is drawn as bars rather than printed as lines.
+fn bars_wanted() -> bool {
+    BARS.load(Ordering::Relaxed)
+        && std::io::stdout().is_terminal()
+        && !events::enabled()
+        && !console::plain()
+        && console::verbosity() > Verbosity::Quiet
+}
+
+impl ProgressView {
+    pub fn new(segments: &[Segment]) -> ProgressView {
+        let now = Instant::now();
+        let bars = bars_wanted().then(|| Bars::new(segments));
+        ProgressView { bars, started: now, last_line: now }
+    }
+
+    /// Refresh the display after segment `id` reported progress.
+    pub fn update(&mut self, tracker: &Tracker, id: usize) {
+        match &self.bars {
+            Some(bars) => {
+                let update = tracker.latest(id);
+                if let Some(Some(bar)) = bars.segments.get(id) {
+                    bar.set_position(millis(update.out_time).min(bar.length().unwrap_or(0)));
+                    bar.set_message(format!("frame {}", update.frame));
+                }
+                let total = bars.overall.length().unwrap_or(0) as f64;
+                bars.overall.set_position((tracker.overall_percent() / 100.0 * total) as u64);
+                bars.overall.set_message(format!("{} frames", tracker.frames()));
+            }
+            None => {
+                if self.last_line.elapsed() >= LINE_INTERVAL {
+                    self.print_line(tracker);
+                    self.last_line = Instant::now();
+                }
+            }
+        }
+    }
+
+    fn print_line(&self, tracker: &Tracker) {
+        let percent = tracker.overall_percent();
+        let eta = if percent > 0.0 {
+            let elapsed = self.started.elapsed().as_secs_f64();
+            format!("{:.0}s", elapsed * (100.0 - percent) / percent)
+        } else {
+            "unknown".to_string()
+        };
+        let per_segment: Vec<String> = (0..tracker.durations.len())
+            .filter(|&id| tracker.durations[id] > 0.0)
+            .map(|id| format!("#{} {:.0}%", id, tracker.segment_percent(id)))
+            .collect();
+        info!("📊 Overall {:.1}% ({} frames, ETA {}) | {}",
+            percent, tracker.frames(), eta, per_segment.join(" "));
+    }
+
+    /// Mark segment `id` as finished.
+    pub fn finish_segment(&mut self, id: usize, success: bool) {
+        if let Some(Some(bar)) = self.bars.as_ref().and_then(|b| b.segments.get(id)) {
+            if success {
+                bar.finish_with_message("done");
+            } else {
+                bar.abandon_with_message("failed");
+            }
+        }
+    }
+
+    /// Remove the bars and restore plain console output.
+    pub fn finish(self) {
+        if let Some(bars) = self.bars {
+            bars.overall.finish();
+            console::detach();
+            let _ = bars.multi.clear();
+        }
+    }
+}
+
+impl Bars {
+    fn new(segments: &[Segment]) -> Bars {
+        let multi = MultiProgress::with_draw_target(ProgressDrawTarget::stdout());
+        let segment_style = ProgressStyle::with_template("{prefix:>4} [{bar:30.cyan/blue}] {percent:>3}% {msg}")
+            .unwrap()
+            .progress_chars("=> ");
+        let overall_style = ProgressStyle::with_template(
+            "Overall [{elapsed_precise}] [{wide_bar:.green}] {percent:>3}% {msg} ETA {eta}",
+        )
+        .unwrap()
+        .progress_chars("=> ");
+
+        let mut bars: Vec<Option<ProgressBar>> = Vec::new();
+        for segment in segments {
+            let bar = multi.add(ProgressBar::new(millis(segment.duration).max(1)));
+            bar.set_style(segment_style.clone());
+            bar.set_prefix(format!("#{}", segment.id));
+            if bars.len() <= segment.id {
+                bars.resize(segment.id + 1, None);
+            }
+            bars[segment.id] = Some(bar);
+        }
+
+        let total: f64 = segments.iter().map(|s| s.duration).sum();
+        let overall = multi.add(ProgressBar::new(millis(total).max(1)));
+        overall.set_style(overall_style);
+        overall.enable_steady_tick(Duration::from_millis(500));
+
+        console::attach(multi.clone());
+        Bars { multi, overall, segments: bars }
+    }
+}
+
+/// Progress of copying files: a byte bar on a terminal, periodic summary
+/// lines otherwise.
+pub struct CopyProgress {
+    bar: Option<ProgressBar>,
+    total: u64,
+    copied: u64,
+    last_line: Instant,
+}
+
+impl CopyProgress {
+    pub fn new(total_bytes: u64) -> CopyProgress {
+        let bar = bars_wanted().then(|| {
+            let bar = ProgressBar::with_draw_target(Some(total_bytes), ProgressDrawTarget::stdout());
+            bar.set_style(
+                ProgressStyle::with_template("Copying [{wide_bar:.green}] {bytes}/{total_bytes} {bytes_per_sec} ETA {eta}")
+                    .unwrap()
+                    .progress_chars("=> "),
+            );
+            bar
+        });
+        CopyProgress { bar, total: total_bytes, copied: 0, last_line: Instant::now() }
+    }
+
+    /// Record `bytes` more copied.
+    pub fn inc(&mut self, bytes: u64) {
+        self.copied += bytes;
+        match &self.bar {
+            Some(bar) => bar.inc(bytes),
+            None => {
+                if self.last_line.elapsed() >= LINE_INTERVAL {
+                    info!("📊 Copied {} of {}", format_size(self.copied), format_size(self.total));
+                    self.last_line = Instant::now();
+                }
+            }
+        }
+    }
+
+    pub fn finish(self) {
+        if let Some(bar) = self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parser_reports_each_block() {
+        let mut parser = ProgressParser::default();
+        assert_eq!(parser.feed("frame=10"), None);
+        assert_eq!(parser.feed("fps=25.00"), None);
+        assert_eq!(parser.feed("out_time_us=400000"), None);
+        assert_eq!(parser.feed("progress=continue"), Some(ProgressUpdate { frame: 10, out_time: 0.4, done: false }));
+
+        // ffmpeg reports a negative time before the first frame
+        assert_eq!(parser.feed("frame=20"), None);
+        assert_eq!(parser.feed("out_time_ms=-5"), None);
+        assert_eq!(parser.feed(" progress=end "), Some(ProgressUpdate { frame: 20, out_time: 0.0, done: true }));
+    }
+
+    #[test]
+    fn parser_keeps_the_last_value_on_junk() {
+        let mut parser = ProgressParser::default();
+        parser.feed("frame=10");
+        assert_eq!(parser.feed("frame=N/A"), None);
+        assert_eq!(parser.feed("not a progress line"), None);
+        assert_eq!(parser.feed("progress=continue").map(|u| u.frame), Some(10));
+    }
+
+    #[test]
+    fn tracker_weights_segments_by_length() {
+        let mut tracker = Tracker::new(vec![2.0, 6.0]);
+        assert_eq!(tracker.overall_percent(), 0.0);
+        tracker.update(0, ProgressUpdate { frame: 25, out_time: 1.0, done: false });
+        assert_eq!(tracker.segment_percent(0), 50.0);
+        assert_eq!(tracker.overall_percent(), 12.5);
+        tracker.update(1, ProgressUpdate { frame: 150, out_time: 5.9, done: true });
+        assert_eq!(tracker.segment_percent(1), 100.0);
+        assert_eq!(tracker.overall_percent(), 87.5);
+        assert_eq!(tracker.frames(), 175);
+
+        // Unknown segments are ignored
+        tracker.update(2, ProgressUpdate { frame: 1, out_time: 1.0, done: true });
+        assert_eq!(tracker.frames(), 175);
+        assert_eq!(tracker.segment_percent(2), 0.0);
+    }
+
+    #[test]
+    fn tracker_caps_segments_running_past_their_length() {
+        let mut tracker = Tracker::new(vec![2.0]);
+        tracker.update(0, ProgressUpdate { frame: 60, out_time: 2.4, done: false });
+        assert_eq!(tracker.overall_percent(), 100.0);
+        assert_eq!(Tracker::new(vec![0.0]).overall_percent(), 0.0);
+    }
+}
@@ -0,0 +1,364 @@
+use crate::console::{debug, info};
+use crate::probe::MediaInfo;
+use crate::{ffmpeg, probe, process, timecode, DeliveryError, Result};
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+/// What a QC finding is about.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Check {
+    /// Audio quieter than the threshold.
+    Silence,
+    /// Black frames.
+    Black,
+    /// Frames that don't change.
+    Freeze,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Check::Silence => "silence",
+            Check::Black => "black",
+            Check::Freeze => "freeze",
+        })
+    }
+}
+
+/// One problem QC found in a file: a stretch of time, in seconds.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub check: Check,
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Finding {
+    /// The finding with its start and end as timecodes at `fps`.
+    pub fn describe(&self, fps: f64) -> String {
+        format!(
+            "{} from {} to {} ({:.2}s)",
+            self.check,
+            timecode::from_seconds(self.start, fps),
+            timecode::from_seconds(self.end, fps),
+            self.end - self.start
+        )
+    }
+}
+
+/// Audio below `threshold` dBFS for at least `duration` seconds is flagged
+/// as silence.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SilenceCheck {
+    pub threshold: f64,
+    pub duration: f64,
+}
+
+/// Threshold of [`SilenceCheck`] unless the job sets one, in dBFS.
+pub const SILENCE_THRESHOLD: f64 = -60.0;
+
+/// Black frames lasting at least `duration` seconds are flagged, except
+/// in the first `head` and last `tail` seconds where black is expected.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BlackCheck {
+    pub duration: f64,
+    pub head: f64,
+    pub tail: f64,
+}
+
+/// Frames that stay the same for at least `duration` seconds are flagged
+/// as frozen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FreezeCheck {
+    pub duration: f64,
+}
+
+/// Noise level, in dB, below which freezedetect counts two frames as the
+/// same: enough to ignore encoder noise on a still picture.
+const FREEZE_NOISE: f64 = -60.0;
+
+/// Share of a frame's pixels that must be black (below 10% luma) for
+/// blackdetect to count the frame as black.
+const BLACK_PICTURE_RATIO: f64 = 0.98;
+
+/// The QC checks run on a finished delivery; `None` skips a check.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Checks {
+    pub silence: Option<SilenceCheck>,
+    pub black: Option<BlackCheck>,
+    pub freeze: Option<FreezeCheck>,
+    /// Fail the job when a check finds anything.
+    pub fail: bool,
+}
+
+impl Checks {
+    /// Whether any check is enabled.
+    pub fn any(&self) -> bool {
+        self.silence.is_some() || self.black.is_some() || self.freeze.is_some()
+    }
+}
+
+/// What QC found in one file.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// Frame rate of the file, for timecodes; 0 without video.
+    pub frame_rate: f64,
+    pub findings: Vec<Finding>,
+}
+
+/// Parse an audio level such as `-60dB`, `-60 dBFS` or `-60`.
+pub fn parse_level(text: &str) -> std::result::Result<f64, String> {
+    let lower = text.trim().to_ascii_lowercase();
+    let number = lower.strip_suffix("dbfs").or_else(|| lower.strip_suffix("db")).unwrap_or(&lower);
+    match number.trim().parse::<f64>() {
+        Ok(level) if level < 0.0 && level.is_finite() => Ok(level),
+        _ => Err(format!("invalid audio level '{}', expected a negative level in dB, e.g. -60dB", text)),
+    }
+}
+
+/// The ffmpeg invocation running silencedetect over the `streams` audio
+/// streams of `file` mixed together, so silence is only flagged where every
+/// channel is quiet.
+pub fn silence_command(ffmpeg: &Path, file: &Path, streams: usize, check: SilenceCheck) -> Command {
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-hide_banner", "-nostats", "-i"]).arg(ffmpeg::path_arg(file));
+    let detect = format!("silencedetect=noise={}dB:duration={}", check.threshold, check.duration);
+    if streams > 1 {
+        let inputs: String = (0..streams).map(|i| format!("[0:a:{}]", i)).collect();
+        cmd.arg("-filter_complex").arg(format!("{}amerge=inputs={},{}", inputs, streams, detect));
+    } else {
+        cmd.args(["-map", "0:a:0", "-af", &detect]);
+    }
+    cmd.args(["-vn", "-f", "null", "-"]);
+    cmd
+}
+
+/// The ffmpeg invocation running blackdetect over the video of `file`.
+pub fn black_command(ffmpeg: &Path, file: &Path, check: BlackCheck) -> Command {
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-hide_banner", "-nostats", "-i"]).arg(ffmpeg::path_arg(file));
+    cmd.args(["-map", "0:v:0", "-vf"]);
+    cmd.arg(format!("blackdetect=d={}:pic_th={}:pix_th=0.10", check.duration, BLACK_PICTURE_RATIO));
+    cmd.args(["-an", "-f", "null", "-"]);
+    cmd
+}
+
+/// The ffmpeg invocation running freezedetect over the video of `file`.
+pub fn freeze_command(ffmpeg: &Path, file: &Path, check: FreezeCheck) -> Command {
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-hide_banner", "-nostats", "-i"]).arg(ffmpeg::path_arg(file));
+    cmd.args(["-map", "0:v:0", "-vf"]);
+    cmd.arg(format!("freezedetect=n={}dB:d={}", FREEZE_NOISE, check.duration));
+    cmd.args(["-an", "-f", "null", "-"]);
+    cmd
+}
+
+/// Pair up the times following each `start_key` and `end_key` in a
+/// detection filter's log. A stretch still open at the end of the log ends
+/// at `duration`.
+pub fn parse_intervals(log: &str, start_key: &str, end_key: &str, duration: f64) -> Vec<(f64, f64)> {
+    let value = |rest: &str| -> Option<f64> {
+        let rest = rest.trim_start();
+        let end = rest.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-')).unwrap_or(rest.len());
+        rest[..end].parse().ok()
+    };
+    let mut marks: Vec<(usize, bool, f64)> = Vec::new();
+    for (key, is_start) in [(start_key, true), (end_key, false)] {
+        for (at, _) in log.match_indices(key) {
+            if let Some(time) = value(&log[at + key.len()..]) {
+                marks.push((at, is_start, time));
+            }
+        }
+    }
+    marks.sort_by_key(|&(at, ..)| at);
+
+    let mut intervals = Vec::new();
+    let mut open = None;
+    for (_, is_start, time) in marks {
+        match (is_start, open) {
+            (true, _) => open = Some(time),
+            (false, Some(start)) => {
+                intervals.push((start, time));
+                open = None;
+            }
+            (false, None) => {}
+        }
+    }
+    if let Some(start) = open {
+        intervals.push((start, duration));
+    }
+    intervals
+}
+
+/// Run the enabled `checks` on `file`.
+pub fn inspect(ffmpeg: &Path, ffprobe: &Path, file: &Path, checks: &Checks) -> Result<Report> {
+    let _span = tracing::info_span!("qc", file = %file.display()).entered();
+    info!("\n🔍 Checking {}...", file.display());
+    let media = probe::media_info(ffprobe, file)?;
+    let mut report = Report { frame_rate: media.video.as_ref().map_or(0.0, |v| v.frame_rate), ..Default::default() };
+    if let Some(check) = checks.silence {
+        report.findings.extend(silence(ffmpeg, file, &media, check)?);
+    }
+    if let Some(check) = checks.black {
+        report.findings.extend(black(ffmpeg, file, &media, check)?);
+    }
+    if let Some(check) = checks.freeze {
+        report.findings.extend(freeze(ffmpeg, file, &media, check)?);
+    }
+    report.findings.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Ok(report)
+}
+
+/// Look for silence in the audio of `file`, described by `media`. Returns
+/// nothing if it has no audio.
+pub fn silence(ffmpeg: &Path, file: &Path, media: &MediaInfo, check: SilenceCheck) -> Result<Vec<Finding>> {
+    if media.audio.is_empty() {
+        info!("ℹ️ {} has no audio to check for silence", file.display());
+        return Ok(Vec::new());
+    }
+    let log = run(ffmpeg, silence_command(ffmpeg, file, media.audio.len(), check))?;
+    Ok(parse_intervals(&log, "silence_start:", "silence_end:", media.duration)
+        .into_iter()
+        .map(|(start, end)| Finding { check: Check::Silence, start, end })
+        .collect())
+}
+
+/// Look for black frames in the video of `file`, described by `media`,
+/// outside the head and tail of `check`. Black running into them counts
+/// from where it leaves them, if it still lasts long enough.
+pub fn black(ffmpeg: &Path, file: &Path, media: &MediaInfo, check: BlackCheck) -> Result<Vec<Finding>> {
+    if media.video.is_none() {
+        return Ok(Vec::new());
+    }
+    let log = run(ffmpeg, black_command(ffmpeg, file, check))?;
+    let tail_start = media.duration - check.tail;
+    Ok(parse_intervals(&log, "black_start:", "black_end:", media.duration)
+        .into_iter()
+        .map(|(start, end)| (start.max(check.head), end.min(tail_start)))
+        .filter(|(start, end)| end - start >= check.duration)
+        .map(|(start, end)| Finding { check: Check::Black, start, end })
+        .collect())
+}
+
+/// Look for frozen stretches in the video of `file`, described by `media`.
+pub fn freeze(ffmpeg: &Path, file: &Path, media: &MediaInfo, check: FreezeCheck) -> Result<Vec<Finding>> {
+    if media.video.is_none() {
+        return Ok(Vec::new());
+    }
+    let log = run(ffmpeg, freeze_command(ffmpeg, file, check))?;
+    Ok(parse_intervals(&log, "freeze_start:", "freeze_end:", media.duration)
+        .into_iter()
+        .map(|(start, end)| Finding { check: Check::Freeze, start, end })
+        .collect())
+}
+
+// Run the analysis `cmd` and return what it logged.
+fn run(ffmpeg: &Path, mut cmd: Command) -> Result<String> {
+    process::contain(&mut cmd);
+    debug!("Command: {}", ffmpeg::display_command(&cmd));
+    let result = cmd.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffmpeg.to_path_buf()),
+        _ => DeliveryError::io("Failed to execute ffmpeg", e),
+    })?;
+    let log = String::from_utf8_lossy(&result.stderr).into_owned();
+    if !result.status.success() {
+        return Err(DeliveryError::QcFailed(log.trim().to_string()));
+    }
+    Ok(log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(cmd: &Command) -> Vec<&str> {
+        cmd.get_args().map(|a| a.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn intervals_pair_starts_with_ends() {
+        let log = "\
+[silencedetect @ 0x6000] silence_end: 0.5 | silence_duration: 0.5
+[silencedetect @ 0x6000] silence_start: 1.5
+[silencedetect @ 0x6000] silence_end: 3.25 | silence_duration: 1.75
+size=N/A time=00:00:05.00 bitrate=N/A speed= 500x
+[silencedetect @ 0x6000] silence_start: 9
+";
+        assert_eq!(parse_intervals(log, "silence_start:", "silence_end:", 10.0), [(1.5, 3.25), (9.0, 10.0)]);
+    }
+
+    #[test]
+    fn intervals_read_blackdetect_lines() {
+        let log = "[blackdetect @ 0x6000] black_start:0 black_end:2.04 black_duration:2.04\n\
+                   [blackdetect @ 0x6000] black_start:58.5 black_end:60 black_duration:1.5\n";
+        assert_eq!(parse_intervals(log, "black_start:", "black_end:", 60.0), [(0.0, 2.04), (58.5, 60.0)]);
+        assert!(parse_intervals("frame=  100 fps=0.0", "black_start:", "black_end:", 60.0).is_empty());
+    }
+
+    #[test]
+    fn intervals_read_freezedetect_lines() {
+        let log = "\
+[freezedetect @ 0x6000] lavfi.freezedetect.freeze_start: 12.012
+[freezedetect @ 0x6000] lavfi.freezedetect.freeze_duration: 3.003
+[freezedetect @ 0x6000] lavfi.freezedetect.freeze_end: 15.015
+[freezedetect @ 0x6000] lavfi.freezedetect.freeze_start: 58
+";
+        assert_eq!(parse_intervals(log, "freeze_start:", "freeze_end:", 60.0), [(12.012, 15.015), (58.0, 60.0)]);
+    }
+
+    #[test]
+    fn parse_level_takes_negative_decibels() {
+        assert_eq!(parse_level("-60dB"), Ok(-60.0));
+        assert_eq!(parse_level(" -50 dBFS "), Ok(-50.0));
+        assert_eq!(parse_level("-70"), Ok(-70.0));
+        for invalid in ["0", "6dB", "-inf", "quiet"] {
+            assert!(parse_level(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn findings_are_described_with_timecodes() {
+        let finding = Finding { check: Check::Black, start: 1.0, end: 2.52 };
+        assert_eq!(finding.describe(25.0), "black from 00:00:01:00 to 00:00:02:13 (1.52s)");
+    }
+
+    #[test]
+    fn silence_is_detected_over_every_stream_mixed() {
+        let check = SilenceCheck { threshold: -60.0, duration: 2.0 };
+        let mixed = silence_command(Path::new("ffmpeg"), Path::new("out.mov"), 2, check);
+        assert_eq!(
+            args(&mixed)[4..],
+            [
+                "-filter_complex",
+                "[0:a:0][0:a:1]amerge=inputs=2,silencedetect=noise=-60dB:duration=2",
+                "-vn",
+                "-f",
+                "null",
+                "-"
+            ]
+        );
+        let single = silence_command(Path::new("ffmpeg"), Path::new("out.mov"), 1, check);
+        assert_eq!(args(&single)[4..8], ["-map", "0:a:0", "-af", "silencedetect=noise=-60dB:duration=2"]);
+    }
+
+    #[test]
+    fn black_is_detected_on_the_video() {
+        let check = BlackCheck { duration: 0.5, head: 2.0, tail: 5.0 };
+        assert_eq!(
+            args(&black_command(Path::new("ffmpeg"), Path::new("out.mov"), check))[4..],
+            ["-map", "0:v:0", "-vf", "blackdetect=d=0.5:pic_th=0.98:pix_th=0.10", "-an", "-f", "null", "-"]
+        );
+    }
+
+    #[test]
+    fn freezes_are_detected_on_the_video() {
+        let check = FreezeCheck { duration: 2.0 };
+        assert_eq!(
+            args(&freeze_command(Path::new("ffmpeg"), Path::new("out.mov"), check))[4..],
+            ["-map", "0:v:0", "-vf", "freezedetect=n=-60dB:d=2", "-an", "-f", "null", "-"]
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use crate::units;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// One output of a rendition ladder: the composited video scaled to
+/// `height` and encoded with its own rate cap.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Rendition {
+    /// Label used in file names and playlists, e.g. `1080p`.
+    pub name: String,
+    /// Height in pixels; the width follows the aspect ratio.
+    pub height: u32,
+    /// Peak bitrate in bits per second the codec's quality setting is capped
+    /// at; `None` leaves it uncapped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<u64>,
+}
+
+impl Rendition {
+    /// `base` (e.g. `ep101.mp4`) with the rendition's name inserted before the
+    /// extension: `ep101_1080p.mp4`.
+    pub fn file_name(&self, base: &str) -> String {
+        match base.rsplit_once('.') {
+            Some((stem, ext)) => format!("{}_{}.{}", stem, self.name, ext),
+            None => format!("{}_{}", base, self.name),
+        }
+    }
+
+    /// Encoder options capping the rate at `bitrate`, over a two second
+    /// buffer.
+    pub fn rate_args(&self) -> Vec<String> {
+        match self.bitrate {
+            Some(bitrate) => vec![
+                "-maxrate".to_string(),
+                bitrate.to_string(),
+                "-bufsize".to_string(),
+                (bitrate * 2).to_string(),
+            ],
+            None => Vec::new(),
+        }
+    }
+}
+
+impl FromStr for Rendition {
+    type Err = String;
+
+    /// Parse `<name>:<height>[:<bitrate>]`, e.g. `720p:720:3M`.
+    fn from_str(text: &str) -> std::result::Result<Rendition, String> {
+        let mut fields = text.trim().split(':');
+        let (Some(name), Some(height)) = (fields.next(), fields.next()) else {
+            return Err(format!("invalid rendition '{}', expected <name>:<height>[:<bitrate>]", text));
+        };
+        let height = height.parse().map_err(|_| format!("invalid height '{}' in rendition '{}'", height, text))?;
+        let bitrate = fields.next().map(units::parse_bitrate).transpose()?;
+        if fields.next().is_some() {
+            return Err(format!("invalid rendition '{}', expected <name>:<height>[:<bitrate>]", text));
+        }
+        Ok(Rendition { name: name.to_string(), height, bitrate })
+    }
+}
+
+/// Fail unless every rendition has a distinct name usable in file names and
+/// an even, non-zero height.
+pub fn check(renditions: &[Rendition]) -> std::result::Result<(), String> {
+    for (index, rendition) in renditions.iter().enumerate() {
+        if rendition.name.is_empty()
+            || !rendition.name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        {
+            return Err(format!("rendition name '{}' may only contain letters, digits, - and _", rendition.name));
+        }
+        if renditions[..index].iter().any(|r| r.name == rendition.name) {
+            return Err(format!("rendition name '{}' is used more than once", rendition.name));
+        }
+        if rendition.height == 0 || rendition.height % 2 != 0 {
+            return Err(format!("rendition '{}' needs an even, non-zero height", rendition.name));
+        }
+    }
+    Ok(())
+}
+
+/// Label of the filter graph output of the rendition at `index`.
+pub fn output_label(index: usize) -> String {
+    format!("[rendition_{}]", index)
+}
+
+/// Filters appended to a graph with one unlabeled output to fork it into
+/// every rendition, scaled to its height, so the source is decoded and
+/// composited once for all of them.
+pub fn filter_suffix(renditions: &[Rendition]) -> String {
+    let sources: String = (0..renditions.len()).map(|i| format!("[rendition_src_{}]", i)).collect();
+    let mut suffix = format!(",split={}{}", renditions.len(), sources);
+    for (index, rendition) in renditions.iter().enumerate() {
+        suffix.push_str(&format!(
+            ";[rendition_src_{}]scale=-2:{}{}",
+            index,
+            rendition.height,
+            output_label(index)
+        ));
+    }
+    suffix
+}
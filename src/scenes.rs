@@ -0,0 +1,61 @@
+use crate::console::{debug, info};
+use crate::{ffmpeg, process, DeliveryError, Result};
+use std::path::Path;
+use std::process::Command;
+use std::time::Instant;
+
+/// Scene change score, 0 to 1, above which a frame starts a new shot.
+pub const SCENE_THRESHOLD: f64 = 0.4;
+
+/// Height the frames are scaled to before scoring them: enough to see cuts,
+/// and much faster than scoring full-size frames.
+const ANALYSIS_HEIGHT: u32 = 360;
+
+/// The ffmpeg invocation logging every frame of the video of `input` that
+/// starts a new shot.
+pub fn detect_command(ffmpeg: &Path, input: &Path) -> Command {
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-hide_banner", "-nostats", "-nostdin", "-i"]).arg(ffmpeg::path_arg(input));
+    cmd.args(["-map", "0:v:0", "-vf"]);
+    cmd.arg(format!("scale=-2:{},select='gt(scene,{})',showinfo", ANALYSIS_HEIGHT, SCENE_THRESHOLD));
+    cmd.args(["-an", "-f", "null", "-"]);
+    cmd
+}
+
+/// Times, in seconds, of the frames showinfo logged in `log`.
+pub fn parse_cuts(log: &str) -> Vec<f64> {
+    let mut cuts: Vec<f64> = log
+        .lines()
+        .filter(|line| line.contains("showinfo"))
+        .filter_map(|line| {
+            let rest = &line[line.find("pts_time:")? + "pts_time:".len()..];
+            rest.split_whitespace().next()?.parse().ok()
+        })
+        .collect();
+    cuts.sort_by(f64::total_cmp);
+    cuts.dedup();
+    cuts
+}
+
+/// Find the scene cuts of `input`, in seconds from its start. Every frame is
+/// decoded, so this takes a while for long sources.
+pub fn detect(ffmpeg: &Path, input: &Path) -> Result<Vec<f64>> {
+    let _span = tracing::info_span!("scenes", input = %input.display()).entered();
+    info!("\n🎬 Detecting scene cuts...");
+    let started = Instant::now();
+
+    let mut cmd = detect_command(ffmpeg, input);
+    process::contain(&mut cmd);
+    debug!("Command: {}", ffmpeg::display_command(&cmd));
+    let output = cmd.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffmpeg.to_path_buf()),
+        _ => DeliveryError::io("Failed to execute ffmpeg", e),
+    })?;
+    let log = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(DeliveryError::ProbeFailed(format!("scene detection failed: {}", log.trim())));
+    }
+    let cuts = parse_cuts(&log);
+    info!("✅ Found {} scene cuts in {:.2} seconds", cuts.len(), started.elapsed().as_secs_f32());
+    Ok(cuts)
+}
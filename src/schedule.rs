@@ -0,0 +1,89 @@
+use crate::overlay::{OverlayLayer, OverlaySettings, Playback, Window};
+use crate::{DeliveryError, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A time in a schedule: seconds, or a clock time such as `00:12:30`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Time {
+    Seconds(f64),
+    Clock(String),
+}
+
+/// One JSON entry of a schedule.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    start: Time,
+    end: Time,
+    overlay: PathBuf,
+}
+
+// The window from `start` to `end`, checked like `--overlay-window`.
+fn window(start: &Time, end: &Time) -> std::result::Result<Window, String> {
+    let text = |time: &Time| match time {
+        Time::Seconds(seconds) => seconds.to_string(),
+        Time::Clock(clock) => clock.clone(),
+    };
+    format!("{}-{}", text(start), text(end)).parse()
+}
+
+/// Load the overlay schedule at `path`, which shows each overlay asset in
+/// its own stretch of the program (e.g. a different sponsor per act): a
+/// JSON array of `{"start", "end", "overlay"}` objects, or CSV lines of
+/// `start,end,overlay` with an optional header line. Times are seconds or
+/// HH:MM:SS[.fff]; relative overlay paths are relative to the schedule.
+///
+/// Every entry becomes an overlay layer shown only in its window, placed
+/// like `settings` and looped if `looped` when it is animated.
+pub fn load(path: &Path, settings: &OverlaySettings, looped: bool) -> Result<Vec<OverlayLayer>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| DeliveryError::io(format!("Failed to read overlay schedule {}", path.display()), e))?;
+    let invalid = |at: String, message: String| {
+        DeliveryError::Config(format!("Invalid overlay schedule {} ({}): {}", path.display(), at, message))
+    };
+    let json = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json"));
+    let entries: Vec<(Window, PathBuf)> = if json {
+        let entries: Vec<Entry> = serde_json::from_str(&text).map_err(|e| invalid(format!("line {}", e.line()), e.to_string()))?;
+        entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let window = window(&entry.start, &entry.end).map_err(|e| invalid(format!("entry {}", index + 1), e))?;
+                Ok((window, entry.overlay.clone()))
+            })
+            .collect::<Result<_>>()?
+    } else {
+        let mut entries = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || (index == 0 && line.to_ascii_lowercase().starts_with("start")) {
+                continue;
+            }
+            // The overlay is the last field, so its path may hold commas
+            let fields: Vec<&str> = line.splitn(3, ',').map(str::trim).collect();
+            let [start, end, overlay] = fields[..] else {
+                return Err(invalid(format!("line {}", index + 1), "expected start,end,overlay".to_string()));
+            };
+            let window = format!("{}-{}", start, end).parse().map_err(|e| invalid(format!("line {}", index + 1), e))?;
+            entries.push((window, PathBuf::from(overlay.trim_matches('"'))));
+        }
+        entries
+    };
+    if entries.is_empty() {
+        return Err(DeliveryError::Config(format!("Overlay schedule {} has no entries", path.display())));
+    }
+
+    let base = path.parent().unwrap_or(Path::new(""));
+    Ok(entries
+        .into_iter()
+        .map(|(window, overlay)| OverlayLayer {
+            path: if overlay.is_relative() { base.join(overlay) } else { overlay },
+            settings: OverlaySettings { window: Some(window), ..settings.clone() },
+            playback: Playback { looped, ..Playback::default() },
+        })
+        .collect())
+}
+
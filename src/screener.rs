@@ -0,0 +1,217 @@
+use crate::burnin::TextBurnIn;
+use crate::console::{error, info};
+use crate::overlay::Position;
+use crate::{interrupt, progress, DeliveryError, EncodeJob, Result};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+/// Default text height of the recipient's watermark in pixels.
+pub const DEFAULT_SIZE: u32 = 64;
+
+/// Default opacity of the recipient's watermark.
+pub const DEFAULT_OPACITY: f64 = 0.3;
+
+/// Default number of screeners encoded at once.
+pub const DEFAULT_AT_ONCE: usize = 2;
+
+/// Someone a screener is made for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipient {
+    pub name: String,
+    pub email: Option<String>,
+}
+
+impl Recipient {
+    /// Text burned into their copy: the name and email.
+    pub fn label(&self) -> String {
+        match &self.email {
+            Some(email) => format!("{} - {}", self.name, email),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Directory their copy is written to: the name in lowercase, with runs
+    /// of other characters than letters and digits as `_`.
+    pub fn slug(&self) -> String {
+        let mut slug = String::new();
+        for c in self.name.chars().flat_map(char::to_lowercase) {
+            if c.is_alphanumeric() {
+                slug.push(c);
+            } else if !slug.is_empty() && !slug.ends_with('_') {
+                slug.push('_');
+            }
+        }
+        let slug = slug.trim_end_matches('_');
+        if slug.is_empty() { "recipient".to_string() } else { slug.to_string() }
+    }
+}
+
+impl FromStr for Recipient {
+    type Err = String;
+
+    /// Parse `Name <email>`, `Name, email` or just a name or email.
+    fn from_str(text: &str) -> std::result::Result<Recipient, String> {
+        let text = text.trim();
+        let (name, email) = match text.strip_suffix('>').and_then(|t| t.split_once('<')) {
+            Some((name, email)) => (name, Some(email)),
+            None => match text.split_once(',') {
+                Some((name, email)) => (name, Some(email)),
+                None => (text, None),
+            },
+        };
+        let (name, email) = (name.trim(), email.map(str::trim).filter(|e| !e.is_empty()));
+        match (name.is_empty(), email) {
+            (true, None) => Err("expected a name or email, e.g. Jane Doe <jane@example.com>".to_string()),
+            (true, Some(email)) => Ok(Recipient { name: email.to_string(), email: None }),
+            (false, email) => Ok(Recipient { name: name.to_string(), email: email.map(str::to_string) }),
+        }
+    }
+}
+
+/// Load the recipients listed in `path`, one per line as [`Recipient`]
+/// parses them. Blank lines and lines starting with `#` are skipped.
+pub fn load(path: &Path) -> Result<Vec<Recipient>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| DeliveryError::io(format!("Failed to read screener list {}", path.display()), e))?;
+    let mut recipients = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let recipient = line.parse().map_err(|e| {
+            DeliveryError::Config(format!("Invalid screener list {} (line {}): {}", path.display(), index + 1, e))
+        })?;
+        recipients.push(recipient);
+    }
+    if recipients.is_empty() {
+        return Err(DeliveryError::Config(format!("Screener list {} has no recipients", path.display())));
+    }
+    Ok(recipients)
+}
+
+/// How the recipient's name is burned into their screener.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watermark {
+    /// Corner, edge or centre the name is placed at.
+    pub position: Position,
+    /// Opacity of the text from 0 to 1.
+    pub opacity: f64,
+    /// Text height in pixels.
+    pub size: u32,
+    /// Font file or fontconfig family name.
+    pub font: Option<String>,
+}
+
+impl Default for Watermark {
+    fn default() -> Watermark {
+        Watermark { position: Position::Center, opacity: DEFAULT_OPACITY, size: DEFAULT_SIZE, font: None }
+    }
+}
+
+impl Watermark {
+    /// The burn-in of `recipient`'s label.
+    pub fn burn_in(&self, recipient: &Recipient) -> TextBurnIn {
+        TextBurnIn {
+            position: self.position,
+            font: self.font.clone(),
+            size: self.size,
+            color: format!("white@{}", self.opacity),
+            ..TextBurnIn::new(recipient.label())
+        }
+    }
+}
+
+/// One copy of `job` per recipient, watermarked with their label and
+/// written to a directory of the output named after them (their `slug`,
+/// numbered if two share one). `{recipient}` in the burn-in text is their
+/// name. With `at_once` copies encoded at a time, each gets that share of
+/// the job's workers.
+pub fn jobs(job: &EncodeJob, recipients: &[Recipient], watermark: &Watermark, at_once: usize) -> Vec<EncodeJob> {
+    let at_once = at_once.clamp(1, recipients.len().max(1));
+    let mut slugs: Vec<String> = Vec::new();
+    recipients
+        .iter()
+        .map(|recipient| {
+            let base = recipient.slug();
+            let mut slug = base.clone();
+            let mut number = 2;
+            while slugs.contains(&slug) {
+                slug = format!("{}_{}", base, number);
+                number += 1;
+            }
+            slugs.push(slug.clone());
+
+            let mut copy = job.clone();
+            copy.output_dir = job.output_dir.join(&slug);
+            copy.segments_dir = job.segments_dir.join(&slug);
+            copy.recipient = Some(recipient.name.clone());
+            copy.watermark = Some(watermark.burn_in(recipient));
+            copy.threads = (job.threads / at_once).max(1);
+            copy.ffmpeg_threads = Some(job.ffmpeg_threads());
+            // Copies encoded at once would pin their workers to the same CPUs
+            copy.pin_cpus = job.pin_cpus && at_once == 1;
+            copy
+        })
+        .collect()
+}
+
+/// Encode the screener `jobs`, `at_once` at a time, each after preparing
+/// its output. A failed screener doesn't stop the others; the first
+/// failure is returned once they are done. Returns the jobs that ran, as
+/// prepared, with the number of frames each wrote.
+pub fn run_all(jobs: Vec<EncodeJob>, at_once: usize) -> Result<Vec<(EncodeJob, usize)>> {
+    let total = jobs.len();
+    let at_once = at_once.clamp(1, total.max(1));
+    let _span = tracing::info_span!("screeners", recipients = total).entered();
+    info!("\n🎟 Encoding {} screener(s), {} at a time...", total, at_once);
+    let started = Instant::now();
+    // Several jobs drawing bars at once would tear each other's apart
+    if at_once > 1 {
+        progress::set_bars(false);
+    }
+
+    let queue: Mutex<VecDeque<(usize, EncodeJob)>> = Mutex::new(jobs.into_iter().enumerate().collect());
+    let done: Mutex<Vec<(usize, EncodeJob, usize)>> = Mutex::new(Vec::new());
+    let failures: Mutex<Vec<(usize, DeliveryError)>> = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..at_once {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap().pop_front();
+                let Some((index, mut job)) = next else { break };
+                if interrupt::requested() {
+                    break;
+                }
+                let recipient = job.recipient.clone().unwrap_or_default();
+                let _span = tracing::info_span!("screener", recipient = %recipient).entered();
+                info!("\n🎟 Screener for {} -> {}", recipient, job.output_dir.display());
+                let result = job.prepare_output().and_then(|go| if go { job.run().map(Some) } else { Ok(None) });
+                match result {
+                    Ok(Some(frames)) => done.lock().unwrap().push((index, job, frames)),
+                    Ok(None) => info!("⏭ The screener for {} is already there, skipping", recipient),
+                    Err(e) => {
+                        error!("❌ The screener for {} failed: {}", recipient, e);
+                        failures.lock().unwrap().push((index, e));
+                    }
+                }
+            });
+        }
+    });
+    progress::set_bars(true);
+
+    interrupt::check()?;
+    let mut failures = failures.into_inner().unwrap();
+    failures.sort_by_key(|(index, _)| *index);
+    if let Some((_, e)) = failures.into_iter().next() {
+        return Err(e);
+    }
+    let mut done = done.into_inner().unwrap();
+    done.sort_by_key(|(index, ..)| *index);
+    info!("✅ Encoded {} screener(s) in {:.2} seconds", done.len(), started.elapsed().as_secs_f32());
+    Ok(done.into_iter().map(|(_, job, frames)| (job, frames)).collect())
+}
@@ -0,0 +1,264 @@
+use crate::console::info;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Where the segment boundaries are placed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitOn {
+    /// Evenly over time (or encode cost, with adaptive segments).
+    #[default]
+    Time,
+    /// At the scene cuts ffmpeg detects nearest an even split.
+    Scenes,
+}
+
+impl FromStr for SplitOn {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<SplitOn, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "time" => Ok(SplitOn::Time),
+            "scenes" => Ok(SplitOn::Scenes),
+            _ => Err(format!("invalid split mode '{}', expected time or scenes", text)),
+        }
+    }
+}
+
+impl fmt::Display for SplitOn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SplitOn::Time => "time",
+            SplitOn::Scenes => "scenes",
+        })
+    }
+}
+
+/// A slice of the source timeline handled by a single worker.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub id: usize,
+    /// Offset into the source, in seconds.
+    pub start: f64,
+    /// Length of the slice, in seconds.
+    pub duration: f64,
+    /// Exact number of frames in the slice, if it starts and ends on frame
+    /// boundaries. Such segments are cut by frame count instead of duration so
+    /// neighbouring segments neither overlap nor leave gaps.
+    pub frames: Option<u64>,
+    /// Piece of the source split off for this segment (see [`crate::split`]),
+    /// relative to the segments directory. The segment is the whole piece
+    /// instead of a slice of the input.
+    pub source: Option<PathBuf>,
+}
+
+impl Segment {
+    /// Directory the worker writes this segment's frames into.
+    pub fn dir(&self, segments_dir: &Path) -> PathBuf {
+        segments_dir.join(format!("segment_{}", self.id))
+    }
+
+    /// Video file the segment is encoded to for video output.
+    pub fn chunk(&self, segments_dir: &Path, ext: &str) -> PathBuf {
+        self.dir(segments_dir).join(format!("chunk.{}", ext))
+    }
+}
+
+/// Split `total_duration` into `count` equal segments. With a known
+/// `frame_rate` the boundaries fall on frames and every segment gets a whole
+/// number of them.
+pub fn plan(total_duration: f64, frame_rate: f64, count: usize) -> Vec<Segment> {
+    let count = count.max(1);
+    let segment_duration = total_duration / count as f64;
+    info!("⏱ Segment duration: {:.2} seconds", segment_duration);
+
+    if frame_rate > 0.0 {
+        let total_frames = (total_duration * frame_rate).round() as u64;
+        let starts: Vec<u64> = (0..count as u64).map(|id| id * total_frames / count as u64).collect();
+        return by_frames(&starts, total_frames, frame_rate);
+    }
+    (0..count)
+        .map(|id| Segment {
+            id,
+            start: id as f64 * segment_duration,
+            duration: segment_duration,
+            frames: None,
+            source: None,
+        })
+        .collect()
+}
+
+// Segments starting at the given frames, the last one ending at `total_frames`.
+// Empty segments are dropped.
+fn by_frames(starts: &[u64], total_frames: u64, frame_rate: f64) -> Vec<Segment> {
+    let mut starts = starts.to_vec();
+    starts.dedup();
+    starts.retain(|&start| start < total_frames.max(1));
+    starts
+        .iter()
+        .enumerate()
+        .map(|(id, &start)| {
+            let frames = starts.get(id + 1).copied().unwrap_or(total_frames.max(start + 1)) - start;
+            Segment {
+                id,
+                start: start as f64 / frame_rate,
+                duration: frames as f64 / frame_rate,
+                frames: Some(frames),
+                source: None,
+            }
+        })
+        .collect()
+}
+
+/// Split `total_duration` into up to `count` segments of roughly equal encode
+/// cost, estimated from the video `packets` (time, size in bytes) of the input.
+/// Boundaries fall on frames when `frame_rate` is known, as with [`plan`].
+///
+/// Big packets mean detailed or busy frames that take longer to composite and
+/// compress, but every frame has a fixed cost too, so each packet counts as its
+/// size plus the average packet size. Falls back to [`plan`] without packets.
+pub fn plan_by_cost(total_duration: f64, frame_rate: f64, count: usize, packets: &[(f64, u64)]) -> Vec<Segment> {
+    let count = count.max(1);
+    if packets.is_empty() {
+        return plan(total_duration, frame_rate, count);
+    }
+    let average = packets.iter().map(|&(_, size)| size as f64).sum::<f64>() / packets.len() as f64;
+    let total_cost = packets.iter().map(|&(_, size)| size as f64 + average).sum::<f64>();
+    let target = total_cost / count as f64;
+
+    // A new segment starts at the first packet past each multiple of `target`
+    let mut starts = vec![0.0];
+    let mut cost = 0.0;
+    for &(time, size) in packets {
+        if cost >= target * starts.len() as f64
+            && starts.len() < count
+            && time > *starts.last().unwrap()
+            && time < total_duration
+        {
+            starts.push(time);
+        }
+        cost += size as f64 + average;
+    }
+
+    from_starts(&starts, total_duration, frame_rate)
+}
+
+/// Split `total_duration` into up to `count` segments that start at scene
+/// `cuts` (seconds from the start), picking for every boundary of an even
+/// split the cut nearest to it. Segments then hold whole shots, and seeking
+/// to their start never lands mid-shot. Boundaries fall on frames when
+/// `frame_rate` is known, as with [`plan`]. Falls back to [`plan`] without
+/// cuts.
+pub fn plan_by_scenes(total_duration: f64, frame_rate: f64, count: usize, cuts: &[f64]) -> Vec<Segment> {
+    let count = count.max(1);
+    let cuts: Vec<f64> = cuts.iter().copied().filter(|&cut| cut > 0.0 && cut < total_duration).collect();
+    if cuts.is_empty() {
+        return plan(total_duration, frame_rate, count);
+    }
+    let mut starts = vec![0.0];
+    for boundary in 1..count {
+        let even = total_duration * boundary as f64 / count as f64;
+        let nearest = cuts.iter().copied().min_by(|a, b| (a - even).abs().total_cmp(&(b - even).abs()));
+        if let Some(cut) = nearest.filter(|&cut| cut > *starts.last().unwrap()) {
+            starts.push(cut);
+        }
+    }
+    from_starts(&starts, total_duration, frame_rate)
+}
+
+// Segments starting at `starts` (seconds, ascending from 0), the last one
+// ending at `total_duration`.
+fn from_starts(starts: &[f64], total_duration: f64, frame_rate: f64) -> Vec<Segment> {
+    let segments: Vec<Segment> = if frame_rate > 0.0 {
+        let frames: Vec<u64> = starts.iter().map(|&start| (start * frame_rate).round() as u64).collect();
+        by_frames(&frames, (total_duration * frame_rate).round() as u64, frame_rate)
+    } else {
+        starts
+            .iter()
+            .enumerate()
+            .map(|(id, &start)| Segment {
+                id,
+                start,
+                duration: starts.get(id + 1).copied().unwrap_or(total_duration) - start,
+                frames: None,
+                source: None,
+            })
+            .collect()
+    };
+    let shortest = segments.iter().map(|s| s.duration).fold(f64::INFINITY, f64::min);
+    let longest = segments.iter().map(|s| s.duration).fold(0.0, f64::max);
+    info!("⏱ Segment durations: {:.2} to {:.2} seconds", shortest, longest);
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (start, duration, frames) of every segment, with times to the millisecond
+    fn layout(segments: &[Segment]) -> Vec<(f64, f64, Option<u64>)> {
+        let ms = |t: f64| (t * 1000.0).round() / 1000.0;
+        segments.iter().map(|s| (ms(s.start), ms(s.duration), s.frames)).collect()
+    }
+
+    #[test]
+    fn even_split_gives_every_segment_whole_frames() {
+        let segments = plan(10.0, 25.0, 4);
+        assert_eq!(
+            layout(&segments),
+            [(0.0, 2.48, Some(62)), (2.48, 2.52, Some(63)), (5.0, 2.48, Some(62)), (7.48, 2.52, Some(63))]
+        );
+        assert!(segments.iter().enumerate().all(|(i, s)| s.id == i));
+    }
+
+    #[test]
+    fn even_split_without_a_frame_rate_splits_the_duration() {
+        assert_eq!(
+            layout(&plan(10.0, 0.0, 4)),
+            [(0.0, 2.5, None), (2.5, 2.5, None), (5.0, 2.5, None), (7.5, 2.5, None)]
+        );
+    }
+
+    #[test]
+    fn even_split_drops_segments_without_frames() {
+        let segments = plan(0.2, 25.0, 8);
+        assert_eq!(segments.len(), 5);
+        assert!(segments.iter().all(|s| s.frames == Some(1)));
+    }
+
+    #[test]
+    fn cost_split_gives_busy_stretches_shorter_segments() {
+        let packets = [(0.0, 1000), (1.0, 100), (2.0, 100), (3.0, 100), (4.0, 100), (5.0, 100)];
+        assert_eq!(layout(&plan_by_cost(6.0, 0.0, 2, &packets)), [(0.0, 2.0, None), (2.0, 4.0, None)]);
+    }
+
+    #[test]
+    fn cost_split_without_packets_is_the_even_split() {
+        assert_eq!(layout(&plan_by_cost(10.0, 25.0, 4, &[])), layout(&plan(10.0, 25.0, 4)));
+    }
+
+    #[test]
+    fn scene_split_moves_boundaries_to_the_nearest_cut() {
+        assert_eq!(layout(&plan_by_scenes(10.0, 0.0, 2, &[3.0, 4.5, 8.0])), [(0.0, 4.5, None), (4.5, 5.5, None)]);
+        assert_eq!(layout(&plan_by_scenes(10.0, 25.0, 2, &[4.52])), [(0.0, 4.52, Some(113)), (4.52, 5.48, Some(137))]);
+    }
+
+    #[test]
+    fn scene_split_uses_a_cut_once() {
+        assert_eq!(layout(&plan_by_scenes(9.0, 0.0, 3, &[5.0])), [(0.0, 5.0, None), (5.0, 4.0, None)]);
+    }
+
+    #[test]
+    fn scene_split_without_usable_cuts_is_the_even_split() {
+        assert_eq!(layout(&plan_by_scenes(10.0, 0.0, 2, &[0.0, 10.0, 12.0])), layout(&plan(10.0, 0.0, 2)));
+    }
+
+    #[test]
+    fn split_mode_parses_and_prints() {
+        assert_eq!(" Scenes ".parse::<SplitOn>(), Ok(SplitOn::Scenes));
+        assert_eq!(SplitOn::Time.to_string(), "time");
+        assert!("shots".parse::<SplitOn>().is_err());
+    }
+}
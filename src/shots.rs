@@ -0,0 +1,177 @@
+use crate::console::{debug, info, warning};
+use crate::{ffmpeg, process, timecode, DeliveryError, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Height of the shot thumbnails in pixels.
+const THUMBNAIL_HEIGHT: u32 = 180;
+
+/// Format of the shot list written next to the output.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ShotListFormat {
+    /// One line per shot with a header line.
+    Csv,
+    /// An array of shots.
+    Json,
+}
+
+impl ShotListFormat {
+    /// File extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ShotListFormat::Csv => "csv",
+            ShotListFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for ShotListFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<ShotListFormat, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "csv" => Ok(ShotListFormat::Csv),
+            "json" => Ok(ShotListFormat::Json),
+            _ => Err(format!("invalid shot list format '{}', expected csv or json", text)),
+        }
+    }
+}
+
+impl fmt::Display for ShotListFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+/// One shot of the source, between two scene cuts.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Shot {
+    /// Position in the source, from 1.
+    pub shot: usize,
+    pub start_timecode: String,
+    pub end_timecode: String,
+    /// First and last frame of the shot, counted from 0.
+    pub first_frame: u64,
+    pub last_frame: u64,
+    /// Frame from the middle of the shot, relative to the shot list; empty
+    /// if it couldn't be extracted.
+    pub thumbnail: String,
+}
+
+/// File name of the shot list: `<stem>_shots.<ext>`.
+pub fn file_name(stem: &str, format: ShotListFormat) -> String {
+    format!("{}_shots.{}", stem, format.extension())
+}
+
+/// Directory next to the shot list the thumbnails are written to.
+pub fn thumbnail_dir(stem: &str) -> String {
+    format!("{}_shots", stem)
+}
+
+/// The shots of a `duration` second source at `fps` with scene `cuts`, as
+/// frame ranges: `(first, last)`, counted from 0.
+pub fn frame_ranges(cuts: &[f64], duration: f64, fps: f64) -> Vec<(u64, u64)> {
+    let total = (duration * fps).round() as u64;
+    let mut starts: Vec<u64> = vec![0];
+    starts.extend(cuts.iter().map(|&cut| (cut * fps).round() as u64).filter(|&frame| frame > 0 && frame < total));
+    starts.dedup();
+    starts.iter().enumerate().map(|(i, &start)| (start, starts.get(i + 1).map_or(total, |&next| next) - 1)).collect()
+}
+
+/// The ffmpeg invocation writing the frame at `time` seconds of `source` to
+/// `output` as a thumbnail.
+pub fn thumbnail_command(ffmpeg: &Path, source: &Path, time: f64, output: &Path) -> Command {
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-v", "error", "-nostdin", "-ss", &format!("{:.6}", time), "-i"]).arg(ffmpeg::path_arg(source));
+    cmd.args(["-map", "0:v:0", "-frames:v", "1", "-vf", &format!("scale=-2:{}", THUMBNAIL_HEIGHT)]);
+    cmd.arg("-y").arg(ffmpeg::path_arg(output));
+    cmd
+}
+
+/// Write the list of the shots of `source` at `fps`, as [`frame_ranges`],
+/// to `output_dir` in `format`, named after `stem`, with a thumbnail of
+/// every shot. A thumbnail that can't be extracted is left out with a
+/// warning. Returns the path of the list.
+pub fn export(
+    ffmpeg: &Path,
+    source: &Path,
+    ranges: &[(u64, u64)],
+    fps: f64,
+    format: ShotListFormat,
+    stem: &str,
+    output_dir: &Path,
+) -> Result<PathBuf> {
+    let _span = tracing::info_span!("shots", shots = ranges.len()).entered();
+    info!("\n🎞 Exporting a list of {} shot(s)...", ranges.len());
+    let started = Instant::now();
+
+    let dir = output_dir.join(thumbnail_dir(stem));
+    fs::create_dir_all(&dir).map_err(|e| DeliveryError::io(format!("Failed to create {}", dir.display()), e))?;
+    let mut shots = Vec::with_capacity(ranges.len());
+    for (index, &(first, last)) in ranges.iter().enumerate() {
+        let name = format!("shot_{:04}.jpg", index + 1);
+        let middle = (first + last) as f64 / 2.0 / fps;
+        let mut cmd = thumbnail_command(ffmpeg, source, middle, &dir.join(&name));
+        process::contain(&mut cmd);
+        debug!("Command: {}", ffmpeg::display_command(&cmd));
+        let result = cmd.output().map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffmpeg.to_path_buf()),
+            _ => DeliveryError::io("Failed to execute ffmpeg", e),
+        })?;
+        let thumbnail = if result.status.success() {
+            format!("{}/{}", thumbnail_dir(stem), name)
+        } else {
+            let stderr = String::from_utf8_lossy(&result.stderr);
+            warning!("⚠️ No thumbnail for shot {}: {}", index + 1, stderr.trim());
+            String::new()
+        };
+        shots.push(Shot {
+            shot: index + 1,
+            start_timecode: timecode::from_seconds(first as f64 / fps, fps),
+            end_timecode: timecode::from_seconds(last as f64 / fps, fps),
+            first_frame: first,
+            last_frame: last,
+            thumbnail,
+        });
+    }
+
+    let text = match format {
+        ShotListFormat::Json => {
+            serde_json::to_string_pretty(&shots).map_err(|e| DeliveryError::Config(e.to_string()))? + "\n"
+        }
+        ShotListFormat::Csv => {
+            let mut text = "shot,start_timecode,end_timecode,first_frame,last_frame,thumbnail\n".to_string();
+            for shot in &shots {
+                text.push_str(&format!(
+                    "{},{},{},{},{},{}\n",
+                    shot.shot,
+                    shot.start_timecode,
+                    shot.end_timecode,
+                    shot.first_frame,
+                    shot.last_frame,
+                    csv_field(&shot.thumbnail)
+                ));
+            }
+            text
+        }
+    };
+    let path = output_dir.join(file_name(stem, format));
+    fs::write(&path, text).map_err(|e| DeliveryError::io(format!("Failed to write {}", path.display()), e))?;
+    info!("✅ Exported the shot list in {:.2} seconds", started.elapsed().as_secs_f32());
+    Ok(path)
+}
+
+// `text` quoted for CSV if it needs to be.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
@@ -0,0 +1,191 @@
+use crate::console::{debug, info};
+use crate::smpte::EditRate;
+use crate::{ffmpeg, process, DeliveryError, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::Instant;
+
+/// Default time the slate is shown for, in seconds.
+pub const DEFAULT_DURATION: f64 = 10.0;
+
+/// Time from the 2-pop to the first frame of the program, in seconds.
+pub const POP_LEAD: f64 = 2.0;
+
+/// Directory in the segments directory the slate is encoded to.
+pub const SLATE_DIR: &str = "slate";
+
+/// Frequency of the 2-pop tone in Hz.
+const POP_FREQUENCY: u32 = 1000;
+
+/// Amplitude of the 2-pop tone: -20 dBFS.
+const POP_AMPLITUDE: f64 = 0.1;
+
+/// A slate shown before the program, saying what the delivery is, and
+/// optionally followed by a 2-pop: black with a frame of tone (and a "2"
+/// flashing on it) two seconds before the first frame of the program.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Slate {
+    /// Title of the program; the job id or output name if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub episode: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Date of the delivery; the day the job was planned if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    /// Time the slate is shown for, in seconds.
+    pub duration: f64,
+    /// Follow the slate with a 2-pop.
+    #[serde(default)]
+    pub two_pop: bool,
+}
+
+impl Default for Slate {
+    fn default() -> Slate {
+        Slate { title: None, episode: None, version: None, date: None, duration: DEFAULT_DURATION, two_pop: false }
+    }
+}
+
+impl Slate {
+    /// Time before the first frame of the program, in seconds: the slate and
+    /// the 2-pop's lead.
+    pub fn pre_roll(&self) -> f64 {
+        self.duration + if self.two_pop { POP_LEAD } else { 0.0 }
+    }
+
+    /// Number of frames before the program at `fps`.
+    pub fn frames(&self, fps: f64) -> u64 {
+        self.pop_frame(fps) + if self.two_pop { (POP_LEAD * fps).round() as u64 } else { 0 }
+    }
+
+    // Frame the 2-pop is on, right after the slate, counted from the first
+    // frame of the slate.
+    fn pop_frame(&self, fps: f64) -> u64 {
+        (self.duration * fps).round() as u64
+    }
+
+    /// The lines written on the slate: the title, then the episode, version
+    /// and date if known, the program's running time `trt` and its `audio`
+    /// layout.
+    pub fn lines(&self, trt: &str, audio: &str) -> Vec<String> {
+        let mut lines = vec![self.title.clone().unwrap_or_default()];
+        for (label, value) in [("Episode", &self.episode), ("Version", &self.version), ("Date", &self.date)] {
+            if let Some(value) = value {
+                lines.push(format!("{}: {}", label, value));
+            }
+        }
+        lines.push(format!("TRT: {}", trt));
+        lines.push(format!("Audio: {}", audio));
+        lines
+    }
+
+    /// The filters drawing `lines` centred on black frames `height` pixels
+    /// high at `fps`, the title larger, for as long as the slate is shown;
+    /// with a 2-pop, a "2" on the frame of the tone.
+    pub fn video_filter(&self, lines: &[String], height: u32, fps: f64) -> String {
+        let (title_size, size) = ((height / 12).max(1), (height / 24).max(1));
+        let pitch = size * 3 / 2;
+        let block = title_size + size + pitch * lines.len().saturating_sub(1) as u32;
+        let shown = ffmpeg::escape_filter_value(&format!("lt(n,{})", self.pop_frame(fps)));
+        let mut filters = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            let (font_size, y) = match index {
+                0 => (title_size, 0),
+                _ => (size, title_size + size + pitch * (index as u32 - 1)),
+            };
+            filters.push(format!(
+                "drawtext=text={}:expansion=none:fontsize={}:fontcolor=white:x=(w-tw)/2:y=(h-{})/2+{}:enable={}",
+                ffmpeg::escape_filter_value(line),
+                font_size,
+                block,
+                y,
+                shown
+            ));
+        }
+        if self.two_pop {
+            let pop = ffmpeg::escape_filter_value(&format!("eq(n,{})", self.pop_frame(fps)));
+            filters.push(format!(
+                "drawtext=text=2:fontsize={}:fontcolor=white:x=(w-tw)/2:y=(h-th)/2:enable={}",
+                height / 3,
+                pop
+            ));
+        }
+        filters.join(",")
+    }
+
+    /// The filter delaying an audio stream by the pre-roll at `fps`, adding
+    /// the tone of the 2-pop to every channel.
+    pub fn audio_filter(&self, fps: f64) -> String {
+        let delay = self.frames(fps) as f64 / fps * 1000.0;
+        let mut filter = format!("adelay=delays={:.3}:all=1", delay);
+        if self.two_pop {
+            let (start, end) = (self.pop_frame(fps) as f64 / fps, (self.pop_frame(fps) + 1) as f64 / fps);
+            let tone = format!(
+                "val(ch)+{}*sin(2*PI*{}*t)*gte(t,{:.6})*lt(t,{:.6})",
+                POP_AMPLITUDE, POP_FREQUENCY, start, end
+            );
+            filter.push_str(&format!(",aeval=exprs={}:c=same", ffmpeg::escape_filter_value(&tone)));
+        }
+        filter
+    }
+
+    /// The ffmpeg invocation encoding the slate (and 2-pop) of a `width` x
+    /// `height` program at `fps`, showing `lines`, to `output` with
+    /// `encoder_args`. It has no audio; [`Slate::audio_filter`] makes room
+    /// for it in the program's.
+    pub fn encode_command(
+        &self,
+        ffmpeg: &Path,
+        lines: &[String],
+        (width, height): (u32, u32),
+        fps: f64,
+        encoder_args: &[String],
+        output: &Path,
+    ) -> Command {
+        let rate = EditRate::from_fps(fps);
+        let source = format!("color=c=black:s={}x{}:r={}/{}", width, height, rate.numerator, rate.denominator);
+        let mut cmd = Command::new(ffmpeg);
+        cmd.args(["-v", "error", "-nostdin", "-f", "lavfi", "-i", &source])
+            .args(["-vf", &self.video_filter(lines, height, fps)])
+            .args(["-frames:v", &self.frames(fps).to_string(), "-an"])
+            .args(encoder_args)
+            .arg("-y")
+            .arg(ffmpeg::path_arg(output));
+        cmd
+    }
+
+    /// Encode the slate as [`Slate::encode_command`] does.
+    pub fn encode(
+        &self,
+        ffmpeg: &Path,
+        lines: &[String],
+        size: (u32, u32),
+        fps: f64,
+        encoder_args: &[String],
+        output: &Path,
+    ) -> Result<()> {
+        let _span = tracing::info_span!("slate").entered();
+        info!("\n🎬 Encoding a {:.1}s slate...", self.pre_roll());
+        let started = Instant::now();
+        if let Some(dir) = output.parent() {
+            fs::create_dir_all(dir).map_err(|e| DeliveryError::io(format!("Failed to create {}", dir.display()), e))?;
+        }
+        let mut cmd = self.encode_command(ffmpeg, lines, size, fps, encoder_args, output);
+        process::contain(&mut cmd);
+        debug!("Command: {}", ffmpeg::display_command(&cmd));
+        let result = cmd.output().map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffmpeg.to_path_buf()),
+            _ => DeliveryError::io("Failed to execute ffmpeg", e),
+        })?;
+        if !result.status.success() {
+            let stderr = String::from_utf8_lossy(&result.stderr);
+            return Err(DeliveryError::JoinFailed(format!("encoding the slate: {}", stderr.trim())));
+        }
+        info!("✅ Encoded the slate in {:.2} seconds", started.elapsed().as_secs_f32());
+        Ok(())
+    }
+}
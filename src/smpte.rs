@@ -0,0 +1,390 @@
+use crate::clock::UtcTime;
+use crate::console::debug;
+use crate::{fetch, ffmpeg, process, DeliveryError, Result};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Issuer and creator written into package XML.
+pub const CREATOR: &str = concat!("delivery_encoder ", env!("CARGO_PKG_VERSION"));
+
+static UUIDS: AtomicU64 = AtomicU64::new(0);
+
+/// A fresh random (version 4) UUID, as `urn:uuid:` identifiers take it.
+/// The bits come from hashing the time, the process and a counter, which
+/// is plenty to keep the assets of one package (and of separate runs)
+/// apart.
+pub fn uuid() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or_default();
+    let mut hasher = Sha256::new();
+    hasher.update(nanos.to_le_bytes());
+    hasher.update(std::process::id().to_le_bytes());
+    hasher.update(UUIDS.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    let mut bytes = hasher.finalize()[..16].to_vec();
+    bytes[6] = bytes[6] & 0x0f | 0x40;
+    bytes[8] = bytes[8] & 0x3f | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// Current time as the `IssueDate` of package XML.
+pub fn issue_date() -> String {
+    UtcTime::now().rfc3339()
+}
+
+/// Rate of a track in edit units per second, written as `<numerator>
+/// <denominator>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EditRate {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl EditRate {
+    /// The edit rate of video at `fps` frames per second: NTSC rates such
+    /// as 23.976 become `24000 1001`, anything else is rounded to whole
+    /// frames.
+    pub fn from_fps(fps: f64) -> EditRate {
+        let whole = (fps * 1.001).round();
+        if fps.fract() > 0.0 && (fps - whole / 1.001).abs() < 0.005 {
+            EditRate { numerator: whole as u64 * 1000, denominator: 1001 }
+        } else {
+            EditRate { numerator: (fps.round() as u64).max(1), denominator: 1 }
+        }
+    }
+
+    /// Edit units of `other` in `units` edit units of this rate.
+    pub fn convert(self, units: u64, other: EditRate) -> u64 {
+        let scale = other.numerator as u128 * self.denominator as u128;
+        let divisor = other.denominator as u128 * self.numerator as u128;
+        ((units as u128 * scale + divisor / 2) / divisor) as u64
+    }
+}
+
+impl fmt::Display for EditRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.numerator, self.denominator)
+    }
+}
+
+/// `text` with the characters XML reserves escaped.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Standard base64 with padding, as XML digests are written.
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Name of the volume index, which says the package fills one volume.
+pub const VOLINDEX: &str = "VOLINDEX.xml";
+
+/// Digest a packing list records its assets with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// SHA-1, which DCPs require and IMF assumes unless told otherwise.
+    Sha1,
+    /// SHA-256, which an IMF packing list can declare.
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Base64 digest of a file's contents.
+    pub fn file_digest(self, path: &Path) -> Result<String> {
+        match self {
+            HashAlgorithm::Sha1 => Ok(base64(&sha1_file(path)?)),
+            HashAlgorithm::Sha256 => Ok(base64(&fetch::sha256_digest(path)?)),
+        }
+    }
+
+    /// XML Signature identifier of the algorithm.
+    pub fn uri(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha1 => "http://www.w3.org/2000/09/xmldsig#sha1",
+            HashAlgorithm::Sha256 => "http://www.w3.org/2001/04/xmlenc#sha256",
+        }
+    }
+}
+
+/// SHA-1 of a file's contents. SHA-1 is only used to identify package
+/// assets, as the DCP specifications require; nothing relies on it for
+/// security.
+pub fn sha1_file(path: &Path) -> Result<Vec<u8>> {
+    let mut file = File::open(path)
+        .map_err(|e| DeliveryError::io(format!("Failed to open {}", path.display()), e))?;
+    let mut hasher = Sha1::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file
+            .read(&mut buf)
+            .map_err(|e| DeliveryError::io(format!("Failed to read {}", path.display()), e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+/// One file of a package, as the packing list and asset map list it.
+#[derive(Debug, Clone)]
+pub struct Asset {
+    /// UUID of the asset.
+    pub id: String,
+    /// File name, relative to the package directory.
+    pub file: String,
+    /// MIME type.
+    pub kind: &'static str,
+    pub size: u64,
+    /// Base64 digest of the file.
+    pub hash: String,
+}
+
+impl Asset {
+    /// Describe `file` in `dir` as the asset `id`, hashed with `algorithm`.
+    pub fn read(dir: &Path, id: &str, file: String, kind: &'static str, algorithm: HashAlgorithm) -> Result<Asset> {
+        let path = dir.join(&file);
+        let size = fs::metadata(&path)
+            .map_err(|e| DeliveryError::io(format!("Failed to read {}", path.display()), e))?
+            .len();
+        let hash = algorithm.file_digest(&path)?;
+        Ok(Asset { id: id.to_string(), file, kind, size, hash })
+    }
+}
+
+/// Packing list `id` of `assets` in the XML namespace `namespace` (SMPTE
+/// ST 429-8 for DCPs, ST 2067-2 for IMF). The algorithm is only declared
+/// when it isn't SHA-1, which DCP packing lists can't do.
+pub fn pkl(namespace: &str, id: &str, title: &str, issued: &str, algorithm: HashAlgorithm, assets: &[Asset]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!("<PackingList xmlns=\"{}\">\n", namespace));
+    xml.push_str(&format!("  <Id>urn:uuid:{}</Id>\n", id));
+    xml.push_str(&format!("  <AnnotationText>{}</AnnotationText>\n", escape(title)));
+    xml.push_str(&format!("  <IssueDate>{}</IssueDate>\n", issued));
+    xml.push_str(&format!("  <Issuer>{}</Issuer>\n  <Creator>{}</Creator>\n", CREATOR, CREATOR));
+    xml.push_str("  <AssetList>\n");
+    for asset in assets {
+        let file = escape(&asset.file);
+        xml.push_str("    <Asset>\n");
+        xml.push_str(&format!("      <Id>urn:uuid:{}</Id>\n", asset.id));
+        xml.push_str(&format!("      <AnnotationText>{}</AnnotationText>\n", file));
+        xml.push_str(&format!("      <Hash>{}</Hash>\n", asset.hash));
+        xml.push_str(&format!("      <Size>{}</Size>\n      <Type>{}</Type>\n", asset.size, asset.kind));
+        xml.push_str(&format!("      <OriginalFileName>{}</OriginalFileName>\n", file));
+        if algorithm != HashAlgorithm::Sha1 {
+            xml.push_str(&format!("      <HashAlgorithm Algorithm=\"{}\"/>\n", algorithm.uri()));
+        }
+        xml.push_str("    </Asset>\n");
+    }
+    xml.push_str("  </AssetList>\n</PackingList>\n");
+    xml
+}
+
+/// Asset map (SMPTE ST 429-9) `id` locating `assets` and the packing list
+/// `pkl` on a single volume.
+pub fn asset_map(id: &str, title: &str, issued: &str, pkl: &Asset, assets: &[Asset]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<AssetMap xmlns=\"http://www.smpte-ra.org/schemas/429-9/2007/AM\">\n");
+    xml.push_str(&format!("  <Id>urn:uuid:{}</Id>\n", id));
+    xml.push_str(&format!("  <AnnotationText>{}</AnnotationText>\n", escape(title)));
+    xml.push_str(&format!("  <Creator>{}</Creator>\n  <VolumeCount>1</VolumeCount>\n", CREATOR));
+    xml.push_str(&format!("  <IssueDate>{}</IssueDate>\n  <Issuer>{}</Issuer>\n", issued, CREATOR));
+    xml.push_str("  <AssetList>\n");
+    for asset in std::iter::once(pkl).chain(assets) {
+        xml.push_str(&format!("    <Asset>\n      <Id>urn:uuid:{}</Id>\n", asset.id));
+        if asset.id == pkl.id {
+            xml.push_str("      <PackingList>true</PackingList>\n");
+        }
+        xml.push_str("      <ChunkList>\n        <Chunk>\n");
+        xml.push_str(&format!("          <Path>{}</Path>\n", escape(&asset.file)));
+        xml.push_str("          <VolumeIndex>1</VolumeIndex>\n          <Offset>0</Offset>\n");
+        xml.push_str(&format!("          <Length>{}</Length>\n", asset.size));
+        xml.push_str("        </Chunk>\n      </ChunkList>\n    </Asset>\n");
+    }
+    xml.push_str("  </AssetList>\n</AssetMap>\n");
+    xml
+}
+
+/// Volume index of the first (only) volume of a package.
+pub fn volume_index() -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<VolumeIndex xmlns=\"http://www.smpte-ra.org/schemas/429-9/2007/AM\">\n");
+    xml.push_str("  <Index>1</Index>\n</VolumeIndex>\n");
+    xml
+}
+
+/// The ffmpeg invocation that rewraps the video stream of `video` into the
+/// MXF track file `track`, without re-encoding.
+pub fn video_track_command(ffmpeg: &Path, video: &Path, track: &Path) -> Command {
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-v", "error", "-i"]).arg(ffmpeg::path_arg(video));
+    cmd.args(["-map", "0:v:0", "-c", "copy", "-f", "mxf", "-y"]).arg(ffmpeg::path_arg(track));
+    cmd
+}
+
+/// The ffmpeg invocation that takes the first `seconds` of the audio of
+/// `source` (whose audio streams have `streams` channels each) into the
+/// MXF track file `track`, as one 24-bit 48 kHz PCM track carrying every
+/// channel.
+pub fn audio_track_command(ffmpeg: &Path, source: &Path, streams: &[u32], seconds: f64, track: &Path) -> Command {
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-v", "error", "-i"]).arg(ffmpeg::path_arg(source));
+    if streams.len() > 1 {
+        let inputs: String = (0..streams.len()).map(|i| format!("[0:a:{}]", i)).collect();
+        cmd.args(["-filter_complex", &format!("{}amerge=inputs={}[audio]", inputs, streams.len())]);
+        cmd.args(["-map", "[audio]"]);
+    } else {
+        cmd.args(["-map", "0:a:0"]);
+    }
+    cmd.args(["-c:a", "pcm_s24le", "-ar", "48000", "-t", &format!("{:.6}", seconds), "-f", "mxf", "-y"])
+        .arg(ffmpeg::path_arg(track));
+    cmd
+}
+
+/// Run one of the ffmpeg invocations that build a package.
+pub fn run(ffmpeg: &Path, mut cmd: Command) -> Result<()> {
+    process::contain(&mut cmd);
+    debug!("Command: {}", ffmpeg::display_command(&cmd));
+    let result = cmd.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffmpeg.to_path_buf()),
+        _ => DeliveryError::io("Failed to execute ffmpeg", e),
+    })?;
+    if !result.status.success() {
+        return Err(DeliveryError::PackageFailed(String::from_utf8_lossy(&result.stderr).trim().to_string()));
+    }
+    Ok(())
+}
+
+/// Write the XML document `xml` to `file` in `dir`.
+pub fn write(dir: &Path, file: &str, xml: &str) -> Result<()> {
+    let path = dir.join(file);
+    fs::write(&path, xml).map_err(|e| DeliveryError::io(format!("Failed to write {}", path.display()), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn sha1_file_digests_the_contents() {
+        let path = env::temp_dir().join(format!("delivery_encoder_sha1_{}", std::process::id()));
+        fs::write(&path, "abc").unwrap();
+        let digest = HashAlgorithm::Sha1.file_digest(&path);
+        fs::remove_file(&path).unwrap();
+        // FIPS 180 test vector for "abc"
+        assert_eq!(digest.unwrap(), "qZk+NkcGgWq6PiVxeFDCbJzQ2J0=");
+    }
+
+    #[test]
+    fn base64_pads_to_whole_quads() {
+        // RFC 4648 test vectors
+        let cases = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (bytes, encoded) in cases {
+            assert_eq!(base64(bytes.as_bytes()), encoded, "{:?}", bytes);
+        }
+        assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+    }
+
+    #[test]
+    fn escape_replaces_xml_reserved_characters() {
+        assert_eq!(escape("Episode 101"), "Episode 101");
+        assert_eq!(escape("Tom & Jerry"), "Tom &amp; Jerry");
+        assert_eq!(escape("<b>\"it's\"</b>"), "&lt;b&gt;&quot;it&apos;s&quot;&lt;/b&gt;");
+        assert_eq!(escape("&amp;"), "&amp;amp;");
+        assert_eq!(escape("Café"), "Café");
+    }
+
+    fn asset(id: &str, file: &str, size: u64, hash: &str) -> Asset {
+        Asset { id: id.to_string(), file: file.to_string(), kind: "application/mxf", size, hash: hash.to_string() }
+    }
+
+    #[test]
+    fn pkl_lists_every_asset_with_its_hash() {
+        let assets = [
+            asset("aaaa", "VIDEO_aaaa.mxf", 1000, "qZk+NkcGgWq6PiVxeFDCbJzQ2J0="),
+            asset("bbbb", "A&B.mxf", 20, "Zm9v"),
+        ];
+        let xml = pkl("urn:test:pkl", "pkl-id", "Tom & Jerry", "2026-10-16T00:00:00Z", HashAlgorithm::Sha1, &assets);
+
+        for element in [
+            "<PackingList xmlns=\"urn:test:pkl\">",
+            "<Id>urn:uuid:pkl-id</Id>",
+            "<AnnotationText>Tom &amp; Jerry</AnnotationText>",
+            "<IssueDate>2026-10-16T00:00:00Z</IssueDate>",
+            &format!("<Issuer>{}</Issuer>", CREATOR),
+            &format!("<Creator>{}</Creator>", CREATOR),
+            "<Id>urn:uuid:aaaa</Id>",
+            "<Hash>qZk+NkcGgWq6PiVxeFDCbJzQ2J0=</Hash>",
+            "<Size>1000</Size>",
+            "<Id>urn:uuid:bbbb</Id>",
+            "<Hash>Zm9v</Hash>",
+            "<OriginalFileName>A&amp;B.mxf</OriginalFileName>",
+        ] {
+            assert!(xml.contains(element), "missing {}", element);
+        }
+        assert_eq!(xml.matches("<Asset>").count(), 2);
+        assert!(!xml.contains("HashAlgorithm"));
+
+        let sha256 = pkl("urn:test:pkl", "pkl-id", "Title", "2026-10-16T00:00:00Z", HashAlgorithm::Sha256, &assets);
+        let declared = format!("<HashAlgorithm Algorithm=\"{}\"/>", HashAlgorithm::Sha256.uri());
+        assert_eq!(sha256.matches(&declared).count(), 2);
+    }
+
+    #[test]
+    fn asset_map_locates_the_packing_list_first() {
+        let packing_list = asset("pkl-id", "PKL_pkl-id.xml", 500, "");
+        let assets = [asset("aaaa", "VIDEO_aaaa.mxf", 1000, ""), asset("bbbb", "AUDIO_bbbb.mxf", 20, "")];
+        let xml = asset_map("map-id", "Title", "2026-10-16T00:00:00Z", &packing_list, &assets);
+
+        assert!(xml.contains("<Id>urn:uuid:map-id</Id>"));
+        assert!(xml.contains("<VolumeCount>1</VolumeCount>"));
+        assert_eq!(xml.matches("<Asset>").count(), 3);
+        assert_eq!(xml.matches("<PackingList>true</PackingList>").count(), 1);
+        let pkl_at = xml.find("<Id>urn:uuid:pkl-id</Id>").unwrap();
+        assert!(pkl_at < xml.find("<PackingList>true</PackingList>").unwrap());
+        assert!(xml.find("<PackingList>true</PackingList>").unwrap() < xml.find("<Id>urn:uuid:aaaa</Id>").unwrap());
+        assert!(xml.contains("<Path>VIDEO_aaaa.mxf</Path>"));
+        assert!(xml.contains("<Path>AUDIO_bbbb.mxf</Path>"));
+        assert!(xml.contains("<Length>1000</Length>"));
+        assert!(xml.contains("<Length>500</Length>"));
+    }
+}
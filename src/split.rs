@@ -0,0 +1,83 @@
+use crate::console::{debug, info};
+use crate::segment::Segment;
+use crate::{ffmpeg, process, DeliveryError, EncodeJob, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Subdirectory of the segments directory the source is split into.
+pub const SOURCE_DIR: &str = "source";
+
+/// List of the pieces written by the segment muxer.
+const LIST_FILE: &str = "pieces.csv";
+
+/// The ffmpeg invocation that copies the input's first video stream, without
+/// re-encoding, into one file per segment. Pieces are cut at the first
+/// keyframe at or after each segment's start.
+pub fn split_command(job: &EncodeJob, segments: &[Segment]) -> Command {
+    let dir = job.segments_dir.join(SOURCE_DIR);
+    let times: Vec<String> = segments.iter().skip(1).map(|s| format!("{:.6}", s.start)).collect();
+    let mut cmd = Command::new(&job.ffmpeg);
+    cmd.args(["-v", "error"])
+        .arg("-i").arg(ffmpeg::path_arg(&job.input))
+        .args(["-map", "0:v:0", "-c", "copy", "-f", "segment"])
+        .args(["-segment_times", &times.join(",")])
+        .args(["-reset_timestamps", "1"])
+        .arg("-segment_list").arg(ffmpeg::path_arg(&dir.join(LIST_FILE)))
+        .args(["-segment_list_type", "csv"])
+        .arg("-y").arg(ffmpeg::sequence_pattern(&dir, "piece_%05d.mkv"));
+    cmd
+}
+
+/// Split the input into one piece per segment with [`split_command`] and
+/// return segments covering exactly those pieces. As pieces start on
+/// keyframes, their boundaries can differ slightly from `segments`.
+pub fn split_source(job: &EncodeJob, segments: &[Segment]) -> Result<Vec<Segment>> {
+    let _span = tracing::info_span!("split", segments = segments.len()).entered();
+    info!("\n✂️ Splitting source into {} pieces at keyframes...", segments.len());
+    let dir = job.segments_dir.join(SOURCE_DIR);
+    fs::create_dir_all(&dir)
+        .map_err(|e| DeliveryError::io(format!("Failed to create {}", dir.display()), e))?;
+
+    let mut cmd = split_command(job, segments);
+    process::contain(&mut cmd);
+    debug!("Command: {}", ffmpeg::display_command(&cmd));
+    let output = cmd.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(job.ffmpeg.clone()),
+        _ => DeliveryError::io("Failed to execute ffmpeg", e),
+    })?;
+    if !output.status.success() {
+        return Err(DeliveryError::SplitFailed(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+
+    // Each line is `file,start,end` with times in seconds
+    let list_path = dir.join(LIST_FILE);
+    let list = fs::read_to_string(&list_path)
+        .map_err(|e| DeliveryError::io(format!("Failed to read {}", list_path.display()), e))?;
+    let pieces: Vec<Segment> = list
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.trim().rsplitn(3, ',');
+            let end: f64 = fields.next()?.parse().ok()?;
+            let start: f64 = fields.next()?.parse().ok()?;
+            let file = fields.next()?.trim_matches('"');
+            Some((file.to_string(), start, end))
+        })
+        .enumerate()
+        .map(|(id, (file, start, end))| {
+            let duration = end - start;
+            Segment {
+                id,
+                start,
+                duration,
+                frames: None,
+                source: Some(PathBuf::from(SOURCE_DIR).join(file)),
+            }
+        })
+        .collect();
+    if pieces.is_empty() {
+        return Err(DeliveryError::Config(format!("{} lists no pieces", list_path.display())));
+    }
+    info!("✅ Split source into {} pieces", pieces.len());
+    Ok(pieces)
+}
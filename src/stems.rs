@@ -0,0 +1,115 @@
+use crate::console::{debug, info};
+use crate::{ffmpeg, output, process, DeliveryError, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Format the source's audio tracks are extracted to, one file per track,
+/// next to the frames or video.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StemFormat {
+    /// 24-bit PCM WAV at the source's sample rate.
+    Wav,
+    /// AAC at 320 kb/s in M4A.
+    Aac,
+}
+
+impl StemFormat {
+    /// File extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            StemFormat::Wav => "wav",
+            StemFormat::Aac => "m4a",
+        }
+    }
+
+    /// `-c:a` and the encoder options of the format.
+    pub fn encoder_args(self) -> &'static [&'static str] {
+        match self {
+            StemFormat::Wav => &["-c:a", "pcm_s24le"],
+            StemFormat::Aac => &["-c:a", "aac", "-b:a", "320k"],
+        }
+    }
+}
+
+impl FromStr for StemFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<StemFormat, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "wav" => Ok(StemFormat::Wav),
+            "aac" => Ok(StemFormat::Aac),
+            _ => Err(format!("invalid audio stem format '{}', expected wav or aac", text)),
+        }
+    }
+}
+
+impl fmt::Display for StemFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StemFormat::Wav => "wav",
+            StemFormat::Aac => "aac",
+        })
+    }
+}
+
+/// File name of the stem of the `track`th (from 0) audio stream:
+/// `<stem>_audio<N>.<ext>`, numbered from 1.
+pub fn file_name(stem: &str, track: usize, format: StemFormat) -> String {
+    format!("{}_audio{}.{}", stem, track + 1, format.extension())
+}
+
+/// The ffmpeg invocation that writes the `track`th audio stream of `source`
+/// to `output` in `format`.
+pub fn stem_command(ffmpeg: &Path, source: &Path, track: usize, format: StemFormat, output: &Path) -> Command {
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-v", "error", "-i"]).arg(ffmpeg::path_arg(source));
+    cmd.args(["-map", &format!("0:a:{}", track), "-vn"]).args(format.encoder_args());
+    cmd.arg("-y").arg(ffmpeg::path_arg(output));
+    cmd
+}
+
+/// Extract each of the `tracks` audio streams of `source` to its own file in
+/// `output_dir`, named after `stem`. Returns the paths of the files.
+pub fn extract(
+    ffmpeg: &Path,
+    source: &Path,
+    tracks: usize,
+    format: StemFormat,
+    stem: &str,
+    output_dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    let _span = tracing::info_span!("stems", tracks).entered();
+    info!("\n🎧 Extracting {} audio track(s) to {}...", tracks, format);
+    let started = Instant::now();
+
+    let staging = output::staging_dir(output_dir);
+    output::prepare_staging(&staging)?;
+    let mut stems = Vec::with_capacity(tracks);
+    for track in 0..tracks {
+        let name = file_name(stem, track, format);
+        let mut cmd = stem_command(ffmpeg, source, track, format, &staging.join(&name));
+        process::contain(&mut cmd);
+        debug!("Command: {}", ffmpeg::display_command(&cmd));
+        let result = cmd.output().map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffmpeg.to_path_buf()),
+            _ => DeliveryError::io("Failed to execute ffmpeg", e),
+        })?;
+        if !result.status.success() {
+            let stderr = String::from_utf8_lossy(&result.stderr).trim().to_string();
+            return Err(DeliveryError::AudioFailed(format!("track {}: {}", track + 1, stderr)));
+        }
+        stems.push(name);
+    }
+    let mut paths = Vec::with_capacity(stems.len());
+    for name in stems {
+        output::publish_file(&staging.join(&name), output_dir)?;
+        paths.push(output_dir.join(name));
+    }
+    info!("✅ Extracted the audio in {:.2} seconds", started.elapsed().as_secs_f32());
+    Ok(paths)
+}
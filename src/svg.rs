@@ -0,0 +1,117 @@
+use crate::console::{debug, info};
+use crate::overlay::OverlayScale;
+use crate::{ffmpeg, process, DeliveryError, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+
+/// Directory in the segments directory SVG overlays are rasterized to.
+pub const SVG_DIR: &str = "svg";
+
+/// Whether the overlay at `path` is an SVG drawing.
+pub fn is_svg(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("svg") || e.eq_ignore_ascii_case("svgz"))
+}
+
+/// An SVG overlay drawn to a PNG at the size it is composited at, so vector
+/// artwork stays sharp at any resolution instead of being scaled as pixels.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Raster {
+    /// PNG the drawing is written to.
+    pub path: PathBuf,
+    /// Width or height it is drawn at, the other side following its aspect
+    /// ratio; neither draws it at its own size.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+}
+
+impl Raster {
+    /// The raster of the SVG at `path` scaled by `scale` against a `width`
+    /// x `height` video (as scale2ref would, so it then scales it by
+    /// nothing), written to the segments directory `segments_dir`.
+    pub fn new(path: &Path, scale: Option<OverlayScale>, (width, height): (u32, u32), segments_dir: &Path) -> Raster {
+        // Without the video's size, draw it at its own and let scale2ref size it
+        let share = |side: u32, share: f64| Some((side as f64 * share) as u32).filter(|&pixels| pixels > 0);
+        let (width, height) = match scale {
+            Some(OverlayScale::Width(part)) => (share(width, part), None),
+            Some(OverlayScale::Height(part)) => (None, share(height, part)),
+            None => (None, None),
+        };
+        let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        // Drawings of the same name from different directories mustn't clash
+        let digest = Sha256::digest(path.as_os_str().as_encoded_bytes());
+        let id: String = digest.iter().take(4).map(|b| format!("{:02x}", b)).collect();
+        let size = match (width, height) {
+            (Some(width), _) => format!("_w{}", width),
+            (_, Some(height)) => format!("_h{}", height),
+            _ => String::new(),
+        };
+        Raster { path: segments_dir.join(SVG_DIR).join(format!("{}_{}{}.png", stem, id, size)), width, height }
+    }
+
+    /// The invocation drawing the SVG at `svg`: resvg if it is on `PATH`,
+    /// else `ffmpeg`, which needs to be built with librsvg.
+    pub fn command(&self, ffmpeg: &Path, svg: &Path) -> Command {
+        match ffmpeg::find_in_path("resvg") {
+            Some(resvg) => {
+                let mut cmd = Command::new(resvg);
+                if let Some(width) = self.width {
+                    cmd.args(["--width", &width.to_string()]);
+                }
+                if let Some(height) = self.height {
+                    cmd.args(["--height", &height.to_string()]);
+                }
+                cmd.arg(svg).arg(&self.path);
+                cmd
+            }
+            None => {
+                let mut cmd = Command::new(ffmpeg);
+                cmd.args(["-v", "error", "-nostdin"]);
+                if let Some(width) = self.width {
+                    cmd.args(["-width", &width.to_string()]);
+                }
+                if let Some(height) = self.height {
+                    cmd.args(["-height", &height.to_string()]);
+                }
+                if self.width.is_some() || self.height.is_some() {
+                    cmd.args(["-keep_ar", "1"]);
+                }
+                cmd.arg("-i").arg(ffmpeg::path_arg(svg));
+                cmd.args(["-frames:v", "1", "-y"]).arg(ffmpeg::path_arg(&self.path));
+                cmd
+            }
+        }
+    }
+
+    /// Draw the SVG at `svg`, as [`Raster::command`] does, unless an earlier
+    /// run already has.
+    pub fn draw(&self, ffmpeg: &Path, svg: &Path) -> Result<()> {
+        if self.path.is_file() {
+            return Ok(());
+        }
+        let started = Instant::now();
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| DeliveryError::io(format!("Failed to create {}", dir.display()), e))?;
+        }
+        let mut cmd = self.command(ffmpeg, svg);
+        process::contain(&mut cmd);
+        debug!("Command: {}", ffmpeg::display_command(&cmd));
+        let result = cmd.output().map_err(|e| DeliveryError::io(format!("Failed to rasterize {}", svg.display()), e))?;
+        if !result.status.success() {
+            let stderr = String::from_utf8_lossy(&result.stderr);
+            let _ = fs::remove_file(&self.path);
+            return Err(DeliveryError::MissingCapability(format!(
+                "Rasterizing {} failed (install resvg, or use an ffmpeg built with librsvg): {}",
+                svg.display(),
+                stderr.trim()
+            )));
+        }
+        info!("🖋 Rasterized {} in {:.2} seconds", svg.display(), started.elapsed().as_secs_f32());
+        Ok(())
+    }
+}
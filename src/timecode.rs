@@ -0,0 +1,168 @@
+use crate::console::warning;
+use crate::probe::MediaInfo;
+use std::fmt;
+use std::str::FromStr;
+
+/// Start timecode written to video output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Timecode {
+    /// The source's own start timecode, if it has one.
+    Source,
+    /// `HH:MM:SS:FF`, or `HH:MM:SS;FF` for drop-frame.
+    At(String),
+}
+
+impl Timecode {
+    /// The timecode the output starts at for a source described by `media`,
+    /// or `None` if the source has none to carry over.
+    pub fn resolve(&self, media: &MediaInfo) -> Option<String> {
+        match self {
+            Timecode::At(timecode) => Some(timecode.clone()),
+            Timecode::Source if media.timecode.is_none() => {
+                warning!("⚠️ The input has no timecode, the output won't carry one");
+                None
+            }
+            Timecode::Source => media.timecode.clone(),
+        }
+    }
+}
+
+/// Fail unless `text` is a timecode as ffmpeg's `-timecode` takes it:
+/// `HH:MM:SS:FF`, with `;` (or `.`) before the frames for drop-frame.
+pub fn check(text: &str) -> std::result::Result<(), String> {
+    let invalid = || format!("invalid timecode '{}', expected HH:MM:SS:FF (HH:MM:SS;FF for drop-frame)", text);
+    let (clock, frames) = text.rsplit_once([':', ';', '.']).ok_or_else(invalid)?;
+    let fields: Vec<&str> = clock.split(':').chain([frames]).collect();
+    if fields.len() != 4 || fields.iter().any(|f| f.len() != 2 || !f.bytes().all(|b| b.is_ascii_digit())) {
+        return Err(invalid());
+    }
+    let values: Vec<u32> = fields.iter().filter_map(|f| f.parse().ok()).collect();
+    if values[0] > 23 || values[1] > 59 || values[2] > 59 {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+/// `seconds` into a file as a non-drop-frame `HH:MM:SS:FF` timecode at
+/// `fps` frames per second (rounded to a whole rate).
+pub fn from_seconds(seconds: f64, fps: f64) -> String {
+    let rate = (fps.round() as u64).max(1);
+    let frames = (seconds.max(0.0) * fps).round() as u64;
+    let (clock, frame) = (frames / rate, frames % rate);
+    format!("{:02}:{:02}:{:02}:{:02}", clock / 3600, clock / 60 % 60, clock % 60, frame)
+}
+
+/// `timecode` moved on by `frames` at `fps` frames per second, counting
+/// drop-frame timecodes (`;` or `.` before the frames) the way 29.97 and
+/// 59.94 fps drop-frame does: frames 0 and 1 (0 to 3 at 59.94) are skipped
+/// every minute except every tenth. Wraps at 24 hours.
+pub fn add_frames(timecode: &str, frames: u64, fps: f64) -> String {
+    let rate = (fps.round() as u64).max(1);
+    let drop = timecode.contains([';', '.']) && rate % 30 == 0;
+    let fields: Vec<u64> = timecode.split([':', ';', '.']).map(|f| f.parse().unwrap_or(0)).collect();
+    let [hours, minutes, seconds, frame] = fields[..] else {
+        return timecode.to_string();
+    };
+    let dropped = if drop { rate / 15 } else { 0 };
+    let total_minutes = hours * 60 + minutes;
+    let day = frames_per_day(rate, dropped) as u128;
+    let skipped = dropped * (total_minutes - total_minutes / 10);
+    let number = (hours * 3600 + minutes * 60 + seconds) * rate + frame - skipped;
+    let mut number = ((number as u128 + frames as u128) % day) as u64;
+    if drop {
+        let (per_ten_minutes, per_minute) = (rate * 600 - dropped * 9, rate * 60 - dropped);
+        let (tens, rest) = (number / per_ten_minutes, number % per_ten_minutes);
+        number += dropped * 9 * tens + if rest > dropped { dropped * ((rest - dropped) / per_minute) } else { 0 };
+    }
+    let (clock, frame) = (number / rate, number % rate);
+    let separator = if drop { ';' } else { ':' };
+    format!("{:02}:{:02}:{:02}{}{:02}", clock / 3600, clock / 60 % 60, clock % 60, separator, frame)
+}
+
+/// `timecode` moved back by `frames` at `fps` frames per second, as
+/// [`add_frames`] counts them, e.g. to where a slate before it starts.
+pub fn subtract_frames(timecode: &str, frames: u64, fps: f64) -> String {
+    let rate = (fps.round() as u64).max(1);
+    let dropped = if timecode.contains([';', '.']) && rate % 30 == 0 { rate / 15 } else { 0 };
+    let day = frames_per_day(rate, dropped);
+    add_frames(timecode, day - frames % day, fps)
+}
+
+// Timecodes in a day at `rate`, `dropped` of which are skipped in every
+// minute but every tenth.
+fn frames_per_day(rate: u64, dropped: u64) -> u64 {
+    24 * 3600 * rate - dropped * (24 * 60 - 24 * 6)
+}
+
+impl FromStr for Timecode {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Timecode, String> {
+        let text = text.trim();
+        if text.eq_ignore_ascii_case("source") {
+            return Ok(Timecode::Source);
+        }
+        check(text)?;
+        Ok(Timecode::At(text.to_string()))
+    }
+}
+
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Timecode::Source => f.write_str("source"),
+            Timecode::At(timecode) => f.write_str(timecode),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_takes_ffmpeg_timecodes() {
+        assert!(check("01:00:00:00").is_ok());
+        assert!(check("01:00:00;00").is_ok());
+        assert!(check("23:59:59.29").is_ok());
+        for invalid in ["24:00:00:00", "01:60:00:00", "1:00:00:00", "01:00:00", "01:00:00:0a", ""] {
+            assert!(check(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parse_takes_source_or_a_timecode() {
+        assert_eq!("Source".parse::<Timecode>(), Ok(Timecode::Source));
+        assert_eq!(" 10:00:00:00 ".parse::<Timecode>(), Ok(Timecode::At("10:00:00:00".to_string())));
+        assert!("10:00".parse::<Timecode>().is_err());
+    }
+
+    #[test]
+    fn from_seconds_counts_whole_frames() {
+        assert_eq!(from_seconds(3661.52, 25.0), "01:01:01:13");
+        assert_eq!(from_seconds(-1.0, 25.0), "00:00:00:00");
+    }
+
+    #[test]
+    fn add_frames_carries_and_wraps_at_a_day() {
+        assert_eq!(add_frames("01:00:00:00", 25, 25.0), "01:00:01:00");
+        assert_eq!(add_frames("00:59:59:24", 1, 25.0), "01:00:00:00");
+        assert_eq!(add_frames("23:59:59:24", 1, 25.0), "00:00:00:00");
+    }
+
+    #[test]
+    fn add_frames_skips_dropped_frames_but_every_tenth_minute() {
+        assert_eq!(add_frames("00:00:59;29", 1, 29.97), "00:01:00;02");
+        assert_eq!(add_frames("00:09:59;29", 1, 29.97), "00:10:00;00");
+        assert_eq!(add_frames("00:00:59;59", 1, 59.94), "00:01:00;04");
+        // Non-drop-frame at 29.97 counts every timecode
+        assert_eq!(add_frames("00:00:59:29", 1, 29.97), "00:01:00:00");
+    }
+
+    #[test]
+    fn subtract_frames_undoes_add_frames() {
+        assert_eq!(subtract_frames("01:00:00:00", 25, 25.0), "00:59:59:00");
+        assert_eq!(subtract_frames("00:01:00;02", 1, 29.97), "00:00:59;29");
+        assert_eq!(subtract_frames("00:00:00:00", 1, 25.0), "23:59:59:24");
+    }
+}
@@ -0,0 +1,265 @@
+use crate::console::{debug, info};
+use crate::segment::Segment;
+use crate::{ffmpeg, interrupt, process, worker, DeliveryError, EncodeJob, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+/// Directory in the segments directory the first pass writes its
+/// statistics to. It is outside the segment directories so a segment that
+/// is re-encoded after a failure keeps them.
+pub const STATS_DIR: &str = "passlog";
+
+/// How strongly a segment's complexity counts when the target bitrate is
+/// shared between segments; x264's default `qcomp`.
+const COMPLEXITY_WEIGHT: f64 = 0.6;
+
+/// Bitrate of every output (one per rendition) of the segments that aren't
+/// encoded at the job's target bitrates, by segment id.
+pub type Bitrates = BTreeMap<usize, Vec<Option<u64>>>;
+
+/// How a two-pass encode hits its target bitrate.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TwoPass {
+    /// Every segment averages the target bitrate on its own.
+    Segment,
+    /// The first pass of every segment runs before any second pass, and the
+    /// target is shared between the segments by their complexity, so the
+    /// whole video averages it while busy scenes get more than calm ones.
+    Global,
+}
+
+impl FromStr for TwoPass {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<TwoPass, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "segment" => Ok(TwoPass::Segment),
+            "global" => Ok(TwoPass::Global),
+            _ => Err(format!("invalid two-pass mode '{}', expected segment or global", text)),
+        }
+    }
+}
+
+impl fmt::Display for TwoPass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TwoPass::Segment => "segment",
+            TwoPass::Global => "global",
+        })
+    }
+}
+
+/// Statistics file of the first pass of `segment`, for the rendition named
+/// `rendition` if the job has several.
+pub fn stats_file(segments_dir: &Path, segment: &Segment, rendition: Option<&str>) -> PathBuf {
+    let name = match rendition {
+        Some(name) => format!("segment_{}.{}.log", segment.id, name),
+        None => format!("segment_{}.log", segment.id),
+    };
+    segments_dir.join(STATS_DIR).join(name)
+}
+
+// Statistics files of every output of `segment`.
+fn stats_files(job: &EncodeJob, segment: &Segment) -> Vec<PathBuf> {
+    if job.renditions.is_empty() {
+        return vec![stats_file(&job.segments_dir, segment, None)];
+    }
+    job.renditions.iter().map(|r| stats_file(&job.segments_dir, segment, Some(&r.name))).collect()
+}
+
+// Remove what an interrupted first pass of `segment` left. The encoders
+// write to `<stats>.temp` and only rename it once the pass is complete.
+fn remove_stats(job: &EncodeJob, segment: &Segment) -> Result<()> {
+    let dir = job.segments_dir.join(STATS_DIR);
+    let prefix = format!("segment_{}.", segment.id);
+    let entries = fs::read_dir(&dir).map_err(|e| DeliveryError::io(format!("Failed to read {}", dir.display()), e))?;
+    for entry in entries.flatten() {
+        if entry.file_name().to_string_lossy().starts_with(&prefix) {
+            let path = entry.path();
+            fs::remove_file(&path).map_err(|e| DeliveryError::io(format!("Failed to remove {}", path.display()), e))?;
+        }
+    }
+    Ok(())
+}
+
+// Run the first pass of one segment.
+fn first_pass(job: &EncodeJob, segment: &Segment) -> Result<()> {
+    remove_stats(job, segment)?;
+    let mut cmd = worker::encode_command(job, segment, Some(1), &job.target_bitrates(), None);
+    process::contain(&mut cmd);
+    if job.background {
+        process::lower_priority(&mut cmd);
+    }
+    debug!("Command: {}", ffmpeg::display_command(&cmd));
+    let output = cmd.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(job.ffmpeg.clone()),
+        _ => DeliveryError::io("Failed to execute ffmpeg", e),
+    })?;
+    if !output.status.success() {
+        return Err(DeliveryError::SegmentFailed {
+            id: segment.id,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Run the first pass of every segment that has no complete statistics
+/// yet, `threads` segments at a time.
+pub fn first_pass_all(job: &EncodeJob, segments: &[Segment]) -> Result<()> {
+    let dir = job.segments_dir.join(STATS_DIR);
+    fs::create_dir_all(&dir).map_err(|e| DeliveryError::io(format!("Failed to create {}", dir.display()), e))?;
+    let pending: Vec<&Segment> =
+        segments.iter().filter(|s| !stats_files(job, s).iter().all(|f| f.exists())).collect();
+    if pending.is_empty() {
+        debug!("♻️ Reusing the first pass statistics of {} segments", segments.len());
+        return Ok(());
+    }
+
+    let _span = tracing::info_span!("first_pass", segments = pending.len()).entered();
+    info!("\n🔍 Running the first pass of {} segments...", pending.len());
+    let started = Instant::now();
+
+    let total = pending.len();
+    let queue: Mutex<VecDeque<&Segment>> = Mutex::new(pending.into_iter().collect());
+    let failures: Mutex<Vec<(usize, DeliveryError)>> = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..job.threads.clamp(1, total) {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap().pop_front();
+                let Some(segment) = next else { break };
+                if interrupt::requested() {
+                    break;
+                }
+                if let Err(e) = first_pass(job, segment) {
+                    failures.lock().unwrap().push((segment.id, e));
+                }
+            });
+        }
+    });
+
+    interrupt::check()?;
+    let mut failures = failures.into_inner().unwrap();
+    if !failures.is_empty() {
+        failures.sort_by_key(|(id, _)| *id);
+        return Err(failures.remove(0).1);
+    }
+    info!("✅ First pass of {} segments done in {:.2} seconds", total, started.elapsed().as_secs_f32());
+    Ok(())
+}
+
+/// Bits the first pass spent on the frames recorded in `stats`: the sum of
+/// the `tex:`, `mv:` and `misc:` fields of x264 and x265 statistics.
+pub fn first_pass_bits(stats: &Path) -> Result<u64> {
+    let text =
+        fs::read_to_string(stats).map_err(|e| DeliveryError::io(format!("Failed to read {}", stats.display()), e))?;
+    let bits = text
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .flat_map(|line| line.split_whitespace())
+        .filter_map(|field| {
+            let (key, value) = field.split_once(':')?;
+            matches!(key, "tex" | "mv" | "misc").then(|| value.trim_end_matches(';').parse::<u64>().ok())?
+        })
+        .sum();
+    Ok(bits)
+}
+
+// Bitrate of each of `segments`, whose first pass spent `bits` on them,
+// sharing `target` by their complexity; `None` if nothing was measured.
+fn share(target: u64, segments: &[Segment], bits: &[u64]) -> Option<Vec<u64>> {
+    let total_duration: f64 = segments.iter().map(|s| s.duration).sum();
+    let weights: Vec<f64> = segments
+        .iter()
+        .zip(bits)
+        .map(|(segment, &bits)| {
+            let complexity = if segment.duration > 0.0 { bits as f64 / segment.duration } else { 0.0 };
+            complexity.powf(COMPLEXITY_WEIGHT)
+        })
+        .collect();
+    let weighted: f64 = segments.iter().zip(&weights).map(|(s, w)| s.duration * w).sum();
+    if weighted <= 0.0 {
+        return None;
+    }
+    let rate = |weight: &f64| (target as f64 * weight * total_duration / weighted).round().max(1.0) as u64;
+    Some(weights.iter().map(rate).collect())
+}
+
+/// Share the target bitrate of every output between `segments` by the
+/// complexity their first pass measured, so the whole video averages it.
+pub fn allocate(job: &EncodeJob, segments: &[Segment]) -> Result<Bitrates> {
+    let targets = job.target_bitrates();
+    let mut bitrates: Bitrates = segments.iter().map(|s| (s.id, targets.clone())).collect();
+    for (output, target) in targets.iter().enumerate() {
+        let Some(target) = *target else { continue };
+        let bits = segments
+            .iter()
+            .map(|segment| first_pass_bits(&stats_files(job, segment)[output]))
+            .collect::<Result<Vec<_>>>()?;
+        let Some(shares) = share(target, segments, &bits) else { continue };
+        for (segment, rate) in segments.iter().zip(shares) {
+            debug!("📊 Segment {}: {} bit/s", segment.id, rate);
+            if let Some(rates) = bitrates.get_mut(&segment.id) {
+                rates[output] = Some(rate);
+            }
+        }
+        let rates = bitrates.values().filter_map(|r| r[output]);
+        let (min, max) = rates.fold((u64::MAX, 0), |(min, max), r| (min.min(r), max.max(r)));
+        info!("📊 {} bit/s shared between {} segments: {} to {} bit/s", target, segments.len(), min, max);
+    }
+    Ok(bitrates)
+}
+
+/// Run the first pass for `job`'s two-pass mode and work out what the
+/// `pending` segments are encoded at in the second. In global mode every
+/// segment of the job needs its statistics, not just the pending ones.
+pub fn prepare(job: &EncodeJob, segments: &[Segment], pending: &[Segment]) -> Result<Bitrates> {
+    match job.two_pass {
+        None => Ok(Bitrates::new()),
+        Some(TwoPass::Segment) => {
+            first_pass_all(job, pending)?;
+            Ok(Bitrates::new())
+        }
+        Some(TwoPass::Global) => {
+            first_pass_all(job, segments)?;
+            allocate(job, segments)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(id: usize, duration: f64) -> Segment {
+        Segment { id, start: 0.0, duration, frames: None, source: None }
+    }
+
+    #[test]
+    fn equally_complex_segments_get_the_target() {
+        let segments = [segment(0, 2.0), segment(1, 4.0)];
+        assert_eq!(share(5_000_000, &segments, &[1_000_000, 2_000_000]), Some(vec![5_000_000, 5_000_000]));
+    }
+
+    #[test]
+    fn busier_segments_get_more_while_the_video_averages_the_target() {
+        let segments = [segment(0, 2.0), segment(1, 6.0)];
+        let rates = share(4_000_000, &segments, &[8_000_000, 3_000_000]).unwrap();
+        assert!(rates[0] > 4_000_000 && rates[1] < 4_000_000);
+        let average = (rates[0] as f64 * 2.0 + rates[1] as f64 * 6.0) / 8.0;
+        assert!((average - 4_000_000.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn nothing_measured_leaves_the_target() {
+        assert_eq!(share(4_000_000, &[segment(0, 2.0)], &[0]), None);
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+/// Parse a byte size such as `512`, `64K`, `10M`, `2.5G` or `1T` (binary
+/// multiples; an optional trailing `B`/`iB` is accepted).
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let upper = text.trim().to_ascii_uppercase();
+    let trimmed = upper.trim_end_matches("IB").trim_end_matches('B');
+    let (number, multiplier) = match trimmed.chars().last() {
+        Some('K') => (&trimmed[..trimmed.len() - 1], 1u64 << 10),
+        Some('M') => (&trimmed[..trimmed.len() - 1], 1 << 20),
+        Some('G') => (&trimmed[..trimmed.len() - 1], 1 << 30),
+        Some('T') => (&trimmed[..trimmed.len() - 1], 1 << 40),
+        _ => (trimmed, 1),
+    };
+    let value: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid size '{}', expected e.g. 512K, 10M or 2G", text))?;
+    if value < 0.0 || !value.is_finite() {
+        return Err(format!("invalid size '{}'", text));
+    }
+    Ok((value * multiplier as f64) as u64)
+}
+
+/// Parse a bitrate in bits per second such as `800k`, `8M` or `8000000`
+/// (decimal multiples; an optional trailing `bps` or `b/s` is accepted).
+pub fn parse_bitrate(text: &str) -> Result<u64, String> {
+    let lower = text.trim().to_ascii_lowercase();
+    let trimmed = lower.trim_end_matches("bps").trim_end_matches("b/s");
+    let (number, multiplier) = match trimmed.chars().last() {
+        Some('k') => (&trimmed[..trimmed.len() - 1], 1e3),
+        Some('m') => (&trimmed[..trimmed.len() - 1], 1e6),
+        Some('g') => (&trimmed[..trimmed.len() - 1], 1e9),
+        _ => (trimmed, 1.0),
+    };
+    let value: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid bitrate '{}', expected e.g. 800k or 8M", text))?;
+    if value <= 0.0 || !value.is_finite() {
+        return Err(format!("invalid bitrate '{}'", text));
+    }
+    Ok((value * multiplier) as u64)
+}
+
+/// Parse a duration such as `90`, `90s`, `2m`, `1.5h` or `500ms` (plain
+/// numbers are seconds).
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let lower = text.trim().to_ascii_lowercase();
+    let (number, multiplier) = if let Some(n) = lower.strip_suffix("ms") {
+        (n, 0.001)
+    } else if let Some(n) = lower.strip_suffix('s') {
+        (n, 1.0)
+    } else if let Some(n) = lower.strip_suffix('m') {
+        (n, 60.0)
+    } else if let Some(n) = lower.strip_suffix('h') {
+        (n, 3600.0)
+    } else {
+        (lower.as_str(), 1.0)
+    };
+    let value: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid duration '{}', expected e.g. 90s, 2m or 1h", text))?;
+    if value < 0.0 || !value.is_finite() {
+        return Err(format!("invalid duration '{}'", text));
+    }
+    Ok(Duration::from_secs_f64(value * multiplier))
+}
+
+/// Format a byte count with a binary unit, e.g. `1.5 GiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
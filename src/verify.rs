@@ -0,0 +1,219 @@
+use crate::combine::segment_frames;
+use crate::console::{debug, error, info, warning};
+use crate::plan::JobPlan;
+use crate::segment::Segment;
+use crate::{ffmpeg, interrupt, probe, process, DeliveryError, Result};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+/// Decode errors quoted per damaged slice of the source.
+const DECODE_ERROR_LINES: usize = 3;
+
+/// Check that every segment of `plan` produced the frames it should, and that
+/// together they add up to the input's frame count when the container records
+/// it. Segments cut by frame count must match exactly; segments cut by
+/// duration may be off by one. Mismatches are listed and fail the job unless
+/// `allow_mismatch` is set. Video and intermediate chunks are counted with
+/// `ffprobe`. Returns
+/// the number of frames the segments hold.
+pub fn frame_counts(segments_dir: &Path, plan: &JobPlan, ffprobe: &Path, allow_mismatch: bool) -> Result<u64> {
+    let _span = tracing::info_span!("verify", segments = plan.segments.len()).entered();
+    info!("\n🔍 Checking frame counts...");
+
+    let mut problems = Vec::new();
+    let mut actual = 0;
+    let mut tolerance = 0;
+    for (segment, planned) in plan.segments().iter().zip(&plan.segments) {
+        let frames = segment_frame_count(segments_dir, segment, plan, ffprobe)?;
+        let allowed = if planned.frames.is_some() { 0 } else { 1 };
+        if frames.abs_diff(planned.expected_frames) > allowed {
+            problems.push(format!("Segment {}: {} frames, expected {}", segment.id, frames, planned.expected_frames));
+        }
+        actual += frames;
+        tolerance += allowed;
+    }
+    let expected = plan.source_frames.unwrap_or(plan.expected_frames());
+    if actual.abs_diff(expected) > tolerance {
+        problems.push(format!("Total: {} frames, expected {}", actual, expected));
+    }
+
+    if problems.is_empty() {
+        info!("✅ All {} frames accounted for", actual);
+        return Ok(actual);
+    }
+    for problem in &problems {
+        if allow_mismatch {
+            warning!("⚠️ {}", problem);
+        } else {
+            error!("❌ {}", problem);
+        }
+    }
+    if allow_mismatch {
+        warning!("⚠️ Frame count mismatch accepted (--allow-frame-mismatch)");
+        Ok(actual)
+    } else {
+        Err(DeliveryError::FrameCountMismatch { expected, actual })
+    }
+}
+
+// Frames `segment` produced: its image files, or the frames in its chunk.
+fn segment_frame_count(segments_dir: &Path, segment: &Segment, plan: &JobPlan, ffprobe: &Path) -> Result<u64> {
+    match plan.chunk_extension() {
+        None => {
+            let dir = segment.dir(segments_dir);
+            let frames = segment_frames(&dir, plan.frame_format.extension())
+                .map_err(|e| DeliveryError::io(format!("Failed to read {}", dir.display()), e))?;
+            Ok(frames.len() as u64)
+        }
+        Some(ext) => {
+            let chunk = segment.chunk(segments_dir, &ext);
+            if chunk.exists() { probe::count_frames(ffprobe, &chunk) } else { Ok(0) }
+        }
+    }
+}
+
+fn hash_file(path: &Path) -> Result<Vec<u8>> {
+    let bytes = fs::read(path).map_err(|e| DeliveryError::io(format!("Failed to read {}", path.display()), e))?;
+    Ok(Sha256::digest(bytes).to_vec())
+}
+
+/// Check every join between consecutive segments of `plan` and return a
+/// description of each broken one:
+///
+/// - the last frame of one segment and the first of the next are identical,
+///   i.e. a frame was probably encoded twice (or the content is static);
+/// - the frames a segment produced end more than half a frame before or after
+///   the next segment starts, leaving a gap or an overlap.
+///
+/// Video and intermediate chunks are only checked for gaps and overlaps.
+pub fn boundaries(segments_dir: &Path, plan: &JobPlan, ffprobe: &Path) -> Result<Vec<String>> {
+    let _span = tracing::info_span!("verify", segments = plan.segments.len()).entered();
+    info!("\n🔍 Checking segment boundaries...");
+
+    let mut segments = plan.segments();
+    segments.sort_by(|a, b| a.start.total_cmp(&b.start));
+    let mut broken = Vec::new();
+    for pair in segments.windows(2) {
+        let (before, after) = (&pair[0], &pair[1]);
+        let join = format!("Join {}/{} at {:.3}s", before.id, after.id, after.start);
+        if plan.chunk_extension().is_none() {
+            let dir = before.dir(segments_dir);
+            let first = segment_frames(&dir, plan.frame_format.extension()).map_err(|e| DeliveryError::io(format!("Failed to read {}", dir.display()), e))?;
+            let dir = after.dir(segments_dir);
+            let second = segment_frames(&dir, plan.frame_format.extension()).map_err(|e| DeliveryError::io(format!("Failed to read {}", dir.display()), e))?;
+
+            if let (Some(last), Some(next)) = (first.last(), second.first()) {
+                if hash_file(last)? == hash_file(next)? {
+                    broken.push(format!("{}: duplicated frame ({} and {} are identical)",
+                        join, last.display(), next.display()));
+                }
+            }
+        }
+
+        if plan.frame_rate > 0.0 {
+            let frames = segment_frame_count(segments_dir, before, plan, ffprobe)?;
+            let end = before.start + frames as f64 / plan.frame_rate;
+            let offset = after.start - end;
+            if offset.abs() > 0.5 / plan.frame_rate {
+                let kind = if offset > 0.0 { "gap" } else { "overlap" };
+                broken.push(format!("{}: {} of {:.3}s (~{} frames)",
+                    join, kind, offset.abs(), (offset.abs() * plan.frame_rate).round() as u64));
+            }
+        }
+    }
+
+    if broken.is_empty() {
+        info!("✅ All {} joins are clean", segments.len().saturating_sub(1));
+    }
+    for problem in &broken {
+        warning!("⚠️ {}", problem);
+    }
+    Ok(broken)
+}
+
+/// The ffmpeg invocation that decodes the slice of `input` covered by
+/// `segment` and throws the result away, logging nothing but decode errors.
+pub fn decode_command(ffmpeg: &Path, input: &Path, segment: &Segment) -> Command {
+    let mut cmd = Command::new(ffmpeg);
+    cmd.args(["-v", "error", "-nostdin", "-ss", &format!("{:.6}", segment.start)])
+        .args(["-t", &format!("{:.6}", segment.duration)])
+        .arg("-i").arg(ffmpeg::path_arg(input))
+        .args(["-map", "0:v:0", "-map", "0:a?", "-f", "null", "-"]);
+    cmd
+}
+
+/// Decode the whole source, one slice per segment and `threads` slices at
+/// a time, and fail if ffmpeg reports any decode error, so a damaged file is
+/// caught before the real encode starts.
+pub fn source(ffmpeg: &Path, input: &Path, segments: &[Segment], threads: usize) -> Result<()> {
+    let _span = tracing::info_span!("verify", segments = segments.len()).entered();
+    info!("\n🔍 Decoding the source to check it...");
+    let started = Instant::now();
+
+    let queue: Mutex<VecDeque<&Segment>> = Mutex::new(segments.iter().collect());
+    let damaged: Mutex<Vec<(&Segment, String)>> = Mutex::new(Vec::new());
+    let failures: Mutex<Vec<DeliveryError>> = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, segments.len().max(1)) {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap().pop_front();
+                let Some(segment) = next else { break };
+                if interrupt::requested() {
+                    break;
+                }
+                match decode_errors(ffmpeg, input, segment) {
+                    Ok(None) => {}
+                    Ok(Some(errors)) => damaged.lock().unwrap().push((segment, errors)),
+                    Err(e) => failures.lock().unwrap().push(e),
+                }
+            });
+        }
+    });
+
+    interrupt::check()?;
+    if let Some(e) = failures.into_inner().unwrap().into_iter().next() {
+        return Err(e);
+    }
+    let mut damaged = damaged.into_inner().unwrap();
+    if damaged.is_empty() {
+        info!("✅ The source decoded cleanly in {:.2} seconds", started.elapsed().as_secs_f32());
+        return Ok(());
+    }
+    damaged.sort_by_key(|(segment, _)| segment.id);
+    let report: Vec<String> = damaged
+        .iter()
+        .map(|(segment, errors)| format!("{:.3}s-{:.3}s: {}", segment.start, segment.start + segment.duration, errors))
+        .collect();
+    Err(DeliveryError::DamagedSource(report.join("\n")))
+}
+
+// Decode `segment`'s slice of `input` and return the errors ffmpeg logged,
+// if any (at most a few lines of them).
+fn decode_errors(ffmpeg: &Path, input: &Path, segment: &Segment) -> Result<Option<String>> {
+    let mut cmd = decode_command(ffmpeg, input, segment);
+    process::contain(&mut cmd);
+    debug!("Command: {}", ffmpeg::display_command(&cmd));
+    let output = cmd.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(ffmpeg.to_path_buf()),
+        _ => DeliveryError::io("Failed to execute ffmpeg", e),
+    })?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    if output.status.success() && lines.is_empty() {
+        return Ok(None);
+    }
+    if lines.is_empty() {
+        return Ok(Some(format!("ffmpeg exited with {}", output.status)));
+    }
+    let mut errors = lines[..lines.len().min(DECODE_ERROR_LINES)].join("; ");
+    if lines.len() > DECODE_ERROR_LINES {
+        errors.push_str(&format!(" (and {} more)", lines.len() - DECODE_ERROR_LINES));
+    }
+    Ok(Some(errors))
+}
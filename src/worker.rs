@@ -0,0 +1,643 @@
+use crate::console::{debug, error, info, trace, warning};
+use crate::checkpoint::Checkpoint;
+use crate::events::{self, Event};
+use crate::{cleanup, ffmpeg, interrupt, logfile, process};
+use crate::progress::{ProgressParser, ProgressUpdate, ProgressView, Tracker};
+use crate::format::OutputFormat;
+use crate::hook::{self, HookMode, WatermarkHook};
+use crate::job::{AlphaMode, VfrMode};
+use crate::{overlay, package, rendition, twopass};
+use crate::twopass::Bitrates;
+use crate::segment::Segment;
+use crate::{DeliveryError, EncodeJob, Result};
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::process::{ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of trailing ffmpeg stderr lines kept for error reports.
+const STDERR_TAIL_LINES: usize = 20;
+
+/// How often the watchdog checks a running segment.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Messages sent from worker threads to the coordinating thread.
+enum Message {
+    Progress(usize, ProgressUpdate),
+    Finished(usize, Result<()>),
+}
+
+/// The ffmpeg invocation that composites and exports `segment`: its only
+/// pass, or the second of a two-pass encode, at the job's target bitrates,
+/// on the GPU of worker `segment.id`.
+pub fn segment_command(job: &EncodeJob, segment: &Segment) -> Command {
+    encode_command(job, segment, job.two_pass.map(|_| 2), &job.target_bitrates(), job.worker_gpu(segment.id))
+}
+
+/// The ffmpeg invocation that composites and exports `segment` with every
+/// output at its entry of `bitrates` (one per rendition), decoding and
+/// encoding on `gpu` if set. Pass 1 of a two-pass encode only writes the
+/// encoder's statistics to the segment's stats files; pass 2 reads them.
+pub fn encode_command(
+    job: &EncodeJob,
+    segment: &Segment,
+    pass: Option<u8>,
+    bitrates: &[Option<u64>],
+    gpu: Option<usize>,
+) -> Command {
+    let output = match job.chunk_extension() {
+        None => ffmpeg::sequence_pattern(&segment.dir(&job.segments_dir), &format!("%05d.{}", job.frame_format.extension())),
+        Some(ext) => ffmpeg::path_arg(&segment.chunk(&job.segments_dir, &ext)),
+    };
+    let threads = job.ffmpeg_threads().to_string();
+    let mut cmd = Command::new(&job.ffmpeg);
+    cmd.args(["-filter_complex_threads", &threads]).args(["-threads", &threads]);
+    // Output options, repeated for every rendition. The watermark hook's
+    // frames are composited and cut to the segment already.
+    let mut options = match &job.watermark_hook {
+        Some(hook) => {
+            cmd.args(hook.encode_input_args(&job.segments_dir, segment, hook_pixel_format(job, hook)));
+            Vec::new()
+        }
+        None => {
+            cmd.args(job.hwaccel.decode_args(gpu));
+            composite_args(job, segment, &mut cmd)
+        }
+    };
+    options.extend(job.color_tag_args());
+    if job.chunk_extension().is_some() {
+        options.push("-an".to_string());
+    }
+    // Keyframes where the packager will cut, counted from the source's start
+    if job.package.iter().any(|p| p.is_streaming()) && job.output_format == OutputFormat::Video {
+        options.extend(["-force_key_frames".to_string(), package::keyframe_times(segment, job.package_segment())]);
+    }
+    options.extend(["-threads".to_string(), threads]);
+
+    // Encoder and pass options of the output at `index`
+    let encoder_args = |index: usize| {
+        let name = job.renditions.get(index).map(|r| r.name.as_str());
+        let stats = twopass::stats_file(&job.segments_dir, segment, name);
+        match job.output_format {
+            OutputFormat::Video => {
+                let bitrate = bitrates.get(index).copied().flatten();
+                job.video_encoder_args(bitrate, pass.map(|p| (p, stats.as_path())), gpu)
+            }
+            OutputFormat::Frames => job.encoder_args(),
+        }
+    };
+    // The first pass only gathers statistics
+    let target = |path: OsString| -> Vec<OsString> {
+        match pass {
+            Some(1) => vec!["-f".into(), "null".into(), "-".into()],
+            _ => vec!["-y".into(), path],
+        }
+    };
+
+    if job.output_format == OutputFormat::Video && !job.renditions.is_empty() {
+        cmd.args(["-progress", "pipe:1", "-nostats"]);
+        for (index, (rendition, (ext, _))) in job.renditions.iter().zip(job.video_chunks("")).enumerate() {
+            cmd.args(["-map", &rendition::output_label(index)])
+                .args(&options)
+                .args(encoder_args(index))
+                .args(rendition.rate_args())
+                .args(target(ffmpeg::path_arg(&segment.chunk(&job.segments_dir, &ext))));
+        }
+    } else {
+        cmd.args(&options)
+            .args(encoder_args(0))
+            .args(["-progress", "pipe:1", "-nostats"])
+            .args(target(output));
+    }
+    cmd
+}
+
+// Add the inputs of `segment` (the source from its start and the overlays)
+// and the filter graph compositing them to `cmd`. Returns the output
+// options taking the segment's frames from the graph.
+fn composite_args(job: &EncodeJob, segment: &Segment, cmd: &mut Command) -> Vec<String> {
+    // `origin` is the time of the source the filter graph's timeline starts at
+    let (mut limit, origin) = match (&segment.source, segment.frames) {
+        // A split-off piece is encoded whole
+        (Some(source), _) => {
+            cmd.arg("-i").arg(ffmpeg::path_arg(&job.segments_dir.join(source)));
+            (Vec::new(), segment.start)
+        }
+        // Seek half a frame early: accurate seeking (ffmpeg's default) drops
+        // every frame before the seek point, so the first frame survives any
+        // rounding of the timestamp, and exactly `frames` frames follow. When
+        // converting to a constant rate the new frame grid starts at the seek
+        // point instead, so it must be exact.
+        (None, Some(frames)) => {
+            let seek = match job.vfr_mode {
+                VfrMode::Cfr(_) => segment.start,
+                VfrMode::Warn => (segment.start - 0.5 * segment.duration / frames.max(1) as f64).max(0.0),
+            };
+            cmd.args(["-ss", &format!("{:.6}", seek)]).arg("-i").arg(ffmpeg::path_arg(&job.input));
+            (vec!["-frames:v".to_string(), frames.to_string()], seek)
+        }
+        (None, None) => {
+            cmd.args(["-ss", &segment.start.to_string()]).arg("-i").arg(ffmpeg::path_arg(&job.input));
+            (vec!["-t".to_string(), segment.duration.to_string()], segment.start)
+        }
+    };
+    let timeline = origin..segment.start + segment.duration;
+    for (path, playback, _) in job.overlay_inputs(&timeline) {
+        cmd.args(overlay::input_args(path, playback, origin));
+    }
+    cmd.args(["-filter_complex", &job.filter_graph(&timeline)]);
+    if let VfrMode::Cfr(rate) = job.vfr_mode {
+        limit.extend(["-fps_mode".to_string(), "cfr".to_string(), "-r".to_string(), rate.to_string()]);
+    }
+    limit
+}
+
+/// The ffmpeg invocation that decodes and composites `segment` for the
+/// job's watermark hook, if it has one, decoding on `gpu` if set.
+pub fn decode_command(job: &EncodeJob, segment: &Segment, gpu: Option<usize>) -> Option<Command> {
+    let hook = job.watermark_hook.as_ref()?;
+    let threads = job.ffmpeg_threads().to_string();
+    let mut cmd = Command::new(&job.ffmpeg);
+    cmd.args(["-nostdin", "-filter_complex_threads", &threads])
+        .args(["-threads", &threads])
+        .args(job.hwaccel.decode_args(gpu));
+    let options = composite_args(job, segment, &mut cmd);
+    // Every frame the graph puts out goes to the hook, none duplicated
+    let passthrough = !matches!(job.vfr_mode, VfrMode::Cfr(_));
+    cmd.args(options)
+        .args(if passthrough { &["-fps_mode", "passthrough"][..] } else { &[] })
+        .args(["-threads", &threads])
+        .args(hook.decode_output_args(&job.segments_dir, segment, hook_pixel_format(job, hook)));
+    Some(cmd)
+}
+
+/// The job's watermark hook's invocation for `segment`, if it has one.
+pub fn hook_command(job: &EncodeJob, segment: &Segment) -> Option<Command> {
+    let hook = job.watermark_hook.as_ref()?;
+    let pix_fmt = hook_pixel_format(job, hook);
+    Some(hook.command(&job.segments_dir, segment, pix_fmt, &job.output_stem(), job.recipient.as_deref()))
+}
+
+// Pixel format of the frames the job hands `hook`.
+fn hook_pixel_format(job: &EncodeJob, hook: &WatermarkHook) -> &'static str {
+    hook.pixel_format(job.bit_depth(), job.alpha == AlphaMode::Preserve)
+}
+
+// Kill the ffmpeg process of segment `id`, registered as `key`, once it has
+// been silent for longer than `stall_timeout` or running for longer than
+// `segment_timeout`. Returns why it was killed, or `None` if ffmpeg finished
+// first.
+fn watchdog(
+    id: usize,
+    key: usize,
+    stall_timeout: Option<Duration>,
+    segment_timeout: Option<Duration>,
+    started: Instant,
+    last_activity_ms: &AtomicU64,
+    finished: &AtomicBool,
+) -> Option<String> {
+    while !finished.load(Ordering::Relaxed) {
+        thread::sleep(WATCHDOG_INTERVAL);
+        let elapsed = started.elapsed();
+        let idle = elapsed.saturating_sub(Duration::from_millis(last_activity_ms.load(Ordering::Relaxed)));
+        let reason = match (stall_timeout, segment_timeout) {
+            (_, Some(limit)) if elapsed > limit => {
+                format!("still running after {:.0}s (--segment-timeout)", limit.as_secs_f64())
+            }
+            (Some(limit), _) if idle > limit => {
+                format!("no output for {:.0}s (--stall-timeout)", limit.as_secs_f64())
+            }
+            _ => continue,
+        };
+        if finished.load(Ordering::Relaxed) {
+            break;
+        }
+        warning!("⚠️ [Thread {}] FFmpeg {}, killing it", id, reason);
+        interrupt::kill(key);
+        return Some(reason);
+    }
+    None
+}
+
+/// Composite and export a single segment with its own ffmpeg process, its
+/// outputs at `bitrates` (see [`encode_command`]).
+///
+/// `on_progress` is called for every `-progress` block ffmpeg reports. If
+/// `cpus` isn't empty, ffmpeg is pinned to those CPUs; it runs on `gpu` if
+/// set.
+pub fn encode_segment(
+    job: &EncodeJob,
+    segment: &Segment,
+    bitrates: &[Option<u64>],
+    cpus: &[usize],
+    gpu: Option<usize>,
+    mut on_progress: impl FnMut(ProgressUpdate),
+) -> Result<()> {
+    let thread_id = segment.id;
+    let span = tracing::info_span!(
+        "segment",
+        segment_id = segment.id,
+        start_time = segment.start,
+        duration = segment.duration,
+    );
+    let _guard = span.enter();
+    let segment_dir = segment.dir(&job.segments_dir);
+
+    // Create segment-specific directory
+    fs::create_dir(&segment_dir)
+        .map_err(|e| DeliveryError::io(format!("[Thread {}] Failed to create segment directory", thread_id), e))?;
+
+    interrupt::check()?;
+    let mut cmd = encode_command(job, segment, job.two_pass.map(|_| 2), bitrates, gpu);
+    prepare(&mut cmd, job, cpus);
+    let feed = start_hook(job, segment, gpu, cpus, &mut cmd)?;
+
+    debug!("[Thread {}] Starting FFmpeg at {:.2}s for {:.2}s",
+        thread_id, segment.start, segment.duration);
+    debug!("[Thread {}] Command: {}", thread_id, ffmpeg::display_command(&cmd));
+
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => DeliveryError::FfmpegNotFound(job.ffmpeg.clone()),
+            _ => DeliveryError::io(format!("[Thread {}] Failed to spawn FFmpeg", thread_id), e),
+        })?;
+
+    process::adopt(&child);
+    process::pin_spawned(&child, cpus);
+
+    // Capture stderr on a helper thread while progress is read from stdout
+    let stderr = child.stderr.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let key = interrupt::register(child);
+    let started = Instant::now();
+    let last_activity_ms = AtomicU64::new(0);
+    let touch = || last_activity_ms.store(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    let finished = AtomicBool::new(false);
+    let (stderr_tail, timed_out) = thread::scope(|scope| {
+        let watcher = (job.stall_timeout.is_some() || job.segment_timeout.is_some()).then(|| {
+            scope.spawn(|| {
+                let _guard = span.enter();
+                let (stall_timeout, segment_timeout) = (job.stall_timeout, job.segment_timeout);
+                watchdog(thread_id, key, stall_timeout, segment_timeout, started, &last_activity_ms, &finished)
+            })
+        });
+        let stderr_reader = scope.spawn(|| {
+            let _guard = span.enter();
+            logfile::set_segment(Some(thread_id));
+            let mut stderr_tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+            for line in BufReader::new(stderr).lines() {
+                match line {
+                    Ok(line) => {
+                        touch();
+                        // Errors are worth showing immediately, the rest only when verbose
+                        if line.contains("error") || line.contains("fail") {
+                            warning!("[Thread {}] {}", thread_id, line);
+                        } else {
+                            debug!("[Thread {}] {}", thread_id, line);
+                        }
+                        if stderr_tail.len() == STDERR_TAIL_LINES {
+                            stderr_tail.pop_front();
+                        }
+                        stderr_tail.push_back(line);
+                    }
+                    Err(e) => {
+                        warning!("⚠️ [Thread {}] Error reading FFmpeg output: {}", thread_id, e);
+                        break;
+                    }
+                }
+            }
+            stderr_tail
+        });
+
+        let mut parser = ProgressParser::default();
+        for line in BufReader::new(stdout).lines().map_while(|l| l.ok()) {
+            touch();
+            trace!("[Thread {}] progress: {}", thread_id, line);
+            if let Some(update) = parser.feed(&line) {
+                on_progress(update);
+            }
+        }
+
+        let stderr_tail = stderr_reader.join().unwrap_or_default();
+        finished.store(true, Ordering::Relaxed);
+        (stderr_tail, watcher.and_then(|w| w.join().ok().flatten()))
+    });
+
+    let mut child = interrupt::release(key).expect("ffmpeg process registered above");
+    let status = child
+        .wait()
+        .map_err(|e| DeliveryError::io(format!("[Thread {}] Failed to wait for FFmpeg", thread_id), e))?;
+    // The hook may have stopped early and left the encode fewer frames. A
+    // failed hook also breaks the decode's pipe, so it is reported first.
+    let fed: Vec<Result<()>> = feed.into_iter().rev().map(|stage| stage.finish(thread_id, status.success())).collect();
+
+    if status.success() {
+        fed.into_iter().collect::<Result<()>>()?;
+        if job.watermark_hook.is_some() {
+            cleanup::remove_dir(&hook::input_dir(&job.segments_dir, segment))?;
+            cleanup::remove_dir(&hook::output_dir(&job.segments_dir, segment))?;
+        }
+        job.embed_icc_profile(segment)?;
+        debug!("✅ [Thread {}] FFmpeg completed successfully", thread_id);
+        Ok(())
+    } else if interrupt::requested() {
+        debug!("[Thread {}] FFmpeg stopped by interrupt", thread_id);
+        Err(DeliveryError::Interrupted)
+    } else if let Some(reason) = timed_out {
+        Err(DeliveryError::SegmentTimedOut { id: segment.id, reason })
+    } else {
+        let exit_code = status.code().unwrap_or(-1);
+        error!("❌ [Thread {}] FFmpeg failed with exit code: {}", thread_id, exit_code);
+        Err(DeliveryError::SegmentFailed {
+            id: segment.id,
+            stderr: Vec::from(stderr_tail).join("\n"),
+        })
+    }
+}
+
+// Contain `cmd`, one of a segment's processes, pin it to `cpus` and lower
+// its priority if the job runs in the background.
+fn prepare(cmd: &mut Command, job: &EncodeJob, cpus: &[usize]) {
+    process::contain(cmd);
+    process::pin(cmd, cpus);
+    if job.background {
+        process::lower_priority(cmd);
+    }
+}
+
+// A process feeding a segment's encode its frames: the decode or the
+// watermark hook, registered as `key`, with the tail of its stderr.
+struct Stage {
+    name: &'static str,
+    key: usize,
+    log: thread::JoinHandle<VecDeque<String>>,
+}
+
+impl Stage {
+    // Spawn `cmd` as the `name` stage of segment `id`, reading `stdin`.
+    // Returns the stage with its stdout, if piped.
+    fn spawn(
+        mut cmd: Command,
+        name: &'static str,
+        id: usize,
+        cpus: &[usize],
+        stdin: Stdio,
+        stdout: Stdio,
+    ) -> Result<(Stage, Option<ChildStdout>)> {
+        debug!("[Thread {}] Command ({}): {}", id, name, ffmpeg::display_command(&cmd));
+        let mut child = cmd
+            .stdin(stdin)
+            .stdout(stdout)
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| DeliveryError::io(format!("[Thread {}] Failed to spawn the {}", id, name), e))?;
+        process::adopt(&child);
+        process::pin_spawned(&child, cpus);
+        let (stderr, stdout) = (child.stderr.take().unwrap(), child.stdout.take());
+        let span = tracing::Span::current();
+        let log = thread::spawn(move || {
+            let _guard = span.enter();
+            let mut tail = VecDeque::new();
+            for line in BufReader::new(stderr).lines().map_while(|l| l.ok()) {
+                debug!("[Thread {}] {}: {}", id, name, line);
+                if tail.len() == STDERR_TAIL_LINES {
+                    tail.pop_front();
+                }
+                tail.push_back(line);
+            }
+            tail
+        });
+        Ok((Stage { name, key: interrupt::register(child), log }, stdout))
+    }
+
+    // Wait for the stage of segment `id` to finish; one that failed fails
+    // the segment. Once the encode it feeds has `failed`, the stage is
+    // stopped and its failure is the encode's.
+    fn finish(self, id: usize, encoded: bool) -> Result<()> {
+        let mut child = interrupt::release(self.key).expect("stage registered when spawned");
+        if !encoded {
+            let _ = child.kill();
+        }
+        let status = child
+            .wait()
+            .map_err(|e| DeliveryError::io(format!("[Thread {}] Failed to wait for the {}", id, self.name), e))?;
+        let tail = self.log.join().unwrap_or_default();
+        if status.success() || !encoded {
+            return Ok(());
+        }
+        error!("❌ [Thread {}] The {} failed with exit code: {}", id, self.name, status.code().unwrap_or(-1));
+        let stderr = format!("the {} failed:\n{}", self.name, Vec::from(tail).join("\n"));
+        Err(DeliveryError::SegmentFailed { id, stderr })
+    }
+}
+
+// Start feeding `segment`'s `encode` through the job's watermark hook, if it
+// has one. In pipe mode the decode and the hook run alongside the encode,
+// which reads the hook's stdout; in files mode both have finished once this
+// returns. Returns the stages still running.
+fn start_hook(
+    job: &EncodeJob,
+    segment: &Segment,
+    gpu: Option<usize>,
+    cpus: &[usize],
+    encode: &mut Command,
+) -> Result<Vec<Stage>> {
+    let (Some(hook), Some(mut decode), Some(mut command)) =
+        (&job.watermark_hook, decode_command(job, segment, gpu), hook_command(job, segment))
+    else {
+        return Ok(Vec::new());
+    };
+    prepare(&mut decode, job, cpus);
+    prepare(&mut command, job, cpus);
+    match hook.mode {
+        HookMode::Pipe => {
+            let (decoder, frames) = Stage::spawn(decode, "decode", segment.id, cpus, Stdio::null(), Stdio::piped())?;
+            let frames = frames.expect("decode stdout piped");
+            let hooked = Stage::spawn(command, "watermark hook", segment.id, cpus, frames.into(), Stdio::piped());
+            let (hooked, marked) = match hooked {
+                Ok(spawned) => spawned,
+                Err(e) => {
+                    decoder.finish(segment.id, false)?;
+                    return Err(e);
+                }
+            };
+            encode.stdin(marked.expect("hook stdout piped"));
+            Ok(vec![decoder, hooked])
+        }
+        HookMode::Files => {
+            for dir in [hook::input_dir(&job.segments_dir, segment), hook::output_dir(&job.segments_dir, segment)] {
+                fs::create_dir_all(&dir)
+                    .map_err(|e| DeliveryError::io(format!("Failed to create {}", dir.display()), e))?;
+            }
+            let (decoder, _) = Stage::spawn(decode, "decode", segment.id, cpus, Stdio::null(), Stdio::null())?;
+            decoder.finish(segment.id, true)?;
+            interrupt::check()?;
+            let (hooked, _) = Stage::spawn(command, "watermark hook", segment.id, cpus, Stdio::null(), Stdio::null())?;
+            hooked.finish(segment.id, true)?;
+            interrupt::check()?;
+            Ok(Vec::new())
+        }
+    }
+}
+
+/// Expected duration of every segment, indexed by segment id.
+fn durations(segments: &[Segment]) -> Vec<f64> {
+    let mut durations = vec![0.0; segments.iter().map(|s| s.id + 1).max().unwrap_or(0)];
+    for segment in segments {
+        durations[segment.id] = segment.duration;
+    }
+    durations
+}
+
+// Encode `segment`, re-encoding it up to `job.retries` times if it fails or
+// times out.
+fn encode_with_retries(
+    job: &EncodeJob,
+    segment: &Segment,
+    bitrates: &Bitrates,
+    cpus: &[usize],
+    gpu: Option<usize>,
+    tx: &mpsc::Sender<Message>,
+) -> Result<()> {
+    let bitrates = bitrates.get(&segment.id).cloned().unwrap_or_else(|| job.target_bitrates());
+    let mut attempt = 0;
+    loop {
+        let result = encode_segment(job, segment, &bitrates, cpus, gpu, |update| {
+            let _ = tx.send(Message::Progress(segment.id, update));
+        });
+        match result {
+            Err(DeliveryError::SegmentFailed { .. } | DeliveryError::SegmentTimedOut { .. })
+                if attempt < job.retries =>
+            {
+                attempt += 1;
+                warning!("⚠️ Segment {} failed, retrying ({}/{})", segment.id, attempt, job.retries);
+                cleanup::remove_dir(&segment.dir(&job.segments_dir))?;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Encode the segments on a pool of `workers` threads fed from a shared queue
+/// and wait for all of them to finish. Segments in `bitrates` are encoded at
+/// those instead of the job's target bitrates.
+///
+/// If any segment fails, the error of the lowest numbered failed segment is returned.
+pub fn run_all(job: &EncodeJob, segments: &[Segment], bitrates: &Bitrates, workers: usize) -> Result<()> {
+    let stage = tracing::info_span!("encode", segments = segments.len());
+    let _guard = stage.enter();
+    let (tx, rx) = mpsc::channel();
+
+    info!("\n⚙️ Starting parallel processing...");
+    if job.pin_cpus && !process::PINNING_SUPPORTED {
+        warning!("⚠️ CPU pinning is not supported on this platform, ignoring --pin-cpus");
+    }
+    let processing_start = Instant::now();
+
+    let total = segments.len();
+    let mut failures = Vec::new();
+    let mut tracker = Tracker::new(durations(segments));
+    let mut view = ProgressView::new(segments);
+    let mut checkpoint = Checkpoint::load(&job.segments_dir)?;
+    let queue: Mutex<VecDeque<&Segment>> = Mutex::new(segments.iter().collect());
+    thread::scope(|scope| {
+        // Spawn worker threads; each takes the next segment from the shared
+        // queue as soon as it is free, so no thread idles while work is left
+        for worker in 0..workers {
+            let tx = tx.clone();
+            debug!("🧵 Starting thread {}...", worker);
+            let stage = &stage;
+            let queue = &queue;
+            let cpus = job.worker_cpus(worker);
+            if !cpus.is_empty() {
+                debug!("📌 Thread {} pinned to CPUs {:?}", worker, cpus);
+            }
+            let gpu = job.worker_gpu(worker);
+            if let Some(gpu) = gpu {
+                debug!("🎮 Thread {} on GPU {}", worker, gpu);
+            }
+            scope.spawn(move || {
+                let _guard = stage.enter();
+                loop {
+                    let next = queue.lock().unwrap().pop_front();
+                    let Some(segment) = next else { break };
+                    if interrupt::requested() {
+                        break;
+                    }
+                    logfile::set_segment(Some(segment.id));
+                    let result = encode_with_retries(job, segment, bitrates, &cpus, gpu, &tx);
+                    tx.send(Message::Finished(segment.id, result)).unwrap();
+                }
+            });
+        }
+
+        // Drop the original transmitter so the channel closes properly
+        drop(tx);
+
+        debug!("⏳ Waiting for threads to complete...");
+
+        // Collect progress and results from worker threads
+        let mut finished = 0;
+        for event in rx.iter() {
+            match event {
+                Message::Progress(thread_id, update) => {
+                    tracker.update(thread_id, update);
+                    view.update(&tracker, thread_id);
+                    events::emit(Event::SegmentProgress {
+                        segment: thread_id,
+                        frame: update.frame,
+                        out_time: update.out_time,
+                        percent: tracker.segment_percent(thread_id),
+                        overall_percent: tracker.overall_percent(),
+                    });
+                }
+                Message::Finished(thread_id, result) => {
+                    finished += 1;
+                    view.finish_segment(thread_id, result.is_ok());
+                    events::emit(Event::SegmentDone {
+                        segment: thread_id,
+                        success: result.is_ok(),
+                        error: result.as_ref().err().map(|e| e.to_string()),
+                    });
+                    match result {
+                        Ok(()) => {
+                            info!("✅ Segment {} completed successfully ({}/{})",
+                                thread_id, finished, total);
+                            checkpoint.completed.insert(thread_id);
+                            if let Err(e) = checkpoint.save(&job.segments_dir) {
+                                warning!("⚠️ Failed to save checkpoint: {}", e);
+                            }
+                        }
+                        Err(DeliveryError::Interrupted) => {
+                            info!("⏹ Segment {} stopped ({}/{})", thread_id, finished, total);
+                        }
+                        Err(e) => {
+                            error!("❌ Segment {} failed ({}/{})", thread_id, finished, total);
+                            failures.push((thread_id, e));
+                        }
+                    }
+                }
+            }
+        }
+    });
+    view.finish();
+
+    interrupt::check()?;
+    if !failures.is_empty() {
+        error!("❌ Only {}/{} segments completed successfully", total - failures.len(), total);
+        failures.sort_by_key(|(id, _)| *id);
+        return Err(failures.remove(0).1);
+    }
+
+    let processing_duration = processing_start.elapsed();
+    info!("\n✅ Parallel processing completed in {:.2} seconds", processing_duration.as_secs_f32());
+    Ok(())
+}
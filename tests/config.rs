@@ -0,0 +1,36 @@
+use delivery_encoder::config::JobConfig;
+use delivery_encoder::overlay::OverlaySettings;
+use delivery_encoder::schedule;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// The tool moves to the project root once the config is loaded, so its
+// paths must not depend on the directory it was loaded from.
+#[test]
+fn relative_paths_resolve_next_to_the_config_from_any_cwd() {
+    let launch = env::temp_dir().join(format!("delivery encoder config {}", std::process::id()));
+    let jobs = launch.join("jobs");
+    fs::create_dir_all(&jobs).unwrap();
+    let toml = "lut = \"grade.cube\"\nburn_in_font = \"Inter.ttf\"\nscreeners = \"screeners.txt\"\n\
+                icc_profile = \"review.icc\"\noverlay_schedule = \"sponsors.csv\"\n\
+                \n[[overlays]]\npath = \"bug.png\"\n";
+    fs::write(jobs.join("job.toml"), toml).unwrap();
+    fs::write(jobs.join("sponsors.csv"), "0,10,sponsor.png\n").unwrap();
+
+    env::set_current_dir(&launch).unwrap();
+    let jobs = env::current_dir().unwrap().join("jobs");
+    let config = JobConfig::load(Path::new("jobs/job.toml")).unwrap();
+    env::set_current_dir(env::temp_dir()).unwrap();
+    let schedule = config.overlay_schedule.clone().unwrap();
+    let scheduled = schedule::load(&schedule, &OverlaySettings::default(), false).unwrap();
+    fs::remove_dir_all(&launch).unwrap();
+
+    assert_eq!(config.lut, Some(jobs.join("grade.cube")));
+    assert_eq!(config.overlays.unwrap()[0].path, jobs.join("bug.png"));
+    assert_eq!(config.burn_in_font, Some(jobs.join("Inter.ttf").to_string_lossy().into_owned()));
+    assert_eq!(schedule, jobs.join("sponsors.csv"));
+    assert_eq!(config.screeners, Some(jobs.join("screeners.txt")));
+    assert_eq!(config.icc_profile, Some(jobs.join("review.icc").to_string_lossy().into_owned()));
+    assert_eq!(scheduled[0].path, jobs.join("sponsor.png"));
+}
@@ -0,0 +1,125 @@
+use delivery_encoder::ffmpeg::{self, display_command, escape_filter_path, escape_filter_value, filter_names};
+use delivery_encoder::segment::Segment;
+use delivery_encoder::worker::segment_command;
+use delivery_encoder::EncodeJob;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+const NASTY_NAMES: &[&str] = &[
+    "my clip.mov",
+    "clip [v2] (final).mov",
+    "übergröße_café_日本語.mov",
+    "it's, like; 50%.mov",
+    "-starts-with-dash.mov",
+    "take:2.mov",
+];
+
+fn segment(frames: Option<u64>) -> Segment {
+    Segment { id: 3, start: 6.0, duration: 2.0, frames, source: None }
+}
+
+fn args(job: &EncodeJob, segment: &Segment) -> Vec<OsString> {
+    segment_command(job, segment).get_args().map(OsStr::to_os_string).collect()
+}
+
+// The argument following `flag`, or following its n-th occurrence.
+fn arg_after(args: &[OsString], flag: &str, nth: usize) -> OsString {
+    args.iter()
+        .enumerate()
+        .filter(|(_, a)| *a == flag)
+        .nth(nth)
+        .map(|(i, _)| args[i + 1].clone())
+        .unwrap_or_else(|| panic!("{} #{} missing in {:?}", flag, nth, args))
+}
+
+#[test]
+fn nasty_input_and_overlay_paths_are_passed_verbatim() {
+    for name in NASTY_NAMES {
+        let dir = std::env::temp_dir().join("delivery encoder [test]").join("ünïcode dir");
+        let input = dir.join(name);
+        let overlay = dir.join(format!("overlay {}.png", name));
+        let job = EncodeJob::new(&input, &overlay, dir.join("out"));
+
+        let args = args(&job, &segment(None));
+        assert_eq!(arg_after(&args, "-i", 0), input.as_os_str(), "input {}", name);
+        assert_eq!(arg_after(&args, "-i", 1), overlay.as_os_str(), "overlay {}", name);
+    }
+}
+
+#[test]
+fn relative_paths_that_look_like_options_or_protocols_get_a_prefix() {
+    assert_eq!(ffmpeg::path_arg(Path::new("-starts-with-dash.mov")), Path::new(".").join("-starts-with-dash.mov"));
+    assert_eq!(ffmpeg::path_arg(Path::new("take:2.mov")), Path::new(".").join("take:2.mov"));
+    assert_eq!(ffmpeg::path_arg(Path::new("my clip.mov")), OsString::from("my clip.mov"));
+    let absolute = std::env::temp_dir().join("-dash.mov");
+    assert_eq!(ffmpeg::path_arg(&absolute), absolute.as_os_str());
+
+    let job = EncodeJob::new("-starts-with-dash.mov", "take:2.png", "out");
+    let args = args(&job, &segment(Some(20)));
+    assert_eq!(arg_after(&args, "-i", 0), Path::new(".").join("-starts-with-dash.mov").as_os_str());
+    assert_eq!(arg_after(&args, "-i", 1), Path::new(".").join("take:2.png").as_os_str());
+}
+
+#[test]
+fn percent_signs_in_the_segments_dir_are_escaped_in_the_pattern() {
+    let mut job = EncodeJob::new("in.mov", "overlay.png", "out");
+    job.segments_dir = PathBuf::from("/scratch/100% final [ü]");
+    let args = args(&job, &segment(Some(20)));
+    let pattern = args.last().unwrap();
+    assert_eq!(pattern, &PathBuf::from("/scratch/100%% final [ü]").join("segment_3").join("%05d.png").into_os_string());
+
+    assert_eq!(
+        ffmpeg::sequence_pattern(Path::new("a%b"), "%05d.png"),
+        Path::new("a%%b").join("%05d.png").into_os_string()
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_paths_survive() {
+    use std::os::unix::ffi::OsStrExt;
+    let dir = PathBuf::from(OsStr::from_bytes(b"/tmp/caf\xe9 50%"));
+    let pattern = ffmpeg::sequence_pattern(&dir, "%05d.png");
+    assert_eq!(pattern.as_bytes(), b"/tmp/caf\xe9 50%%/%05d.png");
+
+    let input = dir.join("clip.mov");
+    let job = EncodeJob::new(&input, "overlay.png", "out");
+    assert_eq!(arg_after(&args(&job, &segment(None)), "-i", 0), input.as_os_str());
+}
+
+#[test]
+fn filter_values_are_escaped_for_both_parser_levels() {
+    assert_eq!(escape_filter_value("logo.png"), "logo.png");
+    assert_eq!(escape_filter_value("a:b"), "a\\\\:b");
+    assert_eq!(escape_filter_value("it's"), "it\\\\\\'s");
+    assert_eq!(escape_filter_value("[x],y;z"), "\\[x\\]\\,y\\;z");
+    assert_eq!(escape_filter_value("C:\\luts\\a b.cube"), "C\\\\:\\\\\\\\luts\\\\\\\\a b.cube");
+    assert_eq!(escape_filter_path(Path::new("übergröße café.cube")), "übergröße café.cube");
+}
+
+#[test]
+fn escaped_values_do_not_split_the_graph() {
+    for name in NASTY_NAMES {
+        let graph = format!("movie={}[m];[0:v][m]overlay", escape_filter_value(name));
+        assert_eq!(filter_names(&graph), ["movie", "overlay"], "graph {}", graph);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn displayed_commands_quote_nasty_paths_for_posix_shells() {
+    let job = EncodeJob::new("/media/it's my clip.mov", "/media/logo [v2].png", "out");
+    let shown = display_command(&segment_command(&job, &segment(None)));
+    assert!(shown.contains(r"'/media/it'\''s my clip.mov'"), "{}", shown);
+    assert!(shown.contains("'/media/logo [v2].png'"), "{}", shown);
+    assert!(shown.contains("'[0:v][1:v]overlay'"), "{}", shown);
+}
+
+#[cfg(windows)]
+#[test]
+fn displayed_commands_quote_nasty_paths_for_windows_shells() {
+    let job = EncodeJob::new(r"C:\Media\my clip.mov", r"C:\Media\logo [v2].png", "out");
+    let shown = display_command(&segment_command(&job, &segment(None)));
+    assert!(shown.contains(r#""C:\Media\my clip.mov""#), "{}", shown);
+    assert!(shown.contains(r#""C:\Media\logo [v2].png""#), "{}", shown);
+}